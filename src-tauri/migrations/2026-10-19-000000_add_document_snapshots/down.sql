@@ -0,0 +1,2 @@
+DROP INDEX idx_document_snapshots_document_id;
+DROP TABLE document_snapshots;
@@ -0,0 +1,11 @@
+-- Snapshots of documents, the state of each is stored in a file of the doc folder
+CREATE TABLE document_snapshots (
+    id TEXT NOT NULL PRIMARY KEY,
+    document_id TEXT NOT NULL,
+    label TEXT NULL,
+    kind TEXT NOT NULL, -- Manual, Run, Interval or Restore
+    create_datetime TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    filename TEXT NOT NULL,
+    FOREIGN KEY (document_id) REFERENCES documents (id) ON DELETE CASCADE
+);
+CREATE INDEX idx_document_snapshots_document_id ON document_snapshots (document_id, create_datetime);
@@ -0,0 +1,48 @@
+DROP TRIGGER delete_solution_document;
+DROP TRIGGER delete_test_case_documents;
+DROP TRIGGER delete_checker_document;
+
+CREATE TABLE solutions_old (
+    id TEXT NOT NULL PRIMARY KEY,
+    author TEXT NOT NULL DEFAULT 'anonymous',
+    name TEXT NOT NULL,
+    language TEXT NOT NULL,
+    problem_id TEXT NOT NULL,
+    document_id TEXT NOT NULL,
+    FOREIGN KEY (problem_id) REFERENCES problems (id) ON DELETE CASCADE
+);
+INSERT INTO solutions_old SELECT id, author, name, language, problem_id, document_id FROM solutions;
+DROP TABLE solutions;
+ALTER TABLE solutions_old RENAME TO solutions;
+CREATE INDEX idx_solutions_problem_id ON solutions (problem_id);
+CREATE INDEX idx_solutions_name ON solutions (name, id);
+CREATE INDEX idx_solutions_author ON solutions (author, id);
+CREATE INDEX idx_solutions_language ON solutions (language, id);
+
+CREATE TABLE test_cases_old (
+    id TEXT NOT NULL PRIMARY KEY,
+    problem_id TEXT NOT NULL,
+    input_document_id TEXT NOT NULL,
+    answer_document_id TEXT NOT NULL,
+    FOREIGN KEY (problem_id) REFERENCES problems (id) ON DELETE CASCADE,
+    FOREIGN KEY (input_document_id) REFERENCES documents (id) ON DELETE CASCADE,
+    FOREIGN KEY (answer_document_id) REFERENCES documents (id) ON DELETE CASCADE
+);
+INSERT INTO test_cases_old SELECT id, problem_id, input_document_id, answer_document_id FROM test_cases;
+DROP TABLE test_cases;
+ALTER TABLE test_cases_old RENAME TO test_cases;
+CREATE INDEX idx_test_cases_problem_id ON test_cases (problem_id);
+
+CREATE TABLE checker_old (
+    id TEXT NOT NULL PRIMARY KEY,
+    name TEXT NOT NULL,
+    language TEXT NOT NULL,
+    description TEXT NULL,
+    document_id TEXT NOT NULL,
+    FOREIGN KEY (document_id) REFERENCES documents (id) ON DELETE CASCADE
+);
+INSERT INTO checker_old SELECT id, name, language, description, document_id FROM checker;
+DROP TABLE checker;
+ALTER TABLE checker_old RENAME TO checker;
+CREATE INDEX idx_checker_name ON checker (name, id);
+CREATE INDEX idx_checker_language ON checker (language, id);
@@ -0,0 +1,84 @@
+-- Owners reference their documents instead of being deleted with them, and deleting an
+-- owner deletes its documents through the triggers below. Foreign keys were not enforced
+-- before, rows of deleted problems or documents are dropped while copying.
+CREATE TABLE solutions_new (
+    id TEXT NOT NULL PRIMARY KEY,
+    author TEXT NOT NULL DEFAULT 'anonymous',
+    name TEXT NOT NULL,
+    language TEXT NOT NULL,
+    problem_id TEXT NOT NULL,
+    document_id TEXT NOT NULL,
+    FOREIGN KEY (problem_id) REFERENCES problems (id) ON DELETE CASCADE,
+    FOREIGN KEY (document_id) REFERENCES documents (id)
+);
+INSERT INTO solutions_new (id, author, name, language, problem_id, document_id)
+SELECT id, author, name, language, problem_id, document_id
+FROM solutions
+WHERE problem_id IN (SELECT id FROM problems)
+    AND document_id IN (SELECT id FROM documents);
+DROP TABLE solutions;
+ALTER TABLE solutions_new RENAME TO solutions;
+CREATE INDEX idx_solutions_problem_id ON solutions (problem_id);
+CREATE INDEX idx_solutions_name ON solutions (name, id);
+CREATE INDEX idx_solutions_author ON solutions (author, id);
+CREATE INDEX idx_solutions_language ON solutions (language, id);
+CREATE INDEX idx_solutions_document_id ON solutions (document_id);
+
+CREATE TABLE test_cases_new (
+    id TEXT NOT NULL PRIMARY KEY,
+    problem_id TEXT NOT NULL,
+    input_document_id TEXT NOT NULL,
+    answer_document_id TEXT NOT NULL,
+    FOREIGN KEY (problem_id) REFERENCES problems (id) ON DELETE CASCADE,
+    FOREIGN KEY (input_document_id) REFERENCES documents (id),
+    FOREIGN KEY (answer_document_id) REFERENCES documents (id)
+);
+INSERT INTO test_cases_new (id, problem_id, input_document_id, answer_document_id)
+SELECT id, problem_id, input_document_id, answer_document_id
+FROM test_cases
+WHERE problem_id IN (SELECT id FROM problems)
+    AND input_document_id IN (SELECT id FROM documents)
+    AND answer_document_id IN (SELECT id FROM documents);
+DROP TABLE test_cases;
+ALTER TABLE test_cases_new RENAME TO test_cases;
+CREATE INDEX idx_test_cases_problem_id ON test_cases (problem_id);
+CREATE INDEX idx_test_cases_input_document_id ON test_cases (input_document_id);
+CREATE INDEX idx_test_cases_answer_document_id ON test_cases (answer_document_id);
+
+CREATE TABLE checker_new (
+    id TEXT NOT NULL PRIMARY KEY,
+    name TEXT NOT NULL,
+    language TEXT NOT NULL,
+    description TEXT NULL,
+    document_id TEXT NOT NULL,
+    FOREIGN KEY (document_id) REFERENCES documents (id)
+);
+INSERT INTO checker_new (id, name, language, description, document_id)
+SELECT id, name, language, description, document_id
+FROM checker
+WHERE document_id IN (SELECT id FROM documents);
+DROP TABLE checker;
+ALTER TABLE checker_new RENAME TO checker;
+CREATE INDEX idx_checker_name ON checker (name, id);
+CREATE INDEX idx_checker_language ON checker (language, id);
+CREATE INDEX idx_checker_document_id ON checker (document_id);
+
+-- Triggers also run for the rows removed by the cascade of a problem
+CREATE TRIGGER delete_solution_document
+AFTER
+DELETE ON solutions FOR EACH ROW BEGIN
+DELETE FROM documents
+WHERE id = OLD.document_id;
+END;
+CREATE TRIGGER delete_test_case_documents
+AFTER
+DELETE ON test_cases FOR EACH ROW BEGIN
+DELETE FROM documents
+WHERE id IN (OLD.input_document_id, OLD.answer_document_id);
+END;
+CREATE TRIGGER delete_checker_document
+AFTER
+DELETE ON checker FOR EACH ROW BEGIN
+DELETE FROM documents
+WHERE id = OLD.document_id;
+END;
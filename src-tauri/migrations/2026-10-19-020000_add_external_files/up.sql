@@ -0,0 +1,8 @@
+-- Files on disk that documents are kept in sync with, at most one per document
+CREATE TABLE external_files (
+    document_id TEXT NOT NULL PRIMARY KEY,
+    path TEXT NOT NULL UNIQUE,
+    synced_hash TEXT NULL, -- SHA-256 of the text at the last sync, NULL before the first one
+    create_datetime TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (document_id) REFERENCES documents (id) ON DELETE CASCADE
+);
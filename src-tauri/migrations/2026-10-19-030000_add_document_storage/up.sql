@@ -0,0 +1,3 @@
+-- Large testcases are kept as raw files next to the Yrs documents, only their size is tracked
+ALTER TABLE documents ADD COLUMN storage TEXT NOT NULL DEFAULT 'Yrs';
+ALTER TABLE documents ADD COLUMN size BIGINT NULL; -- Size of a raw file in bytes, NULL for Yrs
@@ -0,0 +1,576 @@
+/// Pair programming over the network with the y-sync protocol of y-websocket
+/// A host serves chosen documents over WebSocket, one room per document at `/<doc id>`,
+/// and a peer joins a room into a document of its own. Both ends run the same loop: they
+/// exchange state vectors, answer with the updates the other side is missing, then forward
+/// updates and awareness as they happen.
+use std::{
+    collections::{HashMap, HashSet},
+    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+    sync::Arc,
+};
+
+use anyhow::{bail, Result};
+use futures_util::{SinkExt, StreamExt};
+use log::{trace, warn};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, TcpStream},
+    sync::broadcast::{self, error::RecvError},
+    task::{JoinHandle, JoinSet},
+};
+use tokio_tungstenite::{
+    tungstenite::{
+        handshake::server::{ErrorResponse, Request, Response},
+        http::StatusCode,
+        Message as Frame,
+    },
+    MaybeTlsStream, WebSocketStream,
+};
+use uuid::Uuid;
+use yrs::{
+    block::ClientID,
+    sync::{Awareness, AwarenessUpdate, Message, MessageReader, SyncMessage},
+    updates::{
+        decoder::{Decode, DecoderV1},
+        encoder::Encode,
+    },
+    Doc, StateVector, Update,
+};
+
+/// Events buffered for each connection, a connection that falls further behind resends
+/// the whole document
+const EVENT_CAPACITY: usize = 1024;
+
+/// The documents that can be shared, implemented by the app on top of the document repo
+pub trait SharedDocuments: Send + Sync + 'static {
+    /// The state vector of a document, encoded as v1
+    fn state_vector(&self, doc_id: &str) -> Result<Vec<u8>>;
+    /// The updates that a peer with `state_vector` is missing, encoded as one v1 update
+    fn diff(&self, doc_id: &str, state_vector: &[u8]) -> Result<Vec<u8>>;
+    /// Apply an update received from a peer and pass it on to the local editors
+    ///
+    /// # Arguments
+    /// * `origin` - The id of the connection the update came from
+    fn apply(&self, doc_id: &str, update: &[u8], origin: &str) -> Result<()>;
+}
+
+#[derive(Debug, Clone)]
+pub enum CollabEvent {
+    /// An update applied to a document, by a local editor (`origin` is the editor, if any)
+    /// or by a connection (`origin` is the connection)
+    Update {
+        doc_id: String,
+        origin: Option<String>,
+        update: Vec<u8>,
+    },
+    /// Awareness received by a connection, for the other connections of the document
+    Awareness {
+        doc_id: String,
+        origin: String,
+        update: AwarenessUpdate,
+    },
+}
+
+/// The shared documents and the events that every connection forwards to its peer
+#[derive(Clone)]
+pub struct CollabHub {
+    documents: Arc<dyn SharedDocuments>,
+    events: broadcast::Sender<CollabEvent>,
+}
+
+impl CollabHub {
+    pub fn new(documents: Arc<dyn SharedDocuments>) -> Self {
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+        Self { documents, events }
+    }
+
+    /// Publish an update applied to a document, the connections of the document send it
+    /// to their peers except the connection it came from
+    pub fn publish_update(&self, doc_id: &str, origin: Option<String>, update: Vec<u8>) {
+        // No receiver means nothing is shared
+        let _ = self.events.send(CollabEvent::Update {
+            doc_id: doc_id.to_string(),
+            origin,
+            update,
+        });
+    }
+}
+
+/// The awareness of a user who has no editor state to share yet, only a name
+fn local_awareness(user: &str) -> Result<Arc<Awareness>> {
+    let awareness = Awareness::new(Doc::new());
+    awareness.set_local_state(serde_json::json!({ "user": { "name": user } }))?;
+    Ok(Arc::new(awareness))
+}
+
+/// One end of a connection, the host and the joining peer run the same loop
+pub struct CollabPeer<S> {
+    /// Origin of the updates received by this connection
+    id: String,
+    /// The local document that is synced with the peer
+    doc_id: String,
+    hub: CollabHub,
+    awareness: Arc<Awareness>,
+    socket: WebSocketStream<S>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> CollabPeer<S> {
+    fn new(
+        doc_id: String,
+        hub: CollabHub,
+        awareness: Arc<Awareness>,
+        socket: WebSocketStream<S>,
+    ) -> Self {
+        Self {
+            id: format!("collab-{}", Uuid::new_v4()),
+            doc_id,
+            hub,
+            awareness,
+            socket,
+        }
+    }
+
+    /// Sync the document with the peer until either side closes the connection
+    pub async fn run(mut self) -> Result<()> {
+        // Subscribe before the first state vector, so no update falls in between
+        let mut events = self.hub.events.subscribe();
+        // Awareness clients announced by the peer, they are gone with the connection
+        let mut clients = HashSet::new();
+        let result = self.exchange(&mut events, &mut clients).await;
+        self.leave(&clients);
+        let _ = self.socket.close(None).await;
+        result
+    }
+
+    async fn exchange(
+        &mut self,
+        events: &mut broadcast::Receiver<CollabEvent>,
+        clients: &mut HashSet<ClientID>,
+    ) -> Result<()> {
+        let state_vector = self.hub.documents.state_vector(&self.doc_id)?;
+        let state_vector = StateVector::decode_v1(&state_vector)?;
+        self.send(Message::Sync(SyncMessage::SyncStep1(state_vector)))
+            .await?;
+        self.send(Message::Awareness(self.awareness.update()?))
+            .await?;
+        loop {
+            tokio::select! {
+                // Ping and pong frames are answered by tungstenite itself
+                frame = self.socket.next() => {
+                    let data = match frame {
+                        Some(Ok(Frame::Binary(data))) => data,
+                        Some(Ok(Frame::Close(_))) | None => return Ok(()),
+                        Some(Ok(_)) => continue,
+                        Some(Err(e)) => return Err(e.into()),
+                    };
+                    for reply in self.receive(&data, clients)? {
+                        self.send(reply).await?;
+                    }
+                }
+                event = events.recv() => {
+                    let message = match event {
+                        Ok(CollabEvent::Update { doc_id, origin, update })
+                            if doc_id == self.doc_id && origin.as_ref() != Some(&self.id) =>
+                        {
+                            Message::Sync(SyncMessage::Update(update))
+                        }
+                        Ok(CollabEvent::Awareness { doc_id, origin, update })
+                            if doc_id == self.doc_id && origin != self.id =>
+                        {
+                            Message::Awareness(update)
+                        }
+                        Ok(_) => continue,
+                        Err(RecvError::Lagged(skipped)) => {
+                            warn!("{} missed {} events, resending {}", self.id, skipped, self.doc_id);
+                            let empty = StateVector::default().encode_v1();
+                            Message::Sync(SyncMessage::Update(
+                                self.hub.documents.diff(&self.doc_id, &empty)?,
+                            ))
+                        }
+                        Err(RecvError::Closed) => return Ok(()),
+                    };
+                    self.send(message).await?;
+                }
+            }
+        }
+    }
+
+    /// Handle the messages of a frame
+    ///
+    /// # Returns
+    /// * `Result<Vec<Message>>` - The replies to the peer
+    fn receive(&self, data: &[u8], clients: &mut HashSet<ClientID>) -> Result<Vec<Message>> {
+        let mut decoder = DecoderV1::from(data);
+        let mut replies = Vec::new();
+        for message in MessageReader::new(&mut decoder) {
+            match message? {
+                Message::Sync(SyncMessage::SyncStep1(state_vector)) => {
+                    let update = self
+                        .hub
+                        .documents
+                        .diff(&self.doc_id, &state_vector.encode_v1())?;
+                    replies.push(Message::Sync(SyncMessage::SyncStep2(update)));
+                }
+                Message::Sync(SyncMessage::SyncStep2(update) | SyncMessage::Update(update)) => {
+                    if update == Update::EMPTY_V1 {
+                        continue;
+                    }
+                    self.hub.documents.apply(&self.doc_id, &update, &self.id)?;
+                    self.hub
+                        .publish_update(&self.doc_id, Some(self.id.clone()), update);
+                }
+                Message::Awareness(update) => {
+                    clients.extend(update.clients.keys().copied());
+                    self.awareness.apply_update(update.clone())?;
+                    let _ = self.hub.events.send(CollabEvent::Awareness {
+                        doc_id: self.doc_id.clone(),
+                        origin: self.id.clone(),
+                        update,
+                    });
+                }
+                Message::AwarenessQuery => {
+                    replies.push(Message::Awareness(self.awareness.update()?));
+                }
+                Message::Auth(Some(reason)) => bail!("Permission denied: {}", reason),
+                Message::Auth(None) => {}
+                Message::Custom(tag, _) => trace!("{} ignored custom message {}", self.id, tag),
+            }
+        }
+        Ok(replies)
+    }
+
+    /// Clear the awareness of the peer for the other connections
+    fn leave(&self, clients: &HashSet<ClientID>) {
+        let own = self.awareness.client_id();
+        let clients: Vec<ClientID> = clients.iter().copied().filter(|id| *id != own).collect();
+        for client in &clients {
+            self.awareness.remove_state(*client);
+        }
+        match self.awareness.update_with_clients(clients) {
+            Ok(update) if !update.clients.is_empty() => {
+                let _ = self.hub.events.send(CollabEvent::Awareness {
+                    doc_id: self.doc_id.clone(),
+                    origin: self.id.clone(),
+                    update,
+                });
+            }
+            Ok(_) => {}
+            Err(e) => warn!("failed to clear awareness of {}: {}", self.id, e),
+        }
+        trace!("{} left {}", self.id, self.doc_id);
+    }
+
+    async fn send(&mut self, message: Message) -> Result<()> {
+        self.socket.send(Frame::binary(message.encode_v1())).await?;
+        Ok(())
+    }
+}
+
+/// Connect to the room of a host
+///
+/// # Arguments
+/// * `url` - The URL shared by the host, `ws://<host>:<port>/<doc id>?token=<token>`
+/// * `doc_id` - The local document that receives the content of the room
+/// * `user` - The name shown to the other users
+pub async fn join(
+    url: &str,
+    doc_id: String,
+    user: &str,
+    hub: CollabHub,
+) -> Result<CollabPeer<MaybeTlsStream<TcpStream>>> {
+    let (socket, _) = tokio_tungstenite::connect_async(url)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to join {}: {}", url, e))?;
+    trace!("joined {} into {}", url, doc_id);
+    Ok(CollabPeer::new(doc_id, hub, local_awareness(user)?, socket))
+}
+
+/// Serves the rooms of the shared documents until it is dropped
+pub struct CollabServer {
+    address: SocketAddr,
+    token: String,
+    doc_ids: Vec<String>,
+    /// Accepts connections, the connections are dropped with it
+    task: JoinHandle<()>,
+}
+
+impl CollabServer {
+    /// Listen for peers of the documents `doc_ids`
+    ///
+    /// # Arguments
+    /// * `address` - The address to listen on, port 0 picks a free port
+    /// * `user` - The name shown to the peers
+    pub async fn start(
+        address: SocketAddr,
+        doc_ids: Vec<String>,
+        user: &str,
+        hub: CollabHub,
+    ) -> Result<Self> {
+        if doc_ids.is_empty() {
+            bail!("No document to share");
+        }
+        let listener = TcpListener::bind(address)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to listen on {}: {}", address, e))?;
+        let address = listener.local_addr()?;
+        let token = Uuid::new_v4().simple().to_string();
+        let mut rooms = HashMap::new();
+        for doc_id in &doc_ids {
+            rooms.insert(doc_id.clone(), local_awareness(user)?);
+        }
+        let room = Room {
+            token: token.clone(),
+            rooms: Arc::new(rooms),
+            hub,
+        };
+        let task = tokio::spawn(async move {
+            let mut connections = JoinSet::new();
+            loop {
+                tokio::select! {
+                    accepted = listener.accept() => match accepted {
+                        Ok((stream, remote)) => {
+                            connections.spawn(room.clone().serve(stream, remote));
+                        }
+                        Err(e) => warn!("failed to accept collab connection: {}", e),
+                    },
+                    Some(_) = connections.join_next(), if !connections.is_empty() => {}
+                }
+            }
+        });
+        trace!("collab server listening on {}", address);
+        Ok(Self {
+            address,
+            token,
+            doc_ids,
+            task,
+        })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.address.port()
+    }
+
+    pub fn doc_ids(&self) -> &[String] {
+        &self.doc_ids
+    }
+
+    /// The URL that a peer joins the room of `doc_id` with
+    ///
+    /// # Arguments
+    /// * `host` - The address of this machine as the peer reaches it
+    pub fn url_of(&self, host: IpAddr, doc_id: &str) -> String {
+        format!(
+            "ws://{}/{}?token={}",
+            SocketAddr::new(host, self.port()),
+            doc_id,
+            self.token
+        )
+    }
+}
+
+impl Drop for CollabServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// The address of this machine on the local network, the loopback address if it has none
+pub fn lan_address() -> IpAddr {
+    // Connecting a UDP socket sends nothing, it only picks the interface of the route
+    UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+        .and_then(|socket| {
+            socket.connect((Ipv4Addr::new(192, 0, 2, 1), 9))?;
+            socket.local_addr()
+        })
+        .map(|address| address.ip())
+        .ok()
+        .filter(|ip| !ip.is_unspecified())
+        .unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST))
+}
+
+/// What the connections of a server share
+#[derive(Clone)]
+struct Room {
+    token: String,
+    rooms: Arc<HashMap<String, Arc<Awareness>>>,
+    hub: CollabHub,
+}
+
+impl Room {
+    /// The document that a handshake asks for, if its token is right
+    ///
+    /// # Returns
+    /// * `Result<String, (StatusCode, &str)>` - The document, or the status and the reason
+    ///   to reject the handshake with
+    fn authorize(&self, request: &Request) -> std::result::Result<String, (StatusCode, &str)> {
+        let token = request.uri().query().and_then(|query| {
+            url::form_urlencoded::parse(query.as_bytes())
+                .find(|(key, _)| key == "token")
+                .map(|(_, value)| value.into_owned())
+        });
+        if token.as_deref() != Some(self.token.as_str()) {
+            return Err((StatusCode::FORBIDDEN, "Invalid token"));
+        }
+        let doc_id = request.uri().path().trim_start_matches('/');
+        if !self.rooms.contains_key(doc_id) {
+            return Err((StatusCode::NOT_FOUND, "Document is not shared"));
+        }
+        Ok(doc_id.to_string())
+    }
+
+    // The handshake callback of tungstenite returns the whole error response
+    #[allow(clippy::result_large_err)]
+    async fn serve(self, stream: TcpStream, remote: SocketAddr) {
+        let mut doc_id = None;
+        let accepted =
+            tokio_tungstenite::accept_hdr_async(stream, |request: &Request, response: Response| {
+                match self.authorize(request) {
+                    Ok(id) => {
+                        doc_id = Some(id);
+                        Ok(response)
+                    }
+                    Err((status, reason)) => {
+                        let mut response = ErrorResponse::new(Some(reason.to_string()));
+                        *response.status_mut() = status;
+                        Err(response)
+                    }
+                }
+            })
+            .await;
+        let (socket, Some(doc_id)) = (accepted, doc_id) else {
+            warn!("rejected collab connection from {}", remote);
+            return;
+        };
+        let socket = match socket {
+            Ok(socket) => socket,
+            Err(e) => {
+                warn!("collab handshake with {} failed: {}", remote, e);
+                return;
+            }
+        };
+        let awareness = self.rooms[&doc_id].clone();
+        let peer = CollabPeer::new(doc_id, self.hub, awareness, socket);
+        trace!("{} joined {} as {}", remote, peer.doc_id, peer.id);
+        if let Err(e) = peer.run().await {
+            warn!("collab connection with {} failed: {}", remote, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, time::Duration};
+
+    use super::*;
+    use crate::document::DocumentRepo;
+
+    /// A document repo of its own for each end, like two machines
+    struct TestDocuments {
+        repo: DocumentRepo,
+    }
+
+    impl SharedDocuments for TestDocuments {
+        fn state_vector(&self, doc_id: &str) -> Result<Vec<u8>> {
+            self.repo.get_doc_state_vector(doc_id)
+        }
+        fn diff(&self, doc_id: &str, state_vector: &[u8]) -> Result<Vec<u8>> {
+            self.repo.get_doc_diff(doc_id, state_vector)
+        }
+        fn apply(&self, doc_id: &str, update: &[u8], _origin: &str) -> Result<()> {
+            self.repo.apply_change(doc_id, update)
+        }
+    }
+
+    fn end(base: &Path, doc_id: &str, content: &str) -> (Arc<TestDocuments>, CollabHub) {
+        let documents = Arc::new(TestDocuments {
+            repo: DocumentRepo::new(),
+        });
+        documents
+            .repo
+            .manage(doc_id.to_string(), base.join(format!("{}.bin", doc_id)))
+            .unwrap();
+        documents
+            .repo
+            .replace_string_of_doc(doc_id, "content", content)
+            .unwrap();
+        let hub = CollabHub::new(documents.clone());
+        (documents, hub)
+    }
+
+    async fn wait_for(documents: &TestDocuments, doc_id: &str, expected: &str) {
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(10);
+        loop {
+            let content = documents.repo.get_string_of_doc(doc_id, "content").unwrap();
+            if content == expected {
+                return;
+            }
+            assert!(
+                tokio::time::Instant::now() < deadline,
+                "{} is {:?}, expected {:?}",
+                doc_id,
+                content,
+                expected
+            );
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+
+    #[test]
+    fn test_sync_on_localhost() {
+        tauri::async_runtime::block_on(async {
+            let base = std::env::temp_dir().join(format!("collab-{}", Uuid::new_v4()));
+            let (host, host_hub) = end(&base, "host", "int main() {}");
+            let (guest, guest_hub) = end(&base, "guest", "");
+            let server = CollabServer::start(
+                (Ipv4Addr::LOCALHOST, 0).into(),
+                vec!["host".to_string()],
+                "alice",
+                host_hub.clone(),
+            )
+            .await
+            .unwrap();
+            let url = server.url_of(Ipv4Addr::LOCALHOST.into(), "host");
+
+            let wrong_token = url.replace("token=", "token=x");
+            assert!(
+                join(&wrong_token, "guest".to_string(), "bob", guest_hub.clone())
+                    .await
+                    .is_err()
+            );
+            let not_shared = url.replace("/host?", "/other?");
+            assert!(
+                join(&not_shared, "guest".to_string(), "bob", guest_hub.clone())
+                    .await
+                    .is_err()
+            );
+
+            let peer = join(&url, "guest".to_string(), "bob", guest_hub.clone())
+                .await
+                .unwrap();
+            let session = tokio::spawn(peer.run());
+            wait_for(&guest, "guest", "int main() {}").await;
+
+            // An edit of a local editor on either end reaches the other end
+            let update = host
+                .repo
+                .replace_string_of_doc("host", "content", "int main() { return 0; }")
+                .unwrap();
+            host_hub.publish_update("host", Some("editor".to_string()), update);
+            wait_for(&guest, "guest", "int main() { return 0; }").await;
+            let update = guest
+                .repo
+                .replace_string_of_doc("guest", "content", "int main() { return 1; }")
+                .unwrap();
+            guest_hub.publish_update("guest", None, update);
+            wait_for(&host, "host", "int main() { return 1; }").await;
+
+            // Stopping the server ends the session of the guest, cleanly or not
+            drop(server);
+            let _ = tokio::time::timeout(Duration::from_secs(10), session)
+                .await
+                .expect("session outlived the server")
+                .unwrap();
+            std::fs::remove_dir_all(&base).ok();
+        });
+    }
+}
@@ -0,0 +1,328 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::{Arc, Mutex},
+};
+
+use log::{trace, warn};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::{AppHandle, Manager, Runtime, State};
+use tauri_specta::Event;
+use tokio::task::AbortHandle;
+
+use crate::{
+    collab::{self, CollabHub, CollabServer, SharedDocuments},
+    commands::{
+        database::{ensure_document_loaded, DocumentUpdateEvent},
+        history::{snapshot_on_interval, SnapshotTimer},
+    },
+    database::DatabaseRepo,
+    document::DocumentRepo,
+};
+
+/// The documents of the workspace as the peers see them
+struct AppDocuments<R: Runtime> {
+    app: AppHandle<R>,
+}
+
+impl<R: Runtime> AppDocuments<R> {
+    fn loaded(&self, doc_id: &str) -> anyhow::Result<State<'_, DocumentRepo>> {
+        let repo = self.app.state::<DocumentRepo>();
+        ensure_document_loaded(&self.app.state::<DatabaseRepo>(), &repo, doc_id)?;
+        Ok(repo)
+    }
+}
+
+impl<R: Runtime> SharedDocuments for AppDocuments<R> {
+    fn state_vector(&self, doc_id: &str) -> anyhow::Result<Vec<u8>> {
+        self.loaded(doc_id)?.get_doc_state_vector(doc_id)
+    }
+
+    fn diff(&self, doc_id: &str, state_vector: &[u8]) -> anyhow::Result<Vec<u8>> {
+        self.loaded(doc_id)?.get_doc_diff(doc_id, state_vector)
+    }
+
+    fn apply(&self, doc_id: &str, update: &[u8], origin: &str) -> anyhow::Result<()> {
+        let repo = self.loaded(doc_id)?;
+        repo.apply_change(doc_id, update)?;
+        let event = DocumentUpdateEvent {
+            doc_id: doc_id.to_string(),
+            origin: Some(origin.to_string()),
+            update: update.to_vec(),
+        };
+        event.emit(&self.app)?;
+        snapshot_on_interval(
+            &self.app.state::<DatabaseRepo>(),
+            &repo,
+            &self.app.state::<SnapshotTimer>(),
+            doc_id,
+        );
+        Ok(())
+    }
+}
+
+struct HostedServer {
+    server: CollabServer,
+    /// The address in the URLs of the peers
+    host: IpAddr,
+    lan: bool,
+}
+
+struct CollabSession {
+    doc_id: String,
+    url: String,
+    task: AbortHandle,
+}
+
+/// The server sharing documents of this workspace and the sessions joined from peers
+pub struct CollabState {
+    hub: CollabHub,
+    server: Mutex<Option<HostedServer>>,
+    sessions: Mutex<HashMap<String, CollabSession>>,
+}
+
+impl CollabState {
+    pub fn new<R: Runtime>(app: AppHandle<R>) -> Self {
+        Self {
+            hub: CollabHub::new(Arc::new(AppDocuments { app })),
+            server: Mutex::new(None),
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Forward an update of a local editor to the peers of the document
+    pub fn publish(&self, event: &DocumentUpdateEvent) {
+        self.hub
+            .publish_update(&event.doc_id, event.origin.clone(), event.update.clone());
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Type, Clone)]
+pub struct CollabServerInfo {
+    pub port: u16,
+    /// Whether peers on the local network can join, otherwise only this machine can
+    pub lan: bool,
+    /// The URL to join each shared document with, by document id
+    pub urls: HashMap<String, String>,
+}
+
+impl From<&HostedServer> for CollabServerInfo {
+    fn from(hosted: &HostedServer) -> Self {
+        Self {
+            port: hosted.server.port(),
+            lan: hosted.lan,
+            urls: hosted
+                .server
+                .doc_ids()
+                .iter()
+                .map(|doc_id| (doc_id.clone(), hosted.server.url_of(hosted.host, doc_id)))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Type, Clone)]
+pub struct CollabSessionInfo {
+    pub session_id: String,
+    /// The local document that the session syncs
+    pub doc_id: String,
+    pub url: String,
+}
+
+/// A joined session ended because the connection closed
+#[derive(Debug, Serialize, Deserialize, Event, Clone, Type)]
+pub struct CollabSessionEndEvent {
+    pub session_id: String,
+    pub doc_id: String,
+    pub error: Option<String>,
+}
+
+/// Hold documents for the peers, like an editor holds them
+fn manage_documents(
+    db: &DatabaseRepo,
+    repo: &DocumentRepo,
+    doc_ids: &[String],
+) -> anyhow::Result<()> {
+    for (index, doc_id) in doc_ids.iter().enumerate() {
+        let managed = db
+            .get_document_filepath(doc_id)
+            .and_then(|filepath| repo.manage(doc_id.clone(), filepath));
+        if let Err(e) = managed {
+            for doc_id in &doc_ids[..index] {
+                repo.release(doc_id);
+            }
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+/// Share documents with peers over WebSocket, nothing is shared until this is called
+///
+/// # Arguments
+/// * `doc_ids` - The documents to share, each one is a room of its own
+/// * `lan` - Listen on every interface so peers on the local network can join, otherwise
+///   only on the loopback interface
+/// * `port` - The port to listen on, `None` picks a free one
+#[tauri::command]
+#[specta::specta]
+pub async fn start_collab_server(
+    doc_ids: Vec<String>,
+    lan: bool,
+    port: Option<u16>,
+    db: State<'_, DatabaseRepo>,
+    repo: State<'_, DocumentRepo>,
+    state: State<'_, CollabState>,
+) -> Result<CollabServerInfo, String> {
+    if state.server.lock().unwrap().is_some() {
+        return Err("A collab server is running already, stop it first".to_string());
+    }
+    let (address, host) = if lan {
+        (Ipv4Addr::UNSPECIFIED.into(), collab::lan_address())
+    } else {
+        (Ipv4Addr::LOCALHOST.into(), Ipv4Addr::LOCALHOST.into())
+    };
+    manage_documents(&db, &repo, &doc_ids).map_err(|e| e.to_string())?;
+    let started = CollabServer::start(
+        SocketAddr::new(address, port.unwrap_or(0)),
+        doc_ids.clone(),
+        &whoami::username(),
+        state.hub.clone(),
+    )
+    .await;
+    let release = || doc_ids.iter().for_each(|doc_id| repo.release(doc_id));
+    let server = started.map_err(|e| {
+        release();
+        e.to_string()
+    })?;
+
+    let mut guard = state.server.lock().unwrap();
+    if guard.is_some() {
+        release();
+        return Err("A collab server is running already, stop it first".to_string());
+    }
+    let hosted = guard.insert(HostedServer { server, host, lan });
+    trace!("sharing {:?} on port {}", doc_ids, hosted.server.port());
+    Ok(CollabServerInfo::from(&*hosted))
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn get_collab_server(
+    state: State<'_, CollabState>,
+) -> Result<Option<CollabServerInfo>, String> {
+    Ok(state.server.lock().unwrap().as_ref().map(Into::into))
+}
+
+/// Stop sharing, the peers are disconnected
+#[tauri::command]
+#[specta::specta]
+pub async fn stop_collab_server(
+    repo: State<'_, DocumentRepo>,
+    state: State<'_, CollabState>,
+) -> Result<(), String> {
+    let hosted = state.server.lock().unwrap().take();
+    if let Some(hosted) = hosted {
+        for doc_id in hosted.server.doc_ids() {
+            repo.release(doc_id);
+        }
+        trace!("stopped sharing on port {}", hosted.server.port());
+    }
+    Ok(())
+}
+
+/// Join the session of a peer. The content of the peer is merged into the local document,
+/// so join into a new solution to get the same text as the peer.
+///
+/// # Arguments
+/// * `url` - The URL shared by the peer
+/// * `doc_id` - The local document to sync with the peer
+///
+/// # Returns
+/// * `Result<String, String>` - The id of the session, a `CollabSessionEndEvent` with it is
+///   emitted once the connection closes
+#[tauri::command]
+#[specta::specta]
+pub async fn join_collab_session<R: Runtime>(
+    app: AppHandle<R>,
+    url: String,
+    doc_id: String,
+    db: State<'_, DatabaseRepo>,
+    repo: State<'_, DocumentRepo>,
+    state: State<'_, CollabState>,
+) -> Result<String, String> {
+    manage_documents(&db, &repo, std::slice::from_ref(&doc_id)).map_err(|e| e.to_string())?;
+    let peer = collab::join(&url, doc_id.clone(), &whoami::username(), state.hub.clone())
+        .await
+        .map_err(|e| {
+            repo.release(&doc_id);
+            e.to_string()
+        })?;
+
+    let session_id = uuid::Uuid::new_v4().to_string();
+    // Hold the lock until the session is inserted, the task removes it when it ends
+    let mut sessions = state.sessions.lock().unwrap();
+    let task = tokio::spawn({
+        let session_id = session_id.clone();
+        async move {
+            let error = peer.run().await.err().map(|e| e.to_string());
+            let state = app.state::<CollabState>();
+            let session = state.sessions.lock().unwrap().remove(&session_id);
+            let Some(session) = session else {
+                return;
+            };
+            app.state::<DocumentRepo>().release(&session.doc_id);
+            trace!("collab session {} ended: {:?}", session_id, error);
+            let event = CollabSessionEndEvent {
+                session_id,
+                doc_id: session.doc_id,
+                error,
+            };
+            if let Err(e) = event.emit(&app) {
+                warn!("failed to emit end of collab session: {}", e);
+            }
+        }
+    });
+    sessions.insert(
+        session_id.clone(),
+        CollabSession {
+            doc_id,
+            url,
+            task: task.abort_handle(),
+        },
+    );
+    Ok(session_id)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn get_collab_sessions(
+    state: State<'_, CollabState>,
+) -> Result<Vec<CollabSessionInfo>, String> {
+    let sessions = state.sessions.lock().unwrap();
+    Ok(sessions
+        .iter()
+        .map(|(session_id, session)| CollabSessionInfo {
+            session_id: session_id.clone(),
+            doc_id: session.doc_id.clone(),
+            url: session.url.clone(),
+        })
+        .collect())
+}
+
+/// Leave a joined session, the local document keeps its content
+#[tauri::command]
+#[specta::specta]
+pub async fn leave_collab_session(
+    session_id: String,
+    repo: State<'_, DocumentRepo>,
+    state: State<'_, CollabState>,
+) -> Result<(), String> {
+    let session = state.sessions.lock().unwrap().remove(&session_id);
+    if let Some(session) = session {
+        session.task.abort();
+        repo.release(&session.doc_id);
+    }
+    Ok(())
+}
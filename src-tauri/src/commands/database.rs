@@ -1,7 +1,12 @@
 use std::{collections::HashMap, path::PathBuf};
 
 use crate::{
+    commands::{
+        collab::CollabState,
+        history::{snapshot_on_interval, SnapshotTimer},
+    },
     database::{
+        cleanup::GarbageReport,
         config::{AdvLanguageItem, WorkspaceConfig},
         CreateCheckerParams, CreateCheckerResult, CreateProblemParams, CreateProblemResult,
         CreateSolutionParams, CreateSolutionResult, DatabaseRepo, GetProblemsParams,
@@ -11,7 +16,7 @@ use crate::{
     model::{Problem, ProblemChangeset, Solution, SolutionChangeset, TestCase},
     runner::BUNDLED_CHECKER_NAME,
 };
-use log::trace;
+use log::{trace, warn};
 use serde::{Deserialize, Serialize};
 use specta::Type;
 use tauri::{path::BaseDirectory, Manager, Runtime, State};
@@ -59,8 +64,16 @@ pub async fn get_solution(
 
 #[tauri::command]
 #[specta::specta]
-pub async fn delete_problem(problem_id: String, db: State<'_, DatabaseRepo>) -> Result<(), String> {
-    db.delete_problem(&problem_id).map_err(|e| e.to_string())
+pub async fn delete_problem(
+    problem_id: String,
+    db: State<'_, DatabaseRepo>,
+    repo: State<'_, DocumentRepo>,
+) -> Result<(), String> {
+    let document_ids = db.delete_problem(&problem_id).map_err(|e| e.to_string())?;
+    for document_id in &document_ids {
+        repo.remove(document_id);
+    }
+    Ok(())
 }
 
 #[tauri::command]
@@ -90,8 +103,13 @@ pub async fn update_solution(
 pub async fn delete_solution(
     solution_id: String,
     db: State<'_, DatabaseRepo>,
+    repo: State<'_, DocumentRepo>,
 ) -> Result<String, String> {
-    db.delete_solution(&solution_id).map_err(|e| e.to_string())
+    let (problem_id, document_id) = db
+        .delete_solution(&solution_id)
+        .map_err(|e| e.to_string())?;
+    repo.remove(&document_id);
+    Ok(problem_id)
 }
 
 #[tauri::command]
@@ -112,6 +130,16 @@ pub async fn get_problem(
     db.get_problem(&problem_id).map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+#[specta::specta]
+pub async fn get_problem_dir(
+    problem_id: String,
+    db: State<'_, DatabaseRepo>,
+) -> Result<PathBuf, String> {
+    db.get_problem_folder(&problem_id)
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 #[specta::specta]
 pub async fn create_testcase(
@@ -129,9 +157,31 @@ pub async fn create_testcase(
 pub async fn delete_testcase(
     testcase_id: String,
     db: State<'_, DatabaseRepo>,
+    repo: State<'_, DocumentRepo>,
 ) -> Result<(), String> {
     trace!("delete testcase {:?}", testcase_id);
-    db.delete_testcase(&testcase_id).map_err(|e| e.to_string())
+    let document_ids = db
+        .delete_testcase(&testcase_id)
+        .map_err(|e| e.to_string())?;
+    for document_id in &document_ids {
+        repo.remove(document_id);
+    }
+    Ok(())
+}
+
+/// Delete the documents that no solution, testcase or checker owns, and the files of the
+/// doc folder that belong to no document
+#[tauri::command]
+#[specta::specta]
+pub async fn collect_garbage(
+    db: State<'_, DatabaseRepo>,
+    repo: State<'_, DocumentRepo>,
+) -> Result<GarbageReport, String> {
+    let report = db.collect_garbage().map_err(|e| e.to_string())?;
+    for document_id in &report.document_ids {
+        repo.remove(document_id);
+    }
+    Ok(report)
 }
 
 #[tauri::command]
@@ -165,6 +215,30 @@ pub async fn load_document(
     Ok(snapshot)
 }
 
+/// Release a document loaded by `load_document`, once no editor holds it the document may
+/// be dropped from memory
+#[tauri::command]
+#[specta::specta]
+pub async fn unload_document(repo: State<'_, DocumentRepo>, doc_id: String) -> Result<(), String> {
+    trace!("unload document {}", &doc_id);
+    repo.release(&doc_id);
+    Ok(())
+}
+
+/// Load the document into the repo if it is not loaded yet
+pub fn ensure_document_loaded(
+    db: &DatabaseRepo,
+    repo: &DocumentRepo,
+    doc_id: &str,
+) -> anyhow::Result<()> {
+    if !repo.has(doc_id) {
+        trace!("document {} not found, loading it from database...", doc_id);
+        let filepath = db.get_document_filepath(doc_id)?;
+        repo.load(doc_id.to_string(), filepath)?;
+    }
+    Ok(())
+}
+
 #[tauri::command]
 #[specta::specta]
 pub async fn get_string_of_doc(
@@ -173,10 +247,7 @@ pub async fn get_string_of_doc(
     db: State<'_, DatabaseRepo>,
     repo: State<'_, DocumentRepo>,
 ) -> Result<String, String> {
-    if !repo.has(&doc_id) {
-        trace!("document {} not found, loading it from database...", doc_id);
-        load_document(db, repo.clone(), doc_id.clone()).await?;
-    }
+    ensure_document_loaded(&db, &repo, &doc_id).map_err(|e| e.to_string())?;
     let s = repo
         .get_string_of_doc(&doc_id, &name)
         .map_err(|e| e.to_string())?;
@@ -184,14 +255,65 @@ pub async fn get_string_of_doc(
     Ok(s)
 }
 
+/// An update applied to a document, so every editor of the document stays in sync
+#[derive(Debug, Serialize, Deserialize, Event, Clone, Type)]
+pub struct DocumentUpdateEvent {
+    pub doc_id: String,
+    /// The editor that made the update, it has the update already
+    pub origin: Option<String>,
+    pub update: Vec<u8>,
+}
+
+/// Apply an update of an editor and broadcast it to the other editors and to the peers the
+/// document is shared with
+///
+/// # Arguments
+/// * `origin` - An id of the editor, echoed in the `DocumentUpdateEvent` of the update
 #[tauri::command]
 #[specta::specta]
-pub async fn apply_change(
+pub async fn apply_change<R: Runtime>(
+    app: tauri::AppHandle<R>,
     doc_id: String,
     change: Vec<u8>,
+    origin: Option<String>,
+    db: State<'_, DatabaseRepo>,
     repo: State<'_, DocumentRepo>,
+    timer: State<'_, SnapshotTimer>,
 ) -> Result<(), String> {
-    repo.apply_change(&doc_id, change)
+    repo.apply_change(&doc_id, &change)
+        .map_err(|e| e.to_string())?;
+    let event = DocumentUpdateEvent {
+        doc_id: doc_id.clone(),
+        origin,
+        update: change,
+    };
+    // The update is applied and saved, an editor that misses the event catches up with
+    // `sync_document`
+    if let Err(e) = event.emit(&app) {
+        warn!("failed to emit update of document {}: {}", &doc_id, e);
+    }
+    app.state::<CollabState>().publish(&event);
+    snapshot_on_interval(&db, &repo, &timer, &doc_id);
+    Ok(())
+}
+
+/// Get the updates of a document that an editor is missing, e.g. after it missed events
+///
+/// # Arguments
+/// * `state_vector` - The state vector of the editor, encoded as v1
+///
+/// # Returns
+/// * `Result<Vec<u8>, String>` - The missing updates, encoded as one v1 update
+#[tauri::command]
+#[specta::specta]
+pub async fn sync_document(
+    doc_id: String,
+    state_vector: Vec<u8>,
+    db: State<'_, DatabaseRepo>,
+    repo: State<'_, DocumentRepo>,
+) -> Result<Vec<u8>, String> {
+    ensure_document_loaded(&db, &repo, &doc_id).map_err(|e| e.to_string())?;
+    repo.get_doc_diff(&doc_id, &state_vector)
         .map_err(|e| e.to_string())
 }
 
@@ -214,13 +336,24 @@ pub async fn set_workspace_config<R: Runtime>(
     db: State<'_, DatabaseRepo>,
     data: WorkspaceConfig,
 ) -> Result<(), String> {
+    data.env
+        .validate()
+        .map_err(|e| format!("Workspace env: {}", e))?;
+    for (name, language) in &data.language {
+        language
+            .validate()
+            .map_err(|e| format!("Language \"{}\": {}", name, e))?;
+    }
     {
         let mut guard = db.config.write().map_err(|e| e.to_string())?;
         *guard = data.clone();
     }
     db.save_config("config.toml").map_err(|e| e.to_string())?;
     let event = WorkspaceConfigUpdateEvent { new: data };
-    event.emit(&app).map_err(|e| e.to_string())?;
+    // The config is saved already
+    if let Err(e) = event.emit(&app) {
+        warn!("failed to emit workspace config update: {}", e);
+    }
     Ok(())
 }
 
@@ -250,7 +383,7 @@ pub async fn resolve_checker(app: tauri::AppHandle, name: String) -> Result<Path
                 ),
                 BaseDirectory::Resource,
             )
-            .map_err(|e| format!("Failed to resolve checker {}, this may caused by incomplete resource, please check testlib folder or try to reinstall the app: {}", name, e.to_string()))?;
+            .map_err(|e| format!("Failed to resolve checker {}, this may caused by incomplete resource, please check testlib folder or try to reinstall the app: {}", name, e))?;
         trace!("resolved checker {} to {:?}", &name, &path);
         Ok(path)
     } else {
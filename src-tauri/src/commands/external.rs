@@ -0,0 +1,432 @@
+/// Keeps linked documents in sync with their files. A sync runs shortly after a linked file
+/// changes, as reported by a watcher on its folder, or after its document was updated.
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsString,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+
+use log::{trace, warn};
+use notify::{
+    event::{AccessKind, AccessMode},
+    EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::{AppHandle, Manager, Runtime, State};
+use tauri_specta::Event;
+use tokio::sync::Notify;
+
+use crate::{
+    commands::{
+        collab::CollabState,
+        database::{ensure_document_loaded, DocumentUpdateEvent},
+    },
+    database::DatabaseRepo,
+    document::DocumentRepo,
+    external::{LinkedFile, SyncOutcome, SyncSide},
+};
+
+/// Changes this close together are synced at once, e.g. fast typing or the events of a save
+const SYNC_DELAY: Duration = Duration::from_millis(200);
+
+/// What changed since the last sync
+#[derive(Default)]
+struct Pending {
+    documents: HashSet<String>,
+    /// Names of the changed files, the watcher may report a path in another form than the
+    /// link has, e.g. through a symlink
+    file_names: HashSet<OsString>,
+}
+
+impl Pending {
+    /// Take the ids of the links that may have changed
+    fn take(&mut self, links: &HashMap<String, LinkedFile>) -> Vec<String> {
+        let ids = links
+            .values()
+            .filter(|link| {
+                self.documents.contains(&link.document_id)
+                    || link
+                        .path
+                        .file_name()
+                        .is_some_and(|name| self.file_names.contains(name))
+            })
+            .map(|link| link.document_id.clone())
+            .collect();
+        self.documents.clear();
+        self.file_names.clear();
+        ids
+    }
+}
+
+/// The documents linked to files, synced by `watch_external_files`
+pub struct ExternalFiles {
+    links: Mutex<HashMap<String, LinkedFile>>,
+    pending: Mutex<Pending>,
+    changed: Notify,
+    /// Watches the folders of the linked files, `None` if the platform has no watcher
+    watcher: Mutex<Option<RecommendedWatcher>>,
+    /// Folders that `watcher` watches
+    watched: Mutex<HashSet<PathBuf>>,
+}
+
+impl ExternalFiles {
+    fn document_changed(&self, document_id: &str) {
+        self.pending
+            .lock()
+            .unwrap()
+            .documents
+            .insert(document_id.to_string());
+        self.changed.notify_one();
+    }
+
+    fn file_changed(&self, path: &Path) {
+        if let Some(name) = path.file_name() {
+            self.pending
+                .lock()
+                .unwrap()
+                .file_names
+                .insert(name.to_owned());
+            self.changed.notify_one();
+        }
+    }
+
+    /// Watch the folder of a linked file. A folder that doesn't exist yet is watched after
+    /// a sync created it.
+    fn watch_folder(&self, path: &Path) {
+        let Some(folder) = path.parent() else {
+            return;
+        };
+        let mut watched = self.watched.lock().unwrap();
+        if watched.contains(folder) {
+            return;
+        }
+        let mut watcher = self.watcher.lock().unwrap();
+        let Some(watcher) = watcher.as_mut() else {
+            return;
+        };
+        match watcher.watch(folder, RecursiveMode::NonRecursive) {
+            Ok(()) => {
+                trace!("watching {:?}", folder);
+                watched.insert(folder.to_path_buf());
+            }
+            Err(e) => trace!("failed to watch {:?}: {}", folder, e),
+        }
+    }
+
+    /// Stop watching the folder of a file that was unlinked, unless another link is in it
+    fn unwatch_folder(&self, path: &Path, links: &HashMap<String, LinkedFile>) {
+        let Some(folder) = path.parent() else {
+            return;
+        };
+        if links
+            .values()
+            .any(|link| link.path.parent() == Some(folder))
+        {
+            return;
+        }
+        if !self.watched.lock().unwrap().remove(folder) {
+            return;
+        }
+        if let Some(watcher) = self.watcher.lock().unwrap().as_mut() {
+            if let Err(e) = watcher.unwatch(folder) {
+                trace!("failed to unwatch {:?}: {}", folder, e);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Type, Clone)]
+pub struct ExternalFileLink {
+    pub document_id: String,
+    pub path: PathBuf,
+    /// Nothing is synced until a side is kept, after both sides changed or an error
+    pub is_paused: bool,
+}
+
+impl From<&LinkedFile> for ExternalFileLink {
+    fn from(link: &LinkedFile) -> Self {
+        Self {
+            document_id: link.document_id.clone(),
+            path: link.path.clone(),
+            is_paused: link.is_paused,
+        }
+    }
+}
+
+/// Syncing a document with its file paused
+#[derive(Debug, Serialize, Deserialize, Event, Clone, Type)]
+pub struct ExternalFileConflictEvent {
+    pub document_id: String,
+    pub path: PathBuf,
+    /// Why syncing failed, `None` if both sides changed
+    pub error: Option<String>,
+}
+
+/// Sync a link once, then store the new sync point and pass a read file on to the editors
+fn sync_link<R: Runtime>(
+    app: &AppHandle<R>,
+    link: &mut LinkedFile,
+    keep: Option<SyncSide>,
+) -> anyhow::Result<()> {
+    let db = app.state::<DatabaseRepo>();
+    let repo = app.state::<DocumentRepo>();
+    ensure_document_loaded(&db, &repo, &link.document_id)?;
+    let synced_hash = link.synced_hash.clone();
+    let outcome = link.sync(&repo, keep);
+    if link.synced_hash != synced_hash {
+        if let Some(hash) = &link.synced_hash {
+            db.set_external_file_synced(&link.document_id, hash)?;
+        }
+    }
+    match outcome? {
+        SyncOutcome::Read { update } => {
+            let event = DocumentUpdateEvent {
+                doc_id: link.document_id.clone(),
+                origin: None,
+                update,
+            };
+            event.emit(app)?;
+            app.state::<CollabState>().publish(&event);
+        }
+        SyncOutcome::Conflict => {
+            let event = ExternalFileConflictEvent {
+                document_id: link.document_id.clone(),
+                path: link.path.clone(),
+                error: None,
+            };
+            event.emit(app)?;
+        }
+        SyncOutcome::Unchanged | SyncOutcome::Written => {}
+    }
+    Ok(())
+}
+
+/// Sync the links that changed since the last sync, a link that fails is paused so it
+/// fails only once
+fn sync_pending<R: Runtime>(app: &AppHandle<R>) {
+    let state = app.state::<ExternalFiles>();
+    let mut links = state.links.lock().unwrap();
+    let ids = state.pending.lock().unwrap().take(&links);
+    let mut unlinked = Vec::new();
+    for id in ids {
+        let Some(link) = links.get_mut(&id).filter(|link| !link.is_paused) else {
+            continue;
+        };
+        let result = sync_link(app, link, None);
+        state.watch_folder(&link.path);
+        let Err(e) = result else {
+            continue;
+        };
+        // The link is gone with its document
+        if let Ok(None) = app
+            .state::<DatabaseRepo>()
+            .get_external_file(&link.document_id)
+        {
+            unlinked.push(link.document_id.clone());
+            continue;
+        }
+        warn!("failed to sync {:?}: {}", link.path, e);
+        link.is_paused = true;
+        let event = ExternalFileConflictEvent {
+            document_id: link.document_id.clone(),
+            path: link.path.clone(),
+            error: Some(e.to_string()),
+        };
+        if let Err(e) = event.emit(app) {
+            warn!("failed to emit sync error: {}", e);
+        }
+    }
+    for document_id in unlinked {
+        trace!("drop link of deleted document {}", document_id);
+        if let Some(link) = links.remove(&document_id) {
+            state.unwatch_folder(&link.path, &links);
+        }
+    }
+}
+
+/// Load the links of the workspace and keep them in sync while the app runs
+pub fn watch_external_files<R: Runtime>(app: AppHandle<R>) -> anyhow::Result<()> {
+    let links: HashMap<String, LinkedFile> = app
+        .state::<DatabaseRepo>()
+        .get_external_files()?
+        .into_iter()
+        .map(|link| (link.document_id.clone(), LinkedFile::from(link)))
+        .collect();
+    let handler_app = app.clone();
+    let watcher = notify::recommended_watcher(move |result: notify::Result<notify::Event>| {
+        let event = match result {
+            Ok(event) => event,
+            Err(e) => {
+                warn!("failed to watch linked files: {}", e);
+                return;
+            }
+        };
+        // Reading a file, the syncs included, changes nothing
+        if matches!(event.kind, EventKind::Access(kind) if kind != AccessKind::Close(AccessMode::Write))
+        {
+            return;
+        }
+        let state = handler_app.state::<ExternalFiles>();
+        for path in &event.paths {
+            state.file_changed(path);
+        }
+    })
+    .inspect_err(|e| warn!("linked files are only synced on edits, no watcher: {}", e))
+    .ok();
+    // Every link is synced once at startup, the files may have changed while the app was
+    // closed
+    let pending = Pending {
+        documents: links.keys().cloned().collect(),
+        file_names: HashSet::new(),
+    };
+    app.manage(ExternalFiles {
+        links: Mutex::new(links),
+        pending: Mutex::new(pending),
+        changed: Notify::new(),
+        watcher: Mutex::new(watcher),
+        watched: Mutex::new(HashSet::new()),
+    });
+    app.state::<ExternalFiles>().changed.notify_one();
+
+    let listener_app = app.clone();
+    DocumentUpdateEvent::listen_any(&app, move |event| {
+        listener_app
+            .state::<ExternalFiles>()
+            .document_changed(&event.payload.doc_id);
+    });
+    tauri::async_runtime::spawn(async move {
+        loop {
+            app.state::<ExternalFiles>().changed.notified().await;
+            tokio::time::sleep(SYNC_DELAY).await;
+            let app = app.clone();
+            // Files are read and written, keep them off the async workers
+            if let Err(e) = tauri::async_runtime::spawn_blocking(move || sync_pending(&app)).await {
+                warn!("failed to sync linked files: {}", e);
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Link a document to a file on disk, edits on either side are applied to the other
+///
+/// # Arguments
+/// * `path` - An absolute path, the file is created if it doesn't exist
+/// * `keep` - The side whose text is kept if the file has a different text already
+#[tauri::command]
+#[specta::specta]
+pub async fn link_external_file<R: Runtime>(
+    app: AppHandle<R>,
+    document_id: String,
+    path: PathBuf,
+    keep: SyncSide,
+    db: State<'_, DatabaseRepo>,
+    state: State<'_, ExternalFiles>,
+) -> Result<ExternalFileLink, String> {
+    if !path.is_absolute() {
+        return Err(format!(
+            "{} is not an absolute path",
+            path.to_string_lossy()
+        ));
+    }
+    let link = db
+        .link_external_file(&document_id, &path.to_string_lossy())
+        .map_err(|e| e.to_string())?;
+    let sync_app = app.clone();
+    let link = tauri::async_runtime::spawn_blocking(move || {
+        let mut link = LinkedFile::from(link);
+        sync_link(&sync_app, &mut link, Some(keep)).map(|()| link)
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+    let link = match link {
+        Ok(link) => link,
+        Err(e) => {
+            if let Err(e) = db.unlink_external_file(&document_id) {
+                warn!("failed to unlink {}: {}", document_id, e);
+            }
+            return Err(e.to_string());
+        }
+    };
+    let info = ExternalFileLink::from(&link);
+    state.watch_folder(&link.path);
+    state.links.lock().unwrap().insert(document_id, link);
+    Ok(info)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn unlink_external_file(
+    document_id: String,
+    db: State<'_, DatabaseRepo>,
+    state: State<'_, ExternalFiles>,
+) -> Result<(), String> {
+    db.unlink_external_file(&document_id)
+        .map_err(|e| e.to_string())?;
+    let mut links = state.links.lock().unwrap();
+    if let Some(link) = links.remove(&document_id) {
+        state.unwatch_folder(&link.path, &links);
+    }
+    Ok(())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn get_external_file(
+    document_id: String,
+    state: State<'_, ExternalFiles>,
+) -> Result<Option<ExternalFileLink>, String> {
+    let links = state.links.lock().unwrap();
+    Ok(links.get(&document_id).map(Into::into))
+}
+
+/// Resume a paused link by keeping the text of one side
+#[tauri::command]
+#[specta::specta]
+pub async fn resolve_external_file_conflict<R: Runtime>(
+    app: AppHandle<R>,
+    document_id: String,
+    keep: SyncSide,
+) -> Result<ExternalFileLink, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let state = app.state::<ExternalFiles>();
+        let mut links = state.links.lock().unwrap();
+        let link = links
+            .get_mut(&document_id)
+            .ok_or_else(|| format!("Document {} is not linked to a file", document_id))?;
+        sync_link(&app, link, Some(keep)).map_err(|e| e.to_string())?;
+        Ok(ExternalFileLink::from(&*link))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::ExternalFile;
+
+    #[test]
+    fn test_take_pending() {
+        let links: HashMap<String, LinkedFile> = [("a", "/src/a.cpp"), ("b", "/src/b.cpp")]
+            .into_iter()
+            .map(|(id, path)| {
+                let link = LinkedFile::from(ExternalFile {
+                    document_id: id.to_string(),
+                    path: path.to_string(),
+                    synced_hash: None,
+                    create_datetime: chrono::Local::now().naive_local(),
+                });
+                (id.to_string(), link)
+            })
+            .collect();
+        let mut pending = Pending::default();
+        pending.file_names.insert(OsString::from("b.cpp"));
+        pending.documents.insert("unlinked".to_string());
+        assert_eq!(pending.take(&links), vec!["b".to_string()]);
+        assert!(pending.take(&links).is_empty());
+    }
+}
@@ -0,0 +1,271 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use log::{trace, warn};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::{Manager, Runtime, State};
+use tauri_specta::Event;
+
+use crate::{
+    commands::{
+        collab::CollabState,
+        database::{ensure_document_loaded, DocumentUpdateEvent},
+    },
+    database::DatabaseRepo,
+    document::{diff_sequences, string_of_state, DiffOp, DocumentRepo, CONTENT_TEXT},
+    model::{DocumentSnapshot, SnapshotKind},
+};
+
+/// Solutions being edited get a snapshot at most this often
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// When each document got its last interval snapshot in this session
+#[derive(Default)]
+pub struct SnapshotTimer {
+    last: Mutex<HashMap<String, Instant>>,
+}
+
+/// Take a snapshot of a loaded document
+///
+/// # Returns
+/// * `Result<Option<DocumentSnapshot>>` - The snapshot, `None` if an automatic snapshot
+///   was skipped because the text didn't change since the latest one
+pub fn take_snapshot(
+    db: &DatabaseRepo,
+    repo: &DocumentRepo,
+    doc_id: &str,
+    kind: SnapshotKind,
+    label: Option<String>,
+) -> anyhow::Result<Option<DocumentSnapshot>> {
+    ensure_document_loaded(db, repo, doc_id)?;
+    if kind != SnapshotKind::Manual {
+        let current = repo.get_string_of_doc(doc_id, CONTENT_TEXT)?;
+        if let Some((_, data)) = db.get_latest_document_snapshot(doc_id)? {
+            if string_of_state(&data, CONTENT_TEXT)? == current {
+                trace!("skip {:?} snapshot of unchanged document {}", kind, doc_id);
+                return Ok(None);
+            }
+        }
+    }
+    let data = repo.get_doc_data(doc_id)?;
+    let snapshot = db.create_document_snapshot(doc_id, kind, label, &data)?;
+    trace!("took {:?} snapshot {} of {}", kind, snapshot.id, doc_id);
+    Ok(Some(snapshot))
+}
+
+/// Take an interval snapshot of a solution document when its interval is over. Called on
+/// each change, so the first edit of a session takes one too.
+pub fn snapshot_on_interval(
+    db: &DatabaseRepo,
+    repo: &DocumentRepo,
+    timer: &SnapshotTimer,
+    doc_id: &str,
+) {
+    {
+        let mut last = timer.last.lock().unwrap();
+        let now = Instant::now();
+        match last.get(doc_id) {
+            Some(at) if now.duration_since(*at) < SNAPSHOT_INTERVAL => return,
+            _ => last.insert(doc_id.to_string(), now),
+        };
+    }
+    // Only solutions have a history, testcases change too often and are too large
+    match db.get_problem_id_of_document(doc_id) {
+        Ok(Some(_)) => {}
+        Ok(None) => return,
+        Err(e) => {
+            warn!("failed to look up document {}: {}", doc_id, e);
+            return;
+        }
+    }
+    if let Err(e) = take_snapshot(db, repo, doc_id, SnapshotKind::Interval, None) {
+        warn!("failed to take interval snapshot of {}: {}", doc_id, e);
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Type, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotDiffKind {
+    Equal,
+    Insert,
+    Delete,
+}
+
+#[derive(Debug, Serialize, Deserialize, Type, Clone, PartialEq, Eq)]
+pub struct SnapshotDiffLine {
+    pub kind: SnapshotDiffKind,
+    /// Line number in the old text, starts from 1, `None` for inserted lines
+    pub old_line: Option<u32>,
+    /// Line number in the new text, starts from 1, `None` for deleted lines
+    pub new_line: Option<u32>,
+    pub text: String,
+}
+
+/// Line diff of two texts by longest common subsequence
+fn diff_lines(old: &str, new: &str) -> Vec<SnapshotDiffLine> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let (mut i, mut j) = (0, 0);
+    diff_sequences(&old, &new)
+        .into_iter()
+        .map(|op| {
+            let (kind, text) = match op {
+                DiffOp::Equal => (SnapshotDiffKind::Equal, old[i]),
+                DiffOp::Delete => (SnapshotDiffKind::Delete, old[i]),
+                DiffOp::Insert => (SnapshotDiffKind::Insert, new[j]),
+            };
+            let old_line = (op != DiffOp::Insert).then(|| {
+                i += 1;
+                i as u32
+            });
+            let new_line = (op != DiffOp::Delete).then(|| {
+                j += 1;
+                j as u32
+            });
+            SnapshotDiffLine {
+                kind,
+                old_line,
+                new_line,
+                text: text.to_string(),
+            }
+        })
+        .collect()
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn create_document_snapshot(
+    doc_id: String,
+    kind: SnapshotKind,
+    label: Option<String>,
+    db: State<'_, DatabaseRepo>,
+    repo: State<'_, DocumentRepo>,
+) -> Result<Option<DocumentSnapshot>, String> {
+    take_snapshot(&db, &repo, &doc_id, kind, label).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn list_document_snapshots(
+    doc_id: String,
+    db: State<'_, DatabaseRepo>,
+) -> Result<Vec<DocumentSnapshot>, String> {
+    db.get_document_snapshots(&doc_id)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn get_snapshot_text(
+    snapshot_id: String,
+    db: State<'_, DatabaseRepo>,
+) -> Result<String, String> {
+    let (_, data) = db
+        .get_document_snapshot(&snapshot_id)
+        .map_err(|e| e.to_string())?;
+    string_of_state(&data, CONTENT_TEXT).map_err(|e| e.to_string())
+}
+
+/// Diff the text of two snapshots of a document
+///
+/// # Arguments
+/// * `old_snapshot_id` - The snapshot to diff from
+/// * `new_snapshot_id` - The snapshot to diff to, `None` for the current text of the document
+#[tauri::command]
+#[specta::specta]
+pub async fn diff_document_snapshots(
+    old_snapshot_id: String,
+    new_snapshot_id: Option<String>,
+    db: State<'_, DatabaseRepo>,
+    repo: State<'_, DocumentRepo>,
+) -> Result<Vec<SnapshotDiffLine>, String> {
+    let (old, old_data) = db
+        .get_document_snapshot(&old_snapshot_id)
+        .map_err(|e| e.to_string())?;
+    let old_text = string_of_state(&old_data, CONTENT_TEXT).map_err(|e| e.to_string())?;
+    let new_text = match new_snapshot_id {
+        Some(id) => {
+            let (new, new_data) = db.get_document_snapshot(&id).map_err(|e| e.to_string())?;
+            if new.document_id != old.document_id {
+                return Err("Snapshots belong to different documents".to_string());
+            }
+            string_of_state(&new_data, CONTENT_TEXT).map_err(|e| e.to_string())?
+        }
+        None => {
+            ensure_document_loaded(&db, &repo, &old.document_id).map_err(|e| e.to_string())?;
+            repo.get_string_of_doc(&old.document_id, CONTENT_TEXT)
+                .map_err(|e| e.to_string())?
+        }
+    };
+    Ok(diff_lines(&old_text, &new_text))
+}
+
+/// Restore the text of a document to a snapshot. The current text is kept as a `Restore`
+/// snapshot first, so a restore can be undone. The editors receive the update of the
+/// restore as a `DocumentUpdateEvent`, the peers of the document receive it too.
+#[tauri::command]
+#[specta::specta]
+pub async fn restore_document_snapshot<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    snapshot_id: String,
+    db: State<'_, DatabaseRepo>,
+    repo: State<'_, DocumentRepo>,
+) -> Result<(), String> {
+    let (snapshot, data) = db
+        .get_document_snapshot(&snapshot_id)
+        .map_err(|e| e.to_string())?;
+    let text = string_of_state(&data, CONTENT_TEXT).map_err(|e| e.to_string())?;
+    take_snapshot(
+        &db,
+        &repo,
+        &snapshot.document_id,
+        SnapshotKind::Restore,
+        snapshot.label.clone(),
+    )
+    .map_err(|e| e.to_string())?;
+    let update = repo
+        .replace_string_of_doc(&snapshot.document_id, CONTENT_TEXT, &text)
+        .map_err(|e| e.to_string())?;
+    let event = DocumentUpdateEvent {
+        doc_id: snapshot.document_id,
+        origin: None,
+        update,
+    };
+    event.emit(&app).map_err(|e| e.to_string())?;
+    app.state::<CollabState>().publish(&event);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(diff: &[SnapshotDiffLine]) -> String {
+        diff.iter()
+            .map(|line| match line.kind {
+                SnapshotDiffKind::Equal => '=',
+                SnapshotDiffKind::Insert => '+',
+                SnapshotDiffKind::Delete => '-',
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_diff_lines() {
+        let old = "a\nb\nc\nd\ne";
+        let new = "a\nc\nx\nd\ne\nf";
+        let diff = diff_lines(old, new);
+        assert_eq!(kinds(&diff), "=-=+==+");
+        let inserted = &diff[3];
+        assert_eq!(inserted.text, "x");
+        assert_eq!((inserted.old_line, inserted.new_line), (None, Some(3)));
+        let last = diff.last().unwrap();
+        assert_eq!((last.old_line, last.new_line), (None, Some(6)));
+
+        assert_eq!(kinds(&diff_lines("", "a")), "+");
+        assert_eq!(kinds(&diff_lines("a\nb", "a\nb")), "==");
+    }
+}
@@ -0,0 +1,1020 @@
+/// Language server manager
+/// Servers are shared by every editor of the same language in a workspace, restarted
+/// with a backoff when they crash, and shut down with `shutdown`/`exit` when released
+/// or when the app exits. A language with `lsp_extra` gets a group of servers that the
+/// editor talks to as one.
+use std::{
+    collections::{HashMap, VecDeque},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Arc,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::Manager;
+use tauri_specta::Event;
+use tokio::{
+    sync::{broadcast::error::RecvError, Mutex, Notify, OwnedMutexGuard, RwLock},
+    task::JoinSet,
+    time::Instant,
+};
+
+use crate::commands::runner::{get_default_env, get_env_configs};
+use crate::config::ProgramConfigRepo;
+use crate::database::{
+    config::{AdvLanguageItem, LanguageBase, LanguageServerProtocolConnectionType},
+    DatabaseRepo,
+};
+use crate::runner::{
+    cmd::{
+        expand_template, parse_command_sequence_with_env, parse_command_with_env, VAR_BIN, VAR_CWD,
+        VAR_PORT, VAR_PROBLEM_DIR, VAR_SRC,
+    },
+    lang_server::{IOMethod, LangServerLog, LangServerProcess, LangServerWriter},
+    lsp_mux::{LspMultiplexer, Routed},
+    lsp_settings,
+    lsp_workspace::{self, CompileArguments, LspWorkspace},
+};
+
+/// This type is used to identify a language server, it stays the same when the server
+/// is restarted.
+/// JavaScript can't handle u32, so we use a string.
+type LangServerID = String;
+
+/// Stderr logs kept at most, the logs of running servers are never dropped
+const MAX_LANGUAGE_SERVER_LOGS: usize = 16;
+/// Restarts in a row before giving up on a crashing server
+const MAX_RESTARTS: u32 = 5;
+const RESTART_BASE_DELAY: Duration = Duration::from_millis(500);
+const RESTART_MAX_DELAY: Duration = Duration::from_secs(30);
+/// A server that ran this long before crashing gets a fresh set of restarts
+const STABLE_UPTIME: Duration = Duration::from_secs(60);
+/// How long to wait for the answer to `shutdown` and then for the process to exit
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+/// Id of the `shutdown` request sent by the manager, its response is not forwarded
+const SHUTDOWN_REQUEST_ID: &str = "algorimejo-shutdown";
+/// Starts of a spawned socket server, each with a new `PORT`. The port is free when it is
+/// picked, but another process may bind it before the server does.
+const PORT_ATTEMPTS: u32 = 3;
+
+/// Servers launched with the same key are shared
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct LangServerKey {
+    workspace: Option<PathBuf>,
+    commands: String,
+    io_method: IOMethod,
+    /// The language settings, serialized, so editing them starts a new server
+    language: String,
+}
+
+struct ManagedServer {
+    key: LangServerKey,
+    language: Option<AdvLanguageItem>,
+    /// `None` while restarting or shutting down
+    process: Option<LangServerProcess>,
+    writer: Option<LangServerWriter>,
+    log: LangServerLog,
+    /// Number of editors using the server
+    refs: usize,
+    restarts: u32,
+    shutting_down: bool,
+    shutdown_acked: Arc<Notify>,
+    /// Documents of the editors mirrored to disk, kept across restarts
+    workspace: Arc<std::sync::Mutex<LspWorkspace>>,
+    /// Set when the server belongs to a group, its messages go through the group
+    member: Option<GroupMember>,
+}
+
+#[derive(Debug, Clone)]
+struct GroupMember {
+    group: LangServerID,
+    index: usize,
+}
+
+/// The servers of a language with `lsp_extra`, the editors use the id of the group
+struct LangServerGroup {
+    key: LangServerKey,
+    /// The main server first
+    members: Vec<LangServerID>,
+    mux: LspMultiplexer,
+    refs: usize,
+    shutting_down: bool,
+}
+
+/// How to start one server of a group
+struct MemberSpec {
+    name: String,
+    commands: String,
+    io_method: IOMethod,
+    /// The language with the command and settings of this server
+    language: AdvLanguageItem,
+}
+
+#[derive(Default)]
+pub struct LangServerState {
+    /// Locked before `servers` when both are needed
+    groups: Mutex<HashMap<LangServerID, LangServerGroup>>,
+    servers: Mutex<HashMap<LangServerID, ManagedServer>>,
+    /// Keys whose server is starting, editors that launch the same key meanwhile wait for
+    /// it and share it. `groups` and `servers` aren't held while servers start.
+    starting: Mutex<HashMap<LangServerKey, Arc<Mutex<()>>>>,
+    /// Stderr logs in launch order, kept after a server exits so crashes can be reported
+    logs: RwLock<VecDeque<(LangServerID, LangServerLog)>>,
+}
+
+#[derive(Serialize, Deserialize, Type, Event, Clone, Debug)]
+pub struct LanguageServerEvent {
+    pid: LangServerID,
+    response: LanguageServerResponse,
+}
+
+#[derive(Serialize, Deserialize, Type, Clone, Debug)]
+#[serde(tag = "type")]
+pub enum LanguageServerResponse {
+    /// The server is gone for good: released, or it kept crashing
+    Closed {
+        exit_code: i32,
+    },
+    Message {
+        msg: String,
+    },
+    /// A line the server printed to stderr
+    Log {
+        line: String,
+    },
+    /// The server crashed and was started again, it needs to be initialized again
+    Restarted {
+        attempt: u32,
+    },
+}
+
+impl LanguageServerEvent {
+    fn send(app: &tauri::AppHandle, pid: &str, response: LanguageServerResponse) {
+        let event = LanguageServerEvent {
+            pid: pid.to_string(),
+            response,
+        };
+        if let Err(e) = event.emit(app) {
+            log::warn!("failed to emit language server event: {}", e);
+        }
+    }
+}
+
+/// Ask the OS for a free local port, passed to socket language servers as `PORT`
+fn pick_free_port() -> Result<u16, String> {
+    std::net::TcpListener::bind(("127.0.0.1", 0))
+        .and_then(|listener| listener.local_addr())
+        .map(|addr| addr.port())
+        .map_err(|e| e.to_string())
+}
+
+/// Delay before the given restart attempt, doubling from [`RESTART_BASE_DELAY`]
+fn restart_delay(attempt: u32) -> Duration {
+    RESTART_BASE_DELAY
+        .saturating_mul(1 << attempt.saturating_sub(1).min(16))
+        .min(RESTART_MAX_DELAY)
+}
+
+/// Name of a server in logs, the file name of its program or its address
+fn server_name(commands: &str, io_method: &IOMethod) -> String {
+    match (commands.split_whitespace().next(), io_method) {
+        (Some(program), _) => Path::new(program)
+            .file_stem()
+            .map_or(program.to_string(), |stem| {
+                stem.to_string_lossy().to_string()
+            }),
+        (None, IOMethod::Tcp { address }) => address.clone(),
+        (None, IOMethod::WebSocket { url }) => url.clone(),
+        (None, IOMethod::StdIO) => "lsp".to_string(),
+    }
+}
+
+/// The servers of a group: the main one, then those of `lsp_extra`
+fn group_members(key: &LangServerKey, language: &AdvLanguageItem) -> Vec<MemberSpec> {
+    let mut main = language.clone();
+    main.lsp_extra = None;
+    let mut members = vec![MemberSpec {
+        name: server_name(&key.commands, &key.io_method),
+        commands: key.commands.clone(),
+        io_method: key.io_method.clone(),
+        language: main,
+    }];
+    for extra in language.lsp_extra.iter().flatten() {
+        let url = extra.lsp_url.clone().unwrap_or_default();
+        let io_method = match extra.lsp_connect {
+            LanguageServerProtocolConnectionType::StdIO => IOMethod::StdIO,
+            LanguageServerProtocolConnectionType::Tcp => IOMethod::Tcp { address: url },
+            LanguageServerProtocolConnectionType::WebSocket => IOMethod::WebSocket { url },
+        };
+        let mut item = language.clone();
+        item.lsp = Some(extra.lsp.clone());
+        item.lsp_connect = Some(extra.lsp_connect.clone());
+        item.lsp_url = extra.lsp_url.clone();
+        item.lsp_settings = extra.lsp_settings.clone();
+        item.lsp_extra = None;
+        members.push(MemberSpec {
+            name: extra.name.clone(),
+            commands: extra.lsp.clone(),
+            io_method,
+            language: item,
+        });
+    }
+    members
+}
+
+fn is_shutdown_response(message: &str) -> bool {
+    message.contains(SHUTDOWN_REQUEST_ID)
+        && serde_json::from_str::<serde_json::Value>(message)
+            .is_ok_and(|value| value["id"] == SHUTDOWN_REQUEST_ID)
+}
+
+/// Expand the command and the socket address, and start the server process.
+/// A socket server gets a new `PORT` on every start, and on every retry when it can't be
+/// connected to.
+async fn start_server(
+    app: &tauri::AppHandle,
+    key: &LangServerKey,
+    language: Option<&AdvLanguageItem>,
+    log: LangServerLog,
+) -> Result<LangServerProcess, String> {
+    let mut env = get_default_env(app).map_err(|e| e.to_string())?;
+    let is_spawned = !key.commands.trim().is_empty();
+    let mut attempt = 1;
+    loop {
+        let io_method = match &key.io_method {
+            IOMethod::StdIO => IOMethod::StdIO,
+            IOMethod::Tcp { address } => {
+                env.insert(VAR_PORT.to_string(), pick_free_port()?.to_string());
+                IOMethod::Tcp {
+                    address: expand_template(address, &env)?,
+                }
+            }
+            IOMethod::WebSocket { url } => {
+                env.insert(VAR_PORT.to_string(), pick_free_port()?.to_string());
+                IOMethod::WebSocket {
+                    url: expand_template(url, &env)?,
+                }
+            }
+        };
+        let cmd: Option<Command> = if is_spawned {
+            let mut cmd = parse_command_with_env(&key.commands, &env).map_err(|e| e.to_string())?;
+            for config in get_env_configs(app, language) {
+                config.apply(&mut cmd);
+            }
+            Some(cmd)
+        } else {
+            None
+        };
+        let is_socket = io_method != IOMethod::StdIO;
+        match LangServerProcess::launch(cmd, io_method, log.clone()).await {
+            Ok(process) => return Ok(process),
+            Err(e) if is_spawned && is_socket && attempt < PORT_ATTEMPTS => {
+                log::warn!(
+                    "language server didn't accept connections, retrying with another port: {:#}",
+                    e
+                );
+                attempt += 1;
+            }
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+}
+
+/// Stop a server that no editor uses anymore, its supervisor closes it once it exited
+fn release(server: &mut ManagedServer) {
+    server.shutting_down = true;
+    server.writer = None;
+    if let Some(process) = server.process.take() {
+        tokio::spawn(shutdown_server(process, server.shutdown_acked.clone()));
+    }
+}
+
+/// Ask the server to stop with `shutdown` and `exit`, and kill it if it doesn't
+async fn shutdown_server(process: LangServerProcess, acked: Arc<Notify>) {
+    let shutdown = serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": SHUTDOWN_REQUEST_ID,
+        "method": "shutdown",
+    });
+    if process.send_message(&shutdown.to_string()).await.is_ok() {
+        let _ = tokio::time::timeout(SHUTDOWN_TIMEOUT, acked.notified()).await;
+        let exit = serde_json::json!({ "jsonrpc": "2.0", "method": "exit" });
+        if process.send_message(&exit.to_string()).await.is_ok() {
+            let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+            while process.pid().await.is_some()
+                && process.exit_code().await.is_none()
+                && Instant::now() < deadline
+            {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        }
+    }
+    if let Err(e) = process.kill().await {
+        log::trace!("failed to kill language server: {}", e);
+    }
+}
+
+impl LangServerState {
+    /// Wait until no other launch of the key is starting a server
+    async fn lock_key(&self, key: &LangServerKey) -> OwnedMutexGuard<()> {
+        let lock = self
+            .starting
+            .lock()
+            .await
+            .entry(key.clone())
+            .or_default()
+            .clone();
+        lock.lock_owned().await
+    }
+
+    /// Let the next launch of the key go, the entry is removed when nobody waits for it
+    async fn unlock_key(&self, key: &LangServerKey, guard: OwnedMutexGuard<()>) {
+        let mut starting = self.starting.lock().await;
+        drop(guard);
+        if starting
+            .get(key)
+            .is_some_and(|lock| Arc::strong_count(lock) == 1)
+        {
+            starting.remove(key);
+        }
+    }
+
+    /// Shut down every server, used when the app exits
+    pub async fn shutdown_all(&self) {
+        let mut shutdowns = JoinSet::new();
+        {
+            let mut servers = self.servers.lock().await;
+            for server in servers.values_mut() {
+                server.shutting_down = true;
+                server.writer = None;
+                if let Some(process) = server.process.take() {
+                    shutdowns.spawn(shutdown_server(process, server.shutdown_acked.clone()));
+                }
+            }
+        }
+        shutdowns.join_all().await;
+    }
+
+    /// Remove a server that is gone for good and tell the editors. The editors of a group
+    /// are told when its last server is gone.
+    async fn close(&self, app: &tauri::AppHandle, pid: &str, exit_code: i32) {
+        let removed = self.servers.lock().await.remove(pid);
+        let Some(member) = removed.and_then(|server| server.member) else {
+            LanguageServerEvent::send(app, pid, LanguageServerResponse::Closed { exit_code });
+            return;
+        };
+        let mut groups = self.groups.lock().await;
+        let Some(group) = groups.get_mut(&member.group) else {
+            return;
+        };
+        let routed = group.mux.stopped(member.index);
+        if group.mux.is_stopped() {
+            groups.remove(&member.group);
+            drop(groups);
+            LanguageServerEvent::send(
+                app,
+                &member.group,
+                LanguageServerResponse::Closed { exit_code },
+            );
+            return;
+        }
+        let members = group.members.clone();
+        drop(groups);
+        self.deliver(app, &member.group, &members, routed).await;
+    }
+
+    /// Deliver the messages routed by the multiplexer of a group
+    async fn deliver(
+        &self,
+        app: &tauri::AppHandle,
+        group: &str,
+        members: &[LangServerID],
+        routed: Routed,
+    ) {
+        for msg in routed.to_editor {
+            LanguageServerEvent::send(app, group, LanguageServerResponse::Message { msg });
+        }
+        for (index, message) in routed.to_servers {
+            if let Err(e) = send_to_server(app, self, &members[index], message).await {
+                log::warn!(
+                    "failed to send to language server {}: {}",
+                    &members[index],
+                    e
+                );
+            }
+        }
+    }
+
+    /// Route a message with the multiplexer of a group
+    async fn route<F: FnOnce(&mut LspMultiplexer) -> Routed>(
+        &self,
+        app: &tauri::AppHandle,
+        group: &str,
+        f: F,
+    ) {
+        let (routed, members) = {
+            let mut groups = self.groups.lock().await;
+            let Some(group) = groups.get_mut(group) else {
+                return;
+            };
+            (f(&mut group.mux), group.members.clone())
+        };
+        self.deliver(app, group, &members, routed).await;
+    }
+
+    /// Keep the log of a server, dropping the oldest logs of servers that are gone
+    async fn keep_log(&self, pid: &str, log: LangServerLog) {
+        let groups = self.groups.lock().await;
+        let servers = self.servers.lock().await;
+        let mut logs = self.logs.write().await;
+        logs.push_back((pid.to_string(), log));
+        while logs.len() > MAX_LANGUAGE_SERVER_LOGS {
+            match logs
+                .iter()
+                .position(|(id, _)| !servers.contains_key(id) && !groups.contains_key(id))
+            {
+                Some(index) => logs.remove(index),
+                None => break,
+            };
+        }
+    }
+}
+
+/// Forward the messages of a server to the editors and restart it when it crashes.
+/// One supervisor runs per server until the server is closed.
+async fn supervise(app: tauri::AppHandle, pid: LangServerID) {
+    let state = app.state::<LangServerState>();
+    loop {
+        let (reader, writer, acked, workspace, configuration, member) = {
+            let servers = state.servers.lock().await;
+            let Some(server) = servers.get(&pid) else {
+                return;
+            };
+            let Some(process) = server.process.as_ref() else {
+                return;
+            };
+            (
+                process.create_reader(),
+                process.create_writer(),
+                server.shutdown_acked.clone(),
+                server.workspace.clone(),
+                server
+                    .language
+                    .as_ref()
+                    .and_then(|language| language.lsp_settings.as_ref())
+                    .and_then(|settings| settings.configuration.clone()),
+                server.member.clone(),
+            )
+        };
+        let started = Instant::now();
+        while let Ok(message) = reader.receive_message().await {
+            if is_shutdown_response(&message) {
+                acked.notify_one();
+                continue;
+            }
+            log::trace!("lsp <- {}: {}", &pid, &message);
+            if let Some(response) = configuration.as_ref().and_then(|configuration| {
+                lsp_settings::answer_configuration(&message, configuration)
+            }) {
+                if let Err(e) = writer.send_message(&response).await {
+                    log::warn!("failed to answer workspace/configuration: {}", e);
+                }
+                continue;
+            }
+            let message = to_editor(&workspace, message);
+            match &member {
+                Some(member) => {
+                    state
+                        .route(&app, &member.group, |mux| {
+                            mux.from_server(member.index, &message)
+                        })
+                        .await
+                }
+                None => LanguageServerEvent::send(
+                    &app,
+                    &pid,
+                    LanguageServerResponse::Message { msg: message },
+                ),
+            }
+        }
+        let exit_code = reader.exit_code().await.unwrap_or(0);
+        log::trace!("language server {} stopped with {}", &pid, exit_code);
+
+        let (key, language, log, mut attempt, old_process) = {
+            let mut servers = state.servers.lock().await;
+            let Some(server) = servers.get_mut(&pid) else {
+                return;
+            };
+            if server.shutting_down || server.refs == 0 {
+                drop(servers);
+                state.close(&app, &pid, exit_code).await;
+                return;
+            }
+            if started.elapsed() >= STABLE_UPTIME {
+                server.restarts = 0;
+            }
+            server.writer = None;
+            (
+                server.key.clone(),
+                server.language.clone(),
+                server.log.clone(),
+                server.restarts,
+                server.process.take(),
+            )
+        };
+        // The connection may be lost while the process still runs
+        if let Some(process) = old_process {
+            let _ = process.kill().await;
+        }
+
+        loop {
+            attempt += 1;
+            if attempt > MAX_RESTARTS {
+                log.push(format!(
+                    "language server crashed {} times in a row, giving up",
+                    MAX_RESTARTS
+                ));
+                state.close(&app, &pid, exit_code).await;
+                return;
+            }
+            let delay = restart_delay(attempt);
+            log.push(format!(
+                "language server exited with {}, restarting in {:?} (attempt {})",
+                exit_code, delay, attempt
+            ));
+            tokio::time::sleep(delay).await;
+
+            let result = start_server(&app, &key, language.as_ref(), log.clone()).await;
+            let mut servers = state.servers.lock().await;
+            let Some(server) = servers.get_mut(&pid) else {
+                return;
+            };
+            server.restarts = attempt;
+            match result {
+                Ok(process) if !server.shutting_down => {
+                    server.writer = Some(process.create_writer());
+                    server.process = Some(process);
+                    drop(servers);
+                    forward_log(&app, &state, &pid).await;
+                    // The group brings the server up to date, its editors don't notice
+                    match &member {
+                        Some(member) => {
+                            state
+                                .route(&app, &member.group, |mux| mux.restarted(member.index))
+                                .await
+                        }
+                        None => LanguageServerEvent::send(
+                            &app,
+                            &pid,
+                            LanguageServerResponse::Restarted { attempt },
+                        ),
+                    }
+                    break;
+                }
+                Ok(process) => {
+                    drop(servers);
+                    let _ = process.kill().await;
+                    state.close(&app, &pid, exit_code).await;
+                    return;
+                }
+                Err(e) => {
+                    log.push(format!("failed to restart language server: {}", e));
+                }
+            }
+        }
+    }
+}
+
+/// Apply the settings of the language to `initialize`, mirror the solutions a message
+/// refers to, and point its URIs at the mirrored files.
+/// Messages that are not JSON, or that only refer to other documents, are sent as is.
+fn to_server(
+    app: &tauri::AppHandle,
+    workspace: &std::sync::Mutex<LspWorkspace>,
+    language: Option<&AdvLanguageItem>,
+    message: String,
+) -> String {
+    let settings = language.and_then(|language| language.lsp_settings.as_ref());
+    let initialize = settings.is_some() && message.contains("\"initialize\"");
+    if !message.contains("file:///") && !initialize {
+        return message;
+    }
+    let Ok(mut value) = serde_json::from_str::<serde_json::Value>(&message) else {
+        return message;
+    };
+    if let Some(settings) = settings {
+        lsp_settings::apply_to_initialize(&mut value, settings);
+    }
+    let mut workspace = workspace.lock().unwrap();
+    for uri in lsp_workspace::find_virtual_uris(&value) {
+        if !workspace.is_registered(&uri) {
+            if let Err(e) = register_document(app, &mut workspace, language, &uri) {
+                log::warn!("failed to mirror {} for the language server: {}", uri, e);
+            }
+        }
+    }
+    if let Err(e) = workspace.mirror_content(&value) {
+        log::warn!("failed to write document for the language server: {}", e);
+    }
+    workspace.to_server(&mut value);
+    value.to_string()
+}
+
+/// Point the URIs of mirrored files back at the documents of the editors
+fn to_editor(workspace: &std::sync::Mutex<LspWorkspace>, message: String) -> String {
+    let workspace = workspace.lock().unwrap();
+    if workspace.is_empty() || !message.contains("file:///") {
+        return message;
+    }
+    let Ok(mut value) = serde_json::from_str::<serde_json::Value>(&message) else {
+        return message;
+    };
+    workspace.to_editor(&mut value);
+    value.to_string()
+}
+
+/// Mirror a solution to `<problem>/lsp`, with the compile flags of its language for C
+/// and C++. Documents that are not solutions are skipped.
+fn register_document(
+    app: &tauri::AppHandle,
+    workspace: &mut LspWorkspace,
+    language: Option<&AdvLanguageItem>,
+    uri: &str,
+) -> anyhow::Result<()> {
+    let Some(name) = lsp_workspace::virtual_file_name(uri) else {
+        return Ok(());
+    };
+    let document_id = name.split_once('.').map_or(name, |(id, _)| id);
+    let db = app.state::<DatabaseRepo>();
+    let Some(problem_id) = db.get_problem_id_of_document(document_id)? else {
+        return Ok(());
+    };
+    let problem_dir = db.get_problem_folder(&problem_id)?;
+    let dir = problem_dir.join("lsp");
+
+    let compile = match language {
+        Some(language) if matches!(language.base, LanguageBase::C | LanguageBase::Cpp) => {
+            let source = dir.join(name).display().to_string();
+            let mut env = get_default_env(app)?;
+            env.insert(VAR_CWD.to_string(), dir.display().to_string());
+            env.insert(VAR_SRC.to_string(), source.clone());
+            env.insert(VAR_BIN.to_string(), dir.join("main").display().to_string());
+            env.insert(
+                VAR_PROBLEM_DIR.to_string(),
+                problem_dir.display().to_string(),
+            );
+            let sequence = parse_command_sequence_with_env(&language.cmd_compile, &env)
+                .map_err(|e| anyhow::anyhow!(e))?;
+            let steps: Vec<(String, Vec<String>)> = sequence
+                .steps
+                .iter()
+                .map(|step| {
+                    (
+                        step.command.get_program().to_string_lossy().to_string(),
+                        step.command
+                            .get_args()
+                            .map(|arg| arg.to_string_lossy().to_string())
+                            .collect(),
+                    )
+                })
+                .collect();
+            CompileArguments::from_steps(&steps, &source)
+        }
+        _ => None,
+    };
+    workspace.register(uri, &dir, compile)
+}
+
+/// Get a language server for the language, the running one is shared when the same
+/// language of the same workspace was launched before
+///
+/// # Arguments
+/// * `commands` - The command to launch the server, empty to connect to a running one
+/// * `io_method` - How to talk to the server
+/// * `language` - The language settings, their environment is applied to the server
+///
+/// # Returns
+/// The id of the server, release it with `kill_language_server`
+#[tauri::command]
+#[specta::specta]
+pub async fn launch_language_server(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, LangServerState>,
+    commands: String,
+    io_method: IOMethod,
+    language: Option<AdvLanguageItem>,
+) -> Result<LangServerID, String> {
+    let workspace = app
+        .state::<ProgramConfigRepo>()
+        .read()
+        .map_err(|e| e.to_string())?
+        .workspace
+        .clone();
+    let key = LangServerKey {
+        workspace,
+        commands,
+        io_method,
+        language: serde_json::to_string(&language).map_err(|e| e.to_string())?,
+    };
+    let is_group = language
+        .as_ref()
+        .and_then(|language| language.lsp_extra.as_ref())
+        .is_some_and(|extra| !extra.is_empty());
+    let guard = state.lock_key(&key).await;
+    let result = match language {
+        Some(language) if is_group => launch_group(&app, &state, key.clone(), &language).await,
+        language => launch_server(&app, &state, key.clone(), language).await,
+    };
+    state.unlock_key(&key, guard).await;
+    result
+}
+
+/// Start a server, or share the running one. The caller holds the lock of the key.
+async fn launch_server(
+    app: &tauri::AppHandle,
+    state: &LangServerState,
+    key: LangServerKey,
+    language: Option<AdvLanguageItem>,
+) -> Result<LangServerID, String> {
+    if let Some((pid, server)) =
+        state.servers.lock().await.iter_mut().find(|(_, server)| {
+            server.key == key && !server.shutting_down && server.member.is_none()
+        })
+    {
+        server.refs += 1;
+        log::trace!("sharing language server {} ({} users)", pid, server.refs);
+        return Ok(pid.clone());
+    }
+
+    let pid = uuid::Uuid::new_v4().to_string();
+    let log = LangServerLog::default();
+    let process = start_server(app, &key, language.as_ref(), log.clone()).await?;
+    state.servers.lock().await.insert(
+        pid.clone(),
+        ManagedServer {
+            key,
+            language,
+            writer: Some(process.create_writer()),
+            process: Some(process),
+            log: log.clone(),
+            refs: 1,
+            restarts: 0,
+            shutting_down: false,
+            shutdown_acked: Default::default(),
+            workspace: Default::default(),
+            member: None,
+        },
+    );
+
+    state.keep_log(&pid, log).await;
+    forward_log(app, state, &pid).await;
+    tokio::spawn(supervise(app.clone(), pid.clone()));
+    Ok(pid)
+}
+
+/// Start every server of a language with `lsp_extra`, or share the running group.
+/// The caller holds the lock of the key.
+async fn launch_group(
+    app: &tauri::AppHandle,
+    state: &LangServerState,
+    key: LangServerKey,
+    language: &AdvLanguageItem,
+) -> Result<LangServerID, String> {
+    if let Some((id, group)) = state
+        .groups
+        .lock()
+        .await
+        .iter_mut()
+        .find(|(_, group)| group.key == key && !group.shutting_down)
+    {
+        group.refs += 1;
+        log::trace!(
+            "sharing language server group {} ({} users)",
+            id,
+            group.refs
+        );
+        return Ok(id.clone());
+    }
+
+    let id = uuid::Uuid::new_v4().to_string();
+    // One log for the group, each line marked with the name of its server
+    let log = LangServerLog::default();
+    let workspace: Arc<std::sync::Mutex<LspWorkspace>> = Default::default();
+    let specs = group_members(&key, language);
+    let mut started = Vec::new();
+    for spec in &specs {
+        let member_key = LangServerKey {
+            workspace: key.workspace.clone(),
+            commands: spec.commands.clone(),
+            io_method: spec.io_method.clone(),
+            language: key.language.clone(),
+        };
+        let member_log = log.named(&spec.name);
+        match start_server(app, &member_key, Some(&spec.language), member_log.clone()).await {
+            Ok(process) => started.push((member_key, member_log, process)),
+            Err(e) => {
+                for (_, _, process) in started {
+                    tokio::spawn(shutdown_server(process, Default::default()));
+                }
+                return Err(format!("{}: {}", spec.name, e));
+            }
+        }
+    }
+
+    let mut groups = state.groups.lock().await;
+    let mut servers = state.servers.lock().await;
+    let mut members = Vec::new();
+    for (index, ((member_key, member_log, process), spec)) in
+        started.into_iter().zip(&specs).enumerate()
+    {
+        let pid = uuid::Uuid::new_v4().to_string();
+        servers.insert(
+            pid.clone(),
+            ManagedServer {
+                key: member_key,
+                language: Some(spec.language.clone()),
+                writer: Some(process.create_writer()),
+                process: Some(process),
+                log: member_log,
+                refs: 1,
+                restarts: 0,
+                shutting_down: false,
+                shutdown_acked: Default::default(),
+                workspace: workspace.clone(),
+                member: Some(GroupMember {
+                    group: id.clone(),
+                    index,
+                }),
+            },
+        );
+        members.push(pid);
+    }
+    let names = specs.into_iter().map(|spec| spec.name).collect();
+    groups.insert(
+        id.clone(),
+        LangServerGroup {
+            key,
+            members: members.clone(),
+            mux: LspMultiplexer::new(names),
+            refs: 1,
+            shutting_down: false,
+        },
+    );
+    drop(servers);
+    drop(groups);
+
+    state.keep_log(&id, log).await;
+    for pid in members {
+        forward_log(app, state, &pid).await;
+        tokio::spawn(supervise(app.clone(), pid));
+    }
+    Ok(id)
+}
+
+/// Forward the stderr of a server as `Log` events, across restarts. The lines of a
+/// group member are sent as lines of the group.
+async fn forward_log(app: &tauri::AppHandle, state: &LangServerState, pid: &str) {
+    let Some((mut receiver, pid)) = state.servers.lock().await.get(pid).and_then(|server| {
+        let target = server
+            .member
+            .as_ref()
+            .map_or(pid.to_string(), |member| member.group.clone());
+        server
+            .process
+            .as_ref()
+            .map(|process| (process.subscribe_log(), target))
+    }) else {
+        return;
+    };
+    let app = app.clone();
+    tokio::spawn(async move {
+        loop {
+            match receiver.recv().await {
+                Ok(line) => {
+                    LanguageServerEvent::send(&app, &pid, LanguageServerResponse::Log { line })
+                }
+                // The skipped lines are still in the log
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+/// Release a language server, it is shut down when no editor uses it anymore
+///
+/// # Arguments
+/// * `pid` - The id returned by `launch_language_server`
+#[tauri::command]
+#[specta::specta]
+pub async fn kill_language_server(
+    state: tauri::State<'_, LangServerState>,
+    pid: LangServerID,
+) -> Result<(), String> {
+    log::trace!("releasing language server: {}", &pid);
+    let mut groups = state.groups.lock().await;
+    let mut servers = state.servers.lock().await;
+    if let Some(group) = groups.get_mut(&pid) {
+        group.refs = group.refs.saturating_sub(1);
+        if group.refs == 0 {
+            group.shutting_down = true;
+            for member in &group.members {
+                if let Some(server) = servers.get_mut(member) {
+                    release(server);
+                }
+            }
+        }
+        return Ok(());
+    }
+    let server = servers.get_mut(&pid).ok_or("Language server not found")?;
+    server.refs = server.refs.saturating_sub(1);
+    if server.refs == 0 {
+        release(server);
+    }
+    Ok(())
+}
+
+/// Get the last lines a language server printed to stderr, also after it exited
+///
+/// # Arguments
+/// * `pid` - The id returned by `launch_language_server`
+#[tauri::command]
+#[specta::specta]
+pub async fn get_language_server_log(
+    state: tauri::State<'_, LangServerState>,
+    pid: LangServerID,
+) -> Result<Vec<String>, String> {
+    let logs = state.logs.read().await;
+    let (_, log) = logs
+        .iter()
+        .find(|(id, _)| id == &pid)
+        .ok_or("Log not found")?;
+    Ok(log.lines())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn send_message_to_language_server(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, LangServerState>,
+    pid: LangServerID,
+    message: String,
+) -> Result<(), String> {
+    log::trace!("lsp -> {}: {}", &pid, &message);
+    if state.groups.lock().await.contains_key(&pid) {
+        state
+            .route(&app, &pid, |mux| mux.from_editor(&message))
+            .await;
+        return Ok(());
+    }
+    send_to_server(&app, &state, &pid, message).await
+}
+
+/// Send a message to one server, with the settings of its language applied
+async fn send_to_server(
+    app: &tauri::AppHandle,
+    state: &LangServerState,
+    pid: &str,
+    message: String,
+) -> Result<(), String> {
+    let (writer, workspace, language) = {
+        let servers = state.servers.lock().await;
+        let server = servers.get(pid).ok_or("Language server not found")?;
+        let writer = server
+            .writer
+            .clone()
+            .ok_or("Language server is restarting")?;
+        (writer, server.workspace.clone(), server.language.clone())
+    };
+    let message = to_server(app, &workspace, language.as_ref(), message);
+    writer
+        .send_message(&message)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restart_delay() {
+        assert_eq!(restart_delay(1), Duration::from_millis(500));
+        assert_eq!(restart_delay(2), Duration::from_secs(1));
+        assert_eq!(restart_delay(4), Duration::from_secs(4));
+        assert_eq!(restart_delay(100), RESTART_MAX_DELAY);
+    }
+
+    #[test]
+    fn test_is_shutdown_response() {
+        assert!(is_shutdown_response(
+            r#"{"jsonrpc":"2.0","id":"algorimejo-shutdown","result":null}"#
+        ));
+        assert!(!is_shutdown_response(
+            r#"{"jsonrpc":"2.0","id":1,"result":"algorimejo-shutdown"}"#
+        ));
+    }
+}
@@ -4,8 +4,13 @@ use specta::Type;
 use tauri::{Runtime, State};
 use tauri_specta::Event;
 
+pub mod collab;
 pub mod database;
+pub mod external;
+pub mod history;
+pub mod lang_server;
 pub mod runner;
+pub mod storage;
 
 #[derive(Debug, Serialize, Deserialize, Event, Clone, Type)]
 pub struct ProgramConfigUpdateEvent {
@@ -1,6 +1,7 @@
 use std::{
     collections::HashMap,
     hash::{DefaultHasher, Hash, Hasher},
+    io::BufRead,
     path::PathBuf,
 };
 
@@ -9,14 +10,23 @@ use serde::{Deserialize, Serialize};
 use specta::Type;
 use tauri::{path::BaseDirectory, Manager};
 use tauri_specta::Event;
-use tokio::sync::RwLock;
 
+use crate::commands::database::{ensure_document_loaded, get_string_of_doc};
+use crate::database::{
+    config::{AdvLanguageItem, EnvironmentConfig},
+    DatabaseRepo,
+};
+use crate::document::{DocumentRepo, CONTENT_TEXT};
+use crate::external::text_hash;
 use crate::runner::{
-    cmd::parse_command_with_env,
+    bench::{self, BenchmarkReport},
+    cmd::{parse_command_sequence_with_env, parse_command_with_env, VAR_BIN, VAR_CWD, VAR_INPUT},
+    command_set_stack_size,
+    diff::{self, DiffMismatch, DiffMode, DiffPage},
     get_bundled_checker_names,
-    lang_server::{IOMethod, LangServerProcess, LangServerWriter},
     run::{launch_program, launch_program_without_input, ProgramOutput, ProgramSimpleOutput},
     temp_dir,
+    toolchain::{self, ToolchainReport},
 };
 
 pub static ENV_KEY_BUNDLED_LSP: &str = "BUNDLED_LSP";
@@ -33,123 +43,53 @@ pub fn get_default_env(app: &tauri::AppHandle) -> anyhow::Result<HashMap<String,
     Ok(env)
 }
 
-#[tauri::command]
-#[specta::specta]
-pub async fn get_checkers_name() -> Result<Vec<String>, String> {
-    Ok(get_bundled_checker_names()
-        .iter()
-        .map(|s| s.to_string())
-        .collect())
-}
-
-/// This type is used to identify a child process.
-/// JavaScript can't handle u32, so we use a string.
-type ChildPID = String;
-
-#[derive(Default)]
-pub struct LangServerState {
-    pub writers: RwLock<HashMap<ChildPID, LangServerWriter>>,
-    pub processes: RwLock<HashMap<ChildPID, LangServerProcess>>,
-}
-
-#[derive(Serialize, Deserialize, Type, Event, Clone, Debug)]
-pub struct LanguageServerEvent {
-    pid: ChildPID,
-    response: LanguageServerResponse,
-}
-
-#[derive(Serialize, Deserialize, Type, Clone, Debug)]
-#[serde(tag = "type")]
-pub enum LanguageServerResponse {
-    Closed { exit_code: i32 },
-    Message { msg: String },
-}
-
-#[tauri::command]
-#[specta::specta]
-pub async fn launch_language_server(
-    app: tauri::AppHandle,
-    state: tauri::State<'_, LangServerState>,
-    commands: String,
-    io_method: IOMethod,
-) -> Result<ChildPID, String> {
-    let env = get_default_env(&app).map_err(|e| e.to_string())?;
-    let cmd = parse_command_with_env(&commands, &env).map_err(|e| e.to_string())?;
-    let process = LangServerProcess::launch(cmd, io_method).map_err(|e| e.to_string())?;
-    let pid = process.pid().await.ok_or("Failed to get PID")?;
-    let writer = process.create_writer();
-    let reader = process.create_reader();
-
-    {
-        let mut process_state = state.processes.write().await;
-        process_state.insert(pid.to_string(), process);
-        let mut writer_state = state.writers.write().await;
-        writer_state.insert(pid.to_string(), writer);
-    }
-
-    tokio::spawn(async move {
-        let pid = pid.to_string();
-        let handle = app;
-        let state = handle.state::<LangServerState>();
-        let reader = reader;
-        while let Ok(message) = reader.receive_message().await {
-            log::trace!("lsp <- {}: {}", &pid, &message);
-            let response_body = LanguageServerEvent {
-                pid: pid.to_string(),
-                response: LanguageServerResponse::Message { msg: message },
-            };
-            response_body.emit(&handle).unwrap();
-        }
-        if !reader.is_alive().await {
-            log::trace!("language server {} is dead", &pid);
-            let response_body = LanguageServerEvent {
-                pid: pid.to_string(),
-                response: LanguageServerResponse::Closed {
-                    exit_code: reader.exit_code().await.unwrap_or(0),
-                },
-            };
-            response_body.emit(&handle).unwrap();
+/// Build the environment of a program launched in the task directory.
+/// `CWD` and the default `BIN` are added on top of the default environment.
+fn get_task_env(
+    app: &tauri::AppHandle,
+    task_tag: &str,
+    env: HashMap<String, String>,
+) -> Result<HashMap<String, String>, String> {
+    let def_env = get_default_env(app).map_err(|e| e.to_string())?;
+    let mut env: HashMap<String, String> = env.into_iter().chain(def_env).collect();
 
-            log::trace!("recycling language server {} handler", &pid);
-            let mut process_state = state.processes.write().await;
-            process_state.remove(&pid);
-            let mut writer_state = state.writers.write().await;
-            writer_state.remove(&pid);
-        }
+    let temp_dir = temp_dir(task_tag);
+    env.insert(VAR_CWD.to_string(), temp_dir.display().to_string());
+    env.entry(VAR_BIN.to_string()).or_insert_with(|| {
+        temp_dir
+            .join(if cfg!(target_os = "windows") {
+                "main.exe"
+            } else {
+                "main"
+            })
+            .display()
+            .to_string()
     });
-
-    Ok(pid.to_string())
+    Ok(env)
 }
 
-#[tauri::command]
-#[specta::specta]
-pub async fn kill_language_server(
-    state: tauri::State<'_, LangServerState>,
-    pid: ChildPID,
-) -> Result<(), String> {
-    log::trace!("killing language server: {}", &pid);
-    let process_state = state.processes.write().await;
-    let process = process_state.get(&pid).ok_or("Process not found")?;
-
-    process.kill().await.map_err(|e| e.to_string())?;
-    Ok(())
+/// Environment settings of the workspace and then of the language, apply them in order
+pub fn get_env_configs(
+    app: &tauri::AppHandle,
+    language: Option<&AdvLanguageItem>,
+) -> Vec<EnvironmentConfig> {
+    let mut configs = Vec::new();
+    if let Some(db) = app.try_state::<DatabaseRepo>() {
+        if let Ok(config) = db.config.read() {
+            configs.push(config.env.clone());
+        }
+    }
+    configs.extend(language.and_then(|l| l.env.clone()));
+    configs
 }
 
 #[tauri::command]
 #[specta::specta]
-pub async fn send_message_to_language_server(
-    state: tauri::State<'_, LangServerState>,
-    pid: ChildPID,
-    message: String,
-) -> Result<(), String> {
-    log::trace!("lsp -> {}: {}", &pid, &message);
-    let writer_state = state.writers.write().await;
-    let writer = writer_state.get(&pid).ok_or("Writer not found")?;
-    writer
-        .send_message(&message)
-        .await
-        .map_err(|e| e.to_string())?;
-    Ok(())
+pub async fn get_checkers_name() -> Result<Vec<String>, String> {
+    Ok(get_bundled_checker_names()
+        .iter()
+        .map(|s| s.to_string())
+        .collect())
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
@@ -192,6 +132,120 @@ pub async fn write_file_to_task_tag(
     Ok(file)
 }
 
+/// Write the content of a document to a file in the task directory, so the text
+/// doesn't need to travel through the webview
+#[tauri::command]
+#[specta::specta]
+pub async fn write_doc_to_task_tag(
+    db: tauri::State<'_, DatabaseRepo>,
+    repo: tauri::State<'_, DocumentRepo>,
+    task_tag: String,
+    filename: String,
+    doc_id: String,
+) -> Result<PathBuf, String> {
+    let file = temp_dir(&task_tag).join(filename);
+    write_doc_to_file(&db, &repo, &doc_id, &file).map_err(|e| e.to_string())?;
+    Ok(file)
+}
+
+/// A file with the content of a document, for a program that only reads it: a raw
+/// document is its own file, other documents are written to the task directory
+#[tauri::command]
+#[specta::specta]
+pub async fn get_doc_file(
+    db: tauri::State<'_, DatabaseRepo>,
+    repo: tauri::State<'_, DocumentRepo>,
+    task_tag: String,
+    filename: String,
+    doc_id: String,
+) -> Result<PathBuf, String> {
+    doc_file(&db, &repo, &task_tag, &filename, &doc_id).map_err(|e| e.to_string())
+}
+
+/// Hash of the text of a document, so a compile can be cached without the source
+/// traveling through the webview
+#[tauri::command]
+#[specta::specta]
+pub async fn get_doc_hash(
+    db: tauri::State<'_, DatabaseRepo>,
+    repo: tauri::State<'_, DocumentRepo>,
+    doc_id: String,
+) -> Result<String, String> {
+    ensure_document_loaded(&db, &repo, &doc_id).map_err(|e| e.to_string())?;
+    let text = repo
+        .get_string_of_doc(&doc_id, CONTENT_TEXT)
+        .map_err(|e| e.to_string())?;
+    Ok(text_hash(&text))
+}
+
+fn write_doc_to_file(
+    db: &DatabaseRepo,
+    repo: &DocumentRepo,
+    doc_id: &str,
+    file: &std::path::Path,
+) -> anyhow::Result<()> {
+    if let Some(raw) = db.get_raw_document_filepath(doc_id)? {
+        if let Some(parent) = file.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let len = std::fs::copy(&raw, file)?;
+        trace!(
+            "copy {} bytes of raw document {} to {:?}",
+            len,
+            doc_id,
+            file
+        );
+        return Ok(());
+    }
+    ensure_document_loaded(db, repo, doc_id)?;
+    let len = repo.write_string_of_doc(doc_id, "content", file)?;
+    trace!("write {} bytes of document {} to {:?}", len, doc_id, file);
+    Ok(())
+}
+
+/// The file of a raw document, or the document written to `filename` in the task directory
+fn doc_file(
+    db: &DatabaseRepo,
+    repo: &DocumentRepo,
+    task_tag: &str,
+    filename: &str,
+    doc_id: &str,
+) -> anyhow::Result<PathBuf> {
+    if let Some(raw) = db.get_raw_document_filepath(doc_id)? {
+        return Ok(raw);
+    }
+    let file = temp_dir(task_tag).join(filename);
+    write_doc_to_file(db, repo, doc_id, &file)?;
+    Ok(file)
+}
+
+/// Where the stdin of a program comes from
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(tag = "type")]
+pub enum ProgramInputSource {
+    /// A file on disk
+    File { path: PathBuf },
+    /// The content of a document, written to the task directory before the run. A raw
+    /// document is read from its own file.
+    Document { doc_id: String },
+}
+
+impl ProgramInputSource {
+    /// Resolve the source to a file, writing the document out if needed
+    fn resolve(self, app: &tauri::AppHandle, task_tag: &str) -> anyhow::Result<PathBuf> {
+        match self {
+            ProgramInputSource::File { path } => Ok(path),
+            ProgramInputSource::Document { doc_id } => doc_file(
+                &app.state::<DatabaseRepo>(),
+                &app.state::<DocumentRepo>(),
+                task_tag,
+                &format!("case-{}.txt", doc_id),
+                &doc_id,
+            ),
+        }
+    }
+}
+
 #[tauri::command]
 #[specta::specta]
 pub async fn execute_program_callback(
@@ -199,17 +253,30 @@ pub async fn execute_program_callback(
     task_tag: String,
     commands: String,
     env: HashMap<String, String>,
-    input_filename: PathBuf,
+    input: ProgramInputSource,
     timeout_millis: u32,
+    language: Option<AdvLanguageItem>,
 ) -> Result<ProgramOutput, String> {
-    let def_env = get_default_env(&app).map_err(|e| e.to_string())?;
-    let mut env: HashMap<String, String> = env.into_iter().chain(def_env.into_iter()).collect();
+    let input_filename = input.resolve(&app, &task_tag).map_err(|e| e.to_string())?;
+    let mut env = get_task_env(&app, &task_tag, env)?;
+    env.insert(VAR_INPUT.to_string(), input_filename.display().to_string());
 
     let temp_dir = temp_dir(&task_tag);
-    env.insert("CWD".to_string(), temp_dir.display().to_string());
     let mut cmd = parse_command_with_env(&commands, &env).map_err(|e| e.to_string())?;
     cmd.current_dir(&temp_dir);
 
+    for config in get_env_configs(&app, language.as_ref()) {
+        config.apply(&mut cmd);
+    }
+
+    let mut timeout_millis = timeout_millis;
+    if let Some(language) = &language {
+        timeout_millis = language.adjust_time_limit(timeout_millis);
+        if let Some(stack_size) = language.stack_size {
+            command_set_stack_size(&mut cmd, stack_size);
+        }
+    }
+
     let mut hasher = DefaultHasher::new();
     input_filename.hash(&mut hasher);
     let input_hash = hasher.finish();
@@ -256,19 +323,187 @@ pub async fn execute_program(
     commands: String,
     env: HashMap<String, String>,
     timeout_millis: u32,
+    language: Option<AdvLanguageItem>,
 ) -> Result<ProgramSimpleOutput, String> {
-    let def_env = get_default_env(&app).map_err(|e| e.to_string())?;
-    let mut env: HashMap<String, String> = env.into_iter().chain(def_env.into_iter()).collect();
+    let env = get_task_env(&app, &task_tag, env)?;
 
     let temp_dir = temp_dir(&task_tag);
-    env.insert("CWD".to_string(), temp_dir.display().to_string());
-    let mut cmd = parse_command_with_env(&commands, &env).map_err(|e| e.to_string())?;
-    cmd.current_dir(&temp_dir);
+    let mut sequence =
+        parse_command_sequence_with_env(&commands, &env).map_err(|e| e.to_string())?;
+    sequence.current_dir(&temp_dir);
+    for config in get_env_configs(&app, language.as_ref()) {
+        for step in &mut sequence.steps {
+            config.apply(&mut step.command);
+        }
+    }
 
-    log::trace!("launch program: {:?}", &cmd);
-    let output = launch_program_without_input(cmd, timeout_millis as u128)
+    log::trace!("launch program: {:?}", &sequence);
+    let output = launch_program_without_input(sequence, timeout_millis as u128)
         .await
         .map_err(|e| e.to_string())?;
 
     Ok(output)
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct BenchmarkParams {
+    pub problem_id: String,
+    pub commands: String,
+    pub env: HashMap<String, String>,
+    pub inputs: Vec<ProgramInputSource>,
+    pub runs: u32,
+    pub timeout_millis: u32,
+    pub language: Option<AdvLanguageItem>,
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn benchmark_program(
+    app: tauri::AppHandle,
+    db: tauri::State<'_, DatabaseRepo>,
+    task_tag: String,
+    params: BenchmarkParams,
+) -> Result<Vec<BenchmarkReport>, String> {
+    let mut time_limit = db
+        .get_problem(&params.problem_id)
+        .map_err(|e| e.to_string())?
+        .time_limit;
+    let mut timeout_millis = params.timeout_millis;
+    let mut stack_size = None;
+    if let Some(language) = &params.language {
+        time_limit = language.adjust_time_limit(time_limit.max(0) as u32) as i32;
+        timeout_millis = language.adjust_time_limit(timeout_millis);
+        stack_size = language.stack_size;
+    }
+    let env = get_task_env(&app, &task_tag, params.env)?;
+    let env_configs = get_env_configs(&app, params.language.as_ref());
+    let temp_dir = temp_dir(&task_tag);
+
+    let inputs = params
+        .inputs
+        .into_iter()
+        .map(|input| {
+            let input = input.resolve(&app, &task_tag)?;
+            let mut hasher = DefaultHasher::new();
+            input.hash(&mut hasher);
+            let output_file = temp_dir.join(format!("bench-{:x}.txt", hasher.finish()));
+            Ok((input, output_file))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()
+        .map_err(|e| e.to_string())?;
+
+    let commands = params.commands;
+    let runs = params.runs;
+    log::trace!(
+        "benchmark program {} times on {} inputs: {}",
+        runs,
+        inputs.len(),
+        &commands
+    );
+    tokio::task::spawn_blocking(move || {
+        bench::benchmark_program(
+            |input| {
+                let mut env = env.clone();
+                env.insert(VAR_INPUT.to_string(), input.display().to_string());
+                let mut cmd =
+                    parse_command_with_env(&commands, &env).map_err(|e| anyhow::anyhow!(e))?;
+                cmd.current_dir(&temp_dir);
+                for config in &env_configs {
+                    config.apply(&mut cmd);
+                }
+                if let Some(stack_size) = stack_size {
+                    command_set_stack_size(&mut cmd, stack_size);
+                }
+                Ok(cmd)
+            },
+            &inputs,
+            runs,
+            timeout_millis as u64,
+            time_limit,
+        )
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())
+}
+
+/// Reader of an answer document, a raw answer is read from its file as the diff goes
+async fn open_answer(
+    answer_doc_id: String,
+    db: tauri::State<'_, DatabaseRepo>,
+    repo: tauri::State<'_, DocumentRepo>,
+) -> Result<Box<dyn BufRead + Send>, String> {
+    match db
+        .get_raw_document_filepath(&answer_doc_id)
+        .map_err(|e| e.to_string())?
+    {
+        Some(raw) => {
+            let file = std::fs::File::open(raw).map_err(|e| e.to_string())?;
+            Ok(Box::new(std::io::BufReader::new(file)))
+        }
+        None => {
+            let answer = get_string_of_doc(answer_doc_id, "content".to_string(), db, repo).await?;
+            Ok(Box::new(std::io::Cursor::new(answer)))
+        }
+    }
+}
+
+/// Find the first difference between the output file of a run and the answer document
+#[tauri::command]
+#[specta::specta]
+pub async fn diff_output(
+    db: tauri::State<'_, DatabaseRepo>,
+    repo: tauri::State<'_, DocumentRepo>,
+    output_file: PathBuf,
+    answer_doc_id: String,
+    mode: DiffMode,
+) -> Result<Option<DiffMismatch>, String> {
+    let answer = open_answer(answer_doc_id, db, repo).await?;
+    trace!("diff output {:?} in {:?} mode", &output_file, mode);
+    tokio::task::spawn_blocking(move || {
+        let output = std::io::BufReader::new(std::fs::File::open(&output_file)?);
+        diff::find_first_mismatch(output, answer, mode)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())
+}
+
+/// Read a page of the output file of a run and the answer document side by side
+#[tauri::command]
+#[specta::specta]
+pub async fn get_diff_page(
+    db: tauri::State<'_, DatabaseRepo>,
+    repo: tauri::State<'_, DocumentRepo>,
+    output_file: PathBuf,
+    answer_doc_id: String,
+    mode: DiffMode,
+    start_line: u32,
+    limit: u32,
+) -> Result<DiffPage, String> {
+    let answer = open_answer(answer_doc_id, db, repo).await?;
+    tokio::task::spawn_blocking(move || {
+        let output = std::io::BufReader::new(std::fs::File::open(&output_file)?);
+        diff::read_diff_page(output, answer, mode, start_line, limit)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())
+}
+
+/// Look for compilers, interpreters and language servers in PATH and propose language presets.
+/// The PATH prefix of the workspace is searched first.
+#[tauri::command]
+#[specta::specta]
+pub async fn detect_toolchains(
+    db: tauri::State<'_, DatabaseRepo>,
+) -> Result<ToolchainReport, String> {
+    let mut search_paths: Vec<PathBuf> = {
+        let config = db.config.read().map_err(|e| e.to_string())?;
+        config.env.path_prepend.iter().map(PathBuf::from).collect()
+    };
+    if let Some(path) = std::env::var_os("PATH") {
+        search_paths.extend(std::env::split_paths(&path));
+    }
+    Ok(toolchain::detect_toolchains(search_paths).await)
+}
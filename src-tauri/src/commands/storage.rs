@@ -0,0 +1,226 @@
+use std::{
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use log::{trace, warn};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::{AppHandle, Runtime, State};
+use tauri_specta::Event;
+
+use crate::{
+    database::DatabaseRepo,
+    document::{DocumentRepo, CONTENT_TEXT},
+    model::{Document, DocumentStorage},
+};
+
+/// Bytes shown from each end of a raw document
+const PREVIEW_BYTES: u64 = 4 * 1024;
+/// Raw documents up to this size may go back to Yrs, larger ones are too slow to edit
+const MAX_EDITABLE_SIZE: u64 = 1024 * 1024;
+
+/// The ends of a raw document, the middle of a large one is never sent to the webview
+#[derive(Debug, Serialize, Deserialize, Type, Clone, PartialEq, Eq)]
+pub struct DocumentPreview {
+    pub size: u64,
+    /// The first lines of the file, the whole file if it isn't truncated
+    pub head: String,
+    /// The last lines of the file, empty if it isn't truncated
+    pub tail: String,
+    pub is_truncated: bool,
+}
+
+/// A testcase document switched storage, its editors switch between the editor and the
+/// preview
+#[derive(Debug, Serialize, Deserialize, Event, Clone, Type)]
+pub struct DocumentStorageEvent {
+    pub document_id: String,
+    pub storage: DocumentStorage,
+}
+
+impl DocumentStorageEvent {
+    fn send<R: Runtime>(app: &AppHandle<R>, document_id: &str, storage: DocumentStorage) {
+        let event = Self {
+            document_id: document_id.to_string(),
+            storage,
+        };
+        // The storage is switched already, an editor that misses the event fails to save
+        if let Err(e) = event.emit(app) {
+            warn!("failed to emit storage of document {}: {}", document_id, e);
+        }
+    }
+}
+
+/// Read the ends of a file, cut at line breaks so no line is shown in part
+fn read_preview(path: &Path) -> anyhow::Result<DocumentPreview> {
+    let mut file = std::fs::File::open(path)?;
+    let size = file.metadata()?.len();
+    if size <= PREVIEW_BYTES * 2 {
+        // Testcases may hold any bytes, they are shown as far as they are text
+        let mut head = Vec::new();
+        file.read_to_end(&mut head)?;
+        return Ok(DocumentPreview {
+            size,
+            head: String::from_utf8_lossy(&head).into_owned(),
+            tail: String::new(),
+            is_truncated: false,
+        });
+    }
+
+    let mut head = vec![0u8; PREVIEW_BYTES as usize];
+    file.read_exact(&mut head)?;
+    if let Some(end) = head.iter().rposition(|&b| b == b'\n') {
+        head.truncate(end + 1);
+    }
+    let mut tail = vec![0u8; PREVIEW_BYTES as usize];
+    file.seek(SeekFrom::End(-(PREVIEW_BYTES as i64)))?;
+    file.read_exact(&mut tail)?;
+    // The last byte is the line break of the last line
+    if let Some(start) = tail[..tail.len() - 1].iter().position(|&b| b == b'\n') {
+        tail.drain(..=start);
+    }
+    Ok(DocumentPreview {
+        size,
+        head: String::from_utf8_lossy(&head).into_owned(),
+        tail: String::from_utf8_lossy(&tail).into_owned(),
+        is_truncated: true,
+    })
+}
+
+/// Preview a raw document
+///
+/// # Returns
+/// * `Result<Option<DocumentPreview>, String>` - The preview, `None` for a Yrs document
+#[tauri::command]
+#[specta::specta]
+pub async fn get_document_preview(
+    document_id: String,
+    db: State<'_, DatabaseRepo>,
+) -> Result<Option<DocumentPreview>, String> {
+    let Some(path) = db
+        .get_raw_document_filepath(&document_id)
+        .map_err(|e| e.to_string())?
+    else {
+        return Ok(None);
+    };
+    tokio::task::spawn_blocking(move || read_preview(&path))
+        .await
+        .map_err(|e| e.to_string())?
+        .map(Some)
+        .map_err(|e| e.to_string())
+}
+
+/// Store a testcase document as a copy of a file on disk, for tests too large to edit.
+/// The runner feeds the file to programs as is.
+///
+/// # Arguments
+/// * `path` - The file to copy, it isn't linked to the document
+#[tauri::command]
+#[specta::specta]
+pub async fn import_raw_document<R: Runtime>(
+    app: AppHandle<R>,
+    document_id: String,
+    path: PathBuf,
+    db: State<'_, DatabaseRepo>,
+    repo: State<'_, DocumentRepo>,
+) -> Result<Document, String> {
+    let target = db
+        .get_storage_filepath(&document_id, DocumentStorage::Raw)
+        .map_err(|e| e.to_string())?;
+    let size = tokio::task::spawn_blocking(move || -> anyhow::Result<u64> {
+        // Copy next to the target first, a failed copy leaves the document as it was
+        let mut tmp = target.clone().into_os_string();
+        tmp.push(".tmp");
+        let size = std::fs::copy(&path, &tmp)?;
+        std::fs::rename(&tmp, &target)?;
+        trace!("copied {} bytes from {:?} to {:?}", size, path, target);
+        Ok(size)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())?;
+    let document = db
+        .set_document_storage(&document_id, DocumentStorage::Raw, Some(size as i64))
+        .map_err(|e| e.to_string())?;
+    // The Yrs files are gone, drop the loaded state without saving it. Open editors can't
+    // load the document again, they are told to show the raw file instead.
+    let editors = repo.refs(&document_id);
+    repo.remove(&document_id);
+    trace!(
+        "dropped loaded document {}, {} editors had it open",
+        document_id,
+        editors
+    );
+    DocumentStorageEvent::send(&app, &document_id, DocumentStorage::Raw);
+    Ok(document)
+}
+
+/// Turn a small raw document back into a Yrs document that can be edited
+#[tauri::command]
+#[specta::specta]
+pub async fn convert_raw_document<R: Runtime>(
+    app: AppHandle<R>,
+    document_id: String,
+    db: State<'_, DatabaseRepo>,
+) -> Result<Document, String> {
+    let source = db
+        .get_raw_document_filepath(&document_id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Document {} is not a raw file", document_id))?;
+    let size = std::fs::metadata(&source).map_err(|e| e.to_string())?.len();
+    if size > MAX_EDITABLE_SIZE {
+        return Err(format!(
+            "The file has {} bytes, only files up to {} bytes can be edited",
+            size, MAX_EDITABLE_SIZE
+        ));
+    }
+    let text = std::fs::read_to_string(&source).map_err(|e| e.to_string())?;
+    let target = db
+        .get_storage_filepath(&document_id, DocumentStorage::Yrs)
+        .map_err(|e| e.to_string())?;
+    // Write the Yrs file before switching, the editors load it from there
+    let writer = DocumentRepo::new();
+    writer
+        .manage(document_id.clone(), target)
+        .map_err(|e| e.to_string())?;
+    writer
+        .replace_string_of_doc(&document_id, CONTENT_TEXT, &text)
+        .map_err(|e| e.to_string())?;
+    writer.save_all().map_err(|e| e.to_string())?;
+    let document = db
+        .set_document_storage(&document_id, DocumentStorage::Yrs, None)
+        .map_err(|e| e.to_string())?;
+    DocumentStorageEvent::send(&app, &document_id, DocumentStorage::Yrs);
+    Ok(document)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_preview() {
+        let file = std::env::temp_dir().join(format!("preview-{}.txt", uuid::Uuid::new_v4()));
+        std::fs::write(&file, "1 2\n3 4\n").unwrap();
+        let preview = read_preview(&file).unwrap();
+        assert_eq!(preview.head, "1 2\n3 4\n");
+        assert!(!preview.is_truncated);
+
+        let text: String = (0..10000).map(|i| format!("{}\n", i)).collect();
+        std::fs::write(&file, &text).unwrap();
+        let preview = read_preview(&file).unwrap();
+        assert!(preview.is_truncated);
+        assert_eq!(preview.size, text.len() as u64);
+        assert!(preview.head.starts_with("0\n1\n") && preview.head.ends_with('\n'));
+        assert!(text.starts_with(&preview.head));
+        assert!(text.ends_with(&preview.tail) && preview.tail.ends_with("9999\n"));
+        assert!(preview.tail.lines().all(|line| line.parse::<u32>().is_ok()));
+        assert!(text[text.len() - preview.tail.len() - 1..].starts_with('\n'));
+
+        std::fs::write(&file, b"1 \xff\n").unwrap();
+        let preview = read_preview(&file).unwrap();
+        assert_eq!(preview.head, "1 \u{fffd}\n");
+        std::fs::remove_file(&file).unwrap();
+    }
+}
@@ -0,0 +1,210 @@
+/// Removal of documents together with their files
+/// Deleting an owner (solution, testcase or checker) deletes its documents by the triggers
+/// of the database, the files in the doc folder are removed here.
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use diesel::r2d2::CustomizeConnection;
+use log::{trace, warn};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use super::DatabaseRepo;
+use crate::schema::{checker, document_snapshots, documents, solutions, test_cases};
+
+/// Unknown files younger than this may belong to a document being created, so they are kept
+const MIN_GARBAGE_AGE: Duration = Duration::from_secs(60);
+
+/// Options of every connection of the pool, SQLite resets them for each new connection
+#[derive(Debug, Default)]
+pub struct ConnectionOptions;
+
+impl CustomizeConnection<SqliteConnection, diesel::r2d2::Error> for ConnectionOptions {
+    fn on_acquire(&self, conn: &mut SqliteConnection) -> Result<(), diesel::r2d2::Error> {
+        conn.batch_execute("PRAGMA foreign_keys = ON;")
+            .map_err(diesel::r2d2::Error::QueryError)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Type)]
+pub struct GarbageReport {
+    /// Documents that no solution, testcase or checker owned
+    pub document_ids: Vec<String>,
+    pub removed_files: u32,
+    pub freed_bytes: u64,
+}
+
+impl DatabaseRepo {
+    /// Files of documents: the snapshot, the update log and the snapshots of the history
+    pub(super) fn document_files(
+        &self,
+        conn: &mut SqliteConnection,
+        document_ids: &[String],
+    ) -> Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        for filename in documents::table
+            .filter(documents::id.eq_any(document_ids))
+            .select(documents::filename)
+            .load::<String>(conn)?
+        {
+            files.push(self.doc_folder.join(format!("{}.log", filename)));
+            files.push(self.doc_folder.join(filename));
+        }
+        for filename in document_snapshots::table
+            .filter(document_snapshots::document_id.eq_any(document_ids))
+            .select(document_snapshots::filename)
+            .load::<String>(conn)?
+        {
+            files.push(self.doc_folder.join(filename));
+        }
+        Ok(files)
+    }
+
+    /// Remove files after their rows are gone, a file left behind is only garbage
+    ///
+    /// # Returns
+    /// * `(u32, u64)` - The number of removed files and their size in bytes
+    pub(super) fn remove_files(&self, files: &[PathBuf]) -> (u32, u64) {
+        let (mut count, mut bytes) = (0, 0);
+        for file in files {
+            let Ok(metadata) = std::fs::metadata(file) else {
+                continue;
+            };
+            match std::fs::remove_file(file) {
+                Ok(()) => {
+                    trace!("removed {:?}", file);
+                    count += 1;
+                    bytes += metadata.len();
+                }
+                Err(e) => warn!("failed to remove {:?}: {}", file, e),
+            }
+        }
+        (count, bytes)
+    }
+
+    /// Delete documents that nothing owns, and files of the doc folder that belong to no
+    /// document or snapshot
+    pub fn collect_garbage(&self) -> Result<GarbageReport> {
+        let mut conn = self.pool.get().map_err(|e| anyhow::anyhow!("{}", e))?;
+        let document_ids = documents::table
+            .filter(documents::id.ne_all(solutions::table.select(solutions::document_id)))
+            .filter(documents::id.ne_all(test_cases::table.select(test_cases::input_document_id)))
+            .filter(documents::id.ne_all(test_cases::table.select(test_cases::answer_document_id)))
+            .filter(documents::id.ne_all(checker::table.select(checker::document_id)))
+            .select(documents::id)
+            .load::<String>(&mut conn)?;
+        let files = self.document_files(&mut conn, &document_ids)?;
+        // Snapshots of the documents cascade
+        diesel::delete(documents::table.filter(documents::id.eq_any(&document_ids)))
+            .execute(&mut conn)?;
+        let (removed_files, freed_bytes) = self.remove_files(&files);
+        let mut report = GarbageReport {
+            document_ids,
+            removed_files,
+            freed_bytes,
+        };
+
+        if self.doc_folder.exists() {
+            let mut known = HashSet::new();
+            for filename in documents::table
+                .select(documents::filename)
+                .load::<String>(&mut conn)?
+            {
+                known.insert(format!("{}.log", filename));
+                known.insert(filename);
+            }
+            known.extend(
+                document_snapshots::table
+                    .select(document_snapshots::filename)
+                    .load::<String>(&mut conn)?,
+            );
+            let now = SystemTime::now();
+            let mut unknown = Vec::new();
+            for entry in std::fs::read_dir(&self.doc_folder)? {
+                let entry = entry?;
+                let metadata = entry.metadata()?;
+                let is_old = metadata
+                    .modified()
+                    .ok()
+                    .and_then(|modified| now.duration_since(modified).ok())
+                    .is_some_and(|age| age >= MIN_GARBAGE_AGE);
+                if metadata.is_file()
+                    && is_old
+                    && !known.contains(entry.file_name().to_string_lossy().as_ref())
+                {
+                    unknown.push(entry.path());
+                }
+            }
+            let (count, bytes) = self.remove_files(&unknown);
+            report.removed_files += count;
+            report.freed_bytes += bytes;
+        }
+
+        trace!("collected garbage: {:?}", report);
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{CreateProblemParams, CreateSolutionParams};
+    use crate::model::SnapshotKind;
+
+    #[test]
+    fn test_delete_owned_documents() {
+        let db = DatabaseRepo::open_temporary();
+        let base = db.base_folder.clone();
+
+        let problem = db
+            .create_problem(CreateProblemParams {
+                name: "A".to_string(),
+                url: None,
+                description: None,
+                statement: None,
+                checker: None,
+                time_limit: 1000,
+                memory_limit: 1024,
+                initial_solution: Some(CreateSolutionParams {
+                    author: None,
+                    name: "main".to_string(),
+                    language: "C++".to_string(),
+                    content: None,
+                }),
+            })
+            .unwrap()
+            .problem;
+        let document_id = problem.solutions[0].document.as_ref().unwrap().id.clone();
+        let document_file = db.get_document_filepath(&document_id).unwrap();
+        std::fs::create_dir_all(document_file.parent().unwrap()).unwrap();
+        std::fs::write(&document_file, b"state").unwrap();
+        db.create_document_snapshot(&document_id, SnapshotKind::Manual, None, b"state")
+            .unwrap();
+        let testcase = db.create_testcase(&problem.id).unwrap();
+
+        assert_eq!(db.delete_testcase(&testcase.id).unwrap().len(), 2);
+        let deleted = db.delete_problem(&problem.id).unwrap();
+        assert_eq!(deleted, vec![document_id.clone()]);
+        assert!(db.get_document(&document_id).unwrap().is_none());
+        assert!(!document_file.exists());
+        assert_eq!(std::fs::read_dir(&db.doc_folder).unwrap().count(), 0);
+
+        // A document left by an older version, nothing owns it
+        let mut conn = db.pool.get().unwrap();
+        diesel::insert_into(documents::table)
+            .values((
+                documents::id.eq("orphan"),
+                documents::filename.eq("orphan.bin"),
+            ))
+            .execute(&mut conn)
+            .unwrap();
+        drop(conn);
+        let report = db.collect_garbage().unwrap();
+        assert_eq!(report.document_ids, vec!["orphan".to_string()]);
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -1,26 +1,131 @@
-use std::{collections::HashMap, path};
+use std::{collections::HashMap, path, path::PathBuf, process::Command};
 
 use serde::{Deserialize, Serialize};
 use specta::Type;
 
 use crate::commands::runner::ENV_KEY_BUNDLED_LSP;
+use crate::runner::cmd::{
+    check_command, VAR_BIN, VAR_CWD, VAR_INPUT, VAR_PORT, VAR_PROBLEM_DIR, VAR_SRC,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize, Type)]
 pub enum LanguageBase {
     Cpp,
+    C,
     TypeScript,
     JavaScript,
     Go,
     Python,
+    Rust,
+    Java,
+    Kotlin,
+    CSharp,
+    Haskell,
     Text,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Type)]
 pub enum LanguageServerProtocolConnectionType {
     StdIO,
+    Tcp,
     WebSocket,
 }
 
+/// Environment of child processes, applied on top of the environment of the app
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Type)]
+#[serde(default)]
+pub struct EnvironmentConfig {
+    /// Variables to set
+    pub set: HashMap<String, String>,
+    /// Variables to remove
+    pub unset: Vec<String>,
+    /// Directories put in front of PATH, the first one is searched first
+    pub path_prepend: Vec<String>,
+}
+
+impl EnvironmentConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        for key in self.set.keys().chain(self.unset.iter()) {
+            if key.is_empty() || key.contains(['=', '\0']) {
+                return Err(format!("Invalid environment variable name \"{}\"", key));
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply the settings to a command: unset, then set, then prepend to PATH.
+    /// Applying several configs in turn lets the later ones take precedence.
+    pub fn apply(&self, cmd: &mut Command) {
+        for key in &self.unset {
+            cmd.env_remove(key);
+        }
+        for (key, value) in &self.set {
+            cmd.env(key, value);
+        }
+        if self.path_prepend.is_empty() {
+            return;
+        }
+        let current = cmd
+            .get_envs()
+            .find(|(key, _)| {
+                if cfg!(target_os = "windows") {
+                    key.eq_ignore_ascii_case("PATH")
+                } else {
+                    *key == "PATH"
+                }
+            })
+            .map(|(_, value)| value.map(|v| v.to_os_string()))
+            .unwrap_or_else(|| std::env::var_os("PATH"));
+        let paths = self
+            .path_prepend
+            .iter()
+            .map(PathBuf::from)
+            .chain(current.iter().flat_map(std::env::split_paths));
+        match std::env::join_paths(paths) {
+            Ok(path) => {
+                cmd.env("PATH", path);
+            }
+            Err(e) => log::warn!("failed to prepend to PATH: {}", e),
+        }
+    }
+}
+
+/// Language server settings, e.g. fallback flags of clangd or the strictness of pyright
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, Type)]
+#[serde(default)]
+pub struct LanguageServerSettings {
+    /// Merged into `initializationOptions` of the `initialize` request
+    pub initialization_options: Option<serde_json::Value>,
+    /// Answers `workspace/configuration` requests, a section like `python.analysis` is
+    /// looked up by its dotted path
+    pub configuration: Option<serde_json::Value>,
+}
+
+impl LanguageServerSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        for (field, value) in [
+            ("initialization_options", &self.initialization_options),
+            ("configuration", &self.configuration),
+        ] {
+            if value.as_ref().is_some_and(|value| !value.is_object()) {
+                return Err(format!("{}: must be a JSON object", field));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A language server running next to the main one of a language
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct ExtraLanguageServer {
+    /// Shown in logs, and as the source of diagnostics that have none
+    pub name: String,
+    pub lsp: String,
+    pub lsp_connect: LanguageServerProtocolConnectionType,
+    pub lsp_url: Option<String>,
+    pub lsp_settings: Option<LanguageServerSettings>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Type)]
 
 pub struct AdvLanguageItem {
@@ -31,6 +136,168 @@ pub struct AdvLanguageItem {
     pub cmd_run: String,
     pub lsp: Option<String>,
     pub lsp_connect: Option<LanguageServerProtocolConnectionType>,
+    /// Endpoint of the language server when `lsp_connect` is `Tcp` (`127.0.0.1:%PORT`) or
+    /// `WebSocket` (`ws://127.0.0.1:%PORT`). If `lsp` is empty the server is expected to be
+    /// running already
+    pub lsp_url: Option<String>,
+    /// Options and settings handed to the language server on behalf of the editor
+    pub lsp_settings: Option<LanguageServerSettings>,
+    /// More servers running next to `lsp`, e.g. a linter next to a type checker.
+    /// Requests go to `lsp` first, and to these when it lacks the capability.
+    pub lsp_extra: Option<Vec<ExtraLanguageServer>>,
+    /// Multiply the time limit of the problem by this factor, 1.0 if not set
+    pub time_multiplier: Option<f64>,
+    /// Extra milliseconds added to the time limit after the multiplier
+    pub time_offset: Option<u32>,
+    /// Stack size of the program in kilobytes, inherit from the app if not set
+    pub stack_size: Option<u32>,
+    /// Environment of the compile, run and language server processes, applied after the
+    /// environment of the workspace
+    pub env: Option<EnvironmentConfig>,
+}
+
+/// Variables available in `cmd_compile`
+const COMPILE_VARIABLES: &[&str] = &[
+    VAR_CWD,
+    VAR_SRC,
+    VAR_BIN,
+    VAR_PROBLEM_DIR,
+    ENV_KEY_BUNDLED_LSP,
+];
+/// Variables available in `cmd_run`
+const RUN_VARIABLES: &[&str] = &[
+    VAR_CWD,
+    VAR_SRC,
+    VAR_BIN,
+    VAR_INPUT,
+    VAR_PROBLEM_DIR,
+    ENV_KEY_BUNDLED_LSP,
+];
+/// Variables available in `cmd_before_run` and `cmd_after_run`, the hooks don't read the
+/// input so they get no `INPUT`
+const HOOK_VARIABLES: &[&str] = &[
+    VAR_CWD,
+    VAR_SRC,
+    VAR_BIN,
+    VAR_PROBLEM_DIR,
+    ENV_KEY_BUNDLED_LSP,
+];
+/// Variables available in `lsp`
+const LSP_VARIABLES: &[&str] = &[ENV_KEY_BUNDLED_LSP];
+/// Variables available in `lsp` and `lsp_url` when connecting over a socket
+const LSP_SOCKET_VARIABLES: &[&str] = &[ENV_KEY_BUNDLED_LSP, VAR_PORT];
+
+/// Check the command, endpoint and settings of a language server
+fn validate_language_server(
+    connect: Option<&LanguageServerProtocolConnectionType>,
+    lsp: Option<&String>,
+    lsp_url: Option<&String>,
+    settings: Option<&LanguageServerSettings>,
+) -> Result<(), String> {
+    let socket = matches!(
+        connect,
+        Some(
+            LanguageServerProtocolConnectionType::Tcp
+                | LanguageServerProtocolConnectionType::WebSocket
+        )
+    );
+    let lsp_variables = if socket {
+        LSP_SOCKET_VARIABLES
+    } else {
+        LSP_VARIABLES
+    };
+    for (field, command) in [("lsp", lsp), ("lsp_url", lsp_url)] {
+        if let Some(command) = command.filter(|c| !c.trim().is_empty()) {
+            check_command(command, lsp_variables, false)
+                .map_err(|e| format!("{}: {}", field, e))?;
+        }
+    }
+    if socket && lsp_url.is_none_or(|url| url.trim().is_empty()) {
+        return Err("lsp_url: required to connect over a socket".to_string());
+    }
+    if let Some(settings) = settings {
+        settings
+            .validate()
+            .map_err(|e| format!("lsp_settings.{}", e))?;
+    }
+    Ok(())
+}
+
+impl AdvLanguageItem {
+    /// Check that the commands only reference variables available to them, and that
+    /// the run and language server commands are a single step
+    pub fn validate(&self) -> Result<(), String> {
+        let commands = [
+            (
+                "cmd_compile",
+                Some(&self.cmd_compile),
+                COMPILE_VARIABLES,
+                true,
+            ),
+            (
+                "cmd_before_run",
+                self.cmd_before_run.as_ref(),
+                HOOK_VARIABLES,
+                true,
+            ),
+            ("cmd_run", Some(&self.cmd_run), RUN_VARIABLES, false),
+            (
+                "cmd_after_run",
+                self.cmd_after_run.as_ref(),
+                HOOK_VARIABLES,
+                true,
+            ),
+        ];
+        for (field, command, known, allow_sequence) in commands {
+            if let Some(command) = command.filter(|c| !c.trim().is_empty()) {
+                check_command(command, known, allow_sequence)
+                    .map_err(|e| format!("{}: {}", field, e))?;
+            }
+        }
+        validate_language_server(
+            self.lsp_connect.as_ref(),
+            self.lsp.as_ref(),
+            self.lsp_url.as_ref(),
+            self.lsp_settings.as_ref(),
+        )?;
+        for (index, server) in self.lsp_extra.iter().flatten().enumerate() {
+            if server.lsp.trim().is_empty()
+                && matches!(
+                    server.lsp_connect,
+                    LanguageServerProtocolConnectionType::StdIO
+                )
+            {
+                return Err(format!("lsp_extra[{}].lsp: required", index));
+            }
+            validate_language_server(
+                Some(&server.lsp_connect),
+                Some(&server.lsp),
+                server.lsp_url.as_ref(),
+                server.lsp_settings.as_ref(),
+            )
+            .map_err(|e| format!("lsp_extra[{}].{}", index, e))?;
+        }
+        if let Some(env) = &self.env {
+            env.validate().map_err(|e| format!("env: {}", e))?;
+        }
+        Ok(())
+    }
+
+    /// Presets before the templating syntax used `$target` for the source file, in any of
+    /// the commands
+    fn migrate_target(&mut self) {
+        let migrate = |command: &mut String| *command = command.replace("$target", "%SRC");
+        migrate(&mut self.cmd_compile);
+        migrate(&mut self.cmd_run);
+        self.cmd_before_run.iter_mut().for_each(migrate);
+        self.cmd_after_run.iter_mut().for_each(migrate);
+    }
+
+    /// Apply the time multiplier and offset of this language to the time limit of a problem
+    pub fn adjust_time_limit(&self, time_limit: u32) -> u32 {
+        let scaled = (time_limit as f64 * self.time_multiplier.unwrap_or(1.0)).round();
+        (scaled.max(0.0) as u32).saturating_add(self.time_offset.unwrap_or(0))
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Type)]
@@ -38,6 +305,8 @@ pub struct AdvLanguageItem {
 pub struct WorkspaceConfig {
     pub font_family: String,
     pub font_size: u32,
+    /// Environment of all child processes, including checkers
+    pub env: EnvironmentConfig,
     pub language: HashMap<String, AdvLanguageItem>,
 }
 
@@ -46,7 +315,15 @@ impl From<WorkspaceLocalDeserialized> for WorkspaceConfig {
         Self {
             font_family: value.font_family,
             font_size: value.font_size,
-            language: value.language,
+            env: value.env,
+            language: value
+                .language
+                .into_iter()
+                .map(|(name, mut item)| {
+                    item.migrate_target();
+                    (name, item)
+                })
+                .collect(),
         }
     }
 }
@@ -59,6 +336,8 @@ pub struct WorkspaceLocalDeserialized {
     pub font_family: String,
     #[serde(default = "WorkspaceLocalDeserialized::default_font_size")]
     pub font_size: u32,
+    #[serde(default)]
+    pub env: EnvironmentConfig,
     #[serde(default = "WorkspaceLocalDeserialized::default_language")]
     pub language: HashMap<String, AdvLanguageItem>,
 }
@@ -75,18 +354,10 @@ impl WorkspaceLocalDeserialized {
             "cpp 17".to_string(),
             AdvLanguageItem {
                 base: LanguageBase::Cpp,
-                cmd_compile: "g++ -std=c++17 -o main".to_string(),
+                cmd_compile: "g++ -std=c++17 -o %BIN %SRC".to_string(),
                 cmd_before_run: None,
                 cmd_after_run: None,
-                cmd_run: format!(
-                    "%CWD{}main{}",
-                    path::MAIN_SEPARATOR,
-                    if cfg!(target_os = "windows") {
-                        ".exe"
-                    } else {
-                        ""
-                    }
-                ),
+                cmd_run: "%BIN".to_string(),
                 lsp: Some(format!(
                     "%{}{}clangd{}",
                     ENV_KEY_BUNDLED_LSP,
@@ -98,6 +369,13 @@ impl WorkspaceLocalDeserialized {
                     }
                 )),
                 lsp_connect: Some(LanguageServerProtocolConnectionType::StdIO),
+                lsp_url: None,
+                lsp_settings: None,
+                lsp_extra: None,
+                time_multiplier: None,
+                time_offset: None,
+                stack_size: Some(256 * 1024),
+                env: None,
             },
         );
         language.insert(
@@ -107,7 +385,7 @@ impl WorkspaceLocalDeserialized {
                 cmd_compile: "".to_string(),
                 cmd_before_run: None,
                 cmd_after_run: None,
-                cmd_run: "python $target".to_string(),
+                cmd_run: "python %SRC".to_string(),
                 lsp: Some(format!(
                     "%{}{}pylyzer{} --server",
                     ENV_KEY_BUNDLED_LSP,
@@ -119,6 +397,13 @@ impl WorkspaceLocalDeserialized {
                     }
                 )),
                 lsp_connect: Some(LanguageServerProtocolConnectionType::StdIO),
+                lsp_url: None,
+                lsp_settings: None,
+                lsp_extra: None,
+                time_multiplier: None,
+                time_offset: None,
+                stack_size: None,
+                env: None,
             },
         );
         language
@@ -131,6 +416,57 @@ impl Default for WorkspaceLocalDeserialized {
             language: Self::default_language(),
             font_family: Self::default_font_family(),
             font_size: Self::default_font_size(),
+            env: Default::default(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_environment_apply_in_order() {
+        let workspace = EnvironmentConfig {
+            set: HashMap::from([("A".to_string(), "1".to_string())]),
+            unset: vec![],
+            path_prepend: vec!["/opt/first".to_string()],
+        };
+        let language = EnvironmentConfig {
+            set: HashMap::new(),
+            unset: vec!["A".to_string()],
+            path_prepend: vec!["/opt/second".to_string()],
+        };
+        let mut cmd = Command::new("true");
+        workspace.apply(&mut cmd);
+        language.apply(&mut cmd);
+
+        let envs: HashMap<_, _> = cmd.get_envs().collect();
+        assert_eq!(envs[std::ffi::OsStr::new("A")], None);
+        let path = envs[std::ffi::OsStr::new("PATH")].unwrap();
+        let paths: Vec<_> = std::env::split_paths(path).collect();
+        assert_eq!(paths[0], PathBuf::from("/opt/second"));
+        assert_eq!(paths[1], PathBuf::from("/opt/first"));
+
+        let invalid = EnvironmentConfig {
+            unset: vec!["A=B".to_string()],
+            ..Default::default()
+        };
+        assert!(invalid.validate().is_err());
+    }
+    #[test]
+    fn test_hook_variables() {
+        let mut item = WorkspaceLocalDeserialized::default_language()
+            .remove("python 3")
+            .unwrap();
+        item.cmd_run = "python $target".to_string();
+        item.cmd_before_run = Some("cp $target %CWD/backup".to_string());
+        item.migrate_target();
+        assert_eq!(item.cmd_run, "python %SRC");
+        assert_eq!(item.cmd_before_run.as_deref(), Some("cp %SRC %CWD/backup"));
+        assert!(item.validate().is_ok());
+
+        item.cmd_after_run = Some("cat %INPUT".to_string());
+        assert!(item.validate().unwrap_err().starts_with("cmd_after_run"));
+    }
+}
@@ -0,0 +1,69 @@
+/// Links of documents to files on disk, see `crate::external` for the sync itself
+use anyhow::Result;
+use diesel::prelude::*;
+
+use super::DatabaseRepo;
+use crate::model::ExternalFile;
+use crate::schema::external_files;
+
+impl DatabaseRepo {
+    /// Link a document to a file, replacing its previous link. The link starts unsynced.
+    pub fn link_external_file(&self, document_id: &str, path: &str) -> Result<ExternalFile> {
+        let mut conn = self.pool.get().map_err(|e| anyhow::anyhow!("{}", e))?;
+        conn.transaction(|conn| {
+            let linked = external_files::table
+                .filter(external_files::path.eq(path))
+                .filter(external_files::document_id.ne(document_id))
+                .count()
+                .get_result::<i64>(conn)?;
+            if linked > 0 {
+                anyhow::bail!("File {} is linked to another document already", path);
+            }
+            diesel::delete(external_files::table.find(document_id)).execute(conn)?;
+            let link = ExternalFile {
+                document_id: document_id.to_string(),
+                path: path.to_string(),
+                synced_hash: None,
+                // Use local time to match SQLite's CURRENT_TIMESTAMP behavior
+                create_datetime: chrono::Local::now().naive_local(),
+            };
+            diesel::insert_into(external_files::table)
+                .values(&link)
+                .execute(conn)?;
+            Ok(link)
+        })
+    }
+
+    pub fn unlink_external_file(&self, document_id: &str) -> Result<()> {
+        let mut conn = self.pool.get().map_err(|e| anyhow::anyhow!("{}", e))?;
+        diesel::delete(external_files::table.find(document_id)).execute(&mut conn)?;
+        Ok(())
+    }
+
+    pub fn get_external_file(&self, document_id: &str) -> Result<Option<ExternalFile>> {
+        let mut conn = self.pool.get().map_err(|e| anyhow::anyhow!("{}", e))?;
+        let link = external_files::table
+            .find(document_id)
+            .select(ExternalFile::as_select())
+            .first::<ExternalFile>(&mut conn)
+            .optional()?;
+        Ok(link)
+    }
+
+    pub fn get_external_files(&self) -> Result<Vec<ExternalFile>> {
+        let mut conn = self.pool.get().map_err(|e| anyhow::anyhow!("{}", e))?;
+        let links = external_files::table
+            .select(ExternalFile::as_select())
+            .load::<ExternalFile>(&mut conn)?;
+        Ok(links)
+    }
+
+    /// Record the hash of the text that both the document and its file had at a sync
+    pub fn set_external_file_synced(&self, document_id: &str, synced_hash: &str) -> Result<()> {
+        let mut conn = self.pool.get().map_err(|e| anyhow::anyhow!("{}", e))?;
+        diesel::update(external_files::table.find(document_id))
+            .set(external_files::synced_hash.eq(synced_hash))
+            .execute(&mut conn)?;
+        Ok(())
+    }
+}
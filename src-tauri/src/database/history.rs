@@ -0,0 +1,207 @@
+/// Version history of documents
+/// A snapshot is the full state of a document, stored as `<snapshot id>.snap.bin` in the
+/// doc folder next to the document itself. Automatic snapshots are capped per document,
+/// the oldest are deleted with their files.
+use anyhow::Result;
+use diesel::prelude::*;
+use log::trace;
+use uuid::Uuid;
+
+use super::DatabaseRepo;
+use crate::document::write_atomically;
+use crate::model::{DocumentSnapshot, DocumentSnapshotRow, SnapshotKind};
+use crate::schema::document_snapshots;
+
+/// `Run` and `Interval` snapshots kept per document, the user takes the others
+const MAX_AUTOMATIC_SNAPSHOTS: i64 = 50;
+
+impl From<DocumentSnapshotRow> for DocumentSnapshot {
+    fn from(row: DocumentSnapshotRow) -> Self {
+        Self {
+            kind: row.kind.parse().unwrap_or(SnapshotKind::Manual),
+            id: row.id,
+            document_id: row.document_id,
+            label: row.label,
+            create_datetime: row.create_datetime,
+        }
+    }
+}
+
+impl DatabaseRepo {
+    /// Store a snapshot of a document
+    ///
+    /// # Arguments
+    /// * `document_id` - The document the state belongs to
+    /// * `kind` - What took the snapshot
+    /// * `label` - A name given by the user
+    /// * `data` - The state of the document, encoded as a v1 update
+    pub fn create_document_snapshot(
+        &self,
+        document_id: &str,
+        kind: SnapshotKind,
+        label: Option<String>,
+        data: &[u8],
+    ) -> Result<DocumentSnapshot> {
+        let mut conn = self.pool.get().map_err(|e| anyhow::anyhow!("{}", e))?;
+        let snapshot_id = Uuid::new_v4().to_string();
+        let row = DocumentSnapshotRow {
+            filename: format!("{}.snap.bin", snapshot_id),
+            id: snapshot_id,
+            document_id: document_id.to_string(),
+            label,
+            kind: kind.as_str().to_string(),
+            // Use local time to match SQLite's CURRENT_TIMESTAMP behavior
+            create_datetime: chrono::Local::now().naive_local(),
+        };
+
+        // Write the file first, a row without its file would be a broken snapshot
+        std::fs::create_dir_all(&self.doc_folder)?;
+        write_atomically(&self.doc_folder.join(&row.filename), data)?;
+        diesel::insert_into(document_snapshots::table)
+            .values(&row)
+            .execute(&mut conn)?;
+        if matches!(kind, SnapshotKind::Run | SnapshotKind::Interval) {
+            self.prune_automatic_snapshots(&mut conn, document_id)?;
+        }
+        Ok(row.into())
+    }
+
+    /// Delete the oldest automatic snapshots of a document beyond
+    /// [`MAX_AUTOMATIC_SNAPSHOTS`], with their files
+    fn prune_automatic_snapshots(
+        &self,
+        conn: &mut SqliteConnection,
+        document_id: &str,
+    ) -> Result<()> {
+        let automatic = [SnapshotKind::Run.as_str(), SnapshotKind::Interval.as_str()];
+        let stale = document_snapshots::table
+            .filter(document_snapshots::document_id.eq(document_id))
+            .filter(document_snapshots::kind.eq_any(automatic))
+            .order((
+                document_snapshots::create_datetime.desc(),
+                document_snapshots::id.desc(),
+            ))
+            .offset(MAX_AUTOMATIC_SNAPSHOTS)
+            .select((document_snapshots::id, document_snapshots::filename))
+            .load::<(String, String)>(conn)?;
+        if stale.is_empty() {
+            return Ok(());
+        }
+        let (ids, filenames): (Vec<String>, Vec<String>) = stale.into_iter().unzip();
+        // Rows first, a file without its row is only garbage
+        diesel::delete(document_snapshots::table.filter(document_snapshots::id.eq_any(&ids)))
+            .execute(conn)?;
+        let files: Vec<_> = filenames
+            .iter()
+            .map(|filename| self.doc_folder.join(filename))
+            .collect();
+        self.remove_files(&files);
+        trace!(
+            "pruned {} automatic snapshots of document {}",
+            ids.len(),
+            document_id
+        );
+        Ok(())
+    }
+
+    /// Snapshots of a document, the newest first
+    pub fn get_document_snapshots(&self, document_id: &str) -> Result<Vec<DocumentSnapshot>> {
+        let mut conn = self.pool.get().map_err(|e| anyhow::anyhow!("{}", e))?;
+        let rows = document_snapshots::table
+            .filter(document_snapshots::document_id.eq(document_id))
+            .order((
+                document_snapshots::create_datetime.desc(),
+                document_snapshots::id.desc(),
+            ))
+            .select(DocumentSnapshotRow::as_select())
+            .load::<DocumentSnapshotRow>(&mut conn)?;
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+
+    /// The newest snapshot of a document with its state
+    pub fn get_latest_document_snapshot(
+        &self,
+        document_id: &str,
+    ) -> Result<Option<(DocumentSnapshot, Vec<u8>)>> {
+        let mut conn = self.pool.get().map_err(|e| anyhow::anyhow!("{}", e))?;
+        let row = document_snapshots::table
+            .filter(document_snapshots::document_id.eq(document_id))
+            .order((
+                document_snapshots::create_datetime.desc(),
+                document_snapshots::id.desc(),
+            ))
+            .select(DocumentSnapshotRow::as_select())
+            .first::<DocumentSnapshotRow>(&mut conn)
+            .optional()?;
+        row.map(|row| self.read_snapshot_row(row)).transpose()
+    }
+
+    /// A snapshot with its state
+    ///
+    /// # Returns
+    /// * `Result<(DocumentSnapshot, Vec<u8>)>` - The snapshot and the state of the document
+    ///   encoded as a v1 update
+    pub fn get_document_snapshot(&self, snapshot_id: &str) -> Result<(DocumentSnapshot, Vec<u8>)> {
+        let mut conn = self.pool.get().map_err(|e| anyhow::anyhow!("{}", e))?;
+        let row = document_snapshots::table
+            .filter(document_snapshots::id.eq(snapshot_id))
+            .select(DocumentSnapshotRow::as_select())
+            .first::<DocumentSnapshotRow>(&mut conn)
+            .optional()?
+            .ok_or_else(|| anyhow::anyhow!("Snapshot {} not found", snapshot_id))?;
+        self.read_snapshot_row(row)
+    }
+
+    fn read_snapshot_row(&self, row: DocumentSnapshotRow) -> Result<(DocumentSnapshot, Vec<u8>)> {
+        let data = std::fs::read(self.doc_folder.join(&row.filename))
+            .map_err(|e| anyhow::anyhow!("Failed to read snapshot {}: {}", row.id, e))?;
+        Ok((row.into(), data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::documents;
+
+    #[test]
+    fn test_prune_automatic_snapshots() {
+        let db = DatabaseRepo::open_temporary();
+        let mut conn = db.pool.get().unwrap();
+        diesel::insert_into(documents::table)
+            .values((documents::id.eq("doc"), documents::filename.eq("doc.bin")))
+            .execute(&mut conn)
+            .unwrap();
+        drop(conn);
+
+        let manual = db
+            .create_document_snapshot("doc", SnapshotKind::Manual, None, b"state")
+            .unwrap();
+        let mut automatic = Vec::new();
+        for i in 0..MAX_AUTOMATIC_SNAPSHOTS + 2 {
+            let kind = if i % 2 == 0 {
+                SnapshotKind::Run
+            } else {
+                SnapshotKind::Interval
+            };
+            automatic.push(
+                db.create_document_snapshot("doc", kind, None, b"state")
+                    .unwrap(),
+            );
+        }
+
+        let kept = db.get_document_snapshots("doc").unwrap();
+        assert_eq!(kept.len() as i64, MAX_AUTOMATIC_SNAPSHOTS + 1);
+        assert!(kept.iter().any(|snapshot| snapshot.id == manual.id));
+        for stale in &automatic[..2] {
+            assert!(kept.iter().all(|snapshot| snapshot.id != stale.id));
+            assert!(!db
+                .doc_folder
+                .join(format!("{}.snap.bin", stale.id))
+                .exists());
+        }
+        let files = std::fs::read_dir(&db.doc_folder).unwrap().count();
+        assert_eq!(files, kept.len());
+        std::fs::remove_dir_all(&db.base_folder).unwrap();
+    }
+}
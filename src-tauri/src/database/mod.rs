@@ -10,18 +10,22 @@ use diesel::{
     r2d2::{ConnectionManager, Pool},
     SqliteConnection,
 };
-use log::trace;
+use log::{trace, warn};
 use serde::{Deserialize, Serialize};
 use specta::Type;
 use uuid::Uuid;
 
 use crate::model::{
-    Checker, Document, Problem, ProblemChangeset, ProblemRow, Solution, SolutionChangeset,
-    SolutionRow, TestCase,
+    Checker, Document, DocumentStorage, Problem, ProblemChangeset, ProblemRow, Solution,
+    SolutionChangeset, SolutionRow, TestCase,
 };
 
+pub mod cleanup;
 pub mod config;
+pub mod external;
+pub mod history;
 pub mod language;
+pub mod storage;
 
 pub struct DatabaseRepo {
     pool: Pool<ConnectionManager<SqliteConnection>>,
@@ -214,7 +218,7 @@ impl DatabaseRepo {
                 id: problem_id,
                 name: params.name,
                 url: params.url,
-                description: description,
+                description,
                 statement: params.statement,
                 checker: params.checker,
                 create_datetime: now,
@@ -264,7 +268,7 @@ impl DatabaseRepo {
         let solution_id = Uuid::new_v4().to_string();
         let document_id = Uuid::new_v4().to_string();
         let document_filename = format!("{}.sol.bin", solution_id);
-        let author = params.author.unwrap_or_else(|| whoami::username());
+        let author = params.author.unwrap_or_else(whoami::username);
 
         // Create the document
         // let new_document = (
@@ -278,6 +282,8 @@ impl DatabaseRepo {
             create_datetime: now,
             modified_datetime: now,
             filename: document_filename.clone(),
+            storage: DocumentStorage::Yrs,
+            size: None,
         };
 
         diesel::insert_into(documents::table)
@@ -304,11 +310,13 @@ impl DatabaseRepo {
             create_datetime: now,
             modified_datetime: now,
             filename: document_filename,
+            storage: DocumentStorage::Yrs,
+            size: None,
         };
 
         let solution = Solution {
             id: solution_id,
-            author: author,
+            author,
             name: params.name,
             language: params.language,
             problem_id: problem_id.to_string(),
@@ -360,6 +368,8 @@ impl DatabaseRepo {
                 create_datetime: now,
                 modified_datetime: now,
                 filename: document_filename,
+                storage: DocumentStorage::Yrs,
+                size: None,
             };
 
             let checker = Checker {
@@ -375,11 +385,43 @@ impl DatabaseRepo {
         })
     }
 
-    pub fn delete_problem(&self, problem_id: &str) -> Result<()> {
+    /// Deletes a problem with its solutions, testcases, their documents and files
+    ///
+    /// # Arguments
+    /// * `problem_id` - The ID of the problem to delete
+    ///
+    /// # Returns
+    /// * `Result<Vec<String>>` - The IDs of the deleted documents
+    pub fn delete_problem(&self, problem_id: &str) -> Result<Vec<String>> {
         let mut conn = self.pool.get().map_err(|e| anyhow::anyhow!("{}", e))?;
 
+        let mut document_ids = solutions::table
+            .filter(solutions::problem_id.eq(problem_id))
+            .select(solutions::document_id)
+            .load::<String>(&mut conn)?;
+        for (input, answer) in test_cases::table
+            .filter(test_cases::problem_id.eq(problem_id))
+            .select((
+                test_cases::input_document_id,
+                test_cases::answer_document_id,
+            ))
+            .load::<(String, String)>(&mut conn)?
+        {
+            document_ids.push(input);
+            document_ids.push(answer);
+        }
+        let files = self.document_files(&mut conn, &document_ids)?;
+
+        // Solutions and testcases cascade, their triggers delete the documents
         diesel::delete(problems::table.filter(problems::id.eq(problem_id))).execute(&mut conn)?;
-        Ok(())
+        self.remove_files(&files);
+        let problem_folder = self.base_folder.join("problem").join(problem_id);
+        if problem_folder.exists() {
+            if let Err(e) = std::fs::remove_dir_all(&problem_folder) {
+                warn!("failed to remove {:?}: {}", problem_folder, e);
+            }
+        }
+        Ok(document_ids)
     }
 
     /// Deletes a solution from the database by its ID
@@ -388,16 +430,20 @@ impl DatabaseRepo {
     /// * `solution_id` - The ID of the solution to delete
     ///
     /// # Returns
-    /// * `Result<String>` - The ID of the problem that the solution belonged to
-    pub fn delete_solution(&self, solution_id: &str) -> Result<String> {
+    /// * `Result<(String, String)>` - The ID of the problem that the solution belonged to,
+    ///   and the ID of its deleted document
+    pub fn delete_solution(&self, solution_id: &str) -> Result<(String, String)> {
         let mut conn = self.pool.get().map_err(|e| anyhow::anyhow!("{}", e))?;
-        let problem_id = solutions::table
+        let (problem_id, document_id) = solutions::table
             .filter(solutions::id.eq(solution_id))
-            .select(solutions::problem_id)
-            .first::<String>(&mut conn)?;
+            .select((solutions::problem_id, solutions::document_id))
+            .first::<(String, String)>(&mut conn)?;
+        let files = self.document_files(&mut conn, std::slice::from_ref(&document_id))?;
+        // The trigger of solutions deletes the document
         diesel::delete(solutions::table.filter(solutions::id.eq(solution_id)))
             .execute(&mut conn)?;
-        Ok(problem_id)
+        self.remove_files(&files);
+        Ok((problem_id, document_id))
     }
 
     pub fn get_problem(&self, problem_id: &str) -> Result<Problem> {
@@ -583,10 +629,22 @@ impl DatabaseRepo {
             name: solution.name,
             language: solution.language,
             problem_id: solution.problem_id,
-            document: document,
+            document,
         })
     }
 
+    /// Find the problem of the solution that owns a document, `None` for documents that
+    /// are not solutions
+    pub fn get_problem_id_of_document(&self, document_id: &str) -> Result<Option<String>> {
+        let mut conn = self.pool.get().map_err(|e| anyhow::anyhow!("{}", e))?;
+        let problem_id = solutions::table
+            .filter(solutions::document_id.eq(document_id))
+            .select(solutions::problem_id)
+            .first::<String>(&mut conn)
+            .optional()?;
+        Ok(problem_id)
+    }
+
     pub fn update_problem(&self, problem_id: &str, params: ProblemChangeset) -> Result<()> {
         let mut conn = self.pool.get().map_err(|e| anyhow::anyhow!("{}", e))?;
 
@@ -605,16 +663,29 @@ impl DatabaseRepo {
         Ok(())
     }
 
+    /// The Yrs file of a document, a raw document is never loaded whole so it has none
     pub fn get_document_filepath(&self, document_id: &str) -> Result<PathBuf> {
         let mut conn = self.pool.get()?;
         let document = documents::table
             .filter(documents::id.eq(document_id))
             .first::<Document>(&mut conn)?;
+        if document.storage != DocumentStorage::Yrs {
+            anyhow::bail!("Document {} is stored as a raw file", document_id);
+        }
 
         let filepath = self.doc_folder.join(document.filename);
         Ok(filepath)
     }
 
+    /// Directory shared by all tasks of a problem, it is created if missing
+    pub fn get_problem_folder(&self, problem_id: &str) -> Result<PathBuf> {
+        // Make sure the id is a real problem before using it as a path component
+        let problem = self.get_problem(problem_id)?;
+        let folder = self.base_folder.join("problem").join(problem.id);
+        std::fs::create_dir_all(&folder)?;
+        Ok(folder)
+    }
+
     pub fn get_testcases(&self, problem_id: &str) -> Result<Vec<TestCase>> {
         let mut conn = self.pool.get().map_err(|e| anyhow::anyhow!("{}", e))?;
         let testcases = test_cases::table
@@ -634,12 +705,16 @@ impl DatabaseRepo {
             create_datetime: now,
             modified_datetime: now,
             filename: format!("{}.in.bin", &testcase_id),
+            storage: DocumentStorage::Yrs,
+            size: None,
         };
         let answer_document = Document {
             id: answer_document_id.clone(),
             create_datetime: now,
             modified_datetime: now,
             filename: format!("{}.ans.bin", &testcase_id),
+            storage: DocumentStorage::Yrs,
+            size: None,
         };
         let testcase = TestCase {
             id: testcase_id,
@@ -661,11 +736,26 @@ impl DatabaseRepo {
 
         Ok(testcase)
     }
-    pub fn delete_testcase(&self, testcase_id: &str) -> Result<()> {
+    /// Deletes a testcase with its input and answer documents
+    ///
+    /// # Returns
+    /// * `Result<Vec<String>>` - The IDs of the deleted documents
+    pub fn delete_testcase(&self, testcase_id: &str) -> Result<Vec<String>> {
         let mut conn = self.pool.get().map_err(|e| anyhow::anyhow!("{}", e))?;
+        let (input, answer) = test_cases::table
+            .filter(test_cases::id.eq(testcase_id))
+            .select((
+                test_cases::input_document_id,
+                test_cases::answer_document_id,
+            ))
+            .first::<(String, String)>(&mut conn)?;
+        let document_ids = vec![input, answer];
+        let files = self.document_files(&mut conn, &document_ids)?;
+        // The trigger of test_cases deletes the documents
         diesel::delete(test_cases::table.filter(test_cases::id.eq(testcase_id)))
             .execute(&mut conn)?;
-        Ok(())
+        self.remove_files(&files);
+        Ok(document_ids)
     }
     pub fn get_language_item(&self, language: &str) -> Result<AdvLanguageItem> {
         let config = self.config.read().unwrap();
@@ -681,3 +771,30 @@ impl DatabaseRepo {
         Ok(languages)
     }
 }
+
+#[cfg(test)]
+impl DatabaseRepo {
+    /// A repo on a new database in a temporary folder, remove `base_folder` when done
+    pub(crate) fn open_temporary() -> Self {
+        use diesel_migrations::MigrationHarness;
+
+        let base = std::env::temp_dir().join(format!("workspace-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&base).unwrap();
+        let manager = ConnectionManager::<SqliteConnection>::new(
+            base.join("database.sqlite").to_string_lossy(),
+        );
+        let pool = Pool::builder()
+            .connection_customizer(Box::new(cleanup::ConnectionOptions))
+            .build(manager)
+            .unwrap();
+        pool.get()
+            .unwrap()
+            .run_pending_migrations(crate::setup::MIGRATIONS)
+            .unwrap();
+        Self::new(
+            pool,
+            base,
+            config::WorkspaceLocalDeserialized::default().into(),
+        )
+    }
+}
@@ -0,0 +1,135 @@
+/// Storage classes of documents: Yrs documents, or raw files for testcases too large to edit
+use std::path::PathBuf;
+
+use anyhow::Result;
+use diesel::prelude::*;
+use log::trace;
+
+use super::DatabaseRepo;
+use crate::model::{Document, DocumentStorage};
+use crate::schema::{document_snapshots, documents, test_cases};
+
+/// The filename of a document in a storage, `<testcase>.in.bin` becomes `<testcase>.in.txt`
+fn filename_in_storage(filename: &str, storage: DocumentStorage) -> String {
+    let stem = filename
+        .strip_suffix(".bin")
+        .or_else(|| filename.strip_suffix(".txt"))
+        .unwrap_or(filename);
+    match storage {
+        DocumentStorage::Yrs => format!("{}.bin", stem),
+        DocumentStorage::Raw => format!("{}.txt", stem),
+    }
+}
+
+impl DatabaseRepo {
+    /// A document owned by a testcase, other documents are edited together and stay in Yrs
+    fn get_testcase_document(
+        &self,
+        conn: &mut SqliteConnection,
+        document_id: &str,
+    ) -> Result<Document> {
+        let owners = test_cases::table
+            .filter(
+                test_cases::input_document_id
+                    .eq(document_id)
+                    .or(test_cases::answer_document_id.eq(document_id)),
+            )
+            .count()
+            .get_result::<i64>(conn)?;
+        if owners == 0 {
+            anyhow::bail!("Document {} doesn't belong to a testcase", document_id);
+        }
+        let document = documents::table
+            .find(document_id)
+            .select(Document::as_select())
+            .first::<Document>(conn)?;
+        Ok(document)
+    }
+
+    /// The file that a testcase document is kept in with a storage, it may not exist yet
+    pub fn get_storage_filepath(
+        &self,
+        document_id: &str,
+        storage: DocumentStorage,
+    ) -> Result<PathBuf> {
+        let mut conn = self.pool.get().map_err(|e| anyhow::anyhow!("{}", e))?;
+        let document = self.get_testcase_document(&mut conn, document_id)?;
+        Ok(self
+            .doc_folder
+            .join(filename_in_storage(&document.filename, storage)))
+    }
+
+    /// The file of a raw document, `None` if the document is a Yrs document
+    pub fn get_raw_document_filepath(&self, document_id: &str) -> Result<Option<PathBuf>> {
+        let mut conn = self.pool.get().map_err(|e| anyhow::anyhow!("{}", e))?;
+        let (filename, storage) = documents::table
+            .find(document_id)
+            .select((documents::filename, documents::storage))
+            .first::<(String, DocumentStorage)>(&mut conn)?;
+        Ok((storage == DocumentStorage::Raw).then(|| self.doc_folder.join(filename)))
+    }
+
+    /// Switch a testcase document to a storage whose file is written already. The files of
+    /// the old storage and the history of the document are removed.
+    ///
+    /// # Arguments
+    /// * `size` - The size of the raw file in bytes, `None` for Yrs
+    pub fn set_document_storage(
+        &self,
+        document_id: &str,
+        storage: DocumentStorage,
+        size: Option<i64>,
+    ) -> Result<Document> {
+        let mut conn = self.pool.get().map_err(|e| anyhow::anyhow!("{}", e))?;
+        let (document, files) = conn.transaction(|conn| {
+            let document = self.get_testcase_document(conn, document_id)?;
+            let files = self.document_files(conn, &[document_id.to_string()])?;
+            // Snapshots are Yrs states, they can't be restored into a raw file
+            diesel::delete(
+                document_snapshots::table.filter(document_snapshots::document_id.eq(document_id)),
+            )
+            .execute(conn)?;
+            let document = diesel::update(documents::table.find(document_id))
+                .set((
+                    documents::filename.eq(filename_in_storage(&document.filename, storage)),
+                    documents::storage.eq(storage),
+                    documents::size.eq(size),
+                    documents::modified_datetime.eq(chrono::Local::now().naive_local()),
+                ))
+                .returning(Document::as_returning())
+                .get_result::<Document>(conn)?;
+            anyhow::Ok((document, files))
+        })?;
+        let new_file = self.doc_folder.join(&document.filename);
+        let stale: Vec<PathBuf> = files.into_iter().filter(|file| *file != new_file).collect();
+        self.remove_files(&stale);
+        trace!(
+            "stored document {} as {:?}, {:?} bytes",
+            document_id,
+            storage,
+            size
+        );
+        Ok(document)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filename_in_storage() {
+        assert_eq!(
+            filename_in_storage("tc.in.bin", DocumentStorage::Raw),
+            "tc.in.txt"
+        );
+        assert_eq!(
+            filename_in_storage("tc.ans.txt", DocumentStorage::Yrs),
+            "tc.ans.bin"
+        );
+        assert_eq!(
+            filename_in_storage("tc.in.txt", DocumentStorage::Raw),
+            "tc.in.txt"
+        );
+    }
+}
@@ -1,17 +1,46 @@
+/// Documents are stored as a snapshot, `<name>.bin`, and an append-only log of the
+/// updates since, `<name>.bin.log`. A keystroke appends its update to the log, and the
+/// log is folded into a new snapshot once it outgrows it.
 use std::{
     collections::HashMap,
-    path::PathBuf,
-    sync::{Arc, Mutex, RwLock},
+    ffi::OsString,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, RwLock,
+    },
 };
 
 use anyhow::Result;
-use log::trace;
-use yrs::{updates::decoder::Decode, *};
+use log::{trace, warn};
+use yrs::{
+    updates::{decoder::Decode, encoder::Encode},
+    *,
+};
+
+/// The log is compacted once it is larger than the snapshot and at least this large
+const COMPACT_MIN_LOG_SIZE: u64 = 256 * 1024;
+/// Each record of the log is the length of the update as little endian u32, then the update
+const RECORD_HEADER_SIZE: usize = 4;
+/// Documents without an editor kept in memory, the least recently used are dropped beyond
+const MAX_IDLE_DOCUMENTS: usize = 16;
+/// The text of a document that the editors edit
+pub const CONTENT_TEXT: &str = "content";
+/// Above this many cells a diff gives up and replaces the changed items at once
+const MAX_DIFF_CELLS: usize = 4_000_000;
 
 pub struct DocumentHolder {
     doc: Doc,
     path: PathBuf,
+    /// Whether the log holds updates that the snapshot doesn't
     is_modified: Arc<Mutex<bool>>,
+    snapshot_len: u64,
+    log_len: u64,
+    /// Number of editors that have the document open, it is idle at 0
+    refs: usize,
+    /// Tick of the repo clock at the last access
+    last_used: AtomicU64,
 }
 
 impl Default for DocumentHolder {
@@ -20,49 +49,182 @@ impl Default for DocumentHolder {
             doc: Default::default(),
             path: Default::default(),
             is_modified: Arc::new(Mutex::new(false)),
+            snapshot_len: 0,
+            log_len: 0,
+            refs: 0,
+            last_used: AtomicU64::new(0),
         }
     }
 }
+
+/// `<path>` with `suffix` appended to the file name
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name: OsString = path.file_name().unwrap_or_default().to_owned();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Replace the file at `path` with `data`: a crash leaves either the old or the new file,
+/// never a partial one
+pub(crate) fn write_atomically(path: &Path, data: &[u8]) -> Result<()> {
+    let tmp = with_suffix(path, ".tmp");
+    {
+        let mut file = std::fs::File::create(&tmp)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+    }
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Split a log into its updates. A record cut off by a crash ends the log.
+fn read_log(data: &[u8]) -> Vec<&[u8]> {
+    let mut records = Vec::new();
+    let mut rest = data;
+    while rest.len() >= RECORD_HEADER_SIZE {
+        let (header, body) = rest.split_at(RECORD_HEADER_SIZE);
+        let len = u32::from_le_bytes(header.try_into().unwrap()) as usize;
+        if body.len() < len {
+            break;
+        }
+        let (record, next) = body.split_at(len);
+        records.push(record);
+        rest = next;
+    }
+    records
+}
+
 impl DocumentHolder {
+    fn log_path(&self) -> PathBuf {
+        with_suffix(&self.path, ".log")
+    }
+
     fn load_document(filepath: PathBuf) -> Result<Self> {
-        let doc = Doc::new();
+        let mut holder = Self {
+            path: filepath,
+            ..Default::default()
+        };
 
         {
-            let mut txn = Transact::transact_mut(&doc);
-            if filepath.exists() {
-                let data = std::fs::read(&filepath)?;
+            let mut txn = Transact::transact_mut(&holder.doc);
+            if holder.path.exists() {
+                let data = std::fs::read(&holder.path)?;
                 txn.apply_update(Update::decode_v1(&data)?)?;
+                holder.snapshot_len = data.len() as u64;
                 trace!(
                     "load {} bytes from document {}",
                     data.len(),
-                    &filepath.to_string_lossy()
+                    &holder.path.to_string_lossy()
                 );
             } else {
-                trace!("document {} not found", &filepath.to_string_lossy());
+                trace!("document {} not found", &holder.path.to_string_lossy());
+            }
+            let log_path = holder.log_path();
+            if log_path.exists() {
+                let data = std::fs::read(&log_path)?;
+                let mut replayed = 0;
+                let mut valid_len = 0;
+                for record in read_log(&data) {
+                    // Updates already in the snapshot are no-ops, after a crash during
+                    // compaction the log may still hold them
+                    match Update::decode_v1(record) {
+                        Ok(update) => txn.apply_update(update)?,
+                        Err(e) => {
+                            warn!("stopping at a corrupt record of {:?}: {}", &log_path, e);
+                            break;
+                        }
+                    }
+                    replayed += 1;
+                    valid_len += RECORD_HEADER_SIZE + record.len();
+                }
+                // New records go right after the last valid one, behind a torn tail they
+                // would be lost on the next load
+                if valid_len < data.len() {
+                    warn!(
+                        "truncating {:?} from {} to {} bytes, its tail is not a valid record",
+                        &log_path,
+                        data.len(),
+                        valid_len
+                    );
+                    std::fs::OpenOptions::new()
+                        .write(true)
+                        .open(&log_path)?
+                        .set_len(valid_len as u64)?;
+                }
+                holder.log_len = valid_len as u64;
+                *holder.is_modified.lock().unwrap() = replayed > 0;
+                trace!(
+                    "replayed {} updates of document {}",
+                    replayed,
+                    &holder.path.to_string_lossy()
+                );
             }
         }
 
-        Ok(Self {
-            doc,
-            path: filepath,
-            is_modified: Arc::new(Mutex::new(false)),
-        })
+        Ok(holder)
     }
 
-    fn save_document(&self) -> Result<()> {
+    /// Write a snapshot of the document and drop the log, if the log has any update
+    fn save_document(&mut self) -> Result<()> {
+        if !*self.is_modified.lock().unwrap() {
+            return Ok(());
+        }
+        self.compact()
+    }
+
+    /// Fold the log into a new snapshot. The snapshot is replaced before the log is
+    /// removed, so a crash in between only replays updates the snapshot already has.
+    fn compact(&mut self) -> Result<()> {
         let data = self.get_data()?;
         let parent = self.path.parent().expect("Abnormal workspace structure");
         if !parent.exists() {
             std::fs::create_dir_all(parent)?;
         }
-        std::fs::write(&self.path, data)?;
+        write_atomically(&self.path, &data)?;
+        let log_path = self.log_path();
+        if log_path.exists() {
+            std::fs::remove_file(&log_path)?;
+        }
+        trace!(
+            "compacted document {} into {} bytes",
+            &self.path.to_string_lossy(),
+            data.len()
+        );
+        self.snapshot_len = data.len() as u64;
+        self.log_len = 0;
+        *self.is_modified.lock().unwrap() = false;
         Ok(())
     }
 
-    fn apply_change(&self, change: Update) -> Result<()> {
-        let mut txn = Transact::transact_mut(&self.doc);
+    /// Append an update to the log, in one write so a crash cuts off at most this record
+    fn append_to_log(&mut self, update: &[u8]) -> Result<()> {
+        let parent = self.path.parent().expect("Abnormal workspace structure");
+        if !parent.exists() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut record = Vec::with_capacity(RECORD_HEADER_SIZE + update.len());
+        record.extend_from_slice(&(update.len() as u32).to_le_bytes());
+        record.extend_from_slice(update);
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.log_path())?;
+        file.write_all(&record)?;
+        self.log_len += record.len() as u64;
+        Ok(())
+    }
+
+    fn apply_change(&mut self, data: &[u8]) -> Result<()> {
+        let change = Update::decode_v1(data)?;
+        {
+            let mut txn = Transact::transact_mut(&self.doc);
+            txn.apply_update(change)?;
+        }
         *self.is_modified.lock().unwrap() = true;
-        txn.apply_update(change)?;
+        self.append_to_log(data)?;
+        if self.log_len >= COMPACT_MIN_LOG_SIZE && self.log_len > self.snapshot_len {
+            self.compact()?;
+        }
         Ok(())
     }
 
@@ -72,66 +234,383 @@ impl DocumentHolder {
         let data = txn.encode_state_as_update_v1(&empty_state_vector);
         Ok(data)
     }
+    fn get_state_vector(&self) -> Vec<u8> {
+        let txn = Transact::transact(&self.doc);
+        txn.state_vector().encode_v1()
+    }
+    /// Encode the updates that a peer with `state_vector` is missing
+    fn get_diff(&self, state_vector: &[u8]) -> Result<Vec<u8>> {
+        let state_vector = StateVector::decode_v1(state_vector)?;
+        let txn = Transact::transact(&self.doc);
+        Ok(txn.encode_diff_v1(&state_vector))
+    }
+
     fn get_string(&self, name: &str) -> Result<String> {
         let text_ref = self.doc.get_or_insert_text(name);
         let txn = Transact::transact(&self.doc);
         let s = text_ref.get_string(&txn);
         Ok(s)
     }
+
+    /// Replace the text `name` with `content`, editing only the lines that differ and only
+    /// the part of them between their common prefix and suffix, so the cursors of the
+    /// editors outside of the edits stay in place
+    ///
+    /// # Returns
+    /// * `Result<Vec<u8>>` - The update of the edit, empty when nothing changed
+    fn replace_string(&mut self, name: &str, content: &str) -> Result<Vec<u8>> {
+        let text_ref = self.doc.get_or_insert_text(name);
+        let update = {
+            let mut txn = Transact::transact_mut(&self.doc);
+            let current = text_ref.get_string(&txn);
+            let old_lines: Vec<&str> = current.split_inclusive('\n').collect();
+            let new_lines: Vec<&str> = content.split_inclusive('\n').collect();
+            // Runs of changed lines as (offset in the current text, old text, new text)
+            let mut edits: Vec<(usize, String, String)> = Vec::new();
+            let (mut offset, mut i, mut j) = (0, 0, 0);
+            let mut is_editing = false;
+            for op in diff_sequences(&old_lines, &new_lines) {
+                if op == DiffOp::Equal {
+                    offset += old_lines[i].len();
+                    i += 1;
+                    j += 1;
+                    is_editing = false;
+                    continue;
+                }
+                if !is_editing {
+                    edits.push((offset, String::new(), String::new()));
+                    is_editing = true;
+                }
+                let edit = edits.last_mut().unwrap();
+                if op == DiffOp::Delete {
+                    edit.1.push_str(old_lines[i]);
+                    offset += old_lines[i].len();
+                    i += 1;
+                } else {
+                    edit.2.push_str(new_lines[j]);
+                    j += 1;
+                }
+            }
+            // From the end, so the offsets of the edits before stay valid
+            for (offset, old, new) in edits.iter().rev() {
+                let prefix = common_prefix_len(old, new);
+                let suffix = common_prefix_len(
+                    &old[prefix..].chars().rev().collect::<String>(),
+                    &new[prefix..].chars().rev().collect::<String>(),
+                );
+                let removed = old.len() - prefix - suffix;
+                if removed > 0 {
+                    text_ref.remove_range(&mut txn, (offset + prefix) as u32, removed as u32);
+                }
+                let inserted = &new[prefix..new.len() - suffix];
+                if !inserted.is_empty() {
+                    text_ref.insert(&mut txn, (offset + prefix) as u32, inserted);
+                }
+            }
+            txn.encode_update_v1()
+        };
+        if update != Update::EMPTY_V1 {
+            *self.is_modified.lock().unwrap() = true;
+            self.append_to_log(&update)?;
+        }
+        Ok(update)
+    }
+}
+
+/// Length in bytes of the common prefix of two strings, on a char boundary
+fn common_prefix_len(a: &str, b: &str) -> usize {
+    a.char_indices()
+        .zip(b.chars())
+        .find(|((_, x), y)| x != y)
+        .map(|((i, _), _)| i)
+        .unwrap_or_else(|| a.len().min(b.len()))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffOp {
+    Equal,
+    Insert,
+    Delete,
+}
+
+/// Diff two sequences by longest common subsequence, after trimming their common prefix
+/// and suffix
+///
+/// # Returns
+/// * `Vec<DiffOp>` - One op per item, `Equal` and `Delete` take the next item of `old`,
+///   `Equal` and `Insert` the next item of `new`
+pub fn diff_sequences<T: PartialEq>(old: &[T], new: &[T]) -> Vec<DiffOp> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    let mut ops = Vec::with_capacity(old.len().max(new.len()));
+    ops.extend(std::iter::repeat_n(DiffOp::Equal, prefix));
+    let (n, m) = (old_mid.len(), new_mid.len());
+    if (n + 1).saturating_mul(m + 1) > MAX_DIFF_CELLS {
+        ops.extend(std::iter::repeat_n(DiffOp::Delete, n));
+        ops.extend(std::iter::repeat_n(DiffOp::Insert, m));
+    } else {
+        // lcs[a * (m + 1) + b] is the LCS length of old_mid[a..] and new_mid[b..]
+        let mut lcs = vec![0u32; (n + 1) * (m + 1)];
+        for a in (0..n).rev() {
+            for b in (0..m).rev() {
+                lcs[a * (m + 1) + b] = if old_mid[a] == new_mid[b] {
+                    lcs[(a + 1) * (m + 1) + b + 1] + 1
+                } else {
+                    lcs[(a + 1) * (m + 1) + b].max(lcs[a * (m + 1) + b + 1])
+                };
+            }
+        }
+        let (mut a, mut b) = (0, 0);
+        while a < n || b < m {
+            if a < n && b < m && old_mid[a] == new_mid[b] {
+                ops.push(DiffOp::Equal);
+                a += 1;
+                b += 1;
+            } else if b == m || (a < n && lcs[(a + 1) * (m + 1) + b] >= lcs[a * (m + 1) + b + 1]) {
+                ops.push(DiffOp::Delete);
+                a += 1;
+            } else {
+                ops.push(DiffOp::Insert);
+                b += 1;
+            }
+        }
+    }
+    ops.extend(std::iter::repeat_n(DiffOp::Equal, suffix));
+    ops
 }
 
+/// Read the text `name` out of a state encoded as a v1 update, e.g. a snapshot
+pub fn string_of_state(data: &[u8], name: &str) -> Result<String> {
+    let doc = Doc::new();
+    let text_ref = doc.get_or_insert_text(name);
+    let mut txn = Transact::transact_mut(&doc);
+    txn.apply_update(Update::decode_v1(data)?)?;
+    Ok(text_ref.get_string(&txn))
+}
+
+/// Loaded documents. Editors hold a document with `manage` until they `release` it, other
+/// users only load it; documents nobody holds are evicted, least recently used first.
+/// Each document has a lock of its own, so writing one to disk doesn't stall the others.
 pub struct DocumentRepo {
-    docs: RwLock<HashMap<String, DocumentHolder>>,
+    docs: RwLock<HashMap<String, Arc<Mutex<DocumentHolder>>>>,
+    clock: AtomicU64,
+}
+
+impl Default for DocumentRepo {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl DocumentRepo {
     pub fn new() -> Self {
         Self {
             docs: RwLock::new(HashMap::new()),
+            clock: AtomicU64::new(0),
         }
     }
 
+    fn touch(&self, doc: &DocumentHolder) {
+        let tick = self.clock.fetch_add(1, Ordering::Relaxed) + 1;
+        doc.last_used.store(tick, Ordering::Relaxed);
+    }
+
+    /// A loaded document, the map is only locked while it is looked up
+    fn get(&self, doc_id: &str) -> Result<Arc<Mutex<DocumentHolder>>> {
+        self.docs
+            .read()
+            .unwrap()
+            .get(doc_id)
+            .cloned()
+            .ok_or(anyhow::anyhow!("Document {} not loaded", doc_id))
+    }
+
+    /// Load a document for an editor and hold it until the editor releases it
+    ///
+    /// # Returns
+    /// * `Result<Vec<u8>>` - The state of the document
     pub fn manage(&self, doc_id: String, filepath: PathBuf) -> Result<Vec<u8>> {
-        let doc = DocumentHolder::load_document(filepath)?;
-        let snapshot = doc.get_data()?;
-        self.docs.write().unwrap().insert(doc_id, doc);
-        Ok(snapshot)
+        // Read the files without the lock, the document may be loaded meanwhile
+        let loaded = if self.has(&doc_id) {
+            None
+        } else {
+            Some(DocumentHolder::load_document(filepath)?)
+        };
+        let doc = {
+            let mut docs = self.docs.write().unwrap();
+            let doc = match (docs.contains_key(&doc_id), loaded) {
+                (false, Some(loaded)) => docs
+                    .entry(doc_id)
+                    .or_insert_with(|| Arc::new(Mutex::new(loaded))),
+                _ => docs.get(&doc_id).ok_or(anyhow::anyhow!(
+                    "Document {} was evicted while loading",
+                    doc_id
+                ))?,
+            };
+            // Held before the map is unlocked, so it isn't evicted meanwhile
+            doc.lock().unwrap().refs += 1;
+            doc.clone()
+        };
+        let doc = doc.lock().unwrap();
+        self.touch(&doc);
+        doc.get_data()
+    }
+
+    /// Load a document without holding it, it may be evicted once other documents are used
+    pub fn load(&self, doc_id: String, filepath: PathBuf) -> Result<()> {
+        let loaded = DocumentHolder::load_document(filepath)?;
+        let mut docs = self.docs.write().unwrap();
+        let doc = docs
+            .entry(doc_id)
+            .or_insert_with(|| Arc::new(Mutex::new(loaded)));
+        if let Ok(doc) = doc.try_lock() {
+            self.touch(&doc);
+        }
+        self.evict_idle(&mut docs);
+        Ok(())
+    }
+
+    /// Release a document held by `manage`, an unknown document is already released
+    pub fn release(&self, doc_id: &str) {
+        let mut docs = self.docs.write().unwrap();
+        match docs.get(doc_id) {
+            Some(doc) => {
+                let mut doc = doc.lock().unwrap();
+                doc.refs = doc.refs.saturating_sub(1);
+                self.touch(&doc);
+            }
+            None => trace!("release document {} that is not loaded", doc_id),
+        }
+        self.evict_idle(&mut docs);
+    }
+
+    /// Drop the least recently used idle documents beyond the limit. Every change is in the
+    /// log already, the log is compacted before the document is dropped. A document that
+    /// is locked is in use, and not idle.
+    fn evict_idle(&self, docs: &mut HashMap<String, Arc<Mutex<DocumentHolder>>>) {
+        let mut idle: Vec<(u64, String)> = docs
+            .iter()
+            .filter_map(|(id, doc)| {
+                let doc = doc.try_lock().ok()?;
+                (doc.refs == 0).then(|| (doc.last_used.load(Ordering::Relaxed), id.clone()))
+            })
+            .collect();
+        if idle.len() <= MAX_IDLE_DOCUMENTS {
+            return;
+        }
+        let excess = idle.len() - MAX_IDLE_DOCUMENTS;
+        idle.sort_unstable();
+        for (_, doc_id) in idle.into_iter().take(excess) {
+            if let Some(doc) = docs.remove(&doc_id) {
+                if let Err(e) = doc.lock().unwrap().save_document() {
+                    warn!("failed to compact evicted document {}: {}", doc_id, e);
+                }
+                trace!("evicted document {}", doc_id);
+            }
+        }
+    }
+
+    /// Drop a deleted document without saving it, so it isn't written back to its files
+    pub fn remove(&self, doc_id: &str) {
+        self.docs.write().unwrap().remove(doc_id);
+    }
+
+    /// Number of editors that have the document open, 0 if it isn't loaded
+    pub fn refs(&self, doc_id: &str) -> usize {
+        self.get(doc_id).map_or(0, |doc| doc.lock().unwrap().refs)
     }
 
     pub fn has(&self, doc_id: &str) -> bool {
         self.docs.read().unwrap().contains_key(doc_id)
     }
 
+    /// Compact the log of every loaded document into its snapshot
     pub fn save_all(&self) -> Result<()> {
-        for doc in self.docs.read().unwrap().values() {
-            doc.save_document()?;
+        let docs: Vec<_> = self.docs.read().unwrap().values().cloned().collect();
+        for doc in docs {
+            doc.lock().unwrap().save_document()?;
         }
         Ok(())
     }
 
-    pub fn apply_change(&self, doc_id: &str, change: Vec<u8>) -> Result<()> {
-        let change = Update::decode_v1(&change)?;
-        let mut docs = self.docs.write().unwrap();
-        let doc = docs
-            .get_mut(doc_id)
-            .ok_or(anyhow::anyhow!("Document {} not loadded", doc_id))?;
+    /// Apply a change of an editor, the log is compacted when it outgrows the snapshot.
+    /// Only the document is locked meanwhile, not the others.
+    pub fn apply_change(&self, doc_id: &str, change: &[u8]) -> Result<()> {
+        let doc = self.get(doc_id)?;
+        let mut doc = doc.lock().unwrap();
         doc.apply_change(change)?;
-        doc.save_document()?;
+        self.touch(&doc);
         Ok(())
     }
 
     pub fn get_doc_data(&self, doc_id: &str) -> Result<Vec<u8>> {
-        let guard = self.docs.read().unwrap();
-        let doc = guard.get(doc_id).unwrap();
+        let doc = self.get(doc_id)?;
+        let doc = doc.lock().unwrap();
+        self.touch(&doc);
         doc.get_data()
     }
+    /// The state vector of a loaded document, encoded as v1
+    pub fn get_doc_state_vector(&self, doc_id: &str) -> Result<Vec<u8>> {
+        let doc = self.get(doc_id)?;
+        let doc = doc.lock().unwrap();
+        self.touch(&doc);
+        Ok(doc.get_state_vector())
+    }
+    /// The updates of a loaded document that a peer with `state_vector` is missing
+    ///
+    /// # Arguments
+    /// * `state_vector` - The state vector of the peer, encoded as v1
+    pub fn get_doc_diff(&self, doc_id: &str, state_vector: &[u8]) -> Result<Vec<u8>> {
+        let doc = self.get(doc_id)?;
+        let doc = doc.lock().unwrap();
+        self.touch(&doc);
+        doc.get_diff(state_vector)
+    }
+
     pub fn get_string_of_doc(&self, doc_id: &str, name: &str) -> Result<String> {
-        let guard = self.docs.read().unwrap();
-        let doc = guard
-            .get(doc_id)
-            .ok_or(anyhow::anyhow!("Document {} not loaded", doc_id))?;
+        let doc = self.get(doc_id)?;
+        let doc = doc.lock().unwrap();
+        self.touch(&doc);
         doc.get_string(name)
     }
+
+    /// Replace the text `name` of a loaded document, the edit is logged like any change
+    ///
+    /// # Returns
+    /// * `Result<Vec<u8>>` - The update for the editors of the document
+    pub fn replace_string_of_doc(
+        &self,
+        doc_id: &str,
+        name: &str,
+        content: &str,
+    ) -> Result<Vec<u8>> {
+        let doc = self.get(doc_id)?;
+        let mut doc = doc.lock().unwrap();
+        self.touch(&doc);
+        doc.replace_string(name, content)
+    }
+
+    /// Write the text `name` of a loaded document to `path`
+    ///
+    /// # Returns
+    /// * `Result<usize>` - The number of bytes written
+    pub fn write_string_of_doc(&self, doc_id: &str, name: &str, path: &Path) -> Result<usize> {
+        // Release the lock before touching the file system
+        let s = self.get_string_of_doc(doc_id, name)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, &s)?;
+        Ok(s.len())
+    }
 }
 
 impl AsRef<Doc> for DocumentHolder {
@@ -145,3 +624,202 @@ impl AsMut<Doc> for DocumentHolder {
         &mut self.doc
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert(doc: &Doc, text: &str) -> Vec<u8> {
+        let text_ref = doc.get_or_insert_text("content");
+        let mut txn = Transact::transact_mut(doc);
+        let len = text_ref.len(&txn);
+        text_ref.insert(&mut txn, len, text);
+        txn.encode_update_v1()
+    }
+
+    #[test]
+    fn test_update_log() {
+        let dir = std::env::temp_dir().join(format!("document-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("doc.bin");
+        let editor = Doc::new();
+
+        let repo = DocumentRepo::new();
+        repo.manage("doc".to_string(), path.clone()).unwrap();
+        repo.apply_change("doc", &insert(&editor, "hello")).unwrap();
+        repo.apply_change("doc", &insert(&editor, " world"))
+            .unwrap();
+        assert!(!path.exists());
+
+        // A crash in the middle of an append leaves a partial record
+        let log_path = with_suffix(&path, ".log");
+        let mut log = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&log_path)
+            .unwrap();
+        log.write_all(&[42, 0, 0, 0, 1]).unwrap();
+        drop(log);
+
+        let mut holder = DocumentHolder::load_document(path.clone()).unwrap();
+        assert_eq!(holder.get_string("content").unwrap(), "hello world");
+        holder.save_document().unwrap();
+        assert!(path.exists() && !log_path.exists());
+
+        let holder = DocumentHolder::load_document(path).unwrap();
+        assert_eq!(holder.get_string("content").unwrap(), "hello world");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_append_after_torn_log() {
+        let dir = std::env::temp_dir().join(format!("document-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("doc.bin");
+        let editor = Doc::new();
+        let repo = DocumentRepo::new();
+        repo.manage("doc".to_string(), path.clone()).unwrap();
+        repo.apply_change("doc", &insert(&editor, "hello")).unwrap();
+        drop(repo);
+
+        let log_path = with_suffix(&path, ".log");
+        let valid_len = std::fs::metadata(&log_path).unwrap().len();
+        let mut log = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&log_path)
+            .unwrap();
+        log.write_all(&[42, 0, 0, 0, 1]).unwrap();
+        drop(log);
+
+        let repo = DocumentRepo::new();
+        repo.manage("doc".to_string(), path.clone()).unwrap();
+        assert_eq!(std::fs::metadata(&log_path).unwrap().len(), valid_len);
+        repo.apply_change("doc", &insert(&editor, " world"))
+            .unwrap();
+        drop(repo);
+
+        let holder = DocumentHolder::load_document(path).unwrap();
+        assert_eq!(holder.get_string("content").unwrap(), "hello world");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_evict_idle_documents() {
+        let dir = std::env::temp_dir().join(format!("document-{}", uuid::Uuid::new_v4()));
+        let repo = DocumentRepo::new();
+        repo.manage("held".to_string(), dir.join("held.bin"))
+            .unwrap();
+        repo.load("first".to_string(), dir.join("first.bin"))
+            .unwrap();
+        repo.apply_change("first", &insert(&Doc::new(), "kept"))
+            .unwrap();
+        for i in 0..MAX_IDLE_DOCUMENTS {
+            repo.load(format!("doc{}", i), dir.join(format!("doc{}.bin", i)))
+                .unwrap();
+        }
+        // The least recently used idle document is compacted and dropped
+        assert!(!repo.has("first") && repo.has("held") && repo.has("doc0"));
+        let holder = DocumentHolder::load_document(dir.join("first.bin")).unwrap();
+        assert_eq!(holder.get_string("content").unwrap(), "kept");
+        assert!(dir.join("first.bin").exists());
+
+        // A released document is idle but recently used
+        repo.release("held");
+        repo.load("last".to_string(), dir.join("last.bin")).unwrap();
+        assert!(!repo.has("doc0") && repo.has("held") && repo.has("last"));
+        assert!(repo.get_doc_data("doc0").is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_diff_of_state_vector() {
+        let editor = Doc::new();
+        let mut holder = DocumentHolder {
+            path: std::env::temp_dir()
+                .join(format!("document-{}", uuid::Uuid::new_v4()))
+                .join("doc.bin"),
+            ..Default::default()
+        };
+        let peer = Doc::new();
+        let first = insert(&editor, "hello");
+        holder.apply_change(&first).unwrap();
+        Transact::transact_mut(&peer)
+            .apply_update(Update::decode_v1(&first).unwrap())
+            .unwrap();
+        holder.apply_change(&insert(&editor, " world")).unwrap();
+
+        let state_vector = Transact::transact(&peer).state_vector().encode_v1();
+        let diff = holder.get_diff(&state_vector).unwrap();
+        assert!(diff.len() < holder.get_data().unwrap().len());
+        Transact::transact_mut(&peer)
+            .apply_update(Update::decode_v1(&diff).unwrap())
+            .unwrap();
+        let text_ref = peer.get_or_insert_text("content");
+        assert_eq!(
+            text_ref.get_string(&Transact::transact(&peer)),
+            "hello world"
+        );
+        std::fs::remove_dir_all(holder.path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_replace_string() {
+        let editor = Doc::new();
+        let mut holder = DocumentHolder {
+            path: std::env::temp_dir()
+                .join(format!("document-{}", uuid::Uuid::new_v4()))
+                .join("doc.bin"),
+            ..Default::default()
+        };
+        holder
+            .apply_change(&insert(&editor, "int main() {}"))
+            .unwrap();
+        let snapshot = holder.get_data().unwrap();
+        holder.apply_change(&insert(&editor, " // 中文")).unwrap();
+
+        let text = string_of_state(&snapshot, "content").unwrap();
+        let update = holder.replace_string("content", &text).unwrap();
+        assert_eq!(holder.get_string("content").unwrap(), "int main() {}");
+        {
+            let mut txn = Transact::transact_mut(&editor);
+            txn.apply_update(Update::decode_v1(&update).unwrap())
+                .unwrap();
+        }
+        let text_ref = editor.get_or_insert_text("content");
+        assert_eq!(
+            text_ref.get_string(&Transact::transact(&editor)),
+            "int main() {}"
+        );
+        assert_eq!(
+            holder.replace_string("content", &text).unwrap(),
+            Update::EMPTY_V1
+        );
+
+        // Only the changed lines are edited, a concurrent edit between them survives
+        holder
+            .replace_string("content", "int a;\nint b;\nint c;\n")
+            .unwrap();
+        let editor = Doc::new();
+        let text_ref = editor.get_or_insert_text("content");
+        {
+            let mut txn = Transact::transact_mut(&editor);
+            txn.apply_update(Update::decode_v1(&holder.get_data().unwrap()).unwrap())
+                .unwrap();
+        }
+        let concurrent = {
+            let mut txn = Transact::transact_mut(&editor);
+            text_ref.insert(&mut txn, "int a;\nint b".len() as u32, "2");
+            txn.encode_update_v1()
+        };
+        let update = holder
+            .replace_string("content", "long a;\nint b;\nlong c;\n")
+            .unwrap();
+        holder.apply_change(&concurrent).unwrap();
+        {
+            let mut txn = Transact::transact_mut(&editor);
+            txn.apply_update(Update::decode_v1(&update).unwrap())
+                .unwrap();
+        }
+        let expected = "long a;\nint b2;\nlong c;\n";
+        assert_eq!(holder.get_string("content").unwrap(), expected);
+        assert_eq!(text_ref.get_string(&Transact::transact(&editor)), expected);
+        std::fs::remove_dir_all(holder.path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,296 @@
+/// Two-way sync of documents with files on disk, for editing a solution in another editor
+/// Each link remembers the hash of the text that both sides had at the last sync. A side
+/// whose text still has that hash is unchanged, so the other side wins; when both changed
+/// the link pauses until the user keeps one of them.
+use std::{
+    ffi::OsString,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, Result};
+use log::trace;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use specta::Type;
+
+use crate::{
+    document::{DocumentRepo, CONTENT_TEXT},
+    model::ExternalFile,
+};
+
+/// The side of a link whose text is kept
+#[derive(Debug, Serialize, Deserialize, Type, Clone, Copy, PartialEq, Eq)]
+pub enum SyncSide {
+    Document,
+    File,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SyncOutcome {
+    /// Neither side changed, or both changed to the same text
+    Unchanged,
+    /// The text of the document was written to the file
+    Written,
+    /// The text of the file was applied to the document
+    Read {
+        /// The update of the document for its editors
+        update: Vec<u8>,
+    },
+    /// Both sides changed since the last sync, the link is paused
+    Conflict,
+}
+
+pub fn text_hash(text: &str) -> String {
+    Sha256::digest(text.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Modification time and size of a file, a file whose stamp didn't change isn't read again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileStamp {
+    modified: SystemTime,
+    len: u64,
+}
+
+impl FileStamp {
+    /// The stamp of the file, `None` if it doesn't exist
+    fn of(path: &Path) -> Result<Option<Self>> {
+        match std::fs::metadata(path) {
+            Ok(metadata) => Ok(Some(Self {
+                modified: metadata.modified()?,
+                len: metadata.len(),
+            })),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// A document linked to a file, as the watcher tracks it
+#[derive(Debug)]
+pub struct LinkedFile {
+    pub document_id: String,
+    pub path: PathBuf,
+    /// The hash of the text at the last sync
+    pub synced_hash: Option<String>,
+    /// Both sides changed, or the file couldn't be read, until the user keeps a side
+    pub is_paused: bool,
+    stamp: Option<FileStamp>,
+    /// The hash of the file when it was read last, `None` if it doesn't exist
+    file_hash: Option<String>,
+}
+
+impl From<ExternalFile> for LinkedFile {
+    fn from(link: ExternalFile) -> Self {
+        Self {
+            document_id: link.document_id,
+            path: PathBuf::from(link.path),
+            synced_hash: link.synced_hash,
+            is_paused: false,
+            stamp: None,
+            file_hash: None,
+        }
+    }
+}
+
+impl LinkedFile {
+    /// Sync the document with the file once, the document must be loaded
+    ///
+    /// # Arguments
+    /// * `keep` - The side to keep whatever changed, `None` to pick the side that changed
+    ///   and pause on conflicts
+    pub fn sync(&mut self, repo: &DocumentRepo, keep: Option<SyncSide>) -> Result<SyncOutcome> {
+        if self.is_paused && keep.is_none() {
+            return Ok(SyncOutcome::Unchanged);
+        }
+        let text = repo.get_string_of_doc(&self.document_id, CONTENT_TEXT)?;
+        let hash = text_hash(&text);
+
+        let stamp = FileStamp::of(&self.path)?;
+        let mut file_text = None;
+        if stamp != self.stamp || keep.is_some() {
+            match std::fs::read_to_string(&self.path) {
+                Ok(content) => {
+                    self.file_hash = Some(text_hash(&content));
+                    file_text = Some(content);
+                }
+                Err(e) if e.kind() == ErrorKind::NotFound => self.file_hash = None,
+                Err(e) => {
+                    // A stale hash would let the document overwrite a file it never read
+                    self.is_paused = true;
+                    return Err(anyhow::anyhow!(
+                        "Failed to read {}: {}",
+                        self.path.to_string_lossy(),
+                        e
+                    ));
+                }
+            }
+            self.stamp = stamp;
+        }
+
+        let keep = match (keep, self.file_hash.as_deref()) {
+            (_, Some(file_hash)) if file_hash == hash => None,
+            // A missing file is created from the document
+            (_, None) => Some(SyncSide::Document),
+            (Some(keep), _) => Some(keep),
+            (None, file_hash) if file_hash == self.synced_hash.as_deref() => {
+                Some(SyncSide::Document)
+            }
+            (None, _) if self.synced_hash.as_deref() == Some(hash.as_str()) => Some(SyncSide::File),
+            (None, _) => {
+                trace!("{} and {:?} both changed", self.document_id, self.path);
+                self.is_paused = true;
+                return Ok(SyncOutcome::Conflict);
+            }
+        };
+        self.is_paused = false;
+        match keep {
+            None => {
+                self.synced_hash = Some(hash);
+                Ok(SyncOutcome::Unchanged)
+            }
+            Some(SyncSide::Document) => {
+                if let Some(parent) = self.path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                replace_file(&self.path, text.as_bytes())?;
+                trace!("wrote {} to {:?}", self.document_id, self.path);
+                self.stamp = FileStamp::of(&self.path)?;
+                self.file_hash = Some(hash.clone());
+                self.synced_hash = Some(hash);
+                Ok(SyncOutcome::Written)
+            }
+            Some(SyncSide::File) => {
+                let content = match file_text {
+                    Some(content) => content,
+                    None => std::fs::read_to_string(&self.path)?,
+                };
+                let update =
+                    repo.replace_string_of_doc(&self.document_id, CONTENT_TEXT, &content)?;
+                trace!("read {:?} into {}", self.path, self.document_id);
+                self.file_hash = Some(text_hash(&content));
+                self.synced_hash = self.file_hash.clone();
+                Ok(SyncOutcome::Read { update })
+            }
+        }
+    }
+}
+
+/// Replace a linked file with `data` through a hidden file next to it, so another editor
+/// that reads it meanwhile never sees half of it. A symlink is followed rather than
+/// replaced, and the file keeps its permissions.
+fn replace_file(path: &Path, data: &[u8]) -> Result<()> {
+    let path = match std::fs::canonicalize(path) {
+        Ok(path) => path,
+        Err(e) if e.kind() == ErrorKind::NotFound => path.to_path_buf(),
+        Err(e) => return Err(e.into()),
+    };
+    let permissions = match std::fs::metadata(&path) {
+        Ok(metadata) => Some(metadata.permissions()),
+        Err(e) if e.kind() == ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("{:?} is not a file", path))?;
+    let mut tmp_name = OsString::from(".");
+    tmp_name.push(name);
+    tmp_name.push(format!(".{}.tmp", uuid::Uuid::new_v4()));
+    let tmp = path.with_file_name(tmp_name);
+    let result = (|| -> Result<()> {
+        let mut file = std::fs::File::create(&tmp)?;
+        file.write_all(data)?;
+        if let Some(permissions) = permissions {
+            file.set_permissions(permissions)?;
+        }
+        file.sync_all()?;
+        std::fs::rename(&tmp, &path)?;
+        Ok(())
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sync_both_ways() {
+        let base = std::env::temp_dir().join(format!("external-{}", uuid::Uuid::new_v4()));
+        let file = base.join("src").join("main.cpp");
+        let repo = DocumentRepo::new();
+        repo.manage("doc".to_string(), base.join("doc.bin"))
+            .unwrap();
+        repo.replace_string_of_doc("doc", CONTENT_TEXT, "a\n")
+            .unwrap();
+        let mut link = LinkedFile::from(ExternalFile {
+            document_id: "doc".to_string(),
+            path: file.to_string_lossy().into_owned(),
+            synced_hash: None,
+            create_datetime: chrono::Local::now().naive_local(),
+        });
+        let text = || repo.get_string_of_doc("doc", CONTENT_TEXT).unwrap();
+
+        assert_eq!(link.sync(&repo, None).unwrap(), SyncOutcome::Written);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "a\n");
+        assert_eq!(link.sync(&repo, None).unwrap(), SyncOutcome::Unchanged);
+
+        std::fs::write(&file, "a\nb\n").unwrap();
+        assert!(matches!(
+            link.sync(&repo, None).unwrap(),
+            SyncOutcome::Read { .. }
+        ));
+        assert_eq!(text(), "a\nb\n");
+
+        repo.replace_string_of_doc("doc", CONTENT_TEXT, "x\nb\n")
+            .unwrap();
+        assert_eq!(link.sync(&repo, None).unwrap(), SyncOutcome::Written);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "x\nb\n");
+
+        repo.replace_string_of_doc("doc", CONTENT_TEXT, "y\nb\n")
+            .unwrap();
+        std::fs::write(&file, "x\nb\nc\n").unwrap();
+        assert_eq!(link.sync(&repo, None).unwrap(), SyncOutcome::Conflict);
+        assert_eq!(link.sync(&repo, None).unwrap(), SyncOutcome::Unchanged);
+        assert_eq!(text(), "y\nb\n");
+        assert!(matches!(
+            link.sync(&repo, Some(SyncSide::File)).unwrap(),
+            SyncOutcome::Read { .. }
+        ));
+        assert_eq!(text(), "x\nb\nc\n");
+        assert!(!link.is_paused);
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_replace_file_keeps_symlink_and_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let base = std::env::temp_dir().join(format!("external-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&base).unwrap();
+        let target = base.join("main.cpp");
+        let link = base.join("link.cpp");
+        std::fs::write(&target, "a\n").unwrap();
+        std::fs::set_permissions(&target, std::fs::Permissions::from_mode(0o600)).unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        replace_file(&link, b"b\n").unwrap();
+        assert!(std::fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "b\n");
+        let mode = std::fs::metadata(&target).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(std::fs::read_dir(&base).unwrap().count(), 2);
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -4,10 +4,12 @@ use specta_typescript::{formatter, BigIntExportBehavior};
 use tauri::Manager;
 use tauri_specta::{collect_commands, collect_events, Builder};
 
+pub mod collab;
 pub mod commands;
 pub mod config;
 pub mod database;
 pub mod document;
+pub mod external;
 pub mod model;
 pub mod runner;
 pub mod schema;
@@ -20,7 +22,11 @@ pub fn run() {
         .events(collect_events![
             commands::ProgramConfigUpdateEvent,
             commands::database::WorkspaceConfigUpdateEvent,
-            commands::runner::LanguageServerEvent,
+            commands::database::DocumentUpdateEvent,
+            commands::storage::DocumentStorageEvent,
+            commands::collab::CollabSessionEndEvent,
+            commands::external::ExternalFileConflictEvent,
+            commands::lang_server::LanguageServerEvent,
             commands::runner::ProgramOutputEvent,
         ])
         .commands(collect_commands![
@@ -29,6 +35,7 @@ pub fn run() {
             commands::set_prog_config::<tauri::Wry>,
             commands::database::get_problems,
             commands::database::get_problem,
+            commands::database::get_problem_dir,
             commands::database::create_problem,
             commands::database::create_solution,
             commands::database::create_checker,
@@ -40,20 +47,49 @@ pub fn run() {
             commands::database::create_testcase,
             commands::database::delete_testcase,
             commands::database::get_testcases,
+            commands::database::collect_garbage,
             commands::database::get_workspace_config,
             commands::database::set_workspace_config::<tauri::Wry>,
             commands::database::get_string_of_doc,
             // TODO: cataloging
             commands::database::load_document,
-            commands::database::apply_change,
+            commands::database::unload_document,
+            commands::database::apply_change::<tauri::Wry>,
+            commands::database::sync_document,
             commands::database::resolve_checker,
+            commands::history::create_document_snapshot,
+            commands::history::list_document_snapshots,
+            commands::history::get_snapshot_text,
+            commands::history::diff_document_snapshots,
+            commands::history::restore_document_snapshot::<tauri::Wry>,
+            commands::collab::start_collab_server,
+            commands::collab::get_collab_server,
+            commands::collab::stop_collab_server,
+            commands::collab::join_collab_session::<tauri::Wry>,
+            commands::collab::get_collab_sessions,
+            commands::collab::leave_collab_session,
+            commands::external::link_external_file::<tauri::Wry>,
+            commands::external::unlink_external_file,
+            commands::external::get_external_file,
+            commands::external::resolve_external_file_conflict::<tauri::Wry>,
+            commands::storage::get_document_preview,
+            commands::storage::import_raw_document::<tauri::Wry>,
+            commands::storage::convert_raw_document::<tauri::Wry>,
             commands::runner::get_checkers_name,
-            commands::runner::launch_language_server,
-            commands::runner::kill_language_server,
-            commands::runner::send_message_to_language_server,
+            commands::lang_server::launch_language_server,
+            commands::lang_server::kill_language_server,
+            commands::lang_server::send_message_to_language_server,
+            commands::lang_server::get_language_server_log,
             commands::runner::execute_program_callback,
             commands::runner::write_file_to_task_tag,
-            commands::runner::execute_program
+            commands::runner::write_doc_to_task_tag,
+            commands::runner::get_doc_file,
+            commands::runner::get_doc_hash,
+            commands::runner::execute_program,
+            commands::runner::benchmark_program,
+            commands::runner::diff_output,
+            commands::runner::get_diff_page,
+            commands::runner::detect_toolchains
         ]);
 
     #[cfg(debug_assertions)]
@@ -81,10 +117,24 @@ pub fn run() {
             setup::setup_document_repo(app)?;
             setup::setup_decorum(app)?;
 
-            app.manage(commands::runner::LangServerState::default());
+            app.manage(commands::lang_server::LangServerState::default());
+            app.manage(commands::history::SnapshotTimer::default());
+            app.manage(commands::collab::CollabState::new(app.handle().clone()));
+            commands::external::watch_external_files(app.handle().clone())?;
 
             Ok(())
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::Exit = event {
+                // Let language servers save their state, kill_on_drop is only the fallback
+                let state = app.state::<commands::lang_server::LangServerState>();
+                tauri::async_runtime::block_on(state.shutdown_all());
+                // Fold the update logs into snapshots, so the next start doesn't replay them
+                if let Err(e) = app.state::<document::DocumentRepo>().save_all() {
+                    log::warn!("failed to save documents: {}", e);
+                }
+            }
+        });
 }
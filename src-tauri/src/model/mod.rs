@@ -1,6 +1,11 @@
 use chrono::NaiveDateTime;
+use diesel::backend::Backend;
+use diesel::deserialize::FromSql;
 use diesel::prelude::*;
-use diesel::Selectable;
+use diesel::serialize::{IsNull, Output, ToSql};
+use diesel::sql_types::Text;
+use diesel::sqlite::Sqlite;
+use diesel::{AsExpression, FromSqlRow, Selectable};
 use serde::{Deserialize, Serialize};
 use specta::Type;
 
@@ -22,7 +27,6 @@ use specta::Type;
 #[derive(Debug, Serialize, Deserialize, Queryable, Identifiable, Type)]
 #[diesel(table_name = crate::schema::problems)]
 #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
-
 pub struct Problem {
     pub id: String,
     pub name: String,
@@ -54,7 +58,6 @@ pub struct ProblemChangeset {
 #[diesel(table_name = crate::schema::solutions)]
 #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
 #[diesel(belongs_to(Problem))]
-
 pub struct Solution {
     pub id: String,
     pub author: String,
@@ -104,7 +107,6 @@ pub struct SolutionRow {
 #[derive(Debug, Serialize, Deserialize, Queryable, Identifiable, Type)]
 #[diesel(table_name = crate::schema::checker)]
 #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
-
 pub struct Checker {
     pub id: String,
     pub name: String,
@@ -117,21 +119,141 @@ pub struct Checker {
 #[derive(Debug, Serialize, Deserialize, Queryable, Identifiable, Selectable, Type, Insertable)]
 #[diesel(table_name = crate::schema::documents)]
 #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
-
 pub struct Document {
     pub id: String,
     pub create_datetime: NaiveDateTime,
     pub modified_datetime: NaiveDateTime,
     pub filename: String,
+    pub storage: DocumentStorage,
+    /// Size of a raw file in bytes, `None` for Yrs documents
+    pub size: Option<i64>,
+}
+
+/// How the content of a document is kept in the doc folder
+#[derive(
+    Debug, Serialize, Deserialize, Type, Clone, Copy, PartialEq, Eq, AsExpression, FromSqlRow,
+)]
+#[diesel(sql_type = diesel::sql_types::Text)]
+pub enum DocumentStorage {
+    /// A Yrs document that editors load and edit together
+    Yrs,
+    /// The text as a plain file, for testcases too large to edit. It is never loaded
+    /// whole, the runner reads it from disk.
+    Raw,
+}
+
+impl DocumentStorage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DocumentStorage::Yrs => "Yrs",
+            DocumentStorage::Raw => "Raw",
+        }
+    }
+}
+
+impl std::str::FromStr for DocumentStorage {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Yrs" => Ok(DocumentStorage::Yrs),
+            "Raw" => Ok(DocumentStorage::Raw),
+            _ => Err(anyhow::anyhow!("unknown document storage: {}", s)),
+        }
+    }
+}
+
+impl ToSql<Text, Sqlite> for DocumentStorage {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> diesel::serialize::Result {
+        out.set_value(self.as_str());
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, Sqlite> for DocumentStorage {
+    fn from_sql(bytes: <Sqlite as Backend>::RawValue<'_>) -> diesel::deserialize::Result<Self> {
+        let value = <String as FromSql<Text, Sqlite>>::from_sql(bytes)?;
+        Ok(value.parse()?)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Queryable, Identifiable, Type, Selectable, Insertable)]
 #[diesel(table_name = crate::schema::test_cases)]
 #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
-
 pub struct TestCase {
     pub id: String,
     pub problem_id: String,
     pub input_document_id: String,
     pub answer_document_id: String,
 }
+
+/// What took a snapshot of a document
+#[derive(Debug, Serialize, Deserialize, Type, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotKind {
+    /// Taken by the user, usually with a label
+    Manual,
+    /// Taken before a judge run
+    Run,
+    /// Taken while editing, once per interval
+    Interval,
+    /// The state replaced by a restore
+    Restore,
+}
+
+impl SnapshotKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SnapshotKind::Manual => "Manual",
+            SnapshotKind::Run => "Run",
+            SnapshotKind::Interval => "Interval",
+            SnapshotKind::Restore => "Restore",
+        }
+    }
+}
+
+impl std::str::FromStr for SnapshotKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Manual" => Ok(SnapshotKind::Manual),
+            "Run" => Ok(SnapshotKind::Run),
+            "Interval" => Ok(SnapshotKind::Interval),
+            "Restore" => Ok(SnapshotKind::Restore),
+            _ => Err(anyhow::anyhow!("unknown snapshot kind: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Type, Clone)]
+pub struct DocumentSnapshot {
+    pub id: String,
+    pub document_id: String,
+    pub label: Option<String>,
+    pub kind: SnapshotKind,
+    pub create_datetime: NaiveDateTime,
+}
+
+/// A file on disk that a document is kept in sync with
+#[derive(Debug, Queryable, Selectable, Insertable, Clone)]
+#[diesel(table_name = crate::schema::external_files)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct ExternalFile {
+    pub document_id: String,
+    pub path: String,
+    /// SHA-256 of the text at the last sync in hex, `None` before the first sync
+    pub synced_hash: Option<String>,
+    pub create_datetime: NaiveDateTime,
+}
+
+#[derive(Debug, Queryable, Selectable, Insertable)]
+#[diesel(table_name = crate::schema::document_snapshots)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct DocumentSnapshotRow {
+    pub id: String,
+    pub document_id: String,
+    pub label: Option<String>,
+    pub kind: String,
+    pub create_datetime: NaiveDateTime,
+    pub filename: String,
+}
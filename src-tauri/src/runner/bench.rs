@@ -0,0 +1,429 @@
+/// Benchmark runner
+/// This module runs a compiled program repeatedly on the same input and collects
+/// CPU time, wall time and peak memory of every run, so that the result can be
+/// compared with the time limit of the problem.
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    time::Instant,
+};
+
+use anyhow::Result;
+use log::trace;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use crate::runner::command_flag_hide_new_console;
+
+/// A run whose maximum CPU time exceeds this ratio of the time limit is reported as `Tight`
+pub const TIGHT_TIME_LIMIT_RATIO: f64 = 0.8;
+
+/// Resource usage of a single run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+pub struct RunMeasurement {
+    pub exit_code: i32,
+    pub is_timeout: bool,
+    pub wall_millis: f64,
+    pub cpu_millis: f64,
+    /// Peak resident memory in kilobytes
+    pub peak_memory_kb: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+pub struct TimingStatistics {
+    pub min: f64,
+    pub median: f64,
+    pub max: f64,
+    pub stddev: f64,
+}
+
+impl TimingStatistics {
+    /// Compute statistics of the samples, the standard deviation is the sample one (n - 1)
+    pub fn from_samples(samples: &[f64]) -> Self {
+        if samples.is_empty() {
+            return Self {
+                min: 0.0,
+                median: 0.0,
+                max: 0.0,
+                stddev: 0.0,
+            };
+        }
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let n = sorted.len();
+        let median = if n % 2 == 1 {
+            sorted[n / 2]
+        } else {
+            (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0
+        };
+        let mean = sorted.iter().sum::<f64>() / n as f64;
+        let stddev = if n > 1 {
+            (sorted.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1) as f64).sqrt()
+        } else {
+            0.0
+        };
+        Self {
+            min: sorted[0],
+            median,
+            max: sorted[n - 1],
+            stddev,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum TimeLimitStatus {
+    /// Every run finished well under the time limit
+    Safe,
+    /// The slowest run is under the time limit, but above `TIGHT_TIME_LIMIT_RATIO` of it
+    Tight,
+    /// At least one run exceeded the time limit
+    Exceeded,
+}
+
+impl TimeLimitStatus {
+    pub fn classify(runs: &[RunMeasurement], time_limit_millis: f64) -> Self {
+        let max_cpu = runs.iter().map(|r| r.cpu_millis).fold(0.0, f64::max);
+        if runs.iter().any(|r| r.is_timeout) || max_cpu > time_limit_millis {
+            TimeLimitStatus::Exceeded
+        } else if max_cpu > time_limit_millis * TIGHT_TIME_LIMIT_RATIO {
+            TimeLimitStatus::Tight
+        } else {
+            TimeLimitStatus::Safe
+        }
+    }
+}
+
+/// Benchmark result of one input file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+pub struct BenchmarkReport {
+    pub input_file: PathBuf,
+    pub runs: Vec<RunMeasurement>,
+    pub cpu_time: TimingStatistics,
+    pub wall_time: TimingStatistics,
+    /// Maximum of the peak memory of all runs, in kilobytes
+    pub peak_memory_kb: u32,
+    pub time_limit: i32,
+    pub status: TimeLimitStatus,
+}
+
+impl BenchmarkReport {
+    pub fn new(input_file: PathBuf, runs: Vec<RunMeasurement>, time_limit: i32) -> Self {
+        let cpu = runs.iter().map(|r| r.cpu_millis).collect::<Vec<_>>();
+        let wall = runs.iter().map(|r| r.wall_millis).collect::<Vec<_>>();
+        let peak_memory_kb = runs.iter().map(|r| r.peak_memory_kb).max().unwrap_or(0);
+        let status = TimeLimitStatus::classify(&runs, time_limit as f64);
+        Self {
+            input_file,
+            cpu_time: TimingStatistics::from_samples(&cpu),
+            wall_time: TimingStatistics::from_samples(&wall),
+            runs,
+            peak_memory_kb,
+            time_limit,
+            status,
+        }
+    }
+}
+
+/// Run the program `runs` times on every input file
+///
+/// This function blocks, call it with `tokio::task::spawn_blocking`.
+///
+/// # Arguments
+/// * `build_command` - Create the command to run for an input file, it is called once per run
+/// * `inputs` - Pairs of input file and the file to write the program output into
+/// * `runs` - How many times the program runs on each input
+/// * `timeout_millis` - Kill the program after this time
+/// * `time_limit` - The time limit of the problem in milliseconds
+pub fn benchmark_program<F: Fn(&Path) -> Result<Command>>(
+    build_command: F,
+    inputs: &[(PathBuf, PathBuf)],
+    runs: u32,
+    timeout_millis: u64,
+    time_limit: i32,
+) -> Result<Vec<BenchmarkReport>> {
+    if runs == 0 {
+        return Err(anyhow::anyhow!("Benchmark needs at least one run"));
+    }
+    let mut reports = Vec::with_capacity(inputs.len());
+    for (input, output_file) in inputs {
+        let mut measurements = Vec::with_capacity(runs as usize);
+        for i in 0..runs {
+            let measurement =
+                launch_program_measured(build_command(input)?, input, output_file, timeout_millis)?;
+            trace!(
+                "benchmark {:?} run {}/{}: {:?}",
+                input,
+                i + 1,
+                runs,
+                &measurement
+            );
+            measurements.push(measurement);
+        }
+        reports.push(BenchmarkReport::new(
+            input.clone(),
+            measurements,
+            time_limit,
+        ));
+    }
+    Ok(reports)
+}
+
+/// Launch the program with the input file as stdin and the output file as stdout, then
+/// wait for it and collect its resource usage. Stderr is discarded.
+///
+/// This function blocks until the program exits or is killed after `timeout_millis`.
+pub fn launch_program_measured<P: AsRef<Path>, S: AsRef<Path>>(
+    mut cmd: Command,
+    input: P,
+    output_file: S,
+    timeout_millis: u64,
+) -> Result<RunMeasurement> {
+    command_flag_hide_new_console(&mut cmd);
+    if let Some(p) = output_file.as_ref().parent() {
+        if !p.exists() {
+            std::fs::create_dir_all(p)?;
+        }
+    }
+    cmd.stdin(Stdio::from(File::open(input.as_ref())?))
+        .stdout(Stdio::from(File::create(output_file.as_ref())?))
+        .stderr(Stdio::null());
+    trace!("spawn measured program: {:?}", &cmd);
+    let start_time = Instant::now();
+    let child = cmd.spawn()?;
+    wait_measured(child, timeout_millis, start_time)
+}
+
+#[cfg(unix)]
+fn wait_measured(child: Child, timeout_millis: u64, start_time: Instant) -> Result<RunMeasurement> {
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Condvar, Mutex,
+        },
+        time::Duration,
+    };
+
+    let pid = child.id() as libc::pid_t;
+    let finished = Arc::new((Mutex::new(false), Condvar::new()));
+    let is_timeout = Arc::new(AtomicBool::new(false));
+
+    let watchdog = {
+        let finished = Arc::clone(&finished);
+        let is_timeout = Arc::clone(&is_timeout);
+        std::thread::spawn(move || {
+            let (lock, cvar) = &*finished;
+            let guard = lock.lock().unwrap();
+            let (guard, _) = cvar
+                .wait_timeout_while(guard, Duration::from_millis(timeout_millis), |done| !*done)
+                .unwrap();
+            if !*guard {
+                trace!("timeout! kill process {}", pid);
+                is_timeout.store(true, Ordering::SeqCst);
+                unsafe {
+                    libc::kill(pid, libc::SIGKILL);
+                }
+            }
+        })
+    };
+
+    // Wait for the exit without reaping the child, so the watchdog never signals a recycled pid
+    let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+    loop {
+        let ret = unsafe {
+            libc::waitid(
+                libc::P_PID,
+                pid as libc::id_t,
+                &mut info,
+                libc::WEXITED | libc::WNOWAIT,
+            )
+        };
+        if ret == 0 {
+            break;
+        }
+        let err = std::io::Error::last_os_error();
+        if err.kind() != std::io::ErrorKind::Interrupted {
+            return Err(err.into());
+        }
+    }
+    let wall = start_time.elapsed();
+    {
+        let (lock, cvar) = &*finished;
+        *lock.lock().unwrap() = true;
+        cvar.notify_all();
+    }
+    watchdog
+        .join()
+        .map_err(|_| anyhow::anyhow!("Benchmark watchdog panicked"))?;
+
+    let mut status = 0;
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::wait4(pid, &mut status, 0, &mut usage) } < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    // The child is reaped by hand, dropping it does not wait again
+    drop(child);
+
+    let exit_code = if libc::WIFEXITED(status) {
+        libc::WEXITSTATUS(status)
+    } else {
+        -1
+    };
+    let timeval_millis = |t: libc::timeval| t.tv_sec as f64 * 1000.0 + t.tv_usec as f64 / 1000.0;
+    // ru_maxrss is in bytes on macOS and in kilobytes elsewhere
+    #[cfg(target_os = "macos")]
+    let peak_memory_kb = usage.ru_maxrss as u64 / 1024;
+    #[cfg(not(target_os = "macos"))]
+    let peak_memory_kb = usage.ru_maxrss as u64;
+
+    Ok(RunMeasurement {
+        exit_code,
+        is_timeout: is_timeout.load(Ordering::SeqCst),
+        wall_millis: wall.as_secs_f64() * 1000.0,
+        cpu_millis: timeval_millis(usage.ru_utime) + timeval_millis(usage.ru_stime),
+        peak_memory_kb: peak_memory_kb.min(u32::MAX as u64) as u32,
+    })
+}
+
+#[cfg(windows)]
+fn wait_measured(
+    mut child: Child,
+    timeout_millis: u64,
+    start_time: Instant,
+) -> Result<RunMeasurement> {
+    use std::os::windows::io::AsRawHandle;
+    use windows_sys::Win32::{
+        Foundation::{FILETIME, HANDLE, WAIT_TIMEOUT},
+        System::{
+            ProcessStatus::{K32GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS},
+            Threading::{GetProcessTimes, WaitForSingleObject, INFINITE},
+        },
+    };
+
+    let handle = child.as_raw_handle() as HANDLE;
+    let wait_millis = u32::try_from(timeout_millis)
+        .unwrap_or(INFINITE - 1)
+        .min(INFINITE - 1);
+    let is_timeout = unsafe { WaitForSingleObject(handle, wait_millis) } == WAIT_TIMEOUT;
+    if is_timeout {
+        trace!("timeout! kill process {}", child.id());
+        child.kill()?;
+    }
+    let status = child.wait()?;
+    let wall = start_time.elapsed();
+
+    let filetime_millis = |t: FILETIME| {
+        (((t.dwHighDateTime as u64) << 32) | t.dwLowDateTime as u64) as f64 / 10_000.0
+    };
+    let mut creation: FILETIME = unsafe { std::mem::zeroed() };
+    let mut exit: FILETIME = unsafe { std::mem::zeroed() };
+    let mut kernel: FILETIME = unsafe { std::mem::zeroed() };
+    let mut user: FILETIME = unsafe { std::mem::zeroed() };
+    if unsafe { GetProcessTimes(handle, &mut creation, &mut exit, &mut kernel, &mut user) } == 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+
+    let mut counters: PROCESS_MEMORY_COUNTERS = unsafe { std::mem::zeroed() };
+    counters.cb = std::mem::size_of::<PROCESS_MEMORY_COUNTERS>() as u32;
+    if unsafe { K32GetProcessMemoryInfo(handle, &mut counters, counters.cb) } == 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+
+    Ok(RunMeasurement {
+        exit_code: status.code().unwrap_or(-1),
+        is_timeout,
+        wall_millis: wall.as_secs_f64() * 1000.0,
+        cpu_millis: filetime_millis(kernel) + filetime_millis(user),
+        peak_memory_kb: (counters.PeakWorkingSetSize / 1024).min(u32::MAX as usize) as u32,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn measurement(cpu_millis: f64, is_timeout: bool) -> RunMeasurement {
+        RunMeasurement {
+            exit_code: 0,
+            is_timeout,
+            wall_millis: cpu_millis,
+            cpu_millis,
+            peak_memory_kb: 1024,
+        }
+    }
+
+    #[test]
+    fn test_statistics_odd() {
+        let stats = TimingStatistics::from_samples(&[3.0, 1.0, 2.0]);
+        assert_eq!(stats.min, 1.0);
+        assert_eq!(stats.median, 2.0);
+        assert_eq!(stats.max, 3.0);
+        assert!((stats.stddev - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_statistics_even() {
+        let stats = TimingStatistics::from_samples(&[4.0, 1.0, 2.0, 3.0]);
+        assert_eq!(stats.median, 2.5);
+    }
+
+    #[test]
+    fn test_statistics_single() {
+        let stats = TimingStatistics::from_samples(&[5.0]);
+        assert_eq!(stats.median, 5.0);
+        assert_eq!(stats.stddev, 0.0);
+    }
+
+    #[test]
+    fn test_time_limit_status() {
+        let safe = vec![measurement(100.0, false), measurement(200.0, false)];
+        assert_eq!(
+            TimeLimitStatus::classify(&safe, 1000.0),
+            TimeLimitStatus::Safe
+        );
+        let tight = vec![measurement(100.0, false), measurement(900.0, false)];
+        assert_eq!(
+            TimeLimitStatus::classify(&tight, 1000.0),
+            TimeLimitStatus::Tight
+        );
+        let exceeded = vec![measurement(1100.0, false)];
+        assert_eq!(
+            TimeLimitStatus::classify(&exceeded, 1000.0),
+            TimeLimitStatus::Exceeded
+        );
+        let timeout = vec![measurement(10.0, true)];
+        assert_eq!(
+            TimeLimitStatus::classify(&timeout, 1000.0),
+            TimeLimitStatus::Exceeded
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_launch_program_measured() {
+        let dir = crate::runner::temp_dir("bench-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("input.txt");
+        let output = dir.join("output.txt");
+        std::fs::write(&input, "hello\n").unwrap();
+        let result = launch_program_measured(Command::new("cat"), &input, &output, 5000).unwrap();
+        assert_eq!(result.exit_code, 0);
+        assert!(!result.is_timeout);
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), "hello\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_launch_program_measured_timeout() {
+        let dir = crate::runner::temp_dir("bench-test-timeout");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("input.txt");
+        std::fs::write(&input, "").unwrap();
+        let mut cmd = Command::new("sleep");
+        cmd.arg("10");
+        let result = launch_program_measured(cmd, &input, dir.join("output.txt"), 200).unwrap();
+        assert!(result.is_timeout);
+        assert!(result.wall_millis < 5000.0);
+    }
+}
@@ -1,45 +1,131 @@
+//! Command line parsing and variable templating
+//!
+//! A command is split into arguments like a shell does: whitespace separates
+//! arguments, double quotes allow `\"` and `\\` escapes, single quotes are literal.
+//! Variables are then expanded inside each argument:
+//!
+//! * `%NAME` or `%{NAME}` - the value of `NAME`, it is an error if it is not defined
+//! * `%{NAME:-default}` - the value of `NAME`, or `default` if it is not defined
+//! * `%%` - a literal `%`
+//!
+//! A name starts with a letter and is followed by letters, digits or underscores.
+//! The built-in variables are listed below, together with `BUNDLED_LSP`.
+//!
+//! Some commands, like the compile command, may also be a sequence of steps. They are
+//! run one by one without a system shell, and the output of each step is kept apart:
+//!
+//! * `a && b` - run `b` after `a` if `a` exits with 0
+//! * `< file` - read stdin from `file`
+//! * `> file`, `>> file` - write or append stdout to `file`
+//! * `2> file`, `2>> file` - write or append stderr to `file`
+//!
+//! Quote an operator to pass it as a normal argument.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::process::Command;
-use std::{collections::HashMap, path::PathBuf};
 
 use nom::{
     branch::alt,
-    bytes::complete::{escaped, is_not, take_while1},
-    character::complete::{alpha1, alphanumeric1, char, multispace0, multispace1},
-    combinator::{map, recognize},
-    multi::{many0, separated_list0},
-    sequence::delimited,
+    bytes::complete::{escaped, is_not, tag, take_while, take_while1},
+    character::complete::{alpha1, alphanumeric1, char, multispace0},
+    combinator::{map, not, opt, recognize},
+    multi::{many0, many1},
+    sequence::{delimited, preceded, terminated},
     IResult, Parser,
 };
 
+/// Directory of the task, programs are launched in it
+pub const VAR_CWD: &str = "CWD";
+/// Source file of the solution
+pub const VAR_SRC: &str = "SRC";
+/// Executable produced by the compiler, `%CWD/main` unless set by the caller
+pub const VAR_BIN: &str = "BIN";
+/// Input file of the run
+pub const VAR_INPUT: &str = "INPUT";
+/// Directory shared by all tasks of a problem
+pub const VAR_PROBLEM_DIR: &str = "PROBLEM_DIR";
+/// Free local port picked for a language server connected over WebSocket
+pub const VAR_PORT: &str = "PORT";
+
 /// Parse a command string into a Command object (no env substitution here)
 /// Uses a nom-based lexer to handle quotes and escaping similar to common shells.
 pub fn parse_command(command: &str, _env: &HashMap<String, String>) -> Result<Command, String> {
-    let (_, parts) =
-        command_parser(command).map_err(|e| format!("Failed to parse command: {:?}", e))?;
-
-    if parts.is_empty() {
-        return Err("Empty command".to_string());
-    }
+    let steps = split_steps(command, |s| Ok(s.to_string()))?;
+    into_single_command(steps)
+}
 
-    let mut cmd = Command::new(&parts[0]);
-    if parts.len() > 1 {
-        cmd.args(&parts[1..]);
-    }
+/// An operator between or inside the steps of a command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    /// `&&`
+    And,
+    /// `<`
+    RedirectIn,
+    /// `>`
+    RedirectOut,
+    /// `>>`
+    AppendOut,
+    /// `2>`
+    RedirectErr,
+    /// `2>>`
+    AppendErr,
+}
 
-    Ok(cmd)
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word {
+        text: String,
+        /// Whether a part of the word is quoted, a quoted word is kept when it expands to
+        /// nothing
+        quoted: bool,
+    },
+    Operator(Operator),
 }
 
-/// Parse the whole command into whitespace-separated arguments, respecting quotes.
-fn command_parser(input: &str) -> IResult<&str, Vec<String>> {
-    let (input, _) = multispace0.parse(input)?;
-    let (input, parts) = separated_list0(multispace1, argument_parser).parse(input)?;
+/// Parse the whole command into tokens, respecting quotes.
+fn command_parser(input: &str) -> IResult<&str, Vec<Token>> {
+    let (input, tokens) = many0(preceded(
+        multispace0,
+        alt((
+            map(operator_parser, Token::Operator),
+            map(argument_parser, |(text, quoted)| Token::Word {
+                text,
+                quoted,
+            }),
+        )),
+    ))
+    .parse(input)?;
     let (input, _) = multispace0.parse(input)?;
-    Ok((input, parts))
+    Ok((input, tokens))
+}
+
+fn operator_parser(input: &str) -> IResult<&str, Operator> {
+    alt((
+        map(tag("&&"), |_| Operator::And),
+        map(tag("2>>"), |_| Operator::AppendErr),
+        map(tag("2>"), |_| Operator::RedirectErr),
+        map(tag(">>"), |_| Operator::AppendOut),
+        map(tag(">"), |_| Operator::RedirectOut),
+        map(tag("<"), |_| Operator::RedirectIn),
+    ))
+    .parse(input)
 }
 
-/// Parse a single argument: either quoted or a bare word.
-fn argument_parser(input: &str) -> IResult<&str, String> {
-    alt((quoted_argument, simple_argument)).parse(input)
+/// Parse a single argument: quoted parts and bare words next to each other form one argument.
+/// Also returns whether any part is quoted.
+fn argument_parser(input: &str) -> IResult<&str, (String, bool)> {
+    map(
+        many1(alt((
+            map(quoted_argument, |part| (part, true)),
+            map(simple_argument, |part| (part, false)),
+        ))),
+        |parts| {
+            let quoted = parts.iter().any(|(_, quoted)| *quoted);
+            (parts.into_iter().map(|(part, _)| part).collect(), quoted)
+        },
+    )
+    .parse(input)
 }
 
 /// Parse a quoted argument (single or double). Double quotes allow escapes with \.
@@ -90,95 +176,282 @@ fn unescape_string(s: &str) -> String {
     result
 }
 
-/// Parse an unquoted argument token (up to whitespace or quote)
+/// Parse an unquoted argument token (up to whitespace, quote or operator)
 fn simple_argument(input: &str) -> IResult<&str, String> {
     map(
-        take_while1(|c: char| !c.is_whitespace() && c != '"' && c != '\''),
+        recognize(many1(alt((
+            is_not(" \t\r\n\"'<>&"),
+            terminated(tag("&"), not(char('&'))),
+        )))),
         |s: &str| s.to_string(),
     )
     .parse(input)
 }
 
-/// Parse environment variable substitution using nom
-fn env_var_substitution_parser(input: &str) -> IResult<&str, String> {
-    map(
-        recognize((
-            char('%'),
-            recognize((alpha1, many0(alt((alphanumeric1, recognize(char('_'))))))),
-        )),
-        |s: &str| s.to_string(),
-    )
-    .parse(input)
+/// A piece of a command template
+#[derive(Debug, PartialEq, Eq)]
+enum TemplateSegment<'a> {
+    Literal(&'a str),
+    /// `%%`, an escaped percent sign
+    Percent,
+    /// `%NAME`, `%{NAME}` or `%{NAME:-default}`
+    Variable {
+        name: &'a str,
+        default: Option<&'a str>,
+    },
 }
 
-/// Parse a string that may contain environment variable substitutions
-fn string_with_env_vars_parser(input: &str) -> IResult<&str, Vec<String>> {
-    many0(alt((
-        // Parse environment variable
-        env_var_substitution_parser,
-        // Parse literal text (anything that's not %)
-        map(take_while1(|c: char| c != '%'), |s: &str| s.to_string()),
-    )))
+/// Parse a variable name: a letter followed by letters, digits or underscores
+fn variable_name_parser(input: &str) -> IResult<&str, &str> {
+    recognize((alpha1, many0(alt((alphanumeric1, tag("_")))))).parse(input)
+}
+
+fn template_segment_parser(input: &str) -> IResult<&str, TemplateSegment<'_>> {
+    alt((
+        map(tag("%%"), |_| TemplateSegment::Percent),
+        map(
+            delimited(
+                tag("%{"),
+                (
+                    variable_name_parser,
+                    opt(preceded(tag(":-"), take_while(|c: char| c != '}'))),
+                ),
+                char('}'),
+            ),
+            |(name, default)| TemplateSegment::Variable { name, default },
+        ),
+        map(preceded(char('%'), variable_name_parser), |name| {
+            TemplateSegment::Variable {
+                name,
+                default: None,
+            }
+        }),
+        map(take_while1(|c: char| c != '%'), TemplateSegment::Literal),
+    ))
     .parse(input)
 }
 
-/// Substitute %VARNAME occurrences inside each argument using nom-based parsing
-fn substitute_env_vars(parts: Vec<String>, env: &HashMap<String, String>) -> Vec<String> {
-    parts
-        .into_iter()
-        .map(|part| {
-            if !part.contains('%') {
-                return part;
+/// Split a template into segments, a `%` that doesn't start a variable is an error
+fn parse_template(template: &str) -> Result<Vec<TemplateSegment<'_>>, String> {
+    match many0(template_segment_parser).parse(template) {
+        Ok(("", segments)) => Ok(segments),
+        Ok((rest, _)) | Err(nom::Err::Error(nom::error::Error { input: rest, .. })) => {
+            Err(format!(
+                "Invalid variable reference near \"{}\", use %% for a literal %",
+                rest
+            ))
+        }
+        Err(e) => Err(format!("Failed to parse template: {:?}", e)),
+    }
+}
+
+/// Expand the variables in a template
+///
+/// # Arguments
+/// * `template` - Text that may contain `%NAME`, `%{NAME}`, `%{NAME:-default}` and `%%`
+/// * `env` - Values of the variables
+///
+/// # Returns
+/// * `Result<String, String>` - The expanded text, or an error if a variable without
+///   default is not defined
+pub fn expand_template(template: &str, env: &HashMap<String, String>) -> Result<String, String> {
+    let mut result = String::with_capacity(template.len());
+    for segment in parse_template(template)? {
+        match segment {
+            TemplateSegment::Literal(s) => result.push_str(s),
+            TemplateSegment::Percent => result.push('%'),
+            TemplateSegment::Variable { name, default } => {
+                match env.get(name).map(String::as_str).or(default) {
+                    Some(value) => result.push_str(value),
+                    None => return Err(format!("Unknown variable \"{}\"", name)),
+                }
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Check that a template only references the given variables.
+/// Variables with a default value are always accepted.
+fn check_template(template: &str, known: &[&str]) -> Result<(), String> {
+    for segment in parse_template(template)? {
+        if let TemplateSegment::Variable {
+            name,
+            default: None,
+        } = segment
+        {
+            if !known.contains(&name) {
+                return Err(format!(
+                    "Unknown variable \"{}\", available variables: {}",
+                    name,
+                    known.join(", ")
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A file that receives the output of a step
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputRedirect {
+    pub path: PathBuf,
+    pub append: bool,
+}
+
+/// One program of a command sequence, with its redirections
+#[derive(Debug)]
+pub struct CommandStep {
+    pub command: Command,
+    pub stdin: Option<PathBuf>,
+    pub stdout: Option<OutputRedirect>,
+    pub stderr: Option<OutputRedirect>,
+}
+
+impl std::fmt::Display for CommandStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.command.get_program().to_string_lossy())?;
+        for arg in self.command.get_args() {
+            write!(f, " {}", arg.to_string_lossy())?;
+        }
+        Ok(())
+    }
+}
+
+/// Steps joined by `&&`
+#[derive(Debug)]
+pub struct CommandSequence {
+    pub steps: Vec<CommandStep>,
+}
+
+impl CommandSequence {
+    /// Set the working directory of every step, relative redirections are resolved against it
+    pub fn current_dir(&mut self, dir: &Path) {
+        for step in &mut self.steps {
+            step.command.current_dir(dir);
+            if let Some(stdin) = &mut step.stdin {
+                *stdin = dir.join(&stdin);
+            }
+            for redirect in [&mut step.stdout, &mut step.stderr].into_iter().flatten() {
+                redirect.path = dir.join(&redirect.path);
             }
+        }
+    }
+}
 
-            match string_with_env_vars_parser(&part) {
-                Ok((_, segments)) => {
-                    segments
-                        .into_iter()
-                        .map(|segment| {
-                            if segment.starts_with('%') {
-                                let var_name = &segment[1..]; // Remove the % prefix
-                                env.get(var_name).cloned().unwrap_or_else(|| {
-                                    log::warn!(
-                                        "Environment variable '{}' not found, keeping original",
-                                        var_name
-                                    );
-                                    segment.clone()
-                                })
-                            } else {
-                                segment
-                            }
-                        })
-                        .collect::<String>()
+/// Split the tokens into steps, expanding each word with `expand`
+fn split_steps<F: Fn(&str) -> Result<String, String>>(
+    command: &str,
+    expand: F,
+) -> Result<Vec<CommandStep>, String> {
+    let (rest, tokens) =
+        command_parser(command).map_err(|e| format!("Failed to parse command: {:?}", e))?;
+    if !rest.is_empty() {
+        return Err(format!("Failed to parse the command from: {}", rest));
+    }
+
+    let mut steps = Vec::new();
+    let mut tokens = tokens.into_iter();
+    loop {
+        let mut has_next = false;
+        let mut args = Vec::new();
+        let mut stdin = None;
+        let mut stdout = None;
+        let mut stderr = None;
+        while let Some(token) = tokens.next() {
+            let operator = match token {
+                Token::Word { text, quoted } => {
+                    let arg = expand(&text)?;
+                    // An unquoted word that expands to nothing is no argument, like
+                    // `%{FLAGS:-}` without flags
+                    if quoted || !arg.is_empty() {
+                        args.push(arg);
+                    }
+                    continue;
+                }
+                Token::Operator(Operator::And) => {
+                    has_next = true;
+                    break;
+                }
+                Token::Operator(operator) => operator,
+            };
+            let Some(Token::Word { text: target, .. }) = tokens.next() else {
+                return Err("Missing file name after redirection".to_string());
+            };
+            let path = PathBuf::from(expand(&target)?);
+            match operator {
+                Operator::RedirectIn => stdin = Some(path),
+                Operator::RedirectOut | Operator::AppendOut => {
+                    stdout = Some(OutputRedirect {
+                        path,
+                        append: operator == Operator::AppendOut,
+                    })
                 }
-                Err(_) => {
-                    // Fallback to original behavior if nom parsing fails
-                    part
+                Operator::RedirectErr | Operator::AppendErr => {
+                    stderr = Some(OutputRedirect {
+                        path,
+                        append: operator == Operator::AppendErr,
+                    })
                 }
+                Operator::And => unreachable!(),
             }
-        })
-        .collect()
+        }
+        if args.is_empty() {
+            return Err("Empty command".to_string());
+        }
+        let mut command = Command::new(&args[0]);
+        command.args(&args[1..]);
+        steps.push(CommandStep {
+            command,
+            stdin,
+            stdout,
+            stderr,
+        });
+        if !has_next {
+            return Ok(steps);
+        }
+    }
+}
+
+fn into_single_command(mut steps: Vec<CommandStep>) -> Result<Command, String> {
+    let step = steps.remove(0);
+    if !steps.is_empty() || step.stdin.is_some() || step.stdout.is_some() || step.stderr.is_some() {
+        return Err("Multi-step commands and redirections are not supported here".to_string());
+    }
+    Ok(step.command)
 }
 
-/// Parse and then perform env substitution for %VARNAME tokens.
+/// Parse and then expand the variables in each argument, see the module documentation
+/// for the syntax. The command must be a single step without redirections.
 pub fn parse_command_with_env(
     command: &str,
     env: &HashMap<String, String>,
 ) -> Result<Command, String> {
-    let (_, parts) =
-        command_parser(command).map_err(|e| format!("Failed to parse command: {:?}", e))?;
-
-    if parts.is_empty() {
-        return Err("Empty command".to_string());
-    }
+    into_single_command(parse_command_sequence_with_env(command, env)?.steps)
+}
 
-    let processed_parts = substitute_env_vars(parts, env);
+/// Parse a command that may contain several steps and redirections, and expand the
+/// variables in each argument. Arguments are split before the expansion, so a value
+/// containing whitespace stays in one argument.
+pub fn parse_command_sequence_with_env(
+    command: &str,
+    env: &HashMap<String, String>,
+) -> Result<CommandSequence, String> {
+    let steps = split_steps(command, |word| expand_template(word, env))?;
+    Ok(CommandSequence { steps })
+}
 
-    let mut cmd = Command::new(&processed_parts[0]);
-    if processed_parts.len() > 1 {
-        cmd.args(&processed_parts[1..]);
+/// Check the variables of a command, and that it is a single step if `allow_sequence`
+/// is not set. Variables with a default value are always accepted.
+pub fn check_command(command: &str, known: &[&str], allow_sequence: bool) -> Result<(), String> {
+    let steps = split_steps(command, |word| {
+        check_template(word, known)?;
+        Ok(word.to_string())
+    })?;
+    if !allow_sequence {
+        into_single_command(steps)?;
     }
-    Ok(cmd)
+    Ok(())
 }
 
 #[cfg(test)]
@@ -256,9 +529,84 @@ mod tests {
     #[test]
     fn test_missing_env_var() {
         let env = HashMap::new();
-        let result = parse_command_with_env("echo %MISSING_VAR", &env).unwrap();
-        assert_eq!(result.get_program(), "echo");
-        assert_eq!(result.get_args().collect::<Vec<_>>(), vec!["%MISSING_VAR"]);
+        let result = parse_command_with_env("echo %MISSING_VAR", &env);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_braced_and_default_vars() {
+        let mut env = HashMap::new();
+        env.insert("BIN".to_string(), "/tmp/main".to_string());
+        let result = parse_command_with_env("%{BIN}.exe %{FLAGS:-} %{OPT:--O2}", &env).unwrap();
+        assert_eq!(result.get_program(), "/tmp/main.exe");
+        assert_eq!(result.get_args().collect::<Vec<_>>(), vec!["-O2"]);
+        let result = parse_command_with_env("g++ %{FLAGS:-} \"%{EMPTY:-}\" -o %BIN", &env).unwrap();
+        assert_eq!(
+            result.get_args().collect::<Vec<_>>(),
+            vec!["", "-o", "/tmp/main"]
+        );
+    }
+
+    #[test]
+    fn test_escaped_percent() {
+        let mut env = HashMap::new();
+        env.insert("SRC".to_string(), "a b.py".to_string());
+        let result = parse_command_with_env("printf %%d%% %SRC", &env).unwrap();
+        assert_eq!(result.get_args().collect::<Vec<_>>(), vec!["%d%", "a b.py"]);
+        assert!(parse_command_with_env("printf %d", &env).is_err());
+        assert!(parse_command_with_env("echo 100%", &env).is_err());
+    }
+
+    #[test]
+    fn test_check_template() {
+        assert!(check_template("g++ %SRC -o %{BIN}", &["SRC", "BIN"]).is_ok());
+        assert!(check_template("python %target", &["SRC"]).is_err());
+        assert!(check_template("g++ %{FLAGS:--O2} %SRC", &["SRC"]).is_ok());
+        assert!(check_template("python $target", &["SRC"]).is_ok());
+        assert!(check_command("kotlinc %SRC && jar %BIN", &["SRC"], true).is_err());
+        assert!(check_command("a && b", &[], false).is_err());
+    }
+
+    #[test]
+    fn test_command_sequence() {
+        let mut env = HashMap::new();
+        env.insert("SRC".to_string(), "a.kt".to_string());
+        let sequence = parse_command_sequence_with_env(
+            "kotlinc %SRC -d out&&jar cf main.jar \"&&\" >log.txt 2>> err.txt && java -jar main.jar < in.txt",
+            &env,
+        )
+        .unwrap();
+        let steps = sequence.steps;
+        assert_eq!(steps.len(), 3);
+        assert_eq!(steps[0].to_string(), "kotlinc a.kt -d out");
+        assert_eq!(steps[1].to_string(), "jar cf main.jar &&");
+        assert_eq!(
+            steps[1].stdout,
+            Some(OutputRedirect {
+                path: PathBuf::from("log.txt"),
+                append: false
+            })
+        );
+        assert_eq!(
+            steps[1].stderr,
+            Some(OutputRedirect {
+                path: PathBuf::from("err.txt"),
+                append: true
+            })
+        );
+        assert_eq!(steps[2].stdin, Some(PathBuf::from("in.txt")));
+    }
+
+    #[test]
+    fn test_invalid_sequence() {
+        let env = HashMap::new();
+        assert!(parse_command_sequence_with_env("a &&", &env).is_err());
+        assert!(parse_command_sequence_with_env("a && && b", &env).is_err());
+        assert!(parse_command_sequence_with_env("a >", &env).is_err());
+        assert!(parse_command_with_env("a && b", &env).is_err());
+        assert!(parse_command_with_env("a > out.txt", &env).is_err());
+        let result = parse_command_with_env("echo a&b", &env).unwrap();
+        assert_eq!(result.get_args().collect::<Vec<_>>(), vec!["a&b"]);
     }
 
     #[test]
@@ -278,9 +626,10 @@ mod tests {
     fn test_unclosed_quote() {
         let env = HashMap::new();
         let result = parse_command("echo \"Hello World", &env);
-        assert!(
-            result.is_ok(),
-            "nom parser returns error at end; we trim in parser"
+        assert_eq!(
+            result.unwrap_err(),
+            "Failed to parse the command from: \"Hello World"
         );
+        assert!(check_command("echo 'a", &[], false).is_err());
     }
 }
@@ -0,0 +1,369 @@
+/// Output and answer comparison
+/// This module compares the output of a program with the answer by lines or by
+/// whitespace-separated tokens. Both sides are streamed, so large files never need
+/// to be loaded completely, and the webview only receives the requested page.
+use std::io::{self, BufRead};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+/// Lines longer than this are truncated before being sent to the webview
+pub const MAX_DIFF_TEXT_CHARS: usize = 1024;
+
+/// Maximum number of lines in one page
+pub const MAX_DIFF_PAGE_LINES: u32 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum DiffMode {
+    /// Compare line by line, ignoring trailing whitespace and trailing empty lines
+    Line,
+    /// Compare whitespace-separated tokens, ignoring how they are split into lines
+    Token,
+}
+
+/// Position in a text, line and column start from 1, column is counted in characters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct TextPosition {
+    pub line: u32,
+    pub column: u32,
+}
+
+/// The first difference between the output and the answer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct DiffMismatch {
+    /// Index of the mismatched line or token, starting from 0
+    pub index: u32,
+    /// Position in the output, `None` if the output ended
+    pub output_position: Option<TextPosition>,
+    /// Position in the answer, `None` if the answer ended
+    pub answer_position: Option<TextPosition>,
+    pub output: Option<String>,
+    pub answer: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct DiffLine {
+    pub line: u32,
+    pub output: Option<String>,
+    pub answer: Option<String>,
+    pub is_equal: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct DiffPage {
+    pub start_line: u32,
+    pub lines: Vec<DiffLine>,
+    pub has_more: bool,
+}
+
+struct LineReader<R: BufRead> {
+    reader: R,
+    buf: Vec<u8>,
+}
+
+impl<R: BufRead> LineReader<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buf: Vec::new(),
+        }
+    }
+
+    fn next_line(&mut self) -> io::Result<Option<String>> {
+        self.buf.clear();
+        if self.reader.read_until(b'\n', &mut self.buf)? == 0 {
+            return Ok(None);
+        }
+        if self.buf.ends_with(b"\n") {
+            self.buf.pop();
+        }
+        if self.buf.ends_with(b"\r") {
+            self.buf.pop();
+        }
+        Ok(Some(String::from_utf8_lossy(&self.buf).into_owned()))
+    }
+}
+
+struct TokenReader<R: BufRead> {
+    lines: LineReader<R>,
+    line: u32,
+    pending: std::collections::VecDeque<(String, TextPosition)>,
+}
+
+impl<R: BufRead> TokenReader<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            lines: LineReader::new(reader),
+            line: 0,
+            pending: Default::default(),
+        }
+    }
+
+    fn next_token(&mut self) -> io::Result<Option<(String, TextPosition)>> {
+        while self.pending.is_empty() {
+            let Some(line) = self.lines.next_line()? else {
+                return Ok(None);
+            };
+            self.line += 1;
+            let mut token = String::new();
+            let mut start = 0;
+            for (column, ch) in line.chars().enumerate() {
+                if ch.is_whitespace() {
+                    if !token.is_empty() {
+                        self.push_token(std::mem::take(&mut token), start);
+                    }
+                } else {
+                    if token.is_empty() {
+                        start = column;
+                    }
+                    token.push(ch);
+                }
+            }
+            if !token.is_empty() {
+                self.push_token(token, start);
+            }
+        }
+        Ok(self.pending.pop_front())
+    }
+
+    fn push_token(&mut self, token: String, column: usize) {
+        let position = TextPosition {
+            line: self.line,
+            column: column as u32 + 1,
+        };
+        self.pending.push_back((token, position));
+    }
+}
+
+fn truncate(s: String) -> String {
+    match s.char_indices().nth(MAX_DIFF_TEXT_CHARS) {
+        Some((idx, _)) => format!("{}…", &s[..idx]),
+        None => s,
+    }
+}
+
+fn is_line_equal(mode: DiffMode, output: &str, answer: &str) -> bool {
+    match mode {
+        DiffMode::Line => output.trim_end() == answer.trim_end(),
+        DiffMode::Token => output.split_whitespace().eq(answer.split_whitespace()),
+    }
+}
+
+/// Find the first difference between the output and the answer
+///
+/// # Returns
+/// * `Result<Option<DiffMismatch>>` - `None` if they are equal in the given mode
+pub fn find_first_mismatch<O: BufRead, A: BufRead>(
+    output: O,
+    answer: A,
+    mode: DiffMode,
+) -> io::Result<Option<DiffMismatch>> {
+    match mode {
+        DiffMode::Line => find_first_line_mismatch(output, answer),
+        DiffMode::Token => find_first_token_mismatch(output, answer),
+    }
+}
+
+fn find_first_line_mismatch<O: BufRead, A: BufRead>(
+    output: O,
+    answer: A,
+) -> io::Result<Option<DiffMismatch>> {
+    let mut output = LineReader::new(output);
+    let mut answer = LineReader::new(answer);
+    let mut index = 0u32;
+    loop {
+        let (out_line, ans_line) = (output.next_line()?, answer.next_line()?);
+        let is_equal = match (&out_line, &ans_line) {
+            (None, None) => return Ok(None),
+            (Some(o), Some(a)) => is_line_equal(DiffMode::Line, o, a),
+            // Trailing empty lines are ignored
+            (Some(l), None) | (None, Some(l)) => l.trim().is_empty(),
+        };
+        if !is_equal {
+            let column = match (&out_line, &ans_line) {
+                (Some(o), Some(a)) => {
+                    o.chars().zip(a.chars()).take_while(|(x, y)| x == y).count() as u32 + 1
+                }
+                _ => 1,
+            };
+            let position = TextPosition {
+                line: index + 1,
+                column,
+            };
+            return Ok(Some(DiffMismatch {
+                index,
+                output_position: out_line.as_ref().map(|_| position),
+                answer_position: ans_line.as_ref().map(|_| position),
+                output: out_line.map(truncate),
+                answer: ans_line.map(truncate),
+            }));
+        }
+        index += 1;
+    }
+}
+
+fn find_first_token_mismatch<O: BufRead, A: BufRead>(
+    output: O,
+    answer: A,
+) -> io::Result<Option<DiffMismatch>> {
+    let mut output = TokenReader::new(output);
+    let mut answer = TokenReader::new(answer);
+    let mut index = 0u32;
+    loop {
+        let (out_token, ans_token) = (output.next_token()?, answer.next_token()?);
+        let is_equal = match (&out_token, &ans_token) {
+            (None, None) => return Ok(None),
+            (Some((o, _)), Some((a, _))) => o == a,
+            _ => false,
+        };
+        if !is_equal {
+            let (output, output_position) = out_token.unzip();
+            let (answer, answer_position) = ans_token.unzip();
+            return Ok(Some(DiffMismatch {
+                index,
+                output_position,
+                answer_position,
+                output: output.map(truncate),
+                answer: answer.map(truncate),
+            }));
+        }
+        index += 1;
+    }
+}
+
+/// Read a page of the output and the answer side by side, lines are aligned by their line number
+///
+/// # Arguments
+/// * `start_line` - The first line of the page, starting from 0
+/// * `limit` - Number of lines in the page, capped by `MAX_DIFF_PAGE_LINES`
+pub fn read_diff_page<O: BufRead, A: BufRead>(
+    output: O,
+    answer: A,
+    mode: DiffMode,
+    start_line: u32,
+    limit: u32,
+) -> io::Result<DiffPage> {
+    let mut output = LineReader::new(output);
+    let mut answer = LineReader::new(answer);
+    for _ in 0..start_line {
+        let (o, a) = (output.next_line()?, answer.next_line()?);
+        if o.is_none() && a.is_none() {
+            break;
+        }
+    }
+
+    let limit = limit.min(MAX_DIFF_PAGE_LINES);
+    let mut lines = Vec::with_capacity(limit as usize);
+    let mut has_more = false;
+    for i in 0..=limit {
+        let (out_line, ans_line) = (output.next_line()?, answer.next_line()?);
+        if out_line.is_none() && ans_line.is_none() {
+            break;
+        }
+        if i == limit {
+            has_more = true;
+            break;
+        }
+        let is_equal = match (&out_line, &ans_line) {
+            (Some(o), Some(a)) => is_line_equal(mode, o, a),
+            (Some(l), None) | (None, Some(l)) => l.trim().is_empty(),
+            (None, None) => unreachable!(),
+        };
+        lines.push(DiffLine {
+            line: start_line + i + 1,
+            output: out_line.map(truncate),
+            answer: ans_line.map(truncate),
+            is_equal,
+        });
+    }
+
+    Ok(DiffPage {
+        start_line,
+        lines,
+        has_more,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_equal_ignore_trailing_whitespace() {
+        let result = find_first_mismatch(
+            "1 2 \r\n3\n\n".as_bytes(),
+            "1 2\n3".as_bytes(),
+            DiffMode::Line,
+        )
+        .unwrap();
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn test_line_mismatch_position() {
+        let result = find_first_mismatch(
+            "1 2\n3 4\n".as_bytes(),
+            "1 2\n3 5\n".as_bytes(),
+            DiffMode::Line,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(result.index, 1);
+        assert_eq!(
+            result.output_position,
+            Some(TextPosition { line: 2, column: 3 })
+        );
+        assert_eq!(result.output.as_deref(), Some("3 4"));
+        assert_eq!(result.answer.as_deref(), Some("3 5"));
+    }
+
+    #[test]
+    fn test_token_ignore_line_breaks() {
+        let result =
+            find_first_mismatch("1\n2 3".as_bytes(), "1 2\n3\n".as_bytes(), DiffMode::Token)
+                .unwrap();
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn test_token_mismatch_and_eof() {
+        let result = find_first_mismatch("1  2\n4".as_bytes(), "1 2 3".as_bytes(), DiffMode::Token)
+            .unwrap()
+            .unwrap();
+        assert_eq!(result.index, 2);
+        assert_eq!(
+            result.output_position,
+            Some(TextPosition { line: 2, column: 1 })
+        );
+        assert_eq!(
+            result.answer_position,
+            Some(TextPosition { line: 1, column: 5 })
+        );
+
+        let result = find_first_mismatch("1 2".as_bytes(), "1 2 3".as_bytes(), DiffMode::Token)
+            .unwrap()
+            .unwrap();
+        assert_eq!(result.output, None);
+        assert_eq!(result.answer.as_deref(), Some("3"));
+    }
+
+    #[test]
+    fn test_diff_page() {
+        let output = "a\nb\nc\nd\n";
+        let answer = "a\nx\nc\n";
+        let page =
+            read_diff_page(output.as_bytes(), answer.as_bytes(), DiffMode::Line, 1, 2).unwrap();
+        assert!(page.has_more);
+        assert_eq!(page.lines.len(), 2);
+        assert_eq!(page.lines[0].line, 2);
+        assert!(!page.lines[0].is_equal);
+        assert!(page.lines[1].is_equal);
+
+        let page =
+            read_diff_page(output.as_bytes(), answer.as_bytes(), DiffMode::Line, 3, 2).unwrap();
+        assert!(!page.has_more);
+        assert_eq!(page.lines.len(), 1);
+        assert_eq!(page.lines[0].answer, None);
+        assert!(!page.lines[0].is_equal);
+    }
+}
@@ -1,90 +1,415 @@
 /// Language Server Protocol (LSP) process manager
 /// This module provides functionality to launch and communicate with language servers
-/// using the Language Server Protocol over stdio.
+/// using the Language Server Protocol over stdio, TCP or WebSocket.
 use std::{
+    collections::VecDeque,
+    future::Future,
     process::{Command, Stdio},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
 
-use anyhow::Result;
+use anyhow::{bail, Result};
+use futures_util::{
+    stream::{SplitSink, SplitStream},
+    SinkExt, StreamExt,
+};
 use log::trace;
 use serde::{Deserialize, Serialize};
 use specta::Type;
 use tokio::{
-    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    io::{
+        AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt,
+        BufReader,
+    },
+    net::TcpStream,
     process::Child,
-    sync::{Mutex, RwLock},
+    sync::{broadcast, Mutex},
+};
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+use crate::runner::{
+    command_flag_hide_new_console,
+    lsp_codec::{encode_frame, FrameReader},
 };
 
-use crate::runner::command_flag_hide_new_console;
+/// How long to wait for a spawned server to accept TCP or WebSocket connections
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const CONNECT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Lines of stderr kept per language server
+const LOG_CAPACITY: usize = 1000;
+/// Longer stderr lines are truncated, so a server can't grow the log without bound
+const LOG_LINE_MAX_LEN: usize = 4096;
+
+type WebSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Sends whole messages to the language server
+enum MessageWriter {
+    /// Byte stream framed with `Content-Length` headers
+    Stream(Box<dyn AsyncWrite + Unpin + Send>),
+    /// One text frame per message
+    WebSocket(SplitSink<WebSocket, Message>),
+}
+
+/// Receives whole messages from the language server
+enum MessageReader {
+    Stream(FrameReader<Box<dyn AsyncRead + Unpin + Send>>),
+    WebSocket(SplitStream<WebSocket>),
+}
+
+impl MessageWriter {
+    async fn write(&mut self, data: &[u8]) -> Result<()> {
+        match self {
+            MessageWriter::Stream(writer) => {
+                writer.write_all(&encode_frame(data)).await?;
+                writer.flush().await?;
+            }
+            MessageWriter::WebSocket(sink) => {
+                let text = String::from_utf8(data.to_vec())?;
+                sink.send(Message::text(text)).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn close(&mut self) -> Result<()> {
+        match self {
+            MessageWriter::Stream(writer) => writer.shutdown().await?,
+            MessageWriter::WebSocket(sink) => sink.close().await?,
+        }
+        Ok(())
+    }
+}
+
+impl MessageReader {
+    async fn read(&mut self) -> Result<Vec<u8>> {
+        match self {
+            MessageReader::Stream(reader) => reader.read_frame().await,
+            MessageReader::WebSocket(stream) => loop {
+                // Ping and pong frames are answered by tungstenite itself
+                match stream.next().await {
+                    Some(Ok(Message::Text(text))) => return Ok(text.as_bytes().to_vec()),
+                    Some(Ok(Message::Binary(data))) => return Ok(data.to_vec()),
+                    Some(Ok(Message::Close(_))) | None => bail!("WebSocket closed"),
+                    Some(Ok(_)) => continue,
+                    Some(Err(e)) => return Err(e.into()),
+                }
+            },
+        }
+    }
+}
+
+/// Connect to a language server, retrying while a freshly spawned server is starting up
+///
+/// # Arguments
+/// * `address` - The address, only used in messages
+/// * `child` - The spawned server, `None` to try only once
+/// * `connect` - Makes one connection attempt
+async fn wait_for_server<T, E, F, Fut>(
+    address: &str,
+    mut child: Option<&mut Child>,
+    connect: F,
+) -> Result<T>
+where
+    E: std::error::Error + Send + Sync + 'static,
+    F: Fn() -> Fut,
+    Fut: Future<Output = std::result::Result<T, E>>,
+{
+    let deadline = tokio::time::Instant::now() + CONNECT_TIMEOUT;
+    loop {
+        trace!("Connecting to language server: {}", address);
+        let err = match connect().await {
+            Ok(connection) => return Ok(connection),
+            Err(e) => e,
+        };
+        let Some(child) = child.as_mut() else {
+            return Err(
+                anyhow::Error::new(err).context(format!("failed to connect to {}", address))
+            );
+        };
+        if let Some(status) = child.try_wait()? {
+            bail!(
+                "language server exited with {} before accepting connections",
+                status
+            );
+        }
+        if tokio::time::Instant::now() >= deadline {
+            let _ = child.kill().await;
+            return Err(
+                anyhow::Error::new(err).context(format!("failed to connect to {}", address))
+            );
+        }
+        tokio::time::sleep(CONNECT_INTERVAL).await;
+    }
+}
+
+/// The last lines a language server printed to stderr
+#[derive(Clone, Default)]
+pub struct LangServerLog {
+    lines: Arc<std::sync::Mutex<VecDeque<String>>>,
+    /// Put in front of every line, to tell apart servers sharing a log
+    prefix: Option<Arc<str>>,
+}
+
+impl LangServerLog {
+    /// A log that shares the lines of this one and marks its own lines with `[name]`
+    pub fn named(&self, name: &str) -> Self {
+        Self {
+            lines: self.lines.clone(),
+            prefix: Some(format!("[{}] ", name).into()),
+        }
+    }
+
+    /// Add a line, and return it as it was stored
+    pub(crate) fn push(&self, line: String) -> String {
+        let line = match &self.prefix {
+            Some(prefix) => format!("{}{}", prefix, line),
+            None => line,
+        };
+        let mut lines = self.lines.lock().unwrap();
+        if lines.len() == LOG_CAPACITY {
+            lines.pop_front();
+        }
+        lines.push_back(line.clone());
+        line
+    }
+
+    /// Copy the lines of the log, oldest first
+    pub fn lines(&self) -> Vec<String> {
+        self.lines.lock().unwrap().iter().cloned().collect()
+    }
+}
 
-/// Represents a running language server process with stdio communication
+/// Skip to the start of the next line
+async fn skip_line<R: AsyncBufRead + Unpin>(reader: &mut R) -> std::io::Result<()> {
+    loop {
+        let available = reader.fill_buf().await?;
+        if available.is_empty() {
+            return Ok(());
+        }
+        match available.iter().position(|&b| b == b'\n') {
+            Some(end) => {
+                reader.consume(end + 1);
+                return Ok(());
+            }
+            None => {
+                let len = available.len();
+                reader.consume(len);
+            }
+        }
+    }
+}
+
+/// Read stderr until the server closes it, so a chatty server never blocks on a full pipe.
+/// Each line goes to the log and to the subscribers of the process
+async fn drain_stderr<R: AsyncRead + Unpin>(
+    stderr: R,
+    log: LangServerLog,
+    sender: broadcast::Sender<String>,
+) {
+    let mut reader = BufReader::new(stderr);
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        // Only the start of a long line is kept in memory, the rest is skipped
+        let result = (&mut reader)
+            .take(LOG_LINE_MAX_LEN as u64)
+            .read_until(b'\n', &mut buf)
+            .await;
+        match result {
+            Ok(0) => break,
+            Ok(_) => {
+                if buf.len() == LOG_LINE_MAX_LEN && buf.last() != Some(&b'\n') {
+                    if let Err(e) = skip_line(&mut reader).await {
+                        trace!("failed to read language server stderr: {}", e);
+                        break;
+                    }
+                }
+                let line = String::from_utf8_lossy(&buf)
+                    .trim_end_matches(['\r', '\n'])
+                    .to_string();
+                let line = log.push(line);
+                // No subscriber is fine, the line is still in the log
+                let _ = sender.send(line);
+            }
+            Err(e) => {
+                trace!("failed to read language server stderr: {}", e);
+                break;
+            }
+        }
+    }
+}
+
+/// Shared state of a language server: the spawned process, if any, and whether the
+/// connection to it is closed
+#[derive(Clone)]
+struct Connection {
+    proc: Arc<Mutex<Option<Child>>>,
+    closed: Arc<AtomicBool>,
+}
+
+impl Connection {
+    async fn is_alive(&self) -> bool {
+        if self.closed.load(Ordering::SeqCst) {
+            return false;
+        }
+        match self.proc.lock().await.as_mut() {
+            Some(proc) => proc.try_wait().unwrap_or(None).is_none(),
+            None => true,
+        }
+    }
+
+    async fn exit_code(&self) -> Option<i32> {
+        match self.proc.lock().await.as_mut() {
+            Some(proc) => proc
+                .try_wait()
+                .unwrap_or(None)
+                .map(|status| status.code().unwrap_or(0)),
+            None => self.closed.load(Ordering::SeqCst).then_some(0),
+        }
+    }
+
+    async fn pid(&self) -> Option<u32> {
+        self.proc.lock().await.as_ref().and_then(|proc| proc.id())
+    }
+}
+
+/// Represents a running language server, either a process talking over stdio or a
+/// socket connection to a server that may have been spawned by us
 /// This struct is designed to be shared across multiple threads safely
 pub struct LangServerProcess {
-    proc: Arc<Mutex<Child>>,
-    writer: Arc<Mutex<Box<dyn AsyncWrite + Unpin + Send>>>,
-    reader: Arc<Mutex<Box<dyn AsyncRead + Unpin + Send>>>,
+    conn: Connection,
+    writer: Arc<Mutex<MessageWriter>>,
+    reader: Arc<Mutex<MessageReader>>,
+    log: LangServerLog,
+    log_sender: broadcast::Sender<String>,
 }
 
 /// A handle for writing to the language server from a separate thread
+#[derive(Clone)]
 pub struct LangServerWriter {
-    proc: Arc<Mutex<Child>>,
-    writer: Arc<Mutex<Box<dyn AsyncWrite + Unpin + Send>>>,
+    conn: Connection,
+    writer: Arc<Mutex<MessageWriter>>,
 }
 
 /// A handle for reading from the language server from a separate thread
 pub struct LangServerReader {
-    proc: Arc<Mutex<Child>>,
-    reader: Arc<Mutex<Box<dyn AsyncRead + Unpin + Send>>>,
+    conn: Connection,
+    reader: Arc<Mutex<MessageReader>>,
 }
 
 /// Supported I/O methods for language server communication
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Type)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Type)]
 pub enum IOMethod {
     /// Use standard input/output for communication
     StdIO,
+    /// Connect to a TCP socket, e.g. `127.0.0.1:9257`, framed like stdio
+    Tcp { address: String },
+    /// Connect to a WebSocket endpoint, one JSON-RPC message per frame
+    WebSocket { url: String },
 }
 
 impl LangServerProcess {
     /// Launch a new language server process
     ///
     /// # Arguments
-    /// * `command` - The command to execute the language server
+    /// * `command` - The command to execute the language server, `None` to connect to a
+    ///   server that is already running (TCP or WebSocket only)
     /// * `io_method` - The I/O method to use for communication
+    /// * `log` - Where stderr goes, pass the log of the previous process to keep one log
+    ///   across restarts
     ///
     /// # Returns
     /// * `Result<LangServerProcess>` - The running language server process or an error
-    pub fn launch(mut command: Command, io_method: IOMethod) -> Result<LangServerProcess> {
-        command_flag_hide_new_console(&mut command);
-        let mut command = tokio::process::Command::from(command);
-        command.kill_on_drop(true).stderr(Stdio::piped());
-        trace!("Launching language server: {:?}", &command);
-
-        let mut child = match io_method {
-            IOMethod::StdIO => command
-                .stdout(Stdio::piped())
-                .stdin(Stdio::piped())
-                .spawn()?,
+    pub async fn launch(
+        command: Option<Command>,
+        io_method: IOMethod,
+        log: LangServerLog,
+    ) -> Result<LangServerProcess> {
+        let mut child = match command {
+            Some(mut command) => {
+                command_flag_hide_new_console(&mut command);
+                let mut command = tokio::process::Command::from(command);
+                command.kill_on_drop(true).stderr(Stdio::piped());
+                match io_method {
+                    IOMethod::StdIO => command.stdout(Stdio::piped()).stdin(Stdio::piped()),
+                    IOMethod::Tcp { .. } | IOMethod::WebSocket { .. } => {
+                        command.stdout(Stdio::null()).stdin(Stdio::null())
+                    }
+                };
+                trace!("Launching language server: {:?}", &command);
+                Some(command.spawn()?)
+            }
+            None => None,
         };
 
-        let (reader, writer): (
-            Box<dyn AsyncRead + Unpin + Send>,
-            Box<dyn AsyncWrite + Unpin + Send>,
-        ) = match io_method {
+        let (log_sender, _) = broadcast::channel(LOG_CAPACITY);
+        if let Some(stderr) = child.as_mut().and_then(|child| child.stderr.take()) {
+            tokio::spawn(drain_stderr(stderr, log.clone(), log_sender.clone()));
+        }
+
+        let (reader, writer) = match &io_method {
             IOMethod::StdIO => {
+                let Some(child) = child.as_mut() else {
+                    bail!("a command is required to use stdio");
+                };
                 let stdout = child.stdout.take().unwrap();
                 let stdin = child.stdin.take().unwrap();
-                (Box::new(stdout), Box::new(stdin))
+                (
+                    MessageReader::Stream(FrameReader::new(Box::new(stdout))),
+                    MessageWriter::Stream(Box::new(stdin)),
+                )
+            }
+            IOMethod::Tcp { address } => {
+                let stream = wait_for_server(address, child.as_mut(), || {
+                    TcpStream::connect(address.as_str())
+                })
+                .await?;
+                let (read_half, write_half) = stream.into_split();
+                (
+                    MessageReader::Stream(FrameReader::new(Box::new(read_half))),
+                    MessageWriter::Stream(Box::new(write_half)),
+                )
+            }
+            IOMethod::WebSocket { url } => {
+                let (socket, _) = wait_for_server(url, child.as_mut(), || {
+                    tokio_tungstenite::connect_async(url.as_str())
+                })
+                .await?;
+                let (sink, stream) = socket.split();
+                (
+                    MessageReader::WebSocket(stream),
+                    MessageWriter::WebSocket(sink),
+                )
             }
         };
 
         Ok(Self {
-            proc: Arc::new(Mutex::new(child)),
+            conn: Connection {
+                proc: Arc::new(Mutex::new(child)),
+                closed: Arc::new(AtomicBool::new(false)),
+            },
             reader: Arc::new(Mutex::new(reader)),
             writer: Arc::new(Mutex::new(writer)),
+            log,
+            log_sender,
         })
     }
 
+    /// The stderr log of the language server, it keeps filling after the handle is cloned
+    pub fn log(&self) -> LangServerLog {
+        self.log.clone()
+    }
+
+    /// Receive the stderr lines printed from now on, earlier ones are only in [`Self::log`]
+    pub fn subscribe_log(&self) -> broadcast::Receiver<String> {
+        self.log_sender.subscribe()
+    }
+
     /// Create a writer handle that can be moved to a separate thread
     ///
     /// # Returns
@@ -92,7 +417,7 @@ impl LangServerProcess {
     pub fn create_writer(&self) -> LangServerWriter {
         LangServerWriter {
             writer: Arc::clone(&self.writer),
-            proc: Arc::clone(&self.proc),
+            conn: self.conn.clone(),
         }
     }
 
@@ -103,7 +428,7 @@ impl LangServerProcess {
     pub fn create_reader(&self) -> LangServerReader {
         LangServerReader {
             reader: Arc::clone(&self.reader),
-            proc: Arc::clone(&self.proc),
+            conn: self.conn.clone(),
         }
     }
 
@@ -115,12 +440,7 @@ impl LangServerProcess {
     /// # Returns
     /// * `Result<()>` - Success or error
     pub async fn write(&self, data: &[u8]) -> Result<()> {
-        let mut writer = self.writer.lock().await;
-        let header = format!("Content-Length: {}\r\n\r\n", data.len());
-        writer.write_all(header.as_bytes()).await?;
-        writer.write_all(data).await?;
-        writer.flush().await?;
-        Ok(())
+        self.writer.lock().await.write(data).await
     }
 
     /// Read a complete LSP message from the language server
@@ -128,31 +448,11 @@ impl LangServerProcess {
     /// # Returns
     /// * `Result<Vec<u8>>` - The message bytes or an error
     pub async fn read(&self) -> Result<Vec<u8>> {
-        let mut reader = self.reader.lock().await;
-
-        // Read the header first
-        let mut header = String::new();
-        let mut char_buf = [0u8; 1];
-
-        // Read until we get the double CRLF
-        while !header.ends_with("\r\n\r\n") {
-            reader.read_exact(&mut char_buf).await?;
-            header.push(char_buf[0] as char);
+        let result = self.reader.lock().await.read().await;
+        if result.is_err() {
+            self.conn.closed.store(true, Ordering::SeqCst);
         }
-
-        // Parse content length from header
-        let content_length = header
-            .lines()
-            .find(|line| line.starts_with("Content-Length:"))
-            .and_then(|line| line.split(':').nth(1))
-            .and_then(|s| s.trim().parse::<usize>().ok())
-            .ok_or_else(|| anyhow::anyhow!("Invalid Content-Length header"))?;
-
-        // Read the message body
-        let mut buffer = vec![0u8; content_length];
-        reader.read_exact(&mut buffer).await?;
-
-        Ok(buffer)
+        result
     }
 
     /// Send a JSON message to the language server
@@ -175,30 +475,33 @@ impl LangServerProcess {
         Ok(String::from_utf8(data)?)
     }
 
-    /// Check if the language server process is still alive
+    /// Check if the language server is still alive: the process, if we spawned one, is
+    /// running and the connection is open
     ///
     /// # Returns
-    /// * `bool` - True if the process is still running
+    /// * `bool` - True if the language server is still running
     pub async fn is_alive(&self) -> bool {
-        let mut proc = self.proc.lock().await;
-        proc.try_wait().unwrap_or(None).is_none()
+        self.conn.is_alive().await
     }
 
     pub async fn exit_code(&self) -> Option<i32> {
-        let mut proc = self.proc.lock().await;
-        proc.try_wait()
-            .unwrap_or(None)
-            .map(|status| status.code().unwrap_or(0))
+        self.conn.exit_code().await
     }
 
+    /// Process id of the language server, `None` when connected to a server we did not spawn
     pub async fn pid(&self) -> Option<u32> {
-        let proc = self.proc.lock().await;
-        proc.id()
+        self.conn.pid().await
     }
 
+    /// Close the connection and kill the process, if any
     pub async fn kill(&self) -> Result<()> {
-        let mut proc = self.proc.lock().await;
-        proc.kill().await?;
+        if let Err(e) = self.writer.lock().await.close().await {
+            trace!("failed to close language server connection: {}", e);
+        }
+        self.conn.closed.store(true, Ordering::SeqCst);
+        if let Some(proc) = self.conn.proc.lock().await.as_mut() {
+            proc.kill().await?;
+        }
         Ok(())
     }
 }
@@ -212,12 +515,7 @@ impl LangServerWriter {
     /// # Returns
     /// * `Result<()>` - Success or error
     pub async fn write(&self, data: &[u8]) -> Result<()> {
-        let mut writer = self.writer.lock().await;
-        let header = format!("Content-Length: {}\r\n\r\n", data.len());
-        writer.write_all(header.as_bytes()).await?;
-        writer.write_all(data).await?;
-        writer.flush().await?;
-        Ok(())
+        self.writer.lock().await.write(data).await
     }
 
     /// Send a JSON message to the language server
@@ -232,23 +530,20 @@ impl LangServerWriter {
     }
 
     pub async fn is_alive(&self) -> bool {
-        let mut proc = self.proc.lock().await;
-        proc.try_wait().unwrap_or(None).is_none()
+        self.conn.is_alive().await
     }
     pub async fn exit_code(&self) -> Option<i32> {
-        let mut proc = self.proc.lock().await;
-        proc.try_wait()
-            .unwrap_or(None)
-            .map(|status| status.code().unwrap_or(0))
+        self.conn.exit_code().await
     }
     pub async fn pid(&self) -> Option<u32> {
-        let proc = self.proc.lock().await;
-        proc.id()
+        self.conn.pid().await
     }
 
     pub async fn kill(&self) -> Result<()> {
-        let mut proc = self.proc.lock().await;
-        proc.kill().await?;
+        self.conn.closed.store(true, Ordering::SeqCst);
+        if let Some(proc) = self.conn.proc.lock().await.as_mut() {
+            proc.kill().await?;
+        }
         Ok(())
     }
 }
@@ -259,31 +554,11 @@ impl LangServerReader {
     /// # Returns
     /// * `Result<Vec<u8>>` - The message bytes or an error
     pub async fn read(&self) -> Result<Vec<u8>> {
-        let mut reader = self.reader.lock().await;
-
-        // Read the header first
-        let mut header = String::new();
-        let mut char_buf = [0u8; 1];
-
-        // Read until we get the double CRLF
-        while !header.ends_with("\r\n\r\n") {
-            reader.read_exact(&mut char_buf).await?;
-            header.push(char_buf[0] as char);
+        let result = self.reader.lock().await.read().await;
+        if result.is_err() {
+            self.conn.closed.store(true, Ordering::SeqCst);
         }
-
-        // Parse content length from header
-        let content_length = header
-            .lines()
-            .find(|line| line.starts_with("Content-Length:"))
-            .and_then(|line| line.split(':').nth(1))
-            .and_then(|s| s.trim().parse::<usize>().ok())
-            .ok_or_else(|| anyhow::anyhow!("Invalid Content-Length header"))?;
-
-        // Read the message body
-        let mut buffer = vec![0u8; content_length];
-        reader.read_exact(&mut buffer).await?;
-
-        Ok(buffer)
+        result
     }
 
     /// Receive a JSON message from the language server
@@ -296,17 +571,135 @@ impl LangServerReader {
     }
 
     pub async fn is_alive(&self) -> bool {
-        let mut proc = self.proc.lock().await;
-        proc.try_wait().unwrap_or(None).is_none()
+        self.conn.is_alive().await
     }
     pub async fn exit_code(&self) -> Option<i32> {
-        let mut proc = self.proc.lock().await;
-        proc.try_wait()
-            .unwrap_or(None)
-            .map(|status| status.code().unwrap_or(0))
+        self.conn.exit_code().await
     }
     pub async fn pid(&self) -> Option<u32> {
-        let proc = self.proc.lock().await;
-        proc.id()
+        self.conn.pid().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_stderr_is_drained() {
+        tauri::async_runtime::block_on(async {
+            // More than a pipe buffer, the server would block if stderr was not read
+            let mut command = Command::new("sh");
+            command.args([
+                "-c",
+                "i=0; while [ $i -lt 20000 ]; do i=$((i+1)); echo \"line $i\" >&2; done; cat",
+            ]);
+            let server_process =
+                LangServerProcess::launch(Some(command), IOMethod::StdIO, LangServerLog::default())
+                    .await
+                    .unwrap();
+            let log = server_process.log();
+            let deadline = tokio::time::Instant::now() + Duration::from_secs(20);
+            while log.lines().last().map(String::as_str) != Some("line 20000") {
+                assert!(tokio::time::Instant::now() < deadline, "stderr not drained");
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+            assert_eq!(log.lines().len(), LOG_CAPACITY);
+
+            server_process.send_message("{}").await.unwrap();
+            assert_eq!(server_process.receive_message().await.unwrap(), "{}");
+            server_process.kill().await.unwrap();
+        });
+    }
+
+    #[test]
+    fn test_long_stderr_line_is_truncated() {
+        tauri::async_runtime::block_on(async {
+            let long_line = "x".repeat(LOG_LINE_MAX_LEN * 3);
+            let stderr = format!("first\n{}\nlast\n", long_line);
+            let log = LangServerLog::default();
+            let (sender, _) = broadcast::channel(LOG_CAPACITY);
+            drain_stderr(stderr.as_bytes(), log.clone(), sender).await;
+            assert_eq!(
+                log.lines(),
+                vec![
+                    "first".to_string(),
+                    "x".repeat(LOG_LINE_MAX_LEN),
+                    "last".to_string()
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn test_tcp_round_trip() {
+        tauri::async_runtime::block_on(async {
+            let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+                .await
+                .unwrap();
+            let address = listener.local_addr().unwrap().to_string();
+            let server = tauri::async_runtime::spawn(async move {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0u8; "Content-Length: 2\r\n\r\n{}".len()];
+                stream.read_exact(&mut buf).await.unwrap();
+                stream.write_all(&buf).await.unwrap();
+            });
+
+            let server_process = LangServerProcess::launch(
+                None,
+                IOMethod::Tcp { address },
+                LangServerLog::default(),
+            )
+            .await
+            .unwrap();
+            server_process.send_message("{}").await.unwrap();
+            assert_eq!(server_process.receive_message().await.unwrap(), "{}");
+            server.await.unwrap();
+            assert!(server_process.receive_message().await.is_err());
+            assert!(!server_process.is_alive().await);
+        });
+    }
+
+    #[test]
+    fn test_websocket_round_trip() {
+        tauri::async_runtime::block_on(async {
+            let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+                .await
+                .unwrap();
+            let url = format!("ws://{}", listener.local_addr().unwrap());
+            let server = tauri::async_runtime::spawn(async move {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+                while let Some(Ok(message)) = socket.next().await {
+                    if message.is_text() {
+                        socket.send(message).await.unwrap();
+                    }
+                }
+            });
+
+            let server_process = LangServerProcess::launch(
+                None,
+                IOMethod::WebSocket { url },
+                LangServerLog::default(),
+            )
+            .await
+            .unwrap();
+            let reader = server_process.create_reader();
+            server_process
+                .send_message(r#"{"jsonrpc":"2.0","id":1}"#)
+                .await
+                .unwrap();
+            assert_eq!(
+                reader.receive_message().await.unwrap(),
+                r#"{"jsonrpc":"2.0","id":1}"#
+            );
+            assert_eq!(server_process.pid().await, None);
+            assert!(server_process.is_alive().await);
+
+            server_process.kill().await.unwrap();
+            assert!(!server_process.is_alive().await);
+            server.await.unwrap();
+        });
     }
 }
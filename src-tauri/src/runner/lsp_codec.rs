@@ -0,0 +1,247 @@
+/// Base protocol framing of the Language Server Protocol
+/// A message is a header part, `Name: value` lines ended by an empty line, followed by
+/// `Content-Length` bytes of content. Header names are matched case-insensitively and
+/// `Content-Type` must use the utf-8 charset.
+use anyhow::{anyhow, bail, Result};
+use log::warn;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader};
+
+/// Frames above this size are not read, large enough for semantic tokens of big files
+pub const MAX_FRAME_SIZE: usize = 64 * 1024 * 1024;
+/// Bytes read from the start of an oversized frame to find the request it answers
+const OVERSIZED_PREFIX: usize = 1024;
+/// `RequestFailed` of the LSP specification
+const REQUEST_FAILED: i64 = -32803;
+/// A header line longer than this means the stream is not LSP
+const MAX_HEADER_LINE: usize = 8 * 1024;
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Frame the content of a message, header and content in one buffer so it is written at once
+pub fn encode_frame(content: &[u8]) -> Vec<u8> {
+    let header = format!("Content-Length: {}\r\n\r\n", content.len());
+    let mut frame = Vec::with_capacity(header.len() + content.len());
+    frame.extend_from_slice(header.as_bytes());
+    frame.extend_from_slice(content);
+    frame
+}
+
+/// Reads framed messages through a buffer
+pub struct FrameReader<R> {
+    inner: BufReader<R>,
+    max_frame_size: usize,
+    line: Vec<u8>,
+}
+
+impl<R: AsyncRead + Unpin> FrameReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self::with_max_frame_size(inner, MAX_FRAME_SIZE)
+    }
+
+    pub fn with_max_frame_size(inner: R, max_frame_size: usize) -> Self {
+        Self {
+            inner: BufReader::with_capacity(READ_BUFFER_SIZE, inner),
+            max_frame_size,
+            line: Vec::new(),
+        }
+    }
+
+    /// Read the content of the next message. An oversized response is replaced by an
+    /// error response to its request, so one huge response doesn't end the connection.
+    ///
+    /// # Returns
+    /// * `Result<Vec<u8>>` - The content, or an error at the end of the stream, on a
+    ///   malformed header, or on an oversized message that isn't a response
+    pub async fn read_frame(&mut self) -> Result<Vec<u8>> {
+        let length = self.read_header().await?;
+        if length > self.max_frame_size {
+            let mut prefix = Vec::with_capacity(OVERSIZED_PREFIX);
+            let mut rest = (&mut self.inner).take(length as u64);
+            (&mut rest)
+                .take(OVERSIZED_PREFIX as u64)
+                .read_to_end(&mut prefix)
+                .await?;
+            let skipped = tokio::io::copy(&mut rest, &mut tokio::io::sink()).await?;
+            if prefix.len() as u64 + skipped < length as u64 {
+                bail!("stream ended inside a message");
+            }
+            let Some(id) = response_id(&prefix) else {
+                warn!(
+                    "closing the connection, the language server sent a message of {} bytes and the limit is {}",
+                    length, self.max_frame_size
+                );
+                bail!(
+                    "message of {} bytes exceeds the limit of {}",
+                    length,
+                    self.max_frame_size
+                );
+            };
+            warn!(
+                "dropping the response to {} of {} bytes, the limit is {}",
+                id, length, self.max_frame_size
+            );
+            let error = serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": {
+                    "code": REQUEST_FAILED,
+                    "message": format!(
+                        "The response of {} bytes exceeds the limit of {} bytes",
+                        length, self.max_frame_size
+                    ),
+                },
+            });
+            return Ok(error.to_string().into_bytes());
+        }
+        let mut content = vec![0u8; length];
+        self.inner.read_exact(&mut content).await?;
+        Ok(content)
+    }
+
+    /// Read header lines up to the empty line and return the content length
+    async fn read_header(&mut self) -> Result<usize> {
+        let mut content_length = None;
+        loop {
+            self.line.clear();
+            let read = (&mut self.inner)
+                .take(MAX_HEADER_LINE as u64)
+                .read_until(b'\n', &mut self.line)
+                .await?;
+            if read == 0 {
+                bail!("stream ended");
+            }
+            if self.line.last() != Some(&b'\n') {
+                bail!("header line too long or stream ended inside a header");
+            }
+            let line = std::str::from_utf8(&self.line)
+                .map_err(|_| anyhow!("header is not valid utf-8"))?
+                .trim_end_matches(['\r', '\n']);
+            if line.is_empty() {
+                // Some servers print an empty line between messages
+                match content_length {
+                    Some(length) => return Ok(length),
+                    None => continue,
+                }
+            }
+            let (name, value) = line
+                .split_once(':')
+                .ok_or_else(|| anyhow!("malformed header line: {}", line))?;
+            let value = value.trim();
+            if name.trim().eq_ignore_ascii_case("Content-Length") {
+                content_length = Some(
+                    value
+                        .parse::<usize>()
+                        .map_err(|_| anyhow!("invalid Content-Length: {}", value))?,
+                );
+            } else if name.trim().eq_ignore_ascii_case("Content-Type") {
+                check_content_type(value)?;
+            }
+        }
+    }
+}
+
+/// The id of a response from the start of its content. Only the text is searched, the
+/// content is cut, so `id` has to come before `result` or `error` as servers write it.
+fn response_id(prefix: &[u8]) -> Option<serde_json::Value> {
+    let text = String::from_utf8_lossy(prefix);
+    let body = ["\"result\"", "\"error\""]
+        .iter()
+        .filter_map(|key| text.find(key))
+        .min()?;
+    // Requests from the server have a method, they can't be answered for the editor
+    if text[..body].contains("\"method\"") {
+        return None;
+    }
+    let value = text[..body]
+        .find("\"id\"")
+        .and_then(|start| text[start + 4..body].trim_start().strip_prefix(':'))?
+        .trim_start();
+    let end = match value.strip_prefix('"') {
+        Some(string) => string.find('"')? + 2,
+        None => value
+            .find(|c: char| !c.is_ascii_digit() && c != '-')
+            .unwrap_or(value.len()),
+    };
+    serde_json::from_str::<serde_json::Value>(&value[..end])
+        .ok()
+        .filter(|id| id.is_number() || id.is_string())
+}
+
+/// Only utf-8 is supported, `utf8` is accepted for old servers as the specification asks
+fn check_content_type(value: &str) -> Result<()> {
+    for parameter in value.split(';').skip(1) {
+        if let Some((key, charset)) = parameter.split_once('=') {
+            let charset = charset.trim().trim_matches('"');
+            if key.trim().eq_ignore_ascii_case("charset")
+                && !charset.eq_ignore_ascii_case("utf-8")
+                && !charset.eq_ignore_ascii_case("utf8")
+            {
+                bail!("unsupported charset: {}", charset);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_frames() {
+        tauri::async_runtime::block_on(async {
+            let mut stream = encode_frame(b"{}");
+            stream.extend_from_slice(
+                b"content-type: application/vscode-jsonrpc; charset=utf8\r\ncontent-length: 4\r\n\r\n[1,2]",
+            );
+            let mut reader = FrameReader::new(stream.as_slice());
+            assert_eq!(reader.read_frame().await.unwrap(), b"{}");
+            assert_eq!(reader.read_frame().await.unwrap(), b"[1,2");
+            assert!(reader.read_frame().await.is_err());
+
+            let stream = b"Content-Type: text/plain; charset=latin1\r\nContent-Length: 2\r\n\r\n{}";
+            let mut reader = FrameReader::new(stream.as_slice());
+            assert!(reader.read_frame().await.is_err());
+        });
+    }
+
+    #[test]
+    fn test_oversized_frame() {
+        tauri::async_runtime::block_on(async {
+            let large = format!(
+                r#"{{"jsonrpc":"2.0","id":7,"result":"{}"}}"#,
+                "x".repeat(100)
+            );
+            let mut stream = encode_frame(large.as_bytes());
+            stream.extend(encode_frame(b"{}"));
+            let notification = format!(
+                r#"{{"jsonrpc":"2.0","method":"log","params":"{}"}}"#,
+                "x".repeat(100)
+            );
+            stream.extend(encode_frame(notification.as_bytes()));
+            let mut reader = FrameReader::with_max_frame_size(stream.as_slice(), 50);
+            let error: serde_json::Value =
+                serde_json::from_slice(&reader.read_frame().await.unwrap()).unwrap();
+            assert_eq!(error["id"], 7);
+            assert_eq!(error["error"]["code"], REQUEST_FAILED);
+            assert_eq!(reader.read_frame().await.unwrap(), b"{}");
+            assert!(reader.read_frame().await.is_err());
+        });
+    }
+
+    #[test]
+    fn test_response_id() {
+        assert_eq!(
+            response_id(br#"{"jsonrpc":"2.0","id":"a-1","result":[1,2"#),
+            Some(serde_json::json!("a-1"))
+        );
+        assert_eq!(
+            response_id(br#"{"id": -3, "error": {"#),
+            Some(serde_json::json!(-3))
+        );
+        assert_eq!(
+            response_id(br#"{"jsonrpc":"2.0","id":2,"method":"x","params":{"result":"#),
+            None
+        );
+        assert_eq!(response_id(br#"{"jsonrpc":"2.0","result":{"id":1"#), None);
+    }
+}
@@ -0,0 +1,722 @@
+/// Several language servers behind one editor connection
+/// Notifications, document sync included, go to every server. `initialize` goes to every
+/// server and the capabilities of the answers are merged, other requests go to the first
+/// server with the capability they need. Requests of the servers get ids of their own so
+/// the answers of the editor find their way back, and the diagnostics of all servers are
+/// merged per document. A server that syncs documents in full gets the whole text on
+/// every change, whatever sync the editor was told to use.
+use std::collections::HashMap;
+
+use serde_json::{json, Value};
+
+/// Id of the `initialize` request replayed to a restarted server
+const REINITIALIZE_ID: &str = "algorimejo-reinitialize";
+const SERVER_REQUEST_ID_PREFIX: &str = "algorimejo-mux-";
+/// `TextDocumentSyncKind.Full`
+const SYNC_FULL: u64 = 1;
+
+/// The capability a request needs, `None` for requests any server can answer
+fn required_capability(method: &str) -> Option<&'static str> {
+    Some(match method {
+        "textDocument/completion" | "completionItem/resolve" => "completionProvider",
+        "textDocument/hover" => "hoverProvider",
+        "textDocument/signatureHelp" => "signatureHelpProvider",
+        "textDocument/declaration" => "declarationProvider",
+        "textDocument/definition" => "definitionProvider",
+        "textDocument/typeDefinition" => "typeDefinitionProvider",
+        "textDocument/implementation" => "implementationProvider",
+        "textDocument/references" => "referencesProvider",
+        "textDocument/documentHighlight" => "documentHighlightProvider",
+        "textDocument/documentSymbol" => "documentSymbolProvider",
+        "textDocument/codeAction" | "codeAction/resolve" => "codeActionProvider",
+        "textDocument/codeLens" | "codeLens/resolve" => "codeLensProvider",
+        "textDocument/documentLink" | "documentLink/resolve" => "documentLinkProvider",
+        "textDocument/formatting" => "documentFormattingProvider",
+        "textDocument/rangeFormatting" => "documentRangeFormattingProvider",
+        "textDocument/onTypeFormatting" => "documentOnTypeFormattingProvider",
+        "textDocument/rename" | "textDocument/prepareRename" => "renameProvider",
+        "textDocument/foldingRange" => "foldingRangeProvider",
+        "textDocument/selectionRange" => "selectionRangeProvider",
+        "textDocument/inlayHint" | "inlayHint/resolve" => "inlayHintProvider",
+        "workspace/symbol" => "workspaceSymbolProvider",
+        "workspace/executeCommand" => "executeCommandProvider",
+        _ if method.starts_with("textDocument/semanticTokens") => "semanticTokensProvider",
+        _ => return None,
+    })
+}
+
+fn is_enabled(capability: Option<&Value>) -> bool {
+    !matches!(
+        capability,
+        None | Some(Value::Null) | Some(Value::Bool(false))
+    )
+}
+
+fn has_capability(capabilities: &Value, name: &str) -> bool {
+    is_enabled(capabilities.get(name))
+}
+
+/// The `TextDocumentSyncKind` of a server, given alone or as `change` of the options
+fn sync_kind(capabilities: &Value) -> Option<u64> {
+    let sync = &capabilities["textDocumentSync"];
+    sync.as_u64().or_else(|| sync["change"].as_u64())
+}
+
+/// Merge the capabilities of the servers, the first server that has a capability wins.
+/// The commands of `executeCommandProvider` are joined.
+fn merge_capabilities<'a>(all: impl Iterator<Item = &'a Value>) -> Value {
+    let mut merged = serde_json::Map::new();
+    let mut commands = Vec::new();
+    for capabilities in all {
+        let Some(capabilities) = capabilities.as_object() else {
+            continue;
+        };
+        for (name, value) in capabilities {
+            if !is_enabled(merged.get(name)) {
+                merged.insert(name.clone(), value.clone());
+            }
+        }
+        if let Some(list) = capabilities
+            .get("executeCommandProvider")
+            .and_then(|provider| provider["commands"].as_array())
+        {
+            commands.extend(list.iter().cloned());
+        }
+    }
+    if !commands.is_empty() {
+        merged.insert(
+            "executeCommandProvider".to_string(),
+            json!({ "commands": commands }),
+        );
+    }
+    Value::Object(merged)
+}
+
+/// Byte offset of an LSP position, whose character is counted in UTF-16 code units
+fn offset_of(text: &str, position: &Value) -> Option<usize> {
+    let line = position["line"].as_u64()? as usize;
+    let character = position["character"].as_u64()? as usize;
+    let mut offset = 0;
+    for _ in 0..line {
+        offset += text[offset..].find('\n')? + 1;
+    }
+    let mut units = 0;
+    for (index, c) in text[offset..].char_indices() {
+        if units >= character || c == '\n' {
+            return Some(offset + index);
+        }
+        units += c.len_utf16();
+    }
+    Some(text.len())
+}
+
+/// An open document, kept to open it again in a restarted server
+struct OpenDocument {
+    language_id: Value,
+    version: Value,
+    text: String,
+}
+
+impl OpenDocument {
+    /// Apply the changes of `didChange`, `false` if a range can't be resolved
+    fn apply(&mut self, changes: &[Value]) -> bool {
+        apply_content_changes(&mut self.text, changes)
+    }
+}
+
+/// Apply the `contentChanges` of `didChange` to `text`, `false` if a range can't be
+/// resolved
+pub(crate) fn apply_content_changes(text: &mut String, changes: &[Value]) -> bool {
+    for change in changes {
+        let Some(new_text) = change["text"].as_str() else {
+            return false;
+        };
+        match change.get("range") {
+            None | Some(Value::Null) => *text = new_text.to_string(),
+            Some(range) => {
+                let (Some(start), Some(end)) = (
+                    offset_of(text, &range["start"]),
+                    offset_of(text, &range["end"]),
+                ) else {
+                    return false;
+                };
+                if start > end {
+                    return false;
+                }
+                text.replace_range(start..end, new_text);
+            }
+        }
+    }
+    true
+}
+
+/// A request sent to every server, answered once all of them answered
+struct FanOut {
+    /// Merge the `initialize` answers, otherwise the answer of the first server is used
+    initialize: bool,
+    answers: Vec<Option<Value>>,
+}
+
+/// Messages to deliver after a message was routed
+#[derive(Debug, Default, PartialEq)]
+pub struct Routed {
+    pub to_editor: Vec<String>,
+    /// Index of the server and the message
+    pub to_servers: Vec<(usize, String)>,
+}
+
+pub struct LspMultiplexer {
+    /// Used as the `source` of diagnostics that have none
+    names: Vec<String>,
+    alive: Vec<bool>,
+    /// `None` until the server answered `initialize`
+    capabilities: Vec<Option<Value>>,
+    /// The last `initialize` of the editor, replayed to restarted servers
+    initialize: Option<Value>,
+    initialized: bool,
+    fan_outs: HashMap<String, FanOut>,
+    documents: HashMap<String, OpenDocument>,
+    /// Diagnostics of every server per document
+    diagnostics: HashMap<String, Vec<Vec<Value>>>,
+    /// Rewritten id of a server request to the server and its own id
+    server_requests: HashMap<String, (usize, Value)>,
+    next_request: u64,
+    /// Server that answered the last completion, it resolves the items
+    completion_server: usize,
+}
+
+impl LspMultiplexer {
+    /// # Arguments
+    /// * `names` - Names of the servers, the first one is preferred for every request
+    pub fn new(names: Vec<String>) -> Self {
+        let count = names.len();
+        Self {
+            names,
+            alive: vec![true; count],
+            capabilities: vec![None; count],
+            initialize: None,
+            initialized: false,
+            fan_outs: HashMap::new(),
+            documents: HashMap::new(),
+            diagnostics: HashMap::new(),
+            server_requests: HashMap::new(),
+            next_request: 0,
+            completion_server: 0,
+        }
+    }
+
+    fn alive_servers(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.alive.len()).filter(|&index| self.alive[index])
+    }
+
+    fn first_alive(&self) -> usize {
+        self.alive_servers().next().unwrap_or(0)
+    }
+
+    /// Pick the server of a request of the editor
+    fn route_request(&mut self, method: &str, params: &Value) -> usize {
+        if method == "completionItem/resolve" && self.alive[self.completion_server] {
+            return self.completion_server;
+        }
+        let Some(capability) = required_capability(method) else {
+            return self.first_alive();
+        };
+        let server = self
+            .alive_servers()
+            .find(|&index| {
+                let Some(capabilities) = &self.capabilities[index] else {
+                    return false;
+                };
+                if method == "workspace/executeCommand" {
+                    return capabilities[capability]["commands"]
+                        .as_array()
+                        .is_some_and(|commands| commands.contains(&params["command"]));
+                }
+                has_capability(capabilities, capability)
+            })
+            .unwrap_or_else(|| self.first_alive());
+        if method == "textDocument/completion" {
+            self.completion_server = server;
+        }
+        server
+    }
+
+    /// Route a message of the editor
+    pub fn from_editor(&mut self, message: &str) -> Routed {
+        let mut routed = Routed::default();
+        let Ok(value) = serde_json::from_str::<Value>(message) else {
+            routed
+                .to_servers
+                .push((self.first_alive(), message.to_string()));
+            return routed;
+        };
+        let method = value["method"].as_str();
+        match (method, value.get("id")) {
+            (Some(method @ ("initialize" | "shutdown")), Some(id)) => {
+                if method == "initialize" {
+                    self.initialize = Some(value.clone());
+                }
+                let answers = (0..self.alive.len())
+                    .map(|index| (!self.alive[index]).then_some(Value::Null))
+                    .collect();
+                self.fan_outs.insert(
+                    id.to_string(),
+                    FanOut {
+                        initialize: method == "initialize",
+                        answers,
+                    },
+                );
+                for index in self.alive_servers() {
+                    routed.to_servers.push((index, message.to_string()));
+                }
+            }
+            (Some(method), Some(_)) => {
+                let server = self.route_request(method, &value["params"]);
+                routed.to_servers.push((server, message.to_string()));
+            }
+            (None, Some(id)) => {
+                let (server, message) =
+                    match id.as_str().and_then(|id| self.server_requests.remove(id)) {
+                        Some((server, original)) => {
+                            let mut value = value.clone();
+                            value["id"] = original;
+                            (server, value.to_string())
+                        }
+                        None => (self.first_alive(), message.to_string()),
+                    };
+                routed.to_servers.push((server, message));
+            }
+            (Some(method), None) => {
+                self.track_document(method, &value["params"]);
+                if method == "initialized" {
+                    self.initialized = true;
+                }
+                let full_change = (method == "textDocument/didChange")
+                    .then(|| self.full_change(&value))
+                    .flatten();
+                for index in self.alive_servers() {
+                    let message = match &full_change {
+                        Some(full_change)
+                            if self.capabilities[index]
+                                .as_ref()
+                                .and_then(sync_kind)
+                                .is_some_and(|kind| kind == SYNC_FULL) =>
+                        {
+                            full_change.clone()
+                        }
+                        _ => message.to_string(),
+                    };
+                    routed.to_servers.push((index, message));
+                }
+            }
+            (None, None) => {}
+        }
+        routed
+    }
+
+    fn track_document(&mut self, method: &str, params: &Value) {
+        let Some(uri) = params["textDocument"]["uri"].as_str() else {
+            return;
+        };
+        match method {
+            "textDocument/didOpen" => {
+                let document = &params["textDocument"];
+                self.documents.insert(
+                    uri.to_string(),
+                    OpenDocument {
+                        language_id: document["languageId"].clone(),
+                        version: document["version"].clone(),
+                        text: document["text"].as_str().unwrap_or_default().to_string(),
+                    },
+                );
+            }
+            "textDocument/didChange" => {
+                let Some(document) = self.documents.get_mut(uri) else {
+                    return;
+                };
+                document.version = params["textDocument"]["version"].clone();
+                let changes = params["contentChanges"].as_array();
+                if !changes.is_some_and(|changes| document.apply(changes)) {
+                    log::warn!("lost track of {}, it is not reopened after a restart", uri);
+                    self.documents.remove(uri);
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                self.diagnostics.remove(uri);
+            }
+            _ => {}
+        }
+    }
+
+    /// A `didChange` with ranges as one change of the whole text, for servers that sync
+    /// in full. `None` if it has no range or the document isn't tracked.
+    fn full_change(&self, value: &Value) -> Option<String> {
+        let params = &value["params"];
+        let has_range = params["contentChanges"].as_array().is_some_and(|changes| {
+            changes
+                .iter()
+                .any(|change| change.get("range").is_some_and(|range| !range.is_null()))
+        });
+        if !has_range {
+            return None;
+        }
+        let uri = params["textDocument"]["uri"].as_str()?;
+        let document = self.documents.get(uri)?;
+        let mut value = value.clone();
+        value["params"]["contentChanges"] = json!([{ "text": document.text }]);
+        Some(value.to_string())
+    }
+
+    /// Route a message of a server
+    pub fn from_server(&mut self, server: usize, message: &str) -> Routed {
+        let mut routed = Routed::default();
+        let Ok(mut value) = serde_json::from_str::<Value>(message) else {
+            routed.to_editor.push(message.to_string());
+            return routed;
+        };
+        let method = value["method"].as_str().map(str::to_string);
+        match (method.as_deref(), value.get("id").cloned()) {
+            (None, Some(id)) if id == REINITIALIZE_ID => {
+                self.capabilities[server] = Some(value["result"]["capabilities"].clone());
+                routed.to_servers = self.reopen(server);
+            }
+            (None, Some(id)) => {
+                let key = id.to_string();
+                match self.fan_outs.get_mut(&key) {
+                    Some(fan_out) => {
+                        if fan_out.initialize {
+                            self.capabilities[server] =
+                                Some(value["result"]["capabilities"].clone());
+                        }
+                        fan_out.answers[server] = Some(value);
+                        routed.to_editor.extend(self.settle(&key));
+                    }
+                    None => routed.to_editor.push(message.to_string()),
+                }
+            }
+            (Some(_), Some(id)) => {
+                let rewritten = format!("{}{}", SERVER_REQUEST_ID_PREFIX, self.next_request);
+                self.next_request += 1;
+                self.server_requests.insert(rewritten.clone(), (server, id));
+                value["id"] = Value::String(rewritten);
+                routed.to_editor.push(value.to_string());
+            }
+            (Some("textDocument/publishDiagnostics"), None) => {
+                let Some(uri) = value["params"]["uri"].as_str().map(str::to_string) else {
+                    routed.to_editor.push(message.to_string());
+                    return routed;
+                };
+                let mut diagnostics = value["params"]["diagnostics"]
+                    .as_array()
+                    .cloned()
+                    .unwrap_or_default();
+                for diagnostic in &mut diagnostics {
+                    if diagnostic.get("source").is_none_or(Value::is_null) {
+                        diagnostic["source"] = Value::String(self.names[server].clone());
+                    }
+                }
+                let count = self.names.len();
+                self.diagnostics
+                    .entry(uri.clone())
+                    .or_insert_with(|| vec![Vec::new(); count])[server] = diagnostics;
+                routed
+                    .to_editor
+                    .push(self.merged_diagnostics(&uri, &value["params"]));
+            }
+            _ => routed.to_editor.push(message.to_string()),
+        }
+        routed
+    }
+
+    /// The diagnostics of every server for a document, `source` gives the version
+    fn merged_diagnostics(&self, uri: &str, source: &Value) -> String {
+        let diagnostics: Vec<&Value> = self
+            .diagnostics
+            .get(uri)
+            .map(|all| all.iter().flatten().collect())
+            .unwrap_or_default();
+        let mut params = json!({ "uri": uri, "diagnostics": diagnostics });
+        if let Some(version) = source.get("version").filter(|version| !version.is_null()) {
+            params["version"] = version.clone();
+        }
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": params,
+        })
+        .to_string()
+    }
+
+    /// Answer a fan out request once every server answered
+    fn settle(&mut self, key: &str) -> Option<String> {
+        let fan_out = self.fan_outs.get(key)?;
+        if fan_out.answers.iter().any(Option::is_none) {
+            return None;
+        }
+        let fan_out = self.fan_outs.remove(key)?;
+        let answers: Vec<&Value> = fan_out
+            .answers
+            .iter()
+            .flatten()
+            .filter(|answer| !answer.is_null())
+            .collect();
+        let first = answers
+            .iter()
+            .find(|answer| answer.get("result").is_some())
+            .or(answers.first())?;
+        if !fan_out.initialize || first.get("result").is_none() {
+            return Some(first.to_string());
+        }
+        let mut merged = (*first).clone();
+        merged["result"]["capabilities"] = merge_capabilities(
+            answers
+                .iter()
+                .filter_map(|answer| answer.get("result"))
+                .map(|result| &result["capabilities"]),
+        );
+        Some(merged.to_string())
+    }
+
+    /// Forget what a server said, when it stopped or restarted
+    fn forget(&mut self, server: usize) -> Routed {
+        let mut routed = Routed::default();
+        self.capabilities[server] = None;
+        let keys: Vec<String> = self.fan_outs.keys().cloned().collect();
+        for key in keys {
+            if let Some(fan_out) = self.fan_outs.get_mut(&key) {
+                fan_out.answers[server].get_or_insert(Value::Null);
+            }
+            routed.to_editor.extend(self.settle(&key));
+        }
+        let uris: Vec<String> = self
+            .diagnostics
+            .iter()
+            .filter(|(_, all)| !all[server].is_empty())
+            .map(|(uri, _)| uri.clone())
+            .collect();
+        for uri in uris {
+            if let Some(all) = self.diagnostics.get_mut(&uri) {
+                all[server].clear();
+            }
+            routed
+                .to_editor
+                .push(self.merged_diagnostics(&uri, &Value::Null));
+        }
+        self.server_requests
+            .retain(|_, (index, _)| *index != server);
+        routed
+    }
+
+    /// A server crashed and was started again: initialize it with the `initialize` of the
+    /// editor, then open the documents again once it answered
+    pub fn restarted(&mut self, server: usize) -> Routed {
+        let mut routed = self.forget(server);
+        if let Some(initialize) = &self.initialize {
+            let mut initialize = initialize.clone();
+            initialize["id"] = json!(REINITIALIZE_ID);
+            routed.to_servers.push((server, initialize.to_string()));
+        }
+        routed
+    }
+
+    /// Messages that bring a reinitialized server up to date
+    fn reopen(&self, server: usize) -> Vec<(usize, String)> {
+        let mut messages = Vec::new();
+        if !self.initialized {
+            return messages;
+        }
+        let initialized = json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} });
+        messages.push((server, initialized.to_string()));
+        for (uri, document) in &self.documents {
+            let open = json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/didOpen",
+                "params": { "textDocument": {
+                    "uri": uri,
+                    "languageId": document.language_id,
+                    "version": document.version,
+                    "text": document.text,
+                } },
+            });
+            messages.push((server, open.to_string()));
+        }
+        messages
+    }
+
+    /// A server is gone for good, requests go to the others
+    pub fn stopped(&mut self, server: usize) -> Routed {
+        self.alive[server] = false;
+        self.forget(server)
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        !self.alive.iter().any(|&alive| alive)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(message: &str) -> Value {
+        serde_json::from_str(message).unwrap()
+    }
+
+    /// Initialize a checker with hover and a linter with formatting
+    fn initialized() -> LspMultiplexer {
+        let mut mux = LspMultiplexer::new(vec!["pyright".to_string(), "ruff".to_string()]);
+        let initialize = json!({ "jsonrpc": "2.0", "id": 0, "method": "initialize", "params": {} });
+        let routed = mux.from_editor(&initialize.to_string());
+        assert_eq!(routed.to_servers.len(), 2);
+
+        let answer = |capabilities: Value| {
+            json!({ "jsonrpc": "2.0", "id": 0, "result": { "capabilities": capabilities } })
+                .to_string()
+        };
+        let routed = mux.from_server(
+            1,
+            &answer(json!({ "hoverProvider": false, "documentFormattingProvider": true })),
+        );
+        assert!(routed.to_editor.is_empty());
+        let routed = mux.from_server(0, &answer(json!({ "hoverProvider": true })));
+        let merged = parse(&routed.to_editor[0]);
+        assert_eq!(
+            merged["result"]["capabilities"],
+            json!({ "hoverProvider": true, "documentFormattingProvider": true })
+        );
+        mux.from_editor(r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#);
+        mux
+    }
+
+    #[test]
+    fn test_route_by_capability() {
+        let mut mux = initialized();
+        let request = |id: u32, method: &str| {
+            json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": {} }).to_string()
+        };
+        assert_eq!(
+            mux.from_editor(&request(1, "textDocument/hover"))
+                .to_servers[0]
+                .0,
+            0
+        );
+        assert_eq!(
+            mux.from_editor(&request(2, "textDocument/formatting"))
+                .to_servers[0]
+                .0,
+            1
+        );
+
+        let request =
+            json!({ "jsonrpc": "2.0", "id": 7, "method": "workspace/configuration", "params": {} });
+        let routed = mux.from_server(1, &request.to_string());
+        let id = parse(&routed.to_editor[0])["id"].clone();
+        let answer = json!({ "jsonrpc": "2.0", "id": id, "result": [] });
+        let routed = mux.from_editor(&answer.to_string());
+        assert_eq!(routed.to_servers[0].0, 1);
+        assert_eq!(parse(&routed.to_servers[0].1)["id"], 7);
+    }
+
+    #[test]
+    fn test_merge_diagnostics() {
+        let mut mux = initialized();
+        let publish = |message: &str| {
+            json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/publishDiagnostics",
+                "params": { "uri": "file:///a.py", "diagnostics": [{ "message": message }] },
+            })
+            .to_string()
+        };
+        mux.from_server(0, &publish("type error"));
+        let routed = mux.from_server(1, &publish("unused import"));
+        let diagnostics = &parse(&routed.to_editor[0])["params"]["diagnostics"];
+        assert_eq!(
+            diagnostics,
+            &json!([
+                { "message": "type error", "source": "pyright" },
+                { "message": "unused import", "source": "ruff" },
+            ])
+        );
+
+        let routed = mux.stopped(1);
+        let diagnostics = &parse(&routed.to_editor[0])["params"]["diagnostics"];
+        assert_eq!(diagnostics.as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_reopen_after_restart() {
+        let mut mux = initialized();
+        let open = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": "file:///a.py", "languageId": "python", "version": 1, "text": "print(1)\n" } },
+        });
+        assert_eq!(mux.from_editor(&open.to_string()).to_servers.len(), 2);
+        let change = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didChange",
+            "params": {
+                "textDocument": { "uri": "file:///a.py", "version": 2 },
+                "contentChanges": [{ "range": { "start": { "line": 0, "character": 6 }, "end": { "line": 0, "character": 7 } }, "text": "\"é\"" }],
+            },
+        });
+        mux.from_editor(&change.to_string());
+
+        let routed = mux.restarted(1);
+        assert_eq!(parse(&routed.to_servers[0].1)["id"], REINITIALIZE_ID);
+        let answer =
+            json!({ "jsonrpc": "2.0", "id": REINITIALIZE_ID, "result": { "capabilities": {} } });
+        let routed = mux.from_server(1, &answer.to_string());
+        assert!(routed.to_editor.is_empty());
+        assert_eq!(parse(&routed.to_servers[0].1)["method"], "initialized");
+        let reopened = parse(&routed.to_servers[1].1);
+        assert_eq!(reopened["params"]["textDocument"]["text"], "print(\"é\")\n");
+        assert_eq!(reopened["params"]["textDocument"]["version"], 2);
+    }
+
+    #[test]
+    fn test_change_for_full_sync() {
+        let mut mux = LspMultiplexer::new(vec!["clangd".to_string(), "cpplint".to_string()]);
+        let initialize = json!({ "jsonrpc": "2.0", "id": 0, "method": "initialize", "params": {} });
+        mux.from_editor(&initialize.to_string());
+        let answer = |capabilities: Value| {
+            json!({ "jsonrpc": "2.0", "id": 0, "result": { "capabilities": capabilities } })
+                .to_string()
+        };
+        mux.from_server(0, &answer(json!({ "textDocumentSync": 2 })));
+        let routed = mux.from_server(
+            1,
+            &answer(json!({ "textDocumentSync": { "openClose": true, "change": 1 } })),
+        );
+        assert_eq!(
+            parse(&routed.to_editor[0])["result"]["capabilities"]["textDocumentSync"],
+            2
+        );
+
+        let open = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": "file:///a.cpp", "languageId": "cpp", "version": 1, "text": "int a;\n" } },
+        });
+        mux.from_editor(&open.to_string());
+        let change = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didChange",
+            "params": {
+                "textDocument": { "uri": "file:///a.cpp", "version": 2 },
+                "contentChanges": [{ "range": { "start": { "line": 0, "character": 4 }, "end": { "line": 0, "character": 5 } }, "text": "b" }],
+            },
+        });
+        let routed = mux.from_editor(&change.to_string());
+        assert_eq!(routed.to_servers[0], (0, change.to_string()));
+        let full = parse(&routed.to_servers[1].1);
+        assert_eq!(routed.to_servers[1].0, 1);
+        assert_eq!(
+            full["params"]["contentChanges"],
+            json!([{ "text": "int b;\n" }])
+        );
+        assert_eq!(full["params"]["textDocument"]["version"], 2);
+    }
+}
@@ -0,0 +1,146 @@
+/// Language server settings applied on behalf of the editor
+/// The editor doesn't know the settings of a language, so the options are merged into
+/// the `initialize` request here and `workspace/configuration` requests are answered
+/// without a round trip through the editor.
+use serde_json::{json, Value};
+
+use crate::database::config::LanguageServerSettings;
+
+const CONFIGURATION_METHOD: &str = "workspace/configuration";
+
+/// Merge `patch` into `target`, objects are merged key by key and anything else replaced
+fn merge(target: &mut Value, patch: &Value) {
+    match (target, patch) {
+        (Value::Object(target), Value::Object(patch)) => {
+            for (key, value) in patch {
+                merge(target.entry(key.clone()).or_insert(Value::Null), value);
+            }
+        }
+        (target, patch) => *target = patch.clone(),
+    }
+}
+
+/// Merge the initialization options into an `initialize` request, and announce that
+/// `workspace/configuration` is supported when there is a configuration
+///
+/// # Returns
+/// * `bool` - Whether the message is an `initialize` request
+pub fn apply_to_initialize(message: &mut Value, settings: &LanguageServerSettings) -> bool {
+    if message["method"] != "initialize" {
+        return false;
+    }
+    let params = &mut message["params"];
+    if let Some(options) = &settings.initialization_options {
+        merge(params, &json!({ "initializationOptions": options }));
+    }
+    if settings.configuration.is_some() {
+        merge(
+            params,
+            &json!({ "capabilities": { "workspace": { "configuration": true } } }),
+        );
+    }
+    true
+}
+
+/// Look up a section, nested (`{"python": {"analysis": ...}}`) or by its dotted name
+/// (`{"python.analysis": ...}`). No section means the whole configuration.
+fn lookup_section(configuration: &Value, section: Option<&str>) -> Value {
+    let Some(section) = section.filter(|section| !section.is_empty()) else {
+        return configuration.clone();
+    };
+    if let Some(value) = configuration.get(section) {
+        return value.clone();
+    }
+    section
+        .split('.')
+        .try_fold(configuration, |value, key| value.get(key))
+        .cloned()
+        .unwrap_or(Value::Null)
+}
+
+/// Answer a `workspace/configuration` request of the server
+///
+/// # Returns
+/// * `Option<String>` - The response, `None` if the message is not such a request
+pub fn answer_configuration(message: &str, configuration: &Value) -> Option<String> {
+    if !message.contains(CONFIGURATION_METHOD) {
+        return None;
+    }
+    let request = serde_json::from_str::<Value>(message).ok()?;
+    if request["method"] != CONFIGURATION_METHOD {
+        return None;
+    }
+    let id = request.get("id")?;
+    let result: Vec<Value> = request["params"]["items"]
+        .as_array()
+        .map(|items| {
+            items
+                .iter()
+                .map(|item| lookup_section(configuration, item["section"].as_str()))
+                .collect()
+        })
+        .unwrap_or_default();
+    Some(json!({ "jsonrpc": "2.0", "id": id, "result": result }).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_to_initialize() {
+        let settings = LanguageServerSettings {
+            initialization_options: Some(json!({ "fallbackFlags": ["-std=c++17"] })),
+            configuration: Some(json!({})),
+        };
+        let mut message = json!({
+            "id": 0,
+            "method": "initialize",
+            "params": { "capabilities": { "workspace": {} }, "initializationOptions": { "settings": {} } },
+        });
+        assert!(apply_to_initialize(&mut message, &settings));
+        assert_eq!(
+            message["params"]["initializationOptions"],
+            json!({ "settings": {}, "fallbackFlags": ["-std=c++17"] })
+        );
+        assert_eq!(
+            message["params"]["capabilities"]["workspace"]["configuration"],
+            true
+        );
+
+        let mut message = json!({ "method": "initialized", "params": {} });
+        assert!(!apply_to_initialize(&mut message, &settings));
+    }
+
+    #[test]
+    fn test_answer_configuration() {
+        let configuration = json!({
+            "python": { "analysis": { "typeCheckingMode": "strict" } },
+            "clangd.arguments": ["--header-insertion=never"],
+        });
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 3,
+            "method": "workspace/configuration",
+            "params": { "items": [
+                { "section": "python.analysis" },
+                { "section": "clangd.arguments" },
+                { "section": "missing" },
+            ] },
+        });
+        let response: Value = serde_json::from_str(
+            &answer_configuration(&request.to_string(), &configuration).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(response["id"], 3);
+        assert_eq!(
+            response["result"],
+            json!([
+                { "typeCheckingMode": "strict" },
+                ["--header-insertion=never"],
+                null,
+            ])
+        );
+        assert!(answer_configuration(r#"{"id":1,"result":null}"#, &configuration).is_none());
+    }
+}
@@ -0,0 +1,385 @@
+/// On-disk workspace for language servers
+/// Editors address documents with virtual URIs like `file:///<document id>.cpp`. This
+/// module mirrors those documents to real files in the directory of their problem,
+/// writes `compile_flags.txt` and `compile_commands.json` next to them, and rewrites
+/// URIs in LSP messages in both directions.
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::mpsc,
+};
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use serde_json::Value;
+use url::Url;
+
+use super::lsp_mux::apply_content_changes;
+
+const VIRTUAL_URI_PREFIX: &str = "file:///";
+const COMPILE_FLAGS_FILE: &str = "compile_flags.txt";
+const COMPILE_COMMANDS_FILE: &str = "compile_commands.json";
+
+/// How a mirrored file is compiled, for servers like clangd
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileArguments {
+    pub compiler: String,
+    /// Flags without the source file and the output
+    pub flags: Vec<String>,
+}
+
+impl CompileArguments {
+    /// Pick the compiler and flags out of a compile command. The step that compiles
+    /// `source` is used, `-o <output>`, `-c` and the source itself are dropped.
+    ///
+    /// # Arguments
+    /// * `steps` - The programs and arguments of the expanded compile command
+    /// * `source` - The source file passed to the compiler
+    pub fn from_steps(steps: &[(String, Vec<String>)], source: &str) -> Option<Self> {
+        let (compiler, args) = steps
+            .iter()
+            .find(|(_, args)| args.iter().any(|arg| arg == source))
+            .or(steps.first())?;
+        let mut flags = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-o" => {
+                    args.next();
+                }
+                "-c" => {}
+                _ if arg == source || arg.starts_with("-o") => {}
+                _ => flags.push(arg.clone()),
+            }
+        }
+        Some(Self {
+            compiler: compiler.clone(),
+            flags,
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct CompileCommand<'a> {
+    directory: &'a Path,
+    file: &'a Path,
+    arguments: Vec<&'a str>,
+}
+
+#[derive(Debug)]
+struct MirroredFile {
+    path: PathBuf,
+    uri: String,
+    compile: Option<CompileArguments>,
+    /// The text the editor has, kept to apply ranged changes
+    text: String,
+}
+
+/// Writes mirrored files on a thread of its own, so typing doesn't wait for the disk.
+/// Only the latest text of a file is written when writes pile up.
+#[derive(Debug, Default)]
+struct MirrorWriter {
+    sender: Option<mpsc::Sender<(PathBuf, String)>>,
+}
+
+impl MirrorWriter {
+    fn write(&mut self, path: PathBuf, text: String) {
+        let pending = match &self.sender {
+            Some(sender) => match sender.send((path, text)) {
+                Ok(()) => return,
+                Err(mpsc::SendError(pending)) => pending,
+            },
+            None => (path, text),
+        };
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || write_mirrors(receiver));
+        let _ = sender.send(pending);
+        self.sender = Some(sender);
+    }
+}
+
+fn write_mirrors(receiver: mpsc::Receiver<(PathBuf, String)>) {
+    while let Ok((path, text)) = receiver.recv() {
+        let mut latest = HashMap::from([(path, text)]);
+        latest.extend(receiver.try_iter());
+        for (path, text) in latest {
+            if let Err(e) = std::fs::write(&path, text) {
+                log::warn!("failed to write {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+/// Virtual documents mirrored to disk, one per language server
+#[derive(Debug, Default)]
+pub struct LspWorkspace {
+    /// Virtual URI to the mirrored file
+    files: HashMap<String, MirroredFile>,
+    /// URI of the mirrored file to the virtual URI
+    virtual_uris: HashMap<String, String>,
+    writer: MirrorWriter,
+}
+
+/// Whether a string is a virtual URI: a single file name right under the root
+pub fn is_virtual_uri(value: &str) -> bool {
+    value
+        .strip_prefix(VIRTUAL_URI_PREFIX)
+        .is_some_and(|name| !name.is_empty() && !name.contains(['/', '\\', ':']))
+}
+
+/// The file name of a virtual URI, e.g. `<document id>.cpp`
+pub fn virtual_file_name(uri: &str) -> Option<&str> {
+    uri.strip_prefix(VIRTUAL_URI_PREFIX)
+        .filter(|_| is_virtual_uri(uri))
+}
+
+/// Every virtual URI in a message, in keys as well, since `WorkspaceEdit.changes` is
+/// keyed by URI
+pub fn find_virtual_uris(message: &Value) -> Vec<String> {
+    let mut uris = Vec::new();
+    visit_strings(message, &mut |s| {
+        if is_virtual_uri(s) && !uris.iter().any(|uri| uri == s) {
+            uris.push(s.to_string());
+        }
+    });
+    uris
+}
+
+fn visit_strings<F: FnMut(&str)>(value: &Value, f: &mut F) {
+    match value {
+        Value::String(s) => f(s),
+        Value::Array(items) => items.iter().for_each(|item| visit_strings(item, f)),
+        Value::Object(map) => {
+            for (key, item) in map {
+                f(key);
+                visit_strings(item, f);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Replace every string, and every key, that `map` knows
+fn rewrite_strings<F: Fn(&str) -> Option<String>>(value: &mut Value, map: &F) {
+    match value {
+        Value::String(s) => {
+            if let Some(replaced) = map(s) {
+                *s = replaced;
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|item| rewrite_strings(item, map)),
+        Value::Object(object) => {
+            let entries = std::mem::take(object);
+            for (key, mut item) in entries {
+                rewrite_strings(&mut item, map);
+                object.insert(map(&key).unwrap_or(key), item);
+            }
+        }
+        _ => {}
+    }
+}
+
+impl LspWorkspace {
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn is_registered(&self, virtual_uri: &str) -> bool {
+        self.files.contains_key(virtual_uri)
+    }
+
+    /// Mirror a virtual document to `dir` and refresh the compile flags of that directory
+    ///
+    /// # Arguments
+    /// * `virtual_uri` - The URI used by the editor
+    /// * `dir` - The directory of the problem that owns the document
+    /// * `compile` - How to compile the file, `None` for languages without compile flags
+    pub fn register(
+        &mut self,
+        virtual_uri: &str,
+        dir: &Path,
+        compile: Option<CompileArguments>,
+    ) -> Result<()> {
+        let name = virtual_file_name(virtual_uri)
+            .ok_or_else(|| anyhow!("not a virtual uri: {}", virtual_uri))?;
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join(name);
+        if !path.exists() {
+            std::fs::write(&path, "")?;
+        }
+        let uri = Url::from_file_path(&path)
+            .map_err(|_| anyhow!("invalid path: {}", path.display()))?
+            .to_string();
+
+        self.virtual_uris
+            .insert(uri.clone(), virtual_uri.to_string());
+        self.files.insert(
+            virtual_uri.to_string(),
+            MirroredFile {
+                path,
+                uri,
+                compile,
+                text: String::new(),
+            },
+        );
+        self.write_compile_database(dir)
+    }
+
+    /// Write `compile_commands.json` with every mirrored file of `dir`, and
+    /// `compile_flags.txt` for files that are not listed. The files of a server share one
+    /// language, so any of them gives the flags.
+    fn write_compile_database(&self, dir: &Path) -> Result<()> {
+        let files: Vec<(&MirroredFile, &CompileArguments)> = self
+            .files
+            .values()
+            .filter(|file| file.path.parent() == Some(dir))
+            .filter_map(|file| file.compile.as_ref().map(|compile| (file, compile)))
+            .collect();
+        let Some((_, any)) = files.first() else {
+            return Ok(());
+        };
+        let commands: Vec<CompileCommand> = files
+            .iter()
+            .map(|(file, compile)| CompileCommand {
+                directory: dir,
+                file: &file.path,
+                arguments: std::iter::once(compile.compiler.as_str())
+                    .chain(compile.flags.iter().map(String::as_str))
+                    .chain(file.path.to_str())
+                    .collect(),
+            })
+            .collect();
+        std::fs::write(
+            dir.join(COMPILE_COMMANDS_FILE),
+            serde_json::to_string_pretty(&commands)?,
+        )?;
+        std::fs::write(dir.join(COMPILE_FLAGS_FILE), any.flags.join("\n"))?;
+        Ok(())
+    }
+
+    /// Apply `didOpen` and `didChange` to the text of the mirrored file and write it in
+    /// the background. Call it before [`Self::to_server`].
+    pub fn mirror_content(&mut self, message: &Value) -> Result<()> {
+        let params = &message["params"];
+        let Some(file) = params["textDocument"]["uri"]
+            .as_str()
+            .and_then(|uri| self.files.get_mut(uri))
+        else {
+            return Ok(());
+        };
+        match message["method"].as_str() {
+            Some("textDocument/didOpen") => {
+                let Some(text) = params["textDocument"]["text"].as_str() else {
+                    return Ok(());
+                };
+                file.text = text.to_string();
+            }
+            Some("textDocument/didChange") => {
+                let changes = params["contentChanges"]
+                    .as_array()
+                    .map_or(&[][..], Vec::as_slice);
+                let mut text = file.text.clone();
+                if !apply_content_changes(&mut text, changes) {
+                    return Err(anyhow!("invalid change of {}", file.path.display()));
+                }
+                file.text = text;
+            }
+            _ => return Ok(()),
+        }
+        self.writer.write(file.path.clone(), file.text.clone());
+        Ok(())
+    }
+
+    /// Rewrite virtual URIs to the mirrored files, for a message sent to the server
+    pub fn to_server(&self, message: &mut Value) {
+        rewrite_strings(message, &|s| self.files.get(s).map(|file| file.uri.clone()));
+    }
+
+    /// Rewrite URIs of mirrored files back to virtual URIs, for a message sent to the editor
+    pub fn to_editor(&self, message: &mut Value) {
+        rewrite_strings(message, &|s| self.virtual_uris.get(s).cloned());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compile_arguments() {
+        let steps = vec![(
+            "g++".to_string(),
+            ["-std=c++17", "-O2", "-o", "/p/main", "/p/a.cpp", "-lm"]
+                .map(String::from)
+                .to_vec(),
+        )];
+        let compile = CompileArguments::from_steps(&steps, "/p/a.cpp").unwrap();
+        assert_eq!(compile.compiler, "g++");
+        assert_eq!(compile.flags, vec!["-std=c++17", "-O2", "-lm"]);
+    }
+
+    #[test]
+    fn test_rewrite_uris() {
+        let dir = std::env::temp_dir().join(format!("lsp-workspace-{}", uuid::Uuid::new_v4()));
+        let mut workspace = LspWorkspace::default();
+        let compile = CompileArguments {
+            compiler: "g++".to_string(),
+            flags: vec!["-std=c++17".to_string()],
+        };
+        workspace
+            .register("file:///doc.cpp", &dir, Some(compile))
+            .unwrap();
+        let real_uri = Url::from_file_path(dir.join("doc.cpp"))
+            .unwrap()
+            .to_string();
+
+        let mut message = serde_json::json!({
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": "file:///doc.cpp", "text": "int main() {}" } },
+        });
+        assert_eq!(find_virtual_uris(&message), vec!["file:///doc.cpp"]);
+        workspace.mirror_content(&message).unwrap();
+        workspace.to_server(&mut message);
+        assert_eq!(message["params"]["textDocument"]["uri"], real_uri.as_str());
+        assert_eq!(workspace.files["file:///doc.cpp"].text, "int main() {}");
+        let change = serde_json::json!({
+            "method": "textDocument/didChange",
+            "params": {
+                "textDocument": { "uri": "file:///doc.cpp" },
+                "contentChanges": [{
+                    "range": {
+                        "start": { "line": 0, "character": 12 },
+                        "end": { "line": 0, "character": 12 },
+                    },
+                    "text": " return 0; ",
+                }],
+            },
+        });
+        workspace.mirror_content(&change).unwrap();
+        assert_eq!(
+            workspace.files["file:///doc.cpp"].text,
+            "int main() { return 0; }"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.join(COMPILE_FLAGS_FILE)).unwrap(),
+            "-std=c++17"
+        );
+
+        let mut response = serde_json::json!({ "changes": { real_uri.as_str(): [] } });
+        workspace.to_editor(&mut response);
+        assert!(response["changes"].get("file:///doc.cpp").is_some());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_mirrors_latest() {
+        let path = std::env::temp_dir().join(format!("lsp-mirror-{}.cpp", uuid::Uuid::new_v4()));
+        let (sender, receiver) = mpsc::channel();
+        sender.send((path.clone(), "a".to_string())).unwrap();
+        sender.send((path.clone(), "ab".to_string())).unwrap();
+        drop(sender);
+        write_mirrors(receiver);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "ab");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -3,9 +3,16 @@ use std::{path::PathBuf, process::Command};
 use log::trace;
 use once_cell::sync::Lazy;
 
+pub mod bench;
 pub mod cmd;
+pub mod diff;
 pub mod lang_server;
+pub mod lsp_codec;
+pub mod lsp_mux;
+pub mod lsp_settings;
+pub mod lsp_workspace;
 pub mod run;
+pub mod toolchain;
 
 pub static BUNDLED_CHECKER_NAME: Lazy<Vec<&str>> = Lazy::new(|| {
     let chks = include_str!("bundle-chk.txt").lines().collect::<Vec<_>>();
@@ -14,7 +21,7 @@ pub static BUNDLED_CHECKER_NAME: Lazy<Vec<&str>> = Lazy::new(|| {
 });
 
 pub fn get_bundled_checker_names() -> &'static [&'static str] {
-    &BUNDLED_CHECKER_NAME.as_ref()
+    BUNDLED_CHECKER_NAME.as_ref()
 }
 
 // The process is a console application that is being run without a console window. Therefore, the console handle for the application is not set.
@@ -24,6 +31,7 @@ pub const DETACHED_PROCESS: u32 = 0x00000008;
 // The new process has a new console, instead of inheriting its parent's console (the default). For more information, see Creation of a Console.
 pub const CREATE_NEW_CONSOLE: u32 = 0x00000010;
 
+#[cfg_attr(not(windows), allow(unused_variables))]
 pub fn command_flag_hide_new_console(command: &mut Command) {
     #[cfg(all(debug_assertions, windows))]
     {
@@ -38,6 +46,7 @@ pub fn command_flag_hide_new_console(command: &mut Command) {
     }
 }
 
+#[cfg_attr(not(windows), allow(unused_variables))]
 pub fn command_flag_create_new_console(command: &mut Command) {
     #[cfg(windows)]
     {
@@ -46,6 +55,40 @@ pub fn command_flag_create_new_console(command: &mut Command) {
     }
 }
 
+/// Set the stack size of the child process in kilobytes.
+/// On Unix it sets `RLIMIT_STACK` before exec, capped by the hard limit.
+/// On Windows the stack size is stored in the executable header, so it must be set with linker flags
+/// (e.g. `-Wl,--stack,268435456`) and this function does nothing.
+#[cfg_attr(not(unix), allow(unused_variables))]
+pub fn command_set_stack_size(command: &mut Command, stack_size_kb: u32) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        let stack_size = stack_size_kb as libc::rlim_t * 1024;
+        unsafe {
+            command.pre_exec(move || {
+                let mut limit: libc::rlimit = std::mem::zeroed();
+                if libc::getrlimit(libc::RLIMIT_STACK, &mut limit) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                limit.rlim_cur = if limit.rlim_max == libc::RLIM_INFINITY {
+                    stack_size
+                } else {
+                    stack_size.min(limit.rlim_max)
+                };
+                if libc::setrlimit(libc::RLIMIT_STACK, &limit) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+    #[cfg(windows)]
+    {
+        log::warn!("stack size can not be changed at launch on Windows, set it with linker flags");
+    }
+}
+
 pub fn temp_dir(name: &str) -> PathBuf {
     std::env::temp_dir().join(format!("algorime-{}", name))
 }
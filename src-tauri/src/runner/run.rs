@@ -10,74 +10,139 @@ use serde::{Deserialize, Serialize};
 use specta::Type;
 use tokio::{
     fs::File,
-    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, BufWriter},
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader, BufWriter},
     time::Instant,
 };
 
-use crate::runner::command_flag_hide_new_console;
+use crate::runner::{
+    cmd::{CommandSequence, CommandStep, OutputRedirect},
+    command_flag_hide_new_console,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
 pub struct ProgramSimpleOutput {
+    /// Exit code of the last step that ran
+    exit_code: i32,
+    /// Output of all steps, concatenated
+    stdout: String,
+    stderr: String,
+    is_timeout: bool,
+    /// Output of each step that ran, the sequence stops at the first failed step
+    steps: Vec<ProgramStepOutput>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct ProgramStepOutput {
+    command: String,
     exit_code: i32,
     stdout: String,
     stderr: String,
     is_timeout: bool,
 }
 
+/// Run the steps one by one until one of them fails, the timeout is shared by all steps
 pub async fn launch_program_without_input(
-    mut cmd: Command,
+    sequence: CommandSequence,
     timeout_millis: u128,
 ) -> Result<ProgramSimpleOutput> {
+    let start_time = Instant::now();
+    let mut steps: Vec<ProgramStepOutput> = Vec::new();
+    for step in sequence.steps {
+        let remaining = timeout_millis.saturating_sub(start_time.elapsed().as_millis());
+        let output = launch_step_without_input(step, remaining).await?;
+        let is_failed = output.is_timeout || output.exit_code != 0;
+        steps.push(output);
+        if is_failed {
+            break;
+        }
+    }
+
+    let last = steps.last().ok_or(anyhow::anyhow!("Empty command"))?;
+    Ok(ProgramSimpleOutput {
+        exit_code: last.exit_code,
+        is_timeout: last.is_timeout,
+        stdout: steps.iter().map(|s| s.stdout.as_str()).collect(),
+        stderr: steps.iter().map(|s| s.stderr.as_str()).collect(),
+        steps,
+    })
+}
+
+fn redirect_to_stdio(redirect: Option<OutputRedirect>) -> Result<Stdio> {
+    let Some(redirect) = redirect else {
+        return Ok(Stdio::piped());
+    };
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(redirect.append)
+        .truncate(!redirect.append)
+        .open(&redirect.path)?;
+    Ok(Stdio::from(file))
+}
+
+async fn read_all<R: AsyncRead + Unpin>(reader: Option<R>) -> Result<String> {
+    let mut buf = Vec::new();
+    if let Some(reader) = reader {
+        BufReader::new(reader).read_to_end(&mut buf).await?;
+    }
+    Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
+async fn launch_step_without_input(
+    step: CommandStep,
+    timeout_millis: u128,
+) -> Result<ProgramStepOutput> {
+    let command = step.to_string();
+    let CommandStep {
+        command: mut cmd,
+        stdin,
+        stdout,
+        stderr,
+    } = step;
     command_flag_hide_new_console(&mut cmd);
     let mut cmd = tokio::process::Command::from(cmd);
+    let stdin = match stdin {
+        Some(path) => Stdio::from(std::fs::File::open(path)?),
+        None => Stdio::null(),
+    };
     cmd.kill_on_drop(true)
-        .stderr(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stdin(Stdio::piped());
+        .stdin(stdin)
+        .stdout(redirect_to_stdio(stdout)?)
+        .stderr(redirect_to_stdio(stderr)?);
     trace!("spawn program: {:?}", &cmd);
     let mut child = cmd.spawn()?;
     let pid = child.id().unwrap_or(0);
     trace!("pid: {}", pid);
-    let mut stdout_reader = BufReader::new(child.stdout.take().unwrap());
-    let mut stderr_reader = BufReader::new(child.stderr.take().unwrap());
+    let stdout_reader = child.stdout.take();
+    let stderr_reader = child.stderr.take();
 
-    let mut is_timeout = false;
-    let start_time = Instant::now();
-    loop {
-        tokio::select! {
-            _ = tokio::time::sleep(Duration::from_millis(200)) => {
-                if start_time.elapsed().as_millis() > timeout_millis {
-                    is_timeout = true;
-                    trace!("timeout! kill process {}", pid);
-                    child.kill().await?;
-                    break;
-                }
-            }
-            Ok(_) = child.wait() => {
-                trace!("program exited");
-                break;
+    // Read the pipes while waiting, a chatty program would block on a full pipe otherwise
+    let wait = async {
+        let timeout = Duration::from_millis(timeout_millis as u64);
+        match tokio::time::timeout(timeout, child.wait()).await {
+            Ok(status) => Ok::<_, anyhow::Error>((status?, false)),
+            Err(_) => {
+                trace!("timeout! kill process {}", pid);
+                child.kill().await?;
+                Ok((child.wait().await?, true))
             }
         }
-    }
-    let exit_code = child.wait().await?.code().unwrap_or(-1);
-    trace!("process {} exit code: {}", pid, exit_code);
-
-    let mut stdout = String::new();
-    let mut stderr = String::new();
-    let (stdout_result, stderr_result) = tokio::join!(
-        stdout_reader.read_to_string(&mut stdout),
-        stderr_reader.read_to_string(&mut stderr)
-    );
-    let stdout_sz = stdout_result?;
-    let stderr_sz = stderr_result?;
+    };
+    let (status, stdout, stderr) =
+        tokio::join!(wait, read_all(stdout_reader), read_all(stderr_reader));
+    let (status, is_timeout) = status?;
+    let (stdout, stderr) = (stdout?, stderr?);
+    let exit_code = status.code().unwrap_or(-1);
     trace!(
-        "collected program {} output: stdout: {} bytes, stderr: {} bytes",
+        "process {} exit code: {}, stdout: {} bytes, stderr: {} bytes",
         pid,
-        stdout_sz,
-        stderr_sz
+        exit_code,
+        stdout.len(),
+        stderr.len()
     );
 
-    Ok(ProgramSimpleOutput {
+    Ok(ProgramStepOutput {
+        command,
         exit_code,
         stdout,
         stderr,
@@ -103,12 +168,7 @@ pub enum ProgramOutput {
     },
 }
 
-pub async fn launch_program<
-    P: AsRef<Path>,
-    S: AsRef<Path>,
-    C1: FnMut(&str) -> (),
-    C2: FnMut(&str) -> (),
->(
+pub async fn launch_program<P: AsRef<Path>, S: AsRef<Path>, C1: FnMut(&str), C2: FnMut(&str)>(
     mut cmd: Command,
     input: P,
     output_file: S,
@@ -121,32 +181,15 @@ pub async fn launch_program<
     cmd.kill_on_drop(true)
         .stderr(Stdio::piped())
         .stdout(Stdio::piped())
-        .stdin(Stdio::piped());
+        // The input file is the stdin of the child, so a large input is never copied
+        .stdin(Stdio::from(std::fs::File::open(input.as_ref())?));
     trace!("spawn program: {:?}", &cmd);
     let mut child = cmd.spawn()?;
     let pid = child.id().unwrap_or(0);
-    trace!("pid: {}", pid);
+    trace!("pid: {}, input: {:?}", pid, input.as_ref());
     let stdout = child.stdout.take().unwrap();
     let stderr = child.stderr.take().unwrap();
 
-    {
-        let mut stdin = child.stdin.take().unwrap();
-
-        let mut input_reader = File::open(input.as_ref()).await?;
-        let mut buffer = [0u8; 8];
-        let mut input_sz = 0;
-        while let Ok(n) = input_reader.read(&mut buffer).await {
-            if n == 0 {
-                break;
-            }
-            input_sz += n as u64;
-            stdin.write_all(&buffer[..n]).await?;
-        }
-        stdin.flush().await?;
-        stdin.shutdown().await?;
-        trace!("redirect input {} bytes to process {}", input_sz, pid);
-    }
-
     let mut stdout_stream = BufReader::new(stdout).lines();
     let mut stderr_stream = BufReader::new(stderr).lines();
     let parent = output_file.as_ref().parent();
@@ -227,3 +270,34 @@ pub async fn launch_program<
         })
     }
 }
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::runner::cmd::parse_command_sequence_with_env;
+
+    #[test]
+    fn test_launch_sequence() {
+        let dir = std::env::temp_dir().join(format!("algorime-test-seq-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut sequence = parse_command_sequence_with_env(
+            "echo one && echo two > out.txt && cat out.txt && false && echo never",
+            &HashMap::new(),
+        )
+        .unwrap();
+        sequence.current_dir(&dir);
+
+        let output =
+            tauri::async_runtime::block_on(launch_program_without_input(sequence, 5000)).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(output.steps.len(), 4);
+        assert_eq!(output.steps[0].stdout, "one\n");
+        assert_eq!(output.steps[1].stdout, "");
+        assert_eq!(output.steps[2].stdout, "two\n");
+        assert_eq!(output.stdout, "one\ntwo\n");
+        assert_ne!(output.exit_code, 0);
+    }
+}
@@ -0,0 +1,568 @@
+/// Toolchain detection
+/// This module looks for compilers, interpreters and language servers in PATH, reads
+/// their versions and proposes language presets for the tools it found.
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+
+use log::trace;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::task::JoinSet;
+
+use crate::database::config::{
+    AdvLanguageItem, LanguageBase, LanguageServerProtocolConnectionType,
+};
+use crate::runner::command_flag_hide_new_console;
+
+/// Some tools, like kotlinc, start a JVM just to print the version
+const VERSION_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct Probe {
+    /// Name of the tool in the report
+    tool: &'static str,
+    /// Executable names to look for, the first one found is used
+    candidates: &'static [&'static str],
+    /// Arguments to print the version, the tool is not run if empty
+    version_args: &'static [&'static str],
+}
+
+const PROBES: &[Probe] = &[
+    Probe {
+        tool: "g++",
+        candidates: &["g++"],
+        version_args: &["--version"],
+    },
+    Probe {
+        tool: "gcc",
+        candidates: &["gcc"],
+        version_args: &["--version"],
+    },
+    Probe {
+        tool: "clang++",
+        candidates: &["clang++"],
+        version_args: &["--version"],
+    },
+    Probe {
+        tool: "python3",
+        candidates: &["python3", "python"],
+        version_args: &["--version"],
+    },
+    Probe {
+        tool: "pypy3",
+        candidates: &["pypy3"],
+        version_args: &["--version"],
+    },
+    Probe {
+        tool: "go",
+        candidates: &["go"],
+        version_args: &["version"],
+    },
+    Probe {
+        tool: "rustc",
+        candidates: &["rustc"],
+        version_args: &["--version"],
+    },
+    Probe {
+        tool: "node",
+        candidates: &["node"],
+        version_args: &["--version"],
+    },
+    Probe {
+        tool: "javac",
+        candidates: &["javac"],
+        version_args: &["-version"],
+    },
+    Probe {
+        tool: "java",
+        candidates: &["java"],
+        version_args: &["-version"],
+    },
+    Probe {
+        tool: "kotlinc",
+        candidates: &["kotlinc"],
+        version_args: &["-version"],
+    },
+    Probe {
+        tool: "ghc",
+        candidates: &["ghc"],
+        version_args: &["--version"],
+    },
+    Probe {
+        tool: "mcs",
+        candidates: &["mcs"],
+        version_args: &["--version"],
+    },
+    Probe {
+        tool: "mono",
+        candidates: &["mono"],
+        version_args: &["--version"],
+    },
+    Probe {
+        tool: "clangd",
+        candidates: &["clangd"],
+        version_args: &["--version"],
+    },
+    Probe {
+        tool: "pyright",
+        candidates: &["pyright-langserver"],
+        version_args: &[],
+    },
+    Probe {
+        tool: "gopls",
+        candidates: &["gopls"],
+        version_args: &["version"],
+    },
+    Probe {
+        tool: "rust-analyzer",
+        candidates: &["rust-analyzer"],
+        version_args: &["--version"],
+    },
+    Probe {
+        tool: "jdtls",
+        candidates: &["jdtls"],
+        version_args: &[],
+    },
+    Probe {
+        tool: "kotlin-language-server",
+        candidates: &["kotlin-language-server"],
+        version_args: &[],
+    },
+    Probe {
+        tool: "csharp-ls",
+        candidates: &["csharp-ls"],
+        version_args: &["--version"],
+    },
+    Probe {
+        tool: "hls",
+        candidates: &["haskell-language-server-wrapper"],
+        version_args: &["--version"],
+    },
+];
+
+/// A tool found in PATH
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct DetectedTool {
+    pub tool: String,
+    /// The executable name that was found, use it in commands
+    pub executable: String,
+    pub path: PathBuf,
+    /// Version number, `None` if it can't be parsed
+    pub version: Option<String>,
+    /// First line printed by the version command, `None` if the tool has no such command
+    pub version_line: Option<String>,
+}
+
+/// A proposed language entry
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct LanguagePreset {
+    pub name: String,
+    pub language: AdvLanguageItem,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct ToolchainReport {
+    pub tools: Vec<DetectedTool>,
+    pub presets: Vec<LanguagePreset>,
+}
+
+/// Find an executable in the search paths, `PATHEXT` is honored on Windows
+fn find_executable(name: &str, search_paths: &[PathBuf]) -> Option<PathBuf> {
+    let extensions: Vec<OsString> = if cfg!(target_os = "windows") {
+        std::env::var("PATHEXT")
+            .unwrap_or(".COM;.EXE;.BAT;.CMD".to_string())
+            .split(';')
+            .filter(|ext| !ext.is_empty())
+            .map(OsString::from)
+            .collect()
+    } else {
+        vec![OsString::new()]
+    };
+    search_paths.iter().find_map(|dir| {
+        extensions.iter().find_map(|ext| {
+            let mut file = dir.join(name).into_os_string();
+            file.push(ext);
+            let file = PathBuf::from(file);
+            is_executable(&file).then_some(file)
+        })
+    })
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Extract a version number like `13.2.0` from the output of a version command
+///
+/// # Arguments
+/// * `line` - Output like `g++ (Ubuntu 13.2.0-4ubuntu3) 13.2.0` or `go version go1.22.0 linux/amd64`
+pub fn parse_version(line: &str) -> Option<String> {
+    line.split_whitespace()
+        .map(|token| {
+            token
+                .trim_start_matches(|c: char| c.is_ascii_alphabetic() || c == '"')
+                .trim_end_matches([',', ')', ';', '"'])
+        })
+        .find(|token| {
+            let parts: Vec<&str> = token.split('.').collect();
+            parts.len() >= 2
+                && parts
+                    .iter()
+                    .all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()))
+        })
+        .map(str::to_string)
+}
+
+/// Run the version command of a tool, some tools print it to stderr.
+/// Returns `None` if the command fails, like a rustup proxy without the component.
+async fn read_version_line(path: &Path, args: &[&str]) -> Option<String> {
+    let mut cmd = std::process::Command::new(path);
+    cmd.args(args);
+    command_flag_hide_new_console(&mut cmd);
+    let mut cmd = tokio::process::Command::from(cmd);
+    cmd.kill_on_drop(true)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let output = match tokio::time::timeout(VERSION_TIMEOUT, cmd.output()).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) => {
+            log::warn!("failed to run {:?}: {}", path, e);
+            return None;
+        }
+        Err(_) => {
+            log::warn!("reading the version of {:?} timed out", path);
+            return None;
+        }
+    };
+    if !output.status.success() {
+        log::warn!("{:?} exited with {}", path, output.status);
+        return None;
+    }
+    [output.stdout, output.stderr].iter().find_map(|out| {
+        String::from_utf8_lossy(out)
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .map(str::to_string)
+    })
+}
+
+/// Tools whose older major versions can't run the presets, `python` may still be Python 2
+const MIN_MAJOR_VERSIONS: &[(&str, u32)] = &[("python3", 3), ("pypy3", 3)];
+
+/// Whether the version of a tool is recent enough, an unknown version is accepted
+fn is_supported_version(tool: &str, version: Option<&str>) -> bool {
+    let Some((_, min_major)) = MIN_MAJOR_VERSIONS.iter().find(|(name, _)| *name == tool) else {
+        return true;
+    };
+    version
+        .and_then(|v| v.split('.').next())
+        .and_then(|major| major.parse::<u32>().ok())
+        .is_none_or(|major| major >= *min_major)
+}
+
+/// Find the first candidate of a probe that runs and is recent enough
+async fn detect_tool(probe: &Probe, search_paths: &[PathBuf]) -> Option<DetectedTool> {
+    for executable in probe.candidates {
+        let Some(path) = find_executable(executable, search_paths) else {
+            continue;
+        };
+        let version_line = if probe.version_args.is_empty() {
+            None
+        } else {
+            Some(read_version_line(&path, probe.version_args).await?)
+        };
+        let version = version_line.as_deref().and_then(parse_version);
+        if !is_supported_version(probe.tool, version.as_deref()) {
+            trace!("skip {:?}, {} {:?} is too old", &path, probe.tool, &version);
+            continue;
+        }
+        trace!(
+            "detected {} at {:?}: {:?}",
+            probe.tool,
+            &path,
+            &version_line
+        );
+        return Some(DetectedTool {
+            tool: probe.tool.to_string(),
+            executable: executable.to_string(),
+            version,
+            version_line,
+            path,
+        });
+    }
+    None
+}
+
+/// Look for the known tools in the search paths and propose presets for them
+pub async fn detect_toolchains(search_paths: Vec<PathBuf>) -> ToolchainReport {
+    let mut tasks = JoinSet::new();
+    for (index, probe) in PROBES.iter().enumerate() {
+        let search_paths = search_paths.clone();
+        tasks.spawn(async move { (index, detect_tool(probe, &search_paths).await) });
+    }
+    let mut found = Vec::new();
+    while let Some(result) = tasks.join_next().await {
+        match result {
+            Ok((index, Some(tool))) => found.push((index, tool)),
+            Ok((_, None)) => {}
+            Err(e) => log::warn!("toolchain probe failed: {}", e),
+        }
+    }
+    // Keep the order of the probes
+    found.sort_by_key(|(index, _)| *index);
+    let tools: Vec<DetectedTool> = found.into_iter().map(|(_, tool)| tool).collect();
+    let presets = propose_presets(&tools);
+    ToolchainReport { tools, presets }
+}
+
+fn preset_name(label: &str, tool: &DetectedTool) -> String {
+    match &tool.version {
+        Some(version) => format!("{} {}", label, version),
+        None => label.to_string(),
+    }
+}
+
+fn preset(base: LanguageBase, cmd_compile: String, cmd_run: String) -> AdvLanguageItem {
+    AdvLanguageItem {
+        base,
+        cmd_compile,
+        cmd_before_run: None,
+        cmd_after_run: None,
+        cmd_run,
+        lsp: None,
+        lsp_connect: None,
+        lsp_url: None,
+        lsp_settings: None,
+        lsp_extra: None,
+        time_multiplier: None,
+        time_offset: None,
+        stack_size: None,
+        env: None,
+    }
+}
+
+fn with_lsp(mut item: AdvLanguageItem, lsp: Option<String>) -> AdvLanguageItem {
+    if lsp.is_some() {
+        item.lsp = lsp;
+        item.lsp_connect = Some(LanguageServerProtocolConnectionType::StdIO);
+    }
+    item
+}
+
+/// Build presets from the detected tools, the language server of a preset is set when
+/// a matching one was found
+fn propose_presets(tools: &[DetectedTool]) -> Vec<LanguagePreset> {
+    let by_tool: HashMap<&str, &DetectedTool> =
+        tools.iter().map(|t| (t.tool.as_str(), t)).collect();
+    let lsp = |tool: &str, args: &str| {
+        by_tool
+            .get(tool)
+            .map(|t| format!("{}{}", t.executable, args))
+    };
+
+    let mut presets = Vec::new();
+    for compiler in ["g++", "clang++"] {
+        if let Some(tool) = by_tool.get(compiler) {
+            let mut item = preset(
+                LanguageBase::Cpp,
+                format!("{} -std=c++17 -O2 -o %BIN %SRC", tool.executable),
+                "%BIN".to_string(),
+            );
+            item.stack_size = Some(256 * 1024);
+            presets.push(LanguagePreset {
+                name: preset_name(compiler, tool),
+                language: with_lsp(item, lsp("clangd", "")),
+            });
+        }
+    }
+    for (interpreter, label) in [("python3", "python"), ("pypy3", "pypy")] {
+        if let Some(tool) = by_tool.get(interpreter) {
+            let item = preset(
+                LanguageBase::Python,
+                "".to_string(),
+                format!("{} %SRC", tool.executable),
+            );
+            presets.push(LanguagePreset {
+                name: preset_name(label, tool),
+                language: with_lsp(item, lsp("pyright", " --stdio")),
+            });
+        }
+    }
+    if let Some(tool) = by_tool.get("go") {
+        let item = preset(
+            LanguageBase::Go,
+            format!("{} build -o %BIN %SRC", tool.executable),
+            "%BIN".to_string(),
+        );
+        presets.push(LanguagePreset {
+            name: preset_name("go", tool),
+            language: with_lsp(item, lsp("gopls", "")),
+        });
+    }
+    if let Some(tool) = by_tool.get("gcc") {
+        let mut item = preset(
+            LanguageBase::C,
+            format!("{} -std=c11 -O2 -o %BIN %SRC -lm", tool.executable),
+            "%BIN".to_string(),
+        );
+        item.stack_size = Some(256 * 1024);
+        presets.push(LanguagePreset {
+            name: preset_name("gcc", tool),
+            language: with_lsp(item, lsp("clangd", "")),
+        });
+    }
+    if let Some(tool) = by_tool.get("rustc") {
+        let mut item = preset(
+            LanguageBase::Rust,
+            format!("{} --edition 2021 -O -o %BIN %SRC", tool.executable),
+            "%BIN".to_string(),
+        );
+        item.stack_size = Some(256 * 1024);
+        presets.push(LanguagePreset {
+            name: preset_name("rust", tool),
+            language: with_lsp(item, lsp("rust-analyzer", "")),
+        });
+    }
+    // The source file is written as Main.java / Main.kt, see getSourceFileName in the frontend
+    if let (Some(javac), Some(java)) = (by_tool.get("javac"), by_tool.get("java")) {
+        let item = preset(
+            LanguageBase::Java,
+            format!("{} -encoding UTF-8 -d %CWD %SRC", javac.executable),
+            format!("{} -Xss256m -cp %CWD Main", java.executable),
+        );
+        presets.push(LanguagePreset {
+            name: preset_name("java", javac),
+            language: with_lsp(item, lsp("jdtls", "")),
+        });
+    }
+    if let (Some(kotlinc), Some(java)) = (by_tool.get("kotlinc"), by_tool.get("java")) {
+        let item = preset(
+            LanguageBase::Kotlin,
+            format!("{} %SRC -include-runtime -d %BIN.jar", kotlinc.executable),
+            format!("{} -Xss256m -jar %BIN.jar", java.executable),
+        );
+        presets.push(LanguagePreset {
+            name: preset_name("kotlin", kotlinc),
+            language: with_lsp(item, lsp("kotlin-language-server", "")),
+        });
+    }
+    if let (Some(mcs), Some(mono)) = (by_tool.get("mcs"), by_tool.get("mono")) {
+        let item = preset(
+            LanguageBase::CSharp,
+            format!("{} -optimize+ -out:%BIN.exe %SRC", mcs.executable),
+            format!("{} %BIN.exe", mono.executable),
+        );
+        presets.push(LanguagePreset {
+            name: preset_name("c#", mcs),
+            language: with_lsp(item, lsp("csharp-ls", "")),
+        });
+    }
+    if let Some(tool) = by_tool.get("ghc") {
+        let item = preset(
+            LanguageBase::Haskell,
+            format!("{} -O2 -o %BIN %SRC", tool.executable),
+            "%BIN".to_string(),
+        );
+        presets.push(LanguagePreset {
+            name: preset_name("haskell", tool),
+            language: with_lsp(item, lsp("hls", " --lsp")),
+        });
+    }
+    if let Some(tool) = by_tool.get("node") {
+        presets.push(LanguagePreset {
+            name: preset_name("node", tool),
+            language: preset(
+                LanguageBase::JavaScript,
+                "".to_string(),
+                format!("{} %SRC", tool.executable),
+            ),
+        });
+    }
+    presets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_version() {
+        let cases = [
+            ("g++ (Ubuntu 13.2.0-4ubuntu3) 13.2.0", Some("13.2.0")),
+            ("Python 3.12.1", Some("3.12.1")),
+            ("go version go1.22.0 linux/amd64", Some("1.22.0")),
+            ("rustc 1.78.0 (9b00956e5 2024-04-29)", Some("1.78.0")),
+            ("v20.11.0", Some("20.11.0")),
+            ("info: kotlinc-jvm 1.9.22 (JRE 17.0.2+8)", Some("1.9.22")),
+            ("openjdk version \"17.0.2\" 2022-01-18", Some("17.0.2")),
+            ("unknown", None),
+        ];
+        for (line, version) in cases {
+            assert_eq!(parse_version(line).as_deref(), version, "{}", line);
+        }
+    }
+
+    #[test]
+    fn test_supported_version() {
+        assert!(is_supported_version("python3", Some("3.12.1")));
+        assert!(!is_supported_version("python3", Some("2.7.18")));
+        assert!(is_supported_version("python3", None));
+        assert!(is_supported_version("g++", Some("4.8.5")));
+    }
+
+    #[test]
+    fn test_propose_presets() {
+        let tool = |tool: &str, executable: &str| DetectedTool {
+            tool: tool.to_string(),
+            executable: executable.to_string(),
+            path: PathBuf::from(executable),
+            version: Some("1.0".to_string()),
+            version_line: None,
+        };
+        let presets = propose_presets(&[tool("g++", "g++"), tool("python3", "python")]);
+        assert_eq!(presets.len(), 2);
+        assert_eq!(presets[0].name, "g++ 1.0");
+        assert_eq!(presets[0].language.lsp, None);
+        assert_eq!(presets[1].language.cmd_run, "python %SRC");
+        for preset in presets {
+            preset.language.validate().unwrap();
+        }
+
+        let presets = propose_presets(&[
+            tool("javac", "javac"),
+            tool("java", "java"),
+            tool("kotlinc", "kotlinc"),
+            tool("jdtls", "jdtls"),
+        ]);
+        assert_eq!(presets.len(), 2);
+        assert_eq!(presets[0].language.cmd_run, "java -Xss256m -cp %CWD Main");
+        assert_eq!(presets[0].language.lsp.as_deref(), Some("jdtls"));
+        assert_eq!(presets[1].language.lsp, None);
+        for preset in presets {
+            preset.language.validate().unwrap();
+        }
+
+        let presets = propose_presets(&[tool("rustc", "rustc")]);
+        assert_eq!(presets.len(), 1);
+        assert_eq!(
+            presets[0].language.cmd_compile,
+            "rustc --edition 2021 -O -o %BIN %SRC"
+        );
+        presets[0].language.validate().unwrap();
+    }
+}
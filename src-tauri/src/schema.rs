@@ -10,12 +10,34 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    document_snapshots (id) {
+        id -> Text,
+        document_id -> Text,
+        label -> Nullable<Text>,
+        kind -> Text,
+        create_datetime -> Timestamp,
+        filename -> Text,
+    }
+}
+
 diesel::table! {
     documents (id) {
         id -> Text,
         create_datetime -> Timestamp,
         modified_datetime -> Timestamp,
         filename -> Text,
+        storage -> Text,
+        size -> Nullable<BigInt>,
+    }
+}
+
+diesel::table! {
+    external_files (document_id) {
+        document_id -> Text,
+        path -> Text,
+        synced_hash -> Nullable<Text>,
+        create_datetime -> Timestamp,
     }
 }
 
@@ -55,7 +77,18 @@ diesel::table! {
 }
 
 diesel::joinable!(checker -> documents (document_id));
+diesel::joinable!(document_snapshots -> documents (document_id));
+diesel::joinable!(external_files -> documents (document_id));
+diesel::joinable!(solutions -> documents (document_id));
 diesel::joinable!(solutions -> problems (problem_id));
 diesel::joinable!(test_cases -> problems (problem_id));
 
-diesel::allow_tables_to_appear_in_same_query!(checker, documents, problems, solutions, test_cases,);
+diesel::allow_tables_to_appear_in_same_query!(
+    checker,
+    document_snapshots,
+    documents,
+    external_files,
+    problems,
+    solutions,
+    test_cases,
+);
@@ -51,7 +51,9 @@ pub fn setup_database<R: Runtime>(app: &mut tauri::App<R>) -> Result<()> {
 
     let manager = ConnectionManager::<SqliteConnection>::new(database_path);
 
-    let pool = Pool::builder().build(manager)?;
+    let pool = Pool::builder()
+        .connection_customizer(Box::new(database::cleanup::ConnectionOptions))
+        .build(manager)?;
 
     trace!("run pending migrations");
     pool.get()
@@ -10,7 +10,7 @@ import { yCollab, YSyncConfig } from "y-codemirror.next"
 import * as Y from "yjs"
 import { useProgramConfig } from "@/hooks/use-program-config"
 import { useWorkspaceConfig } from "@/hooks/use-workspace-config"
-import { commands } from "@/lib/client"
+import { commands, events } from "@/lib/client"
 import { getFileExtensionOfLanguage, textLanguageItem } from "@/lib/client/type"
 import { ErrorLabel } from "../error-label"
 import { Skeleton } from "../ui/skeleton"
@@ -26,15 +26,19 @@ interface CodeEditorProps {
 
 export function CodeEditorSuspend({ className,	documentID,	language = "Text",	textarea }: CodeEditorProps) {
 	const [isDocumentLoaded, setIsDocumentLoaded] = useState(false)
-	const ydoc = useMemo(() => {
-		const doc = new Y.Doc()
+	// A fresh Y.Doc for each document
+	// eslint-disable-next-line react-hooks/exhaustive-deps
+	const ydoc = useMemo(() => new Y.Doc(), [documentID])
 
-		commands.loadDocument(documentID).then((data) => {
+	// The backend keeps the document in memory while an editor holds it
+	useEffect(() => {
+		const loading = commands.loadDocument(documentID).then((data) => {
 			setIsDocumentLoaded(true)
 			const updates = new Uint8Array(data)
-			Y.applyUpdate(doc, updates)
-			const content = doc.getText("content")
+			Y.applyUpdate(ydoc, updates)
+			const content = ydoc.getText("content")
 			log.trace(`content of document ${documentID}: ${content.toString()}`)
+			return true
 		}).catch((reason) => {
 			if (reason instanceof Error) {
 				toast.error(`failed to load document ${documentID}: ${reason.message}`)
@@ -42,17 +46,28 @@ export function CodeEditorSuspend({ className,	documentID,	language = "Text",	te
 			else {
 				toast.error(`failed to load document ${documentID}: ${reason}`)
 			}
+			return false
 		})
-
-		return doc
-	}, [documentID])
+		return () => {
+			// Release after the load finished, or the release could arrive first
+			loading.then((isLoaded) => {
+				if (isLoaded) {
+					return commands.unloadDocument(documentID)
+				}
+			}).catch((e) => {
+				log.warn(`failed to unload document ${documentID}: ${e}`)
+			})
+		}
+	}, [ydoc, documentID])
 
 	const ytext = useMemo(() => ydoc.getText("content"), [ydoc])
+	// Tells the updates of this editor apart from the ones it has to apply
+	const editorOrigin = useMemo(() => String(ydoc.clientID), [ydoc])
 
 	useEffect(() => {
 		const cb = (update: Uint8Array, origin: any, _doc: Y.Doc, _transaction: Y.Transaction) => {
 			if (origin instanceof YSyncConfig) {
-				commands.applyChange(documentID, Array.from(update)).catch((e) => {
+				commands.applyChange(documentID, Array.from(update), editorOrigin).catch((e) => {
 					toast.error(`failed to apply change with local error message: ${e}`)
 				})
 			}
@@ -61,7 +76,30 @@ export function CodeEditorSuspend({ className,	documentID,	language = "Text",	te
 		return () => {
 			ydoc.off("update", cb)
 		}
-	}, [ydoc, documentID])
+	}, [ydoc, documentID, editorOrigin])
+
+	// Updates of other editors and of the backend. They don't come from YSyncConfig, so
+	// they are not sent back.
+	useEffect(() => {
+		const unlisten = events.documentUpdateEvent.listen((event) => {
+			if (event.payload.doc_id !== documentID || event.payload.origin === editorOrigin)
+				return
+			Y.applyUpdate(ydoc, new Uint8Array(event.payload.update), "backend")
+		})
+		// Catch up on updates that may have been missed while the window was in the background
+		const sync = () => {
+			commands.syncDocument(documentID, Array.from(Y.encodeStateVector(ydoc))).then((update) => {
+				Y.applyUpdate(ydoc, new Uint8Array(update), "backend")
+			}).catch((e) => {
+				log.warn(`failed to sync document ${documentID}: ${e}`)
+			})
+		}
+		window.addEventListener("focus", sync)
+		return () => {
+			unlisten.then(f => f())
+			window.removeEventListener("focus", sync)
+		}
+	}, [ydoc, documentID, editorOrigin])
 
 	const workspaceConfig = useWorkspaceConfig()
 	const programConfig = useProgramConfig()
@@ -5,6 +5,7 @@ import { useQuery } from "@tanstack/react-query"
 import * as log from "@tauri-apps/plugin-log"
 import { toast } from "react-toastify"
 import { match } from "ts-pattern"
+import { algorimejo } from "@/lib/algorimejo"
 import { getLanguageID } from "@/lib/client/type"
 import { LanguageServerStdIOTransport } from "./lsp-stdio-transport"
 
@@ -18,45 +19,84 @@ function getLanguageSyntaxExtension(lang: Language): Promise<Extension> {
 		.with("TypeScript", () => import("@codemirror/lang-javascript").then(mod => mod.javascript({ typescript: true })))
 		.with("JavaScript", () => import("@codemirror/lang-javascript").then(mod => mod.javascript({ typescript: false })))
 		.with("Go", () => import("@codemirror/lang-go").then(mod => mod.go()))
+		.with("C", () => import("@codemirror/lang-cpp").then(mod => mod.cpp()))
+		// no bundled highlighter yet, the language server still works
+		.with("Rust", "Java", "Kotlin", "CSharp", "Haskell", () => Promise.resolve([]))
 		.otherwise(() => {
 			log.warn(`unknown language: ${lang}`)
 			return Promise.resolve([])
 		})
 }
 
+/**
+ * One client per language settings, shared by every editor, so a server is initialized once
+ */
+const languageServerClients = new Map<string, Promise<LanguageServerClient>>()
+/**
+ * Servers that restarted, the next client attaches to them instead of launching a new one
+ */
+const restartedLanguageServers = new Map<string, string>()
+
+function getLanguageServerClient(lang: AdvLanguageItem): Promise<LanguageServerClient> {
+	const key = JSON.stringify(lang)
+	let client = languageServerClients.get(key)
+	if (client === undefined) {
+		client = createLanguageServerClient(lang, key)
+		languageServerClients.set(key, client)
+		client.catch(() => languageServerClients.delete(key))
+	}
+	return client
+}
+
+async function createLanguageServerClient(lang: AdvLanguageItem, key: string): Promise<LanguageServerClient> {
+	const restartedPid = restartedLanguageServers.get(key)
+	restartedLanguageServers.delete(key)
+	const transport = restartedPid === undefined
+		? await LanguageServerStdIOTransport.launch(lang.lsp ?? "", lang)
+		: LanguageServerStdIOTransport.attach(restartedPid)
+	transport.onRestarted(() => {
+		// the new process needs a new initialize handshake, rebuild the client and the editor extensions
+		transport.detach()
+		restartedLanguageServers.set(key, transport.pid)
+		languageServerClients.delete(key)
+		algorimejo.queryClient.invalidateQueries({ queryKey: ["language-extension"] })
+	})
+	const client = new LanguageServerClient({
+		rootUri: "file:///",
+		workspaceFolders: [{
+			name: "algorimejo",
+			uri: "file:///",
+		}],
+		transport,
+		initializationOptions: {
+			settings: {
+				// TODO: need fix pylyzer
+				// python: { path: "C:/Users/lnslf/scoop/apps/miniconda3/current/python.exe" },
+			},
+		},
+	},
+	)
+	client.onNotification((notification) => {
+		const method = notification.method as string
+		const param = notification.params as any
+		if (method === "window/showMessage") {
+			toast.info(`Language Server: ${param.message}`)
+		}
+	})
+	return client
+}
+
 export async function getLanguageExtension(lang: AdvLanguageItem, documentUri: string): Promise<Extension> {
 	const extensions = []
 
 	const syntaxExtension = await getLanguageSyntaxExtension(lang.base)
 	extensions.push(syntaxExtension)
 
-	if (lang.lsp !== null && lang.lsp_connect !== null) {
-		const client = new LanguageServerClient({
-			rootUri: "file:///",
-			workspaceFolders: [{
-				name: "algorimejo",
-				uri: "file:///",
-			}],
-			transport: await LanguageServerStdIOTransport.launch(lang.lsp),
-			initializationOptions: {
-				settings: {
-					// TODO: need fix pylyzer
-					// python: { path: "C:/Users/lnslf/scoop/apps/miniconda3/current/python.exe" },
-				},
-			},
-		},
-		)
-		client.onNotification((notification) => {
-			const method = notification.method as string
-			const param = notification.params as any
-			if (method === "window/showMessage") {
-				toast.info(`Language Server: ${param.message}`)
-			}
-		})
+	if (lang.lsp_connect !== null && (lang.lsp !== null || (lang.lsp_connect !== "StdIO" && lang.lsp_url !== null))) {
 		const lsp = languageServerWithClient({
 			documentUri,
 			languageId: getLanguageID(lang.base),
-			client,
+			client: await getLanguageServerClient(lang),
 			allowHTMLContent: true,
 		})
 		extensions.push(lsp)
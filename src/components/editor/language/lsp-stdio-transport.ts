@@ -1,34 +1,74 @@
+import type { AdvLanguageItem, IOMethod } from "@/lib/client"
 import type { JSONRPCRequestData } from "@open-rpc/client-js/build/Request"
 import { ERR_UNKNOWN, JSONRPCError } from "@open-rpc/client-js/build/Error"
 import { getBatchRequests, getNotifications } from "@open-rpc/client-js/build/Request"
 import { Transport } from "@open-rpc/client-js/build/transports/Transport.js"
+import { match } from "ts-pattern"
 import { commands, events } from "@/lib/client"
 
 export class LanguageServerStdIOTransport extends Transport {
 	private closed: boolean
-	private constructor(private readonly pid: string) {
+	private unlisten: Promise<() => void> | null = null
+	private restartedListener: (() => void) | null = null
+	private constructor(readonly pid: string) {
 		super()
 		this.closed = true
 	}
 
-	static async launch(lspLaunchCommand: string): Promise<LanguageServerStdIOTransport> {
-		const pid = await commands.launchLanguageServer(lspLaunchCommand, "StdIO")
+	static async launch(lspLaunchCommand: string, language: AdvLanguageItem | null = null): Promise<LanguageServerStdIOTransport> {
+		// the backend connects to socket servers itself, messages still go through the same commands and events
+		const ioMethod: IOMethod = match(language?.lsp_connect ?? "StdIO")
+			.with("Tcp", (): IOMethod => ({ Tcp: { address: language?.lsp_url ?? "" } }))
+			.with("WebSocket", (): IOMethod => ({ WebSocket: { url: language?.lsp_url ?? "" } }))
+			.otherwise(() => "StdIO")
+		const pid = await commands.launchLanguageServer(lspLaunchCommand, ioMethod, language)
 		return new LanguageServerStdIOTransport(pid)
 	}
 
+	/**
+	 * Talk to a server that was launched before, used by a new client after the server restarted
+	 */
+	static attach(pid: string): LanguageServerStdIOTransport {
+		return new LanguageServerStdIOTransport(pid)
+	}
+
+	/**
+	 * The server crashed and was started again by the backend, the client must be replaced
+	 */
+	onRestarted(listener: () => void) {
+		this.restartedListener = listener
+	}
+
 	async connect(): Promise<any> {
 		this.closed = false
-		events.languageServerEvent.listen((event) => {
-			if (event.payload.pid === this.pid && event.payload.response.type === "Message") {
-				this.transportRequestManager.resolveResponse(event.payload.response.msg)
+		this.unlisten = events.languageServerEvent.listen((event) => {
+			if (event.payload.pid !== this.pid) {
+				return
 			}
-			else if (event.payload.pid === this.pid && event.payload.response.type === "Closed") {
+			const response = event.payload.response
+			if (response.type === "Message") {
+				this.transportRequestManager.resolveResponse(response.msg)
+			}
+			else if (response.type === "Closed") {
 				this.closed = true
 			}
+			else if (response.type === "Restarted") {
+				this.restartedListener?.()
+			}
 		})
 	}
 
+	/**
+	 * Stop receiving messages without releasing the server
+	 */
+	detach(): void {
+		this.closed = true
+		this.unlisten?.then(unlisten => unlisten())
+		this.unlisten = null
+	}
+
 	close(): void {
+		this.detach()
 		commands.killLanguageServer(this.pid)
 	}
 
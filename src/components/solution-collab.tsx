@@ -0,0 +1,169 @@
+import type { Problem, Solution } from "@/lib/client"
+import { useQueryClient } from "@tanstack/react-query"
+import { useState } from "react"
+import { toast } from "react-toastify"
+import { Button } from "@/components/ui/button"
+import { Input } from "@/components/ui/input"
+import { Label } from "@/components/ui/label"
+import { Switch } from "@/components/ui/switch"
+import {
+	COLLAB_SERVER_QUERY_KEY,
+	COLLAB_SESSIONS_QUERY_KEY,
+	useCollabServer,
+	useCollabSessions,
+} from "@/hooks/use-collab"
+import { useSolutionCreator } from "@/hooks/use-solution-creator"
+import { useSolutionDeleter } from "@/hooks/use-solution-deleter"
+import { algorimejo } from "@/lib/algorimejo"
+import { commands } from "@/lib/client"
+
+interface SolutionCollabProps {
+	documentID: string
+	solution: Solution
+	problem: Problem
+}
+export function SolutionCollab({ documentID, solution, problem }: SolutionCollabProps) {
+	const server = useCollabServer()
+	const sessions = useCollabSessions()
+	const solutionCreator = useSolutionCreator()
+	const solutionDeleter = useSolutionDeleter()
+	const queryClient = useQueryClient()
+	const [lan, setLan] = useState(true)
+	const [joinURL, setJoinURL] = useState("")
+	const [isJoining, setIsJoining] = useState(false)
+
+	const shareURL = server.data?.urls[documentID]
+	const joinedSessions = sessions.data?.filter(session => session.doc_id === documentID) ?? []
+
+	function reportError(action: string) {
+		return (e: unknown) => {
+			toast.error(`Failed to ${action}: ${e instanceof Error ? e.message : e}`)
+		}
+	}
+	function refreshServer() {
+		queryClient.invalidateQueries({ queryKey: COLLAB_SERVER_QUERY_KEY })
+	}
+	function refreshSessions() {
+		queryClient.invalidateQueries({ queryKey: COLLAB_SESSIONS_QUERY_KEY })
+	}
+	function handleShare() {
+		commands.startCollabServer([documentID], lan, null)
+			.then(refreshServer)
+			.catch(reportError("start sharing"))
+	}
+	function handleStopSharing() {
+		commands.stopCollabServer()
+			.then(refreshServer)
+			.catch(reportError("stop sharing"))
+	}
+	function handleCopy(url: string) {
+		navigator.clipboard.writeText(url)
+			.then(() => toast.success("Copied the URL"))
+			.catch(reportError("copy the URL"))
+	}
+	async function handleJoin() {
+		// The text of the peer is merged into the document, a new solution ends up with the same text
+		setIsJoining(true)
+		try {
+			const { solution: joined } = await solutionCreator.mutateAsync({
+				problemId: problem.id,
+				params: {
+					author: null,
+					name: `${solution.name} (pair)`,
+					language: solution.language,
+					content: null,
+				},
+			})
+			if (!joined.document) {
+				throw new Error(`Solution ${joined.name} has no document`)
+			}
+			try {
+				await commands.joinCollabSession(joinURL.trim(), joined.document.id)
+			}
+			catch (e) {
+				solutionDeleter.mutate(joined.id)
+				throw e
+			}
+			setJoinURL("")
+			refreshSessions()
+			algorimejo.createSolutionEditorTab(joined.id, problem.id, {
+				title: `${joined.name} - ${problem.name}`,
+			})
+		}
+		catch (e) {
+			reportError("join")(e)
+		}
+		finally {
+			setIsJoining(false)
+		}
+	}
+	function handleLeave(sessionID: string) {
+		commands.leaveCollabSession(sessionID)
+			.then(refreshSessions)
+			.catch(reportError("leave the session"))
+	}
+
+	return (
+		<div className="space-y-6">
+			<section className="space-y-2">
+				<div className="text-sm font-medium">Share this solution</div>
+				{shareURL
+					? (
+							<>
+								<div className="flex gap-2">
+									<Input readOnly value={shareURL} onFocus={e => e.target.select()} />
+									<Button type="button" variant="outline" onClick={() => handleCopy(shareURL)}>Copy</Button>
+								</div>
+								<div className="text-xs text-muted-foreground">
+									{server.data?.lan
+										? "Peers on the local network can join with this URL"
+										: "Only this machine can join with this URL"}
+								</div>
+								<Button type="button" variant="destructive" onClick={handleStopSharing}>Stop Sharing</Button>
+							</>
+						)
+					: server.data
+						? (
+								<div className="flex items-center gap-2">
+									<span className="flex-1 text-sm text-muted-foreground">Another solution is being shared</span>
+									<Button type="button" variant="outline" onClick={handleStopSharing}>Stop Sharing</Button>
+								</div>
+							)
+						: (
+								<div className="flex items-center gap-2">
+									<Switch id="collab-lan" checked={lan} onCheckedChange={setLan} />
+									<Label htmlFor="collab-lan" className="flex-1">Allow peers on the local network</Label>
+									<Button type="button" onClick={handleShare}>Start Sharing</Button>
+								</div>
+							)}
+			</section>
+			<section className="space-y-2">
+				<div className="text-sm font-medium">Join a peer</div>
+				<div className="flex gap-2">
+					<Input
+						value={joinURL}
+						onChange={e => setJoinURL(e.target.value)}
+						placeholder="ws://192.168.1.2:40000/..."
+					/>
+					<Button type="button" disabled={!joinURL.trim() || isJoining} onClick={handleJoin}>Join</Button>
+				</div>
+				<div className="text-xs text-muted-foreground">
+					Joining creates a new solution of this problem that follows the peer
+				</div>
+			</section>
+			{joinedSessions.length > 0 && (
+				<section className="space-y-2">
+					<div className="text-sm font-medium">Joined sessions</div>
+					<ul className="space-y-1">
+						{joinedSessions.map(session => (
+							<li key={session.session_id} className="flex items-center gap-2 text-sm">
+								<span className="flex-1 truncate">{session.url}</span>
+								<Button type="button" size="sm" variant="outline" onClick={() => handleLeave(session.session_id)}>Leave</Button>
+							</li>
+						))}
+					</ul>
+				</section>
+			)}
+		</div>
+	)
+}
@@ -0,0 +1,91 @@
+import type { SyncSide } from "@/lib/client"
+import { useQueryClient } from "@tanstack/react-query"
+import { useState } from "react"
+import { toast } from "react-toastify"
+import { ErrorLabel } from "@/components/error-label"
+import { Button } from "@/components/ui/button"
+import { Input } from "@/components/ui/input"
+import { Skeleton } from "@/components/ui/skeleton"
+import { externalFileQueryKeyOf, useExternalFile } from "@/hooks/use-external-file"
+import { commands } from "@/lib/client"
+
+interface SolutionFileLinkProps {
+	documentID: string
+}
+export function SolutionFileLink({ documentID }: SolutionFileLinkProps) {
+	const link = useExternalFile(documentID)
+	const queryClient = useQueryClient()
+	const [path, setPath] = useState("")
+
+	function refresh() {
+		queryClient.invalidateQueries({ queryKey: externalFileQueryKeyOf(documentID) })
+	}
+	function reportError(action: string) {
+		return (e: unknown) => {
+			toast.error(`Failed to ${action}: ${e instanceof Error ? e.message : e}`)
+		}
+	}
+	function handleLink(keep: SyncSide) {
+		commands.linkExternalFile(documentID, path.trim(), keep).then(() => {
+			setPath("")
+			refresh()
+		}).catch(reportError("link the file"))
+	}
+	function handleUnlink() {
+		commands.unlinkExternalFile(documentID)
+			.then(refresh)
+			.catch(reportError("unlink the file"))
+	}
+	function handleKeep(keep: SyncSide) {
+		commands.resolveExternalFileConflict(documentID, keep)
+			.then(refresh)
+			.catch(reportError("resume syncing"))
+	}
+
+	if (link.status === "error") {
+		return <ErrorLabel message={link.error} location="loading the linked file" />
+	}
+	if (link.status === "pending") {
+		return <Skeleton className="h-10 w-full" />
+	}
+	if (link.data) {
+		return (
+			<div className="space-y-4">
+				<div className="space-y-1">
+					<div className="text-sm font-medium">Linked file</div>
+					<div className="text-sm break-all">{link.data.path}</div>
+					<div className="text-xs text-muted-foreground">
+						{link.data.is_paused
+							? "Syncing is paused, the solution and the file both changed"
+							: "Edits in the solution and in the file are applied to each other"}
+					</div>
+				</div>
+				<div className="flex gap-2">
+					{link.data.is_paused && (
+						<>
+							<Button type="button" onClick={() => handleKeep("Document")}>Keep Solution</Button>
+							<Button type="button" onClick={() => handleKeep("File")}>Keep File</Button>
+						</>
+					)}
+					<Button type="button" variant="outline" onClick={handleUnlink}>Unlink</Button>
+				</div>
+			</div>
+		)
+	}
+	return (
+		<div className="space-y-4">
+			<Input
+				value={path}
+				onChange={e => setPath(e.target.value)}
+				placeholder="Absolute path of the file, e.g. /home/me/contest/a.cpp"
+			/>
+			<div className="text-xs text-muted-foreground">
+				A missing file is created from the solution. If the file has a different text, choose which one to keep.
+			</div>
+			<div className="flex gap-2">
+				<Button type="button" disabled={!path.trim()} onClick={() => handleLink("Document")}>Link, Keep Solution</Button>
+				<Button type="button" variant="outline" disabled={!path.trim()} onClick={() => handleLink("File")}>Link, Keep File</Button>
+			</div>
+		</div>
+	)
+}
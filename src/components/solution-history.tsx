@@ -0,0 +1,142 @@
+import type { DocumentSnapshot, SnapshotDiffLine } from "@/lib/client"
+import { useQueryClient } from "@tanstack/react-query"
+import { useState } from "react"
+import { toast } from "react-toastify"
+import { ErrorLabel } from "@/components/error-label"
+import { Button } from "@/components/ui/button"
+import { Input } from "@/components/ui/input"
+import { ScrollArea } from "@/components/ui/scroll-area"
+import { Skeleton } from "@/components/ui/skeleton"
+import { documentSnapshotsQueryKeyOf, useDocumentSnapshots } from "@/hooks/use-document-snapshots"
+import { commands } from "@/lib/client"
+import { cn } from "@/lib/utils"
+
+type SnapshotView = {
+	type: "text"
+	snapshot: DocumentSnapshot
+	text: string
+} | {
+	type: "diff"
+	snapshot: DocumentSnapshot
+	lines: SnapshotDiffLine[]
+}
+
+function snapshotTitle(snapshot: DocumentSnapshot) {
+	return snapshot.label ? `${snapshot.label} (${snapshot.kind})` : snapshot.kind
+}
+
+function DiffLines({ lines }: { lines: SnapshotDiffLine[] }) {
+	return (
+		<pre className="text-xs">
+			{lines.map((line, index) => (
+				<div
+					// eslint-disable-next-line react/no-array-index-key
+					key={index}
+					className={cn(
+						"flex gap-2",
+						line.kind === "Insert" && "bg-green-500/20",
+						line.kind === "Delete" && "bg-red-500/20",
+					)}
+				>
+					<span className="w-8 shrink-0 text-right text-muted-foreground select-none">{line.old_line ?? ""}</span>
+					<span className="w-8 shrink-0 text-right text-muted-foreground select-none">{line.new_line ?? ""}</span>
+					<span className="w-3 shrink-0 select-none">
+						{line.kind === "Insert" ? "+" : line.kind === "Delete" ? "-" : " "}
+					</span>
+					<span>{line.text}</span>
+				</div>
+			))}
+		</pre>
+	)
+}
+
+interface SolutionHistoryProps {
+	documentID: string
+}
+export function SolutionHistory({ documentID }: SolutionHistoryProps) {
+	const snapshots = useDocumentSnapshots(documentID)
+	const queryClient = useQueryClient()
+	const [label, setLabel] = useState("")
+	const [view, setView] = useState<SnapshotView | null>(null)
+
+	function refresh() {
+		queryClient.invalidateQueries({ queryKey: documentSnapshotsQueryKeyOf(documentID) })
+	}
+	function reportError(action: string) {
+		return (e: unknown) => {
+			toast.error(`Failed to ${action}: ${e instanceof Error ? e.message : e}`)
+		}
+	}
+	function handleTakeSnapshot() {
+		commands.createDocumentSnapshot(documentID, "Manual", label.trim() || null).then(() => {
+			setLabel("")
+			refresh()
+		}).catch(reportError("take snapshot"))
+	}
+	function handleView(snapshot: DocumentSnapshot) {
+		commands.getSnapshotText(snapshot.id).then((text) => {
+			setView({ type: "text", snapshot, text })
+		}).catch(reportError("load snapshot"))
+	}
+	function handleDiff(snapshot: DocumentSnapshot) {
+		commands.diffDocumentSnapshots(snapshot.id, null).then((lines) => {
+			setView({ type: "diff", snapshot, lines })
+		}).catch(reportError("diff snapshot"))
+	}
+	function handleRestore(snapshot: DocumentSnapshot) {
+		// The open editors receive the restore as a document update
+		commands.restoreDocumentSnapshot(snapshot.id).then(() => {
+			toast.success(`Restored ${snapshotTitle(snapshot)}`)
+			setView(null)
+			refresh()
+		}).catch(reportError("restore snapshot"))
+	}
+
+	if (snapshots.status === "error") {
+		return <ErrorLabel message={snapshots.error} location="loading snapshots" />
+	}
+	return (
+		<div className="space-y-4">
+			<div className="flex gap-2">
+				<Input
+					value={label}
+					onChange={e => setLabel(e.target.value)}
+					placeholder="Label of the snapshot"
+				/>
+				<Button type="button" onClick={handleTakeSnapshot}>Take Snapshot</Button>
+			</div>
+			{snapshots.status === "pending"
+				? <Skeleton className="h-10 w-full" />
+				: (
+						<ScrollArea className="h-48">
+							{snapshots.data.length === 0 && (
+								<div className="text-sm text-muted-foreground">No snapshot yet</div>
+							)}
+							<ul className="space-y-1">
+								{snapshots.data.map(snapshot => (
+									<li key={snapshot.id} className="flex items-center gap-2 text-sm">
+										<span className="flex-1 truncate">{snapshotTitle(snapshot)}</span>
+										<span className="text-xs text-muted-foreground">{snapshot.create_datetime}</span>
+										<Button type="button" size="sm" variant="ghost" onClick={() => handleView(snapshot)}>View</Button>
+										<Button type="button" size="sm" variant="ghost" onClick={() => handleDiff(snapshot)}>Diff</Button>
+										<Button type="button" size="sm" variant="outline" onClick={() => handleRestore(snapshot)}>Restore</Button>
+									</li>
+								))}
+							</ul>
+						</ScrollArea>
+					)}
+			{view && (
+				<div className="space-y-2">
+					<div className="text-sm font-medium">
+						{view.type === "text" ? snapshotTitle(view.snapshot) : `${snapshotTitle(view.snapshot)} → current`}
+					</div>
+					<ScrollArea className="h-64 rounded border p-2">
+						{view.type === "text"
+							? <pre className="text-xs">{view.text}</pre>
+							: <DiffLines lines={view.lines} />}
+					</ScrollArea>
+				</div>
+			)}
+		</div>
+	)
+}
@@ -3,6 +3,9 @@ import type { Problem, Solution } from "@/lib/client"
 import * as log from "@tauri-apps/plugin-log"
 import { useRef, useState } from "react"
 import { toast } from "react-toastify"
+import { SolutionCollab } from "@/components/solution-collab"
+import { SolutionFileLink } from "@/components/solution-file-link"
+import { SolutionHistory } from "@/components/solution-history"
 import { SolutionSetting } from "@/components/solution-setting"
 import {
 	AlertDialog,
@@ -40,6 +43,9 @@ export function ProblemListItem({
 }: ProblemListItemProps) {
 	const [isRenaming, setIsRenaming] = useState(false)
 	const [isEditingOptions, setIsEditingOptions] = useState(false)
+	const [isViewingHistory, setIsViewingHistory] = useState(false)
+	const [isPairing, setIsPairing] = useState(false)
+	const [isLinkingFile, setIsLinkingFile] = useState(false)
 	const solutionChangesetMutation = useSolutionChangeset()
 	const solutionDeleterMutation = useSolutionDeleter()
 	const inputRenameRef = useRef<HTMLInputElement>(null)
@@ -140,6 +146,63 @@ export function ProblemListItem({
 				</DialogContent>
 			</Dialog>
 
+			{solution.document && (
+				<Dialog open={isViewingHistory} onOpenChange={setIsViewingHistory}>
+					<DialogContent>
+						<DialogHeader>
+							<DialogTitle className="select-none">
+								History:
+								{" "}
+								{solution.name}
+								{" "}
+								-
+								{" "}
+								{problem.name}
+							</DialogTitle>
+						</DialogHeader>
+						<SolutionHistory documentID={solution.document.id} />
+					</DialogContent>
+				</Dialog>
+			)}
+
+			{solution.document && (
+				<Dialog open={isPairing} onOpenChange={setIsPairing}>
+					<DialogContent>
+						<DialogHeader>
+							<DialogTitle className="select-none">
+								Pair Programming:
+								{" "}
+								{solution.name}
+								{" "}
+								-
+								{" "}
+								{problem.name}
+							</DialogTitle>
+						</DialogHeader>
+						<SolutionCollab documentID={solution.document.id} solution={solution} problem={problem} />
+					</DialogContent>
+				</Dialog>
+			)}
+
+			{solution.document && (
+				<Dialog open={isLinkingFile} onOpenChange={setIsLinkingFile}>
+					<DialogContent>
+						<DialogHeader>
+							<DialogTitle className="select-none">
+								Link File:
+								{" "}
+								{solution.name}
+								{" "}
+								-
+								{" "}
+								{problem.name}
+							</DialogTitle>
+						</DialogHeader>
+						<SolutionFileLink documentID={solution.document.id} />
+					</DialogContent>
+				</Dialog>
+			)}
+
 			{isRenaming
 				? (
 						<input
@@ -173,6 +236,9 @@ export function ProblemListItem({
 								<ContextMenuItem>Open</ContextMenuItem>
 								<ContextMenuSeparator />
 								<ContextMenuItem onClick={() => setIsEditingOptions(true)}>Options</ContextMenuItem>
+								<ContextMenuItem disabled={!solution.document} onClick={() => setIsViewingHistory(true)}>History</ContextMenuItem>
+								<ContextMenuItem disabled={!solution.document} onClick={() => setIsPairing(true)}>Pair Programming</ContextMenuItem>
+								<ContextMenuItem disabled={!solution.document} onClick={() => setIsLinkingFile(true)}>Link File</ContextMenuItem>
 								<ContextMenuSeparator />
 								<ContextMenuItem onClick={handleStartRename}>
 									Rename
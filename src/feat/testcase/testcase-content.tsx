@@ -174,6 +174,7 @@ function TestcaseList({ problem, testcases, solutionID }: TestcaseListProps) {
 		itemsRef.current[index]?.clearOutput()
 		const info = await runTestcase({
 			tag,
+			problemID: problem.id,
 			testcaseInputDocID: testcase.input_document_id,
 			testcaseOutputDocID: testcase.answer_document_id,
 			solutionDocID: solution.data.document!.id,
@@ -189,7 +190,7 @@ function TestcaseList({ problem, testcases, solutionID }: TestcaseListProps) {
 		})
 		dispatchItemsStatus({ type: "set", index, status: info.result })
 		log.trace(`testcase ${tag} result: ${JSON.stringify(info)}`)
-	}, [languageItem.data, problem.checker, problem.time_limit, solution.data])
+	}, [languageItem.data, problem.checker, problem.id, problem.time_limit, solution.data])
 
 	const handleRunAllTestcases = useCallback(() => {
 		for (let i = 0; i < testcases.length; i++) {
@@ -0,0 +1,114 @@
+import { useQueryClient } from "@tanstack/react-query"
+import { LucideFileInput, LucidePencil } from "lucide-react"
+import { useState } from "react"
+import { toast } from "react-toastify"
+import { CodeEditor } from "@/components/editor"
+import { ErrorLabel } from "@/components/error-label"
+import { Button } from "@/components/ui/button"
+import { Input } from "@/components/ui/input"
+import { Popover, PopoverContent, PopoverTrigger } from "@/components/ui/popover"
+import { Skeleton } from "@/components/ui/skeleton"
+import { documentPreviewQueryKeyOf, useDocumentPreview } from "@/hooks/use-document-preview"
+import { commands } from "@/lib/client"
+
+function formatSize(bytes: number) {
+	if (bytes < 1024) {
+		return `${bytes} B`
+	}
+	if (bytes < 1024 * 1024) {
+		return `${(bytes / 1024).toFixed(1)} KiB`
+	}
+	return `${(bytes / 1024 / 1024).toFixed(1)} MiB`
+}
+
+interface TestcaseDocumentProps {
+	documentID: string
+	label: string
+}
+export function TestcaseDocument({ documentID, label }: TestcaseDocumentProps) {
+	const preview = useDocumentPreview(documentID)
+	const queryClient = useQueryClient()
+	const [path, setPath] = useState("")
+	const [isImporting, setIsImporting] = useState(false)
+
+	function reportError(action: string) {
+		return (e: unknown) => {
+			toast.error(`Failed to ${action}: ${e instanceof Error ? e.message : e}`)
+		}
+	}
+	function refresh() {
+		queryClient.invalidateQueries({ queryKey: documentPreviewQueryKeyOf(documentID) })
+	}
+	function handleImport() {
+		setIsImporting(true)
+		commands.importRawDocument(documentID, path.trim())
+			.then(() => {
+				setPath("")
+				refresh()
+			})
+			.catch(reportError("import the file"))
+			.finally(() => setIsImporting(false))
+	}
+	function handleEdit() {
+		commands.convertRawDocument(documentID)
+			.then(refresh)
+			.catch(reportError("edit the file"))
+	}
+
+	return (
+		<div className="flex flex-col space-y-1.5">
+			<div className="flex items-center gap-2">
+				<span className="text-xs font-medium text-muted-foreground">{label}</span>
+				{preview.data && (
+					<span className="text-xs text-muted-foreground">{formatSize(preview.data.size)}</span>
+				)}
+				<span className="flex-1" />
+				{preview.data && (
+					<Button variant="ghost" size="sm" className="h-5 w-5 p-0" title="Edit as text" onClick={handleEdit}>
+						<LucidePencil className="h-3 w-3" />
+					</Button>
+				)}
+				<Popover>
+					<PopoverTrigger asChild>
+						<Button variant="ghost" size="sm" className="h-5 w-5 p-0" title="Import a large file">
+							<LucideFileInput className="h-3 w-3" />
+						</Button>
+					</PopoverTrigger>
+					<PopoverContent className="space-y-2">
+						<Input
+							value={path}
+							onChange={e => setPath(e.target.value)}
+							placeholder="Path of the file, e.g. /home/me/tests/1.in"
+						/>
+						<div className="text-xs text-muted-foreground">
+							The file is copied as is and fed to the program directly, only its first and last lines are shown
+						</div>
+						<Button type="button" size="sm" disabled={!path.trim() || isImporting} onClick={handleImport}>Import</Button>
+					</PopoverContent>
+				</Popover>
+			</div>
+			<div className="flex-1 overflow-hidden rounded-md border">
+				{preview.status === "pending" && <Skeleton className="size-full min-h-24" />}
+				{preview.status === "error" && <ErrorLabel message={preview.error.message} />}
+				{preview.status === "success" && (preview.data
+					? (
+							<pre className="size-full min-h-24 overflow-auto p-2 font-mono text-xs">
+								{preview.data.head}
+								{preview.data.is_truncated && (
+									<span className="my-1 block text-muted-foreground select-none">...</span>
+								)}
+								{preview.data.tail}
+							</pre>
+						)
+					: (
+							<CodeEditor
+								className="size-full min-h-24"
+								documentID={documentID}
+								language="Text"
+								textarea
+							/>
+						))}
+			</div>
+		</div>
+	)
+}
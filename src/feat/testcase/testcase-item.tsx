@@ -3,11 +3,11 @@ import type { TestCase } from "@/lib/client"
 import type { RunTestResultStatus } from "@/lib/runner"
 import { LucideBugPlay, LucidePlay, LucideTrash } from "lucide-react"
 import { forwardRef, useImperativeHandle, useRef } from "react"
-import { CodeEditor } from "@/components/editor"
 import { CodeMirrorTextarea } from "@/components/editor/textarea"
 import { Button } from "@/components/ui/button"
 import { runTestStatusToColor } from "@/lib/runner"
 import { cn } from "@/lib/utils"
+import { TestcaseDocument } from "./testcase-document"
 
 interface TestcaseItemProps {
 	testcase: TestCase
@@ -79,33 +79,9 @@ export const TestcaseItem = forwardRef<TestcaseItemRef, TestcaseItemProps>(
 						"grid-cols-3": colsNum === 3,
 					})}
 				>
-					<div className="flex flex-col space-y-1.5">
-						<div className="flex items-center gap-2">
-							<span className="text-xs font-medium text-muted-foreground">Input</span>
-						</div>
-						<div className="flex-1 overflow-hidden rounded-md border">
-							<CodeEditor
-								className="size-full min-h-24"
-								documentID={testcase.input_document_id}
-								language="Text"
-								textarea
-							/>
-						</div>
-					</div>
+					<TestcaseDocument documentID={testcase.input_document_id} label="Input" />
 
-					<div className="flex flex-col space-y-1.5">
-						<div className="flex items-center gap-2">
-							<span className="text-xs font-medium text-muted-foreground">Answer</span>
-						</div>
-						<div className="flex-1 overflow-hidden rounded-md border">
-							<CodeEditor
-								className="size-full min-h-24"
-								documentID={testcase.answer_document_id}
-								language="Text"
-								textarea
-							/>
-						</div>
-					</div>
+					<TestcaseDocument documentID={testcase.answer_document_id} label="Answer" />
 
 					<div className="flex flex-col space-y-1.5">
 						<div className="flex items-center gap-2">
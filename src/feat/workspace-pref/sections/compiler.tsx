@@ -1,7 +1,8 @@
-import type { LanguageBase, LanguageServerProtocolConnectionType } from "@/lib/client"
+import type { EnvironmentConfig, ExtraLanguageServer, JsonValue, LanguageBase, LanguagePreset, LanguageServerProtocolConnectionType, LanguageServerSettings, ToolchainReport } from "@/lib/client"
 import { cloneDeep } from "lodash/fp"
-import { LucideCircleQuestionMark, LucideCopy, LucidePlusSquare, LucideSave, LucideSettings, LucideTextCursorInput, LucideTrash } from "lucide-react"
+import { LucideCircleQuestionMark, LucideCopy, LucidePlusSquare, LucideSave, LucideScanSearch, LucideSettings, LucideTextCursorInput, LucideTrash } from "lucide-react"
 import { useState } from "react"
+import { toast } from "sonner"
 import { v4 as uuid } from "uuid"
 import { PrefsItem, PrefsSection } from "@/components/prefs"
 import { Button } from "@/components/ui/button"
@@ -14,7 +15,9 @@ import { ScrollArea } from "@/components/ui/scroll-area"
 import { Select, SelectContent, SelectItem, SelectTrigger, SelectValue } from "@/components/ui/select"
 import { Separator } from "@/components/ui/separator"
 import { Skeleton } from "@/components/ui/skeleton"
+import { Textarea } from "@/components/ui/textarea"
 import { Tooltip, TooltipContent, TooltipTrigger } from "@/components/ui/tooltip"
+import { commands } from "@/lib/client"
 import { languageBaseValues, languageServerProtocolConnectionTypeValues } from "@/lib/client/type"
 import { useWorkspacePrefsChangeset, useWorkspacePrefsChangesetApply, useWorkspacePrefsChangesetSetter } from "../workspace-prefs-changeset-context"
 
@@ -35,6 +38,13 @@ export function CompilerSection() {
 				cmd_before_run: null,
 				lsp: null,
 				lsp_connect: null,
+				lsp_url: null,
+				lsp_settings: null,
+				lsp_extra: null,
+				time_multiplier: null,
+				time_offset: null,
+				stack_size: null,
+				env: null,
 			}
 		})
 	}
@@ -55,6 +65,16 @@ export function CompilerSection() {
 			setSelectedLanguageName(newName)
 		}, true)
 	}
+	function handleAddPreset(preset: LanguagePreset) {
+		let name = preset.name
+		while (changeset.language![name]) {
+			name = `${preset.name} ${uuid().substring(0, 4)}`
+		}
+		setChangeset((draft) => {
+			draft.language![name] = cloneDeep(preset.language)
+		})
+		setSelectedLanguageName(name)
+	}
 	const [newLanguageName, setNewLanguageName] = useState("")
 	function handleRenameLanguage() {
 		setChangeset((draft) => {
@@ -122,6 +142,7 @@ export function CompilerSection() {
 								<Button disabled={!allowDeleteLanguage} variant="outline" size="sm" className="h-8 w-8 p-0" onClick={() => handleRemoveLanguage(selectedLanguageName)}>
 									<LucideTrash className="h-4 w-4" />
 								</Button>
+								<ToolchainDetectDialog onAdd={handleAddPreset} />
 							</div>
 							<Separator />
 							<ScrollArea className="flex-1 p-4">
@@ -192,7 +213,7 @@ export function CompilerSection() {
 										</Label>
 										<Input
 											id="compile-cmd"
-											placeholder="e.g., gcc -o %output %input"
+											placeholder="e.g., g++ -o %BIN %SRC"
 											autoComplete="off"
 											autoCorrect="off"
 											value={changeset.language[selectedLanguageName]!.cmd_compile}
@@ -207,7 +228,7 @@ export function CompilerSection() {
 											id="run-cmd"
 											autoComplete="off"
 											autoCorrect="off"
-											placeholder="e.g., ./%output"
+											placeholder="e.g., %BIN"
 											value={changeset.language[selectedLanguageName]!.cmd_run ?? ""}
 											onInput={e => setChangeset((draft) => {
 												draft.language![selectedLanguageName]!.cmd_run = e.currentTarget.value
@@ -272,6 +293,75 @@ export function CompilerSection() {
 
 							<Separator />
 
+							{/* Limits */}
+							<div className="space-y-4">
+								<h4 className="text-sm font-semibold tracking-wide text-muted-foreground uppercase">Limits</h4>
+								<div className="grid grid-cols-3 gap-4">
+									<div className="space-y-2">
+										<Label htmlFor="time-multiplier" className="text-sm font-medium">Time Multiplier</Label>
+										<Input
+											id="time-multiplier"
+											type="number"
+											min={0}
+											step={0.1}
+											placeholder="1.0"
+											value={changeset.language[selectedLanguageName]!.time_multiplier ?? ""}
+											onInput={e => setChangeset((draft) => {
+												const value = e.currentTarget.valueAsNumber
+												draft.language![selectedLanguageName]!.time_multiplier = Number.isNaN(value) ? null : value
+											})}
+										/>
+									</div>
+									<div className="space-y-2">
+										<Label htmlFor="time-offset" className="text-sm font-medium">Extra Time (ms)</Label>
+										<Input
+											id="time-offset"
+											type="number"
+											min={0}
+											step={1}
+											placeholder="0"
+											value={changeset.language[selectedLanguageName]!.time_offset ?? ""}
+											onInput={e => setChangeset((draft) => {
+												const value = e.currentTarget.valueAsNumber
+												draft.language![selectedLanguageName]!.time_offset = Number.isNaN(value) ? null : Math.max(0, Math.floor(value))
+											})}
+										/>
+									</div>
+									<div className="space-y-2">
+										<Label htmlFor="stack-size" className="text-sm font-medium">Stack Size (KiB)</Label>
+										<Input
+											id="stack-size"
+											type="number"
+											min={0}
+											step={1024}
+											placeholder="Inherit"
+											value={changeset.language[selectedLanguageName]!.stack_size ?? ""}
+											onInput={e => setChangeset((draft) => {
+												const value = e.currentTarget.valueAsNumber
+												draft.language![selectedLanguageName]!.stack_size = Number.isNaN(value) ? null : Math.max(0, Math.floor(value))
+											})}
+										/>
+									</div>
+								</div>
+							</div>
+
+							<Separator />
+
+							{/* Environment */}
+							<div className="space-y-4">
+								<h4 className="text-sm font-semibold tracking-wide text-muted-foreground uppercase">Environment</h4>
+								<EnvironmentEditor
+									key={selectedLanguageName}
+									idPrefix="language-env"
+									value={changeset.language[selectedLanguageName]!.env}
+									onChange={value => setChangeset((draft) => {
+										draft.language![selectedLanguageName]!.env = isEnvironmentEmpty(value) ? null : value
+									})}
+								/>
+							</div>
+
+							<Separator />
+
 							{/* Language Server */}
 							<div className="space-y-4">
 								<h4 className="text-sm font-semibold tracking-wide text-muted-foreground uppercase">Language Server</h4>
@@ -326,6 +416,42 @@ export function CompilerSection() {
 											))}
 										</RadioGroup>
 									</div>
+									{(changeset.language[selectedLanguageName]!.lsp_connect === "Tcp" || changeset.language[selectedLanguageName]!.lsp_connect === "WebSocket") && (
+										<div className="space-y-2">
+											<Label htmlFor="lsp-url" className="text-sm font-medium">
+												{changeset.language[selectedLanguageName]!.lsp_connect === "Tcp" ? "Address" : "WebSocket URL"}
+											</Label>
+											<Input
+												autoComplete="off"
+												autoCorrect="off"
+												id="lsp-url"
+												placeholder={changeset.language[selectedLanguageName]!.lsp_connect === "Tcp" ? "e.g., 127.0.0.1:%PORT" : "e.g., ws://127.0.0.1:%PORT"}
+												value={changeset.language[selectedLanguageName]!.lsp_url ?? ""}
+												onInput={e => setChangeset((draft) => {
+													const value = e.currentTarget.value
+													draft.language[selectedLanguageName]!.lsp_url = value.trim().length === 0 ? null : value
+												})}
+											/>
+											<p className="text-xs text-muted-foreground">
+												%PORT is a free local port, pass it to the launch command too. Leave the launch command empty to connect to a running server.
+											</p>
+										</div>
+									)}
+									<LanguageServerSettingsEditor
+										key={selectedLanguageName}
+										idPrefix="lsp"
+										value={changeset.language[selectedLanguageName]!.lsp_settings}
+										onChange={value => setChangeset((draft) => {
+											draft.language[selectedLanguageName]!.lsp_settings = value
+										})}
+									/>
+									<ExtraLanguageServersEditor
+										key={`${selectedLanguageName}-extra`}
+										value={changeset.language[selectedLanguageName]!.lsp_extra ?? []}
+										onChange={value => setChangeset((draft) => {
+											draft.language[selectedLanguageName]!.lsp_extra = value.length === 0 ? null : value
+										})}
+									/>
 								</div>
 							</div>
 
@@ -343,23 +469,312 @@ export function CompilerSection() {
 
 				</div>
 			</PrefsItem>
+			<PrefsItem name="Environment" description="Environment of compilers, programs, checkers and language servers, the settings of a language are applied after these" hoverHighlight={false}>
+				<div className="flex w-full flex-col gap-4">
+					<EnvironmentEditor
+						idPrefix="workspace-env"
+						value={changeset.env}
+						onChange={value => setChangeset((draft) => {
+							draft.env = value
+						})}
+					/>
+					<div className="flex justify-end">
+						<Button onClick={applyChangeset} className="gap-2">
+							<LucideSave className="h-4 w-4" />
+							Save Changes
+						</Button>
+					</div>
+				</div>
+			</PrefsItem>
 		</PrefsSection>
 	)
 }
 
+function ToolchainDetectDialog({ onAdd }: { onAdd: (preset: LanguagePreset) => void }) {
+	const [report, setReport] = useState<ToolchainReport | null>(null)
+	const [detecting, setDetecting] = useState(false)
+	async function handleDetect() {
+		setDetecting(true)
+		try {
+			setReport(await commands.detectToolchains())
+		}
+		catch (e) {
+			toast.error(`Fail to detect toolchains: ${e}`)
+		}
+		finally {
+			setDetecting(false)
+		}
+	}
+	return (
+		<Dialog onOpenChange={open => open && handleDetect()}>
+			<DialogTrigger asChild>
+				<Button variant="outline" size="sm" className="h-8 w-8 p-0">
+					<LucideScanSearch className="h-4 w-4" />
+				</Button>
+			</DialogTrigger>
+			<DialogContent className="sm:max-w-[560px]">
+				<DialogHeader>
+					<DialogTitle>Detect Toolchains</DialogTitle>
+					<DialogDescription>
+						Compilers, interpreters and language servers found in PATH.
+					</DialogDescription>
+				</DialogHeader>
+				{detecting || !report
+					? <Skeleton className="h-40 w-full" />
+					: (
+							<ScrollArea className="max-h-96">
+								<div className="space-y-4">
+									<ul className="space-y-1 text-sm">
+										{report.tools.map(tool => (
+											<li key={tool.tool} className="flex justify-between gap-4">
+												<span className="font-medium">{tool.tool}</span>
+												<span className="truncate text-muted-foreground" title={tool.path}>{tool.version ?? tool.version_line ?? tool.path}</span>
+											</li>
+										))}
+									</ul>
+									<Separator />
+									<div className="space-y-2">
+										{report.presets.length === 0 && <p className="text-sm text-muted-foreground">No preset available</p>}
+										{report.presets.map(preset => (
+											<div key={preset.name} className="flex items-center justify-between gap-4">
+												<div className="min-w-0">
+													<p className="text-sm font-medium">{preset.name}</p>
+													<p className="truncate text-xs text-muted-foreground">{[preset.language.cmd_compile, preset.language.cmd_run].filter(cmd => cmd.length > 0).join(" ; ")}</p>
+												</div>
+												<DialogClose asChild>
+													<Button variant="outline" size="sm" onClick={() => onAdd(preset)}>Add</Button>
+												</DialogClose>
+											</div>
+										))}
+									</div>
+								</div>
+							</ScrollArea>
+						)}
+			</DialogContent>
+		</Dialog>
+	)
+}
+
+function isEnvironmentEmpty(env: EnvironmentConfig) {
+	return Object.keys(env.set).length === 0 && env.unset.length === 0 && env.path_prepend.length === 0
+}
+
+function splitLines(text: string) {
+	return text.split("\n").map(line => line.trim()).filter(line => line.length > 0)
+}
+
+interface EnvironmentEditorProps {
+	idPrefix: string
+	value: EnvironmentConfig | null
+	onChange: (value: EnvironmentConfig) => void
+}
+
+// The text areas are uncontrolled so that the text is kept as typed, remount with a key to reset
+function EnvironmentEditor({ idPrefix, value, onChange }: EnvironmentEditorProps) {
+	const env: EnvironmentConfig = value ?? { set: {}, unset: [], path_prepend: [] }
+	return (
+		<div className="grid grid-cols-3 gap-4">
+			<div className="space-y-2">
+				<Label htmlFor={`${idPrefix}-set`} className="text-sm font-medium">Set Variables</Label>
+				<Textarea
+					id={`${idPrefix}-set`}
+					placeholder="KEY=VALUE, one per line"
+					autoComplete="off"
+					autoCorrect="off"
+					defaultValue={Object.entries(env.set).map(([key, value]) => `${key}=${value}`).join("\n")}
+					onChange={(e) => {
+						const set: Record<string, string> = {}
+						for (const line of splitLines(e.currentTarget.value)) {
+							const index = line.indexOf("=")
+							if (index > 0) {
+								set[line.substring(0, index).trim()] = line.substring(index + 1)
+							}
+						}
+						onChange({ ...env, set })
+					}}
+				/>
+			</div>
+			<div className="space-y-2">
+				<Label htmlFor={`${idPrefix}-unset`} className="text-sm font-medium">Unset Variables</Label>
+				<Textarea
+					id={`${idPrefix}-unset`}
+					placeholder="One name per line"
+					autoComplete="off"
+					autoCorrect="off"
+					defaultValue={env.unset.join("\n")}
+					onChange={e => onChange({ ...env, unset: splitLines(e.currentTarget.value) })}
+				/>
+			</div>
+			<div className="space-y-2">
+				<Label htmlFor={`${idPrefix}-path`} className="text-sm font-medium">Prepend to PATH</Label>
+				<Textarea
+					id={`${idPrefix}-path`}
+					placeholder="One directory per line, searched first"
+					autoComplete="off"
+					autoCorrect="off"
+					defaultValue={env.path_prepend.join("\n")}
+					onChange={e => onChange({ ...env, path_prepend: splitLines(e.currentTarget.value) })}
+				/>
+			</div>
+		</div>
+	)
+}
+
+interface LanguageServerSettingsEditorProps {
+	idPrefix: string
+	value: LanguageServerSettings | null
+	onChange: (value: LanguageServerSettings | null) => void
+}
+
+// Empty text clears a setting, text that is not a JSON object is kept as typed and not applied
+function LanguageServerSettingsEditor({ idPrefix, value, onChange }: LanguageServerSettingsEditorProps) {
+	const settings: LanguageServerSettings = value ?? { initialization_options: null, configuration: null }
+	const [errors, setErrors] = useState<Partial<Record<keyof LanguageServerSettings, string>>>({})
+	function handleChange(field: keyof LanguageServerSettings, text: string) {
+		let parsed: JsonValue = null
+		if (text.trim().length > 0) {
+			try {
+				parsed = JSON.parse(text) as JsonValue
+			}
+			catch (e) {
+				setErrors(errors => ({ ...errors, [field]: String(e) }))
+				return
+			}
+			if (typeof parsed !== "object" || parsed === null || Array.isArray(parsed)) {
+				setErrors(errors => ({ ...errors, [field]: "Must be a JSON object" }))
+				return
+			}
+		}
+		setErrors(errors => ({ ...errors, [field]: undefined }))
+		const next = { ...settings, [field]: parsed }
+		onChange(next.initialization_options === null && next.configuration === null ? null : next)
+	}
+	const fields: { field: keyof LanguageServerSettings, label: string, placeholder: string }[] = [
+		{ field: "initialization_options", label: "Initialization Options", placeholder: "e.g., { \"fallbackFlags\": [\"-std=c++17\"] }" },
+		{ field: "configuration", label: "Settings", placeholder: "e.g., { \"python\": { \"analysis\": { \"typeCheckingMode\": \"strict\" } } }" },
+	]
+	return (
+		<div className="grid grid-cols-2 gap-4">
+			{fields.map(({ field, label, placeholder }) => (
+				<div className="space-y-2" key={field}>
+					<Label htmlFor={`${idPrefix}-${field}`} className="text-sm font-medium">{label}</Label>
+					<Textarea
+						id={`${idPrefix}-${field}`}
+						className="font-mono"
+						placeholder={placeholder}
+						autoComplete="off"
+						autoCorrect="off"
+						spellCheck={false}
+						defaultValue={settings[field] === null ? "" : JSON.stringify(settings[field], null, 2)}
+						onChange={e => handleChange(field, e.currentTarget.value)}
+					/>
+					{errors[field] && <p className="text-xs text-destructive">{errors[field]}</p>}
+				</div>
+			))}
+			<p className="col-span-2 text-xs text-muted-foreground">
+				Initialization options are sent with the initialize request, settings answer the workspace/configuration requests of the server.
+			</p>
+		</div>
+	)
+}
+
+interface ExtraLanguageServersEditorProps {
+	value: ExtraLanguageServer[]
+	onChange: (value: ExtraLanguageServer[]) => void
+}
+
+// Keys change with the number of servers, so the uncontrolled settings text areas are reset after adding or removing one
+function ExtraLanguageServersEditor({ value, onChange }: ExtraLanguageServersEditorProps) {
+	function update(index: number, patch: Partial<ExtraLanguageServer>) {
+		onChange(value.map((server, i) => i === index ? { ...server, ...patch } : server))
+	}
+	function handleAdd() {
+		onChange([...value, { name: `server ${value.length + 1}`, lsp: "", lsp_connect: "StdIO", lsp_url: null, lsp_settings: null }])
+	}
+	return (
+		<div className="space-y-4">
+			<div className="flex items-center justify-between">
+				<Label className="text-sm font-medium">Additional Language Servers</Label>
+				<Button variant="outline" size="sm" className="gap-2" onClick={handleAdd}>
+					<LucidePlusSquare className="h-4 w-4" />
+					Add
+				</Button>
+			</div>
+			<p className="text-xs text-muted-foreground">
+				Run next to the main server, e.g. a linter next to a type checker. Requests go to the main server first and to these when it lacks the feature, diagnostics of all servers are shown.
+			</p>
+			{value.map((server, index) => (
+				<div key={`${index}-${value.length}`} className="space-y-3 rounded-md border p-3">
+					<div className="grid grid-cols-[1fr_2fr_auto_auto] items-end gap-2">
+						<div className="space-y-2">
+							<Label htmlFor={`lsp-extra-${index}-name`} className="text-sm">Name</Label>
+							<Input
+								id={`lsp-extra-${index}-name`}
+								autoComplete="off"
+								autoCorrect="off"
+								value={server.name}
+								onInput={e => update(index, { name: e.currentTarget.value })}
+							/>
+						</div>
+						<div className="space-y-2">
+							<Label htmlFor={`lsp-extra-${index}-cmd`} className="text-sm">Launch Command</Label>
+							<Input
+								id={`lsp-extra-${index}-cmd`}
+								autoComplete="off"
+								autoCorrect="off"
+								placeholder="e.g., ruff server"
+								value={server.lsp}
+								onInput={e => update(index, { lsp: e.currentTarget.value })}
+							/>
+						</div>
+						<Select value={server.lsp_connect} onValueChange={lspConnect => update(index, { lsp_connect: lspConnect as LanguageServerProtocolConnectionType })}>
+							<SelectTrigger><SelectValue /></SelectTrigger>
+							<SelectContent>
+								{languageServerProtocolConnectionTypeValues.map(type => (
+									<SelectItem key={type} value={type}>{type}</SelectItem>
+								))}
+							</SelectContent>
+						</Select>
+						<Button variant="ghost" size="icon" onClick={() => onChange(value.filter((_, i) => i !== index))}>
+							<LucideTrash className="h-4 w-4" />
+						</Button>
+					</div>
+					{server.lsp_connect !== "StdIO" && (
+						<Input
+							autoComplete="off"
+							autoCorrect="off"
+							placeholder={server.lsp_connect === "Tcp" ? "e.g., 127.0.0.1:%PORT" : "e.g., ws://127.0.0.1:%PORT"}
+							value={server.lsp_url ?? ""}
+							onInput={e => update(index, { lsp_url: e.currentTarget.value.trim().length === 0 ? null : e.currentTarget.value })}
+						/>
+					)}
+					<LanguageServerSettingsEditor
+						idPrefix={`lsp-extra-${index}`}
+						value={server.lsp_settings}
+						onChange={lspSettings => update(index, { lsp_settings: lspSettings })}
+					/>
+				</div>
+			))}
+		</div>
+	)
+}
+
 export function CommandInputTooltip() {
 	return (
 		<Tooltip>
 			<TooltipTrigger><LucideCircleQuestionMark className="size-4 text-muted-foreground" /></TooltipTrigger>
 			<TooltipContent>
-				<p>You can use the following placeholders in your command:</p>
+				<p>You can use the following variables in your command:</p>
 				<ul>
-					<li>%src: The source file</li>
-					<li>%target: The target file</li>
-					<li>%args: The arguments passed to the command</li>
-					<li>%dir: Working directory</li>
-					<li>%uuid: A random UUID</li>
+					<li>%SRC: The source file</li>
+					<li>%BIN: The executable file, %CWD/main by default</li>
+					<li>%CWD: Working directory of the task</li>
+					<li>%INPUT: The input file (the run command only, not the hooks)</li>
+					<li>%PROBLEM_DIR: Directory shared by the tasks of a problem</li>
+					<li>%BUNDLED_LSP: Directory of the bundled language servers</li>
 				</ul>
+				<p>Use %{NAME} to separate a variable from the text after it, %{NAME:-default} for a default value and %% for a literal %.</p>
+				<p>Compile commands and hooks may chain steps with &amp;&amp; and redirect with &lt;, &gt;, &gt;&gt; and 2&gt;, no shell is involved.</p>
 			</TooltipContent>
 		</Tooltip>
 	)
@@ -370,12 +785,11 @@ export function LanguageServerTooltip() {
 		<Tooltip>
 			<TooltipTrigger><LucideCircleQuestionMark className="size-4 text-muted-foreground" /></TooltipTrigger>
 			<TooltipContent>
-				<p>You can use the following placeholders in your command:</p>
+				<p>You can use the following variables in your command:</p>
 				<ul>
-					<li>%src: The source file</li>
-					<li>%dir: Working directory</li>
-					<li>%port: Websocket port(if enabled)</li>
+					<li>%BUNDLED_LSP: Directory of the bundled language servers</li>
 				</ul>
+				<p>Use %% for a literal %.</p>
 			</TooltipContent>
 		</Tooltip>
 	)
@@ -0,0 +1,28 @@
+import { useState } from "react"
+import { toast } from "react-toastify"
+import { PrefsItem, PrefsSection } from "@/components/prefs"
+import { Button } from "@/components/ui/button"
+import { commands } from "@/lib/client"
+
+export function StorageSection() {
+	const [isCollecting, setIsCollecting] = useState(false)
+	function handleCollectGarbage() {
+		setIsCollecting(true)
+		commands.collectGarbage().then((report) => {
+			toast.success(`Removed ${report.document_ids.length} orphaned documents and ${report.removed_files} files, freed ${(report.freed_bytes / 1024).toFixed(1)} KiB`)
+		}).catch((e) => {
+			toast.error(`Failed to clean up documents: ${e instanceof Error ? e.message : e}`)
+		}).finally(() => {
+			setIsCollecting(false)
+		})
+	}
+	return (
+		<PrefsSection section="Storage">
+			<PrefsItem name="Clean Up Documents" description="Remove documents and files that no problem, solution or testcase uses anymore">
+				<Button type="button" variant="outline" disabled={isCollecting} onClick={handleCollectGarbage}>
+					Clean Up
+				</Button>
+			</PrefsItem>
+		</PrefsSection>
+	)
+}
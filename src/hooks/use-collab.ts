@@ -0,0 +1,34 @@
+import { useQuery } from "@tanstack/react-query"
+import { toast } from "react-toastify"
+import { algorimejo } from "@/lib/algorimejo"
+import { commands, events } from "@/lib/client"
+
+export const COLLAB_SERVER_QUERY_KEY = ["collab-server"]
+export const COLLAB_SESSIONS_QUERY_KEY = ["collab-sessions"]
+
+export function useCollabServer() {
+	return useQuery({
+		queryKey: COLLAB_SERVER_QUERY_KEY,
+		queryFn: () => commands.getCollabServer(),
+	})
+}
+
+export function useCollabSessions() {
+	return useQuery({
+		queryKey: COLLAB_SESSIONS_QUERY_KEY,
+		queryFn: () => commands.getCollabSessions(),
+	})
+}
+
+events.collabSessionEndEvent.listen((event) => {
+	const { error } = event.payload
+	if (error) {
+		toast.error(`Pair session ended: ${error}`)
+	}
+	else {
+		toast.info("Pair session ended by the host")
+	}
+	algorimejo.queryClient.invalidateQueries({
+		queryKey: COLLAB_SESSIONS_QUERY_KEY,
+	})
+})
@@ -0,0 +1,23 @@
+import { useQuery } from "@tanstack/react-query"
+import { algorimejo } from "@/lib/algorimejo"
+import { commands, events } from "@/lib/client"
+
+export const DOCUMENT_PREVIEW_QUERY_KEY = "document-preview"
+
+export function documentPreviewQueryKeyOf(documentID: string) {
+	return [DOCUMENT_PREVIEW_QUERY_KEY, documentID]
+}
+
+export function useDocumentPreview(documentID: string) {
+	return useQuery({
+		queryKey: documentPreviewQueryKeyOf(documentID),
+		queryFn: () => commands.getDocumentPreview(documentID),
+	})
+}
+
+// Every editor of the document switches between the editor and the preview
+events.documentStorageEvent.listen((event) => {
+	algorimejo.queryClient.invalidateQueries({
+		queryKey: documentPreviewQueryKeyOf(event.payload.document_id),
+	})
+})
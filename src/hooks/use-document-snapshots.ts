@@ -0,0 +1,15 @@
+import { useQuery } from "@tanstack/react-query"
+import { commands } from "@/lib/client"
+
+export const DOCUMENT_SNAPSHOTS_QUERY_KEY = "document-snapshots"
+
+export function documentSnapshotsQueryKeyOf(documentID: string) {
+	return [DOCUMENT_SNAPSHOTS_QUERY_KEY, documentID]
+}
+
+export function useDocumentSnapshots(documentID: string) {
+	return useQuery({
+		queryKey: documentSnapshotsQueryKeyOf(documentID),
+		queryFn: () => commands.listDocumentSnapshots(documentID),
+	})
+}
@@ -0,0 +1,30 @@
+import { useQuery } from "@tanstack/react-query"
+import { toast } from "react-toastify"
+import { algorimejo } from "@/lib/algorimejo"
+import { commands, events } from "@/lib/client"
+
+export const EXTERNAL_FILE_QUERY_KEY = "external-file"
+
+export function externalFileQueryKeyOf(documentID: string) {
+	return [EXTERNAL_FILE_QUERY_KEY, documentID]
+}
+
+export function useExternalFile(documentID: string) {
+	return useQuery({
+		queryKey: externalFileQueryKeyOf(documentID),
+		queryFn: () => commands.getExternalFile(documentID),
+	})
+}
+
+events.externalFileConflictEvent.listen((event) => {
+	const { document_id, path, error } = event.payload
+	if (error) {
+		toast.error(`Stopped syncing ${path}: ${error}`)
+	}
+	else {
+		toast.warn(`Both the solution and ${path} changed, choose which one to keep in Link File`)
+	}
+	algorimejo.queryClient.invalidateQueries({
+		queryKey: externalFileQueryKeyOf(document_id),
+	})
+})
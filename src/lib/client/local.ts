@@ -20,6 +20,9 @@ async getProblems(params: GetProblemsParams) : Promise<GetProblemsResult> {
 async getProblem(problemId: string) : Promise<Problem> {
     return await TAURI_INVOKE("get_problem", { problemId });
 },
+async getProblemDir(problemId: string) : Promise<string> {
+    return await TAURI_INVOKE("get_problem_dir", { problemId });
+},
 async createProblem(params: CreateProblemParams) : Promise<CreateProblemResult> {
     return await TAURI_INVOKE("create_problem", { params });
 },
@@ -53,6 +56,13 @@ async deleteTestcase(testcaseId: string) : Promise<null> {
 async getTestcases(problemId: string) : Promise<TestCase[]> {
     return await TAURI_INVOKE("get_testcases", { problemId });
 },
+/**
+ * Delete the documents that no solution, testcase or checker owns, and the files of the
+ * doc folder that belong to no document
+ */
+async collectGarbage() : Promise<GarbageReport> {
+    return await TAURI_INVOKE("collect_garbage");
+},
 async getWorkspaceConfig() : Promise<WorkspaceConfig> {
     return await TAURI_INVOKE("get_workspace_config");
 },
@@ -65,32 +75,247 @@ async getStringOfDoc(docId: string, name: string) : Promise<string> {
 async loadDocument(docId: string) : Promise<number[]> {
     return await TAURI_INVOKE("load_document", { docId });
 },
-async applyChange(docId: string, change: number[]) : Promise<null> {
-    return await TAURI_INVOKE("apply_change", { docId, change });
+/**
+ * Release a document loaded by `load_document`, once no editor holds it the document may
+ * be dropped from memory
+ */
+async unloadDocument(docId: string) : Promise<null> {
+    return await TAURI_INVOKE("unload_document", { docId });
+},
+/**
+ * Apply an update of an editor and broadcast it to the other editors and to the peers the
+ * document is shared with
+ * 
+ * # Arguments
+ * * `origin` - An id of the editor, echoed in the `DocumentUpdateEvent` of the update
+ */
+async applyChange(docId: string, change: number[], origin: string | null) : Promise<null> {
+    return await TAURI_INVOKE("apply_change", { docId, change, origin });
+},
+/**
+ * Get the updates of a document that an editor is missing, e.g. after it missed events
+ * 
+ * # Arguments
+ * * `state_vector` - The state vector of the editor, encoded as v1
+ * 
+ * # Returns
+ * * `Result<Vec<u8>, String>` - The missing updates, encoded as one v1 update
+ */
+async syncDocument(docId: string, stateVector: number[]) : Promise<number[]> {
+    return await TAURI_INVOKE("sync_document", { docId, stateVector });
 },
 async resolveChecker(name: string) : Promise<string> {
     return await TAURI_INVOKE("resolve_checker", { name });
 },
+async createDocumentSnapshot(docId: string, kind: SnapshotKind, label: string | null) : Promise<DocumentSnapshot | null> {
+    return await TAURI_INVOKE("create_document_snapshot", { docId, kind, label });
+},
+async listDocumentSnapshots(docId: string) : Promise<DocumentSnapshot[]> {
+    return await TAURI_INVOKE("list_document_snapshots", { docId });
+},
+async getSnapshotText(snapshotId: string) : Promise<string> {
+    return await TAURI_INVOKE("get_snapshot_text", { snapshotId });
+},
+/**
+ * Diff the text of two snapshots of a document
+ * 
+ * # Arguments
+ * * `old_snapshot_id` - The snapshot to diff from
+ * * `new_snapshot_id` - The snapshot to diff to, `None` for the current text of the document
+ */
+async diffDocumentSnapshots(oldSnapshotId: string, newSnapshotId: string | null) : Promise<SnapshotDiffLine[]> {
+    return await TAURI_INVOKE("diff_document_snapshots", { oldSnapshotId, newSnapshotId });
+},
+/**
+ * Restore the text of a document to a snapshot. The current text is kept as a `Restore`
+ * snapshot first, so a restore can be undone. The editors receive the update of the
+ * restore as a `DocumentUpdateEvent`, the peers of the document receive it too.
+ */
+async restoreDocumentSnapshot(snapshotId: string) : Promise<null> {
+    return await TAURI_INVOKE("restore_document_snapshot", { snapshotId });
+},
+/**
+ * Share documents with peers over WebSocket, nothing is shared until this is called
+ * 
+ * # Arguments
+ * * `doc_ids` - The documents to share, each one is a room of its own
+ * * `lan` - Listen on every interface so peers on the local network can join, otherwise
+ * only on the loopback interface
+ * * `port` - The port to listen on, `None` picks a free one
+ */
+async startCollabServer(docIds: string[], lan: boolean, port: number | null) : Promise<CollabServerInfo> {
+    return await TAURI_INVOKE("start_collab_server", { docIds, lan, port });
+},
+async getCollabServer() : Promise<CollabServerInfo | null> {
+    return await TAURI_INVOKE("get_collab_server");
+},
+/**
+ * Stop sharing, the peers are disconnected
+ */
+async stopCollabServer() : Promise<null> {
+    return await TAURI_INVOKE("stop_collab_server");
+},
+/**
+ * Join the session of a peer. The content of the peer is merged into the local document,
+ * so join into a new solution to get the same text as the peer.
+ * 
+ * # Arguments
+ * * `url` - The URL shared by the peer
+ * * `doc_id` - The local document to sync with the peer
+ * 
+ * # Returns
+ * * `Result<String, String>` - The id of the session, a `CollabSessionEndEvent` with it is
+ * emitted once the connection closes
+ */
+async joinCollabSession(url: string, docId: string) : Promise<string> {
+    return await TAURI_INVOKE("join_collab_session", { url, docId });
+},
+async getCollabSessions() : Promise<CollabSessionInfo[]> {
+    return await TAURI_INVOKE("get_collab_sessions");
+},
+/**
+ * Leave a joined session, the local document keeps its content
+ */
+async leaveCollabSession(sessionId: string) : Promise<null> {
+    return await TAURI_INVOKE("leave_collab_session", { sessionId });
+},
+/**
+ * Link a document to a file on disk, edits on either side are applied to the other
+ * 
+ * # Arguments
+ * * `path` - An absolute path, the file is created if it doesn't exist
+ * * `keep` - The side whose text is kept if the file has a different text already
+ */
+async linkExternalFile(documentId: string, path: string, keep: SyncSide) : Promise<ExternalFileLink> {
+    return await TAURI_INVOKE("link_external_file", { documentId, path, keep });
+},
+async unlinkExternalFile(documentId: string) : Promise<null> {
+    return await TAURI_INVOKE("unlink_external_file", { documentId });
+},
+async getExternalFile(documentId: string) : Promise<ExternalFileLink | null> {
+    return await TAURI_INVOKE("get_external_file", { documentId });
+},
+/**
+ * Resume a paused link by keeping the text of one side
+ */
+async resolveExternalFileConflict(documentId: string, keep: SyncSide) : Promise<ExternalFileLink> {
+    return await TAURI_INVOKE("resolve_external_file_conflict", { documentId, keep });
+},
+/**
+ * Preview a raw document
+ * 
+ * # Returns
+ * * `Result<Option<DocumentPreview>, String>` - The preview, `None` for a Yrs document
+ */
+async getDocumentPreview(documentId: string) : Promise<DocumentPreview | null> {
+    return await TAURI_INVOKE("get_document_preview", { documentId });
+},
+/**
+ * Store a testcase document as a copy of a file on disk, for tests too large to edit.
+ * The runner feeds the file to programs as is.
+ * 
+ * # Arguments
+ * * `path` - The file to copy, it isn't linked to the document
+ */
+async importRawDocument(documentId: string, path: string) : Promise<Document> {
+    return await TAURI_INVOKE("import_raw_document", { documentId, path });
+},
+/**
+ * Turn a small raw document back into a Yrs document that can be edited
+ */
+async convertRawDocument(documentId: string) : Promise<Document> {
+    return await TAURI_INVOKE("convert_raw_document", { documentId });
+},
 async getCheckersName() : Promise<string[]> {
     return await TAURI_INVOKE("get_checkers_name");
 },
-async launchLanguageServer(commands: string, ioMethod: IOMethod) : Promise<string> {
-    return await TAURI_INVOKE("launch_language_server", { commands, ioMethod });
+/**
+ * Get a language server for the language, the running one is shared when the same
+ * language of the same workspace was launched before
+ * 
+ * # Arguments
+ * * `commands` - The command to launch the server, empty to connect to a running one
+ * * `io_method` - How to talk to the server
+ * * `language` - The language settings, their environment is applied to the server
+ * 
+ * # Returns
+ * The id of the server, release it with `kill_language_server`
+ */
+async launchLanguageServer(commands: string, ioMethod: IOMethod, language: AdvLanguageItem | null) : Promise<string> {
+    return await TAURI_INVOKE("launch_language_server", { commands, ioMethod, language });
 },
+/**
+ * Release a language server, it is shut down when no editor uses it anymore
+ * 
+ * # Arguments
+ * * `pid` - The id returned by `launch_language_server`
+ */
 async killLanguageServer(pid: string) : Promise<null> {
     return await TAURI_INVOKE("kill_language_server", { pid });
 },
 async sendMessageToLanguageServer(pid: string, message: string) : Promise<null> {
     return await TAURI_INVOKE("send_message_to_language_server", { pid, message });
 },
-async executeProgramCallback(taskTag: string, commands: string, env: Partial<{ [key in string]: string }>, inputFilename: string, timeoutMillis: number) : Promise<ProgramOutput> {
-    return await TAURI_INVOKE("execute_program_callback", { taskTag, commands, env, inputFilename, timeoutMillis });
+/**
+ * Get the last lines a language server printed to stderr, also after it exited
+ * 
+ * # Arguments
+ * * `pid` - The id returned by `launch_language_server`
+ */
+async getLanguageServerLog(pid: string) : Promise<string[]> {
+    return await TAURI_INVOKE("get_language_server_log", { pid });
+},
+async executeProgramCallback(taskTag: string, commands: string, env: Partial<{ [key in string]: string }>, input: ProgramInputSource, timeoutMillis: number, language: AdvLanguageItem | null) : Promise<ProgramOutput> {
+    return await TAURI_INVOKE("execute_program_callback", { taskTag, commands, env, input, timeoutMillis, language });
 },
 async writeFileToTaskTag(taskTag: string, filename: string, content: string) : Promise<string> {
     return await TAURI_INVOKE("write_file_to_task_tag", { taskTag, filename, content });
 },
-async executeProgram(taskTag: string, commands: string, env: Partial<{ [key in string]: string }>, timeoutMillis: number) : Promise<ProgramSimpleOutput> {
-    return await TAURI_INVOKE("execute_program", { taskTag, commands, env, timeoutMillis });
+/**
+ * Write the content of a document to a file in the task directory, so the text
+ * doesn't need to travel through the webview
+ */
+async writeDocToTaskTag(taskTag: string, filename: string, docId: string) : Promise<string> {
+    return await TAURI_INVOKE("write_doc_to_task_tag", { taskTag, filename, docId });
+},
+/**
+ * A file with the content of a document, for a program that only reads it: a raw
+ * document is its own file, other documents are written to the task directory
+ */
+async getDocFile(taskTag: string, filename: string, docId: string) : Promise<string> {
+    return await TAURI_INVOKE("get_doc_file", { taskTag, filename, docId });
+},
+/**
+ * Hash of the text of a document, so a compile can be cached without the source
+ * traveling through the webview
+ */
+async getDocHash(docId: string) : Promise<string> {
+    return await TAURI_INVOKE("get_doc_hash", { docId });
+},
+async executeProgram(taskTag: string, commands: string, env: Partial<{ [key in string]: string }>, timeoutMillis: number, language: AdvLanguageItem | null) : Promise<ProgramSimpleOutput> {
+    return await TAURI_INVOKE("execute_program", { taskTag, commands, env, timeoutMillis, language });
+},
+async benchmarkProgram(taskTag: string, params: BenchmarkParams) : Promise<BenchmarkReport[]> {
+    return await TAURI_INVOKE("benchmark_program", { taskTag, params });
+},
+/**
+ * Find the first difference between the output file of a run and the answer document
+ */
+async diffOutput(outputFile: string, answerDocId: string, mode: DiffMode) : Promise<DiffMismatch | null> {
+    return await TAURI_INVOKE("diff_output", { outputFile, answerDocId, mode });
+},
+/**
+ * Read a page of the output file of a run and the answer document side by side
+ */
+async getDiffPage(outputFile: string, answerDocId: string, mode: DiffMode, startLine: number, limit: number) : Promise<DiffPage> {
+    return await TAURI_INVOKE("get_diff_page", { outputFile, answerDocId, mode, startLine, limit });
+},
+/**
+ * Look for compilers, interpreters and language servers in PATH and propose language presets.
+ * The PATH prefix of the workspace is searched first.
+ */
+async detectToolchains() : Promise<ToolchainReport> {
+    return await TAURI_INVOKE("detect_toolchains");
 }
 }
 
@@ -98,11 +323,19 @@ async executeProgram(taskTag: string, commands: string, env: Partial<{ [key in s
 
 
 export const events = __makeEvents__<{
+collabSessionEndEvent: CollabSessionEndEvent,
+documentStorageEvent: DocumentStorageEvent,
+documentUpdateEvent: DocumentUpdateEvent,
+externalFileConflictEvent: ExternalFileConflictEvent,
 languageServerEvent: LanguageServerEvent,
 programConfigUpdateEvent: ProgramConfigUpdateEvent,
 programOutputEvent: ProgramOutputEvent,
 workspaceConfigUpdateEvent: WorkspaceConfigUpdateEvent
 }>({
+collabSessionEndEvent: "collab-session-end-event",
+documentStorageEvent: "document-storage-event",
+documentUpdateEvent: "document-update-event",
+externalFileConflictEvent: "external-file-conflict-event",
 languageServerEvent: "language-server-event",
 programConfigUpdateEvent: "program-config-update-event",
 programOutputEvent: "program-output-event",
@@ -115,15 +348,202 @@ workspaceConfigUpdateEvent: "workspace-config-update-event"
 
 /** user-defined types **/
 
-export type AdvLanguageItem = { base: LanguageBase; cmd_compile: string; cmd_before_run: string | null; cmd_after_run: string | null; cmd_run: string; lsp: string | null; lsp_connect: LanguageServerProtocolConnectionType | null }
+export type AdvLanguageItem = { base: LanguageBase; cmd_compile: string; cmd_before_run: string | null; cmd_after_run: string | null; cmd_run: string; lsp: string | null; lsp_connect: LanguageServerProtocolConnectionType | null; 
+/**
+ * Endpoint of the language server when `lsp_connect` is `Tcp` (`127.0.0.1:%PORT`) or
+ * `WebSocket` (`ws://127.0.0.1:%PORT`). If `lsp` is empty the server is expected to be
+ * running already
+ */
+lsp_url: string | null; 
+/**
+ * Options and settings handed to the language server on behalf of the editor
+ */
+lsp_settings: LanguageServerSettings | null; 
+/**
+ * More servers running next to `lsp`, e.g. a linter next to a type checker.
+ * Requests go to `lsp` first, and to these when it lacks the capability.
+ */
+lsp_extra: ExtraLanguageServer[] | null; 
+/**
+ * Multiply the time limit of the problem by this factor, 1.0 if not set
+ */
+time_multiplier: number | null; 
+/**
+ * Extra milliseconds added to the time limit after the multiplier
+ */
+time_offset: number | null; 
+/**
+ * Stack size of the program in kilobytes, inherit from the app if not set
+ */
+stack_size: number | null; 
+/**
+ * Environment of the compile, run and language server processes, applied after the
+ * environment of the workspace
+ */
+env: EnvironmentConfig | null }
+export type BenchmarkParams = { problem_id: string; commands: string; env: Partial<{ [key in string]: string }>; inputs: ProgramInputSource[]; runs: number; timeout_millis: number; language: AdvLanguageItem | null }
+/**
+ * Benchmark result of one input file
+ */
+export type BenchmarkReport = { input_file: string; runs: RunMeasurement[]; cpu_time: TimingStatistics; wall_time: TimingStatistics; 
+/**
+ * Maximum of the peak memory of all runs, in kilobytes
+ */
+peak_memory_kb: number; time_limit: number; status: TimeLimitStatus }
 export type Checker = { id: string; name: string; language: string; description: string | null; document_id: string; document: Document | null }
+export type CollabServerInfo = { port: number; 
+/**
+ * Whether peers on the local network can join, otherwise only this machine can
+ */
+lan: boolean; 
+/**
+ * The URL to join each shared document with, by document id
+ */
+urls: Partial<{ [key in string]: string }> }
+/**
+ * A joined session ended because the connection closed
+ */
+export type CollabSessionEndEvent = { session_id: string; doc_id: string; error: string | null }
+export type CollabSessionInfo = { session_id: string; 
+/**
+ * The local document that the session syncs
+ */
+doc_id: string; url: string }
 export type CreateCheckerParams = { name: string; language: string; description: string | null; content: string | null }
 export type CreateCheckerResult = { checker: Checker }
 export type CreateProblemParams = { name: string; url: string | null; description: string | null; statement: string | null; checker: string | null; time_limit: number; memory_limit: number; initial_solution: CreateSolutionParams | null }
 export type CreateProblemResult = { problem: Problem }
 export type CreateSolutionParams = { author: string | null; name: string; language: string; content: string | null }
 export type CreateSolutionResult = { solution: Solution }
-export type Document = { id: string; create_datetime: string; modified_datetime: string; filename: string }
+/**
+ * A tool found in PATH
+ */
+export type DetectedTool = { tool: string; 
+/**
+ * The executable name that was found, use it in commands
+ */
+executable: string; path: string; 
+/**
+ * Version number, `None` if it can't be parsed
+ */
+version: string | null; 
+/**
+ * First line printed by the version command, `None` if the tool has no such command
+ */
+version_line: string | null }
+export type DiffLine = { line: number; output: string | null; answer: string | null; is_equal: boolean }
+/**
+ * The first difference between the output and the answer
+ */
+export type DiffMismatch = { 
+/**
+ * Index of the mismatched line or token, starting from 0
+ */
+index: number; 
+/**
+ * Position in the output, `None` if the output ended
+ */
+output_position: TextPosition | null; 
+/**
+ * Position in the answer, `None` if the answer ended
+ */
+answer_position: TextPosition | null; output: string | null; answer: string | null }
+export type DiffMode = 
+/**
+ * Compare line by line, ignoring trailing whitespace and trailing empty lines
+ */
+"Line" | 
+/**
+ * Compare whitespace-separated tokens, ignoring how they are split into lines
+ */
+"Token"
+export type DiffPage = { start_line: number; lines: DiffLine[]; has_more: boolean }
+export type Document = { id: string; create_datetime: string; modified_datetime: string; filename: string; storage: DocumentStorage; 
+/**
+ * Size of a raw file in bytes, `None` for Yrs documents
+ */
+size: number | null }
+/**
+ * The ends of a raw document, the middle of a large one is never sent to the webview
+ */
+export type DocumentPreview = { size: number; 
+/**
+ * The first lines of the file, the whole file if it isn't truncated
+ */
+head: string; 
+/**
+ * The last lines of the file, empty if it isn't truncated
+ */
+tail: string; is_truncated: boolean }
+export type DocumentSnapshot = { id: string; document_id: string; label: string | null; kind: SnapshotKind; create_datetime: string }
+/**
+ * How the content of a document is kept in the doc folder
+ */
+export type DocumentStorage = 
+/**
+ * A Yrs document that editors load and edit together
+ */
+"Yrs" | 
+/**
+ * The text as a plain file, for testcases too large to edit. It is never loaded
+ * whole, the runner reads it from disk.
+ */
+"Raw"
+/**
+ * A testcase document switched storage, its editors switch between the editor and the
+ * preview
+ */
+export type DocumentStorageEvent = { document_id: string; storage: DocumentStorage }
+/**
+ * An update applied to a document, so every editor of the document stays in sync
+ */
+export type DocumentUpdateEvent = { doc_id: string; 
+/**
+ * The editor that made the update, it has the update already
+ */
+origin: string | null; update: number[] }
+/**
+ * Environment of child processes, applied on top of the environment of the app
+ */
+export type EnvironmentConfig = { 
+/**
+ * Variables to set
+ */
+set: Partial<{ [key in string]: string }>; 
+/**
+ * Variables to remove
+ */
+unset: string[]; 
+/**
+ * Directories put in front of PATH, the first one is searched first
+ */
+path_prepend: string[] }
+/**
+ * A language server running next to the main one of a language
+ */
+/**
+ * Syncing a document with its file paused
+ */
+export type ExternalFileConflictEvent = { document_id: string; path: string; 
+/**
+ * Why syncing failed, `None` if both sides changed
+ */
+error: string | null }
+export type ExternalFileLink = { document_id: string; path: string; 
+/**
+ * Nothing is synced until a side is kept, after both sides changed or an error
+ */
+is_paused: boolean }
+export type ExtraLanguageServer = { 
+/**
+ * Shown in logs, and as the source of diagnostics that have none
+ */
+name: string; lsp: string; lsp_connect: LanguageServerProtocolConnectionType; lsp_url: string | null; lsp_settings: LanguageServerSettings | null }
+export type GarbageReport = { 
+/**
+ * Documents that no solution, testcase or checker owned
+ */
+document_ids: string[]; removed_files: number; freed_bytes: number }
 export type GetProblemsParams = { cursor: string | null; limit: number | null; search: string | null; sort_by: GetProblemsSortBy | null; sort_order: SortOrder | null }
 export type GetProblemsResult = { problems: Problem[]; next_cursor: string | null; has_more: boolean }
 export type GetProblemsSortBy = "Name" | "CreateDatetime" | "ModifiedDatetime"
@@ -134,24 +554,153 @@ export type IOMethod =
 /**
  * Use standard input/output for communication
  */
-"StdIO"
-export type LanguageBase = "Cpp" | "TypeScript" | "JavaScript" | "Go" | "Python" | "Text"
+"StdIO" | 
+/**
+ * Connect to a TCP socket, e.g. `127.0.0.1:9257`, framed like stdio
+ */
+{ Tcp: { address: string } } | 
+/**
+ * Connect to a WebSocket endpoint, one JSON-RPC message per frame
+ */
+{ WebSocket: { url: string } }
+export type JsonValue = null | boolean | number | string | JsonValue[] | Partial<{ [key in string]: JsonValue }>
+export type LanguageBase = "Cpp" | "C" | "TypeScript" | "JavaScript" | "Go" | "Python" | "Rust" | "Java" | "Kotlin" | "CSharp" | "Haskell" | "Text"
+/**
+ * A proposed language entry
+ */
+export type LanguagePreset = { name: string; language: AdvLanguageItem }
 export type LanguageServerEvent = { pid: string; response: LanguageServerResponse }
-export type LanguageServerProtocolConnectionType = "StdIO" | "WebSocket"
-export type LanguageServerResponse = { type: "Closed"; exit_code: number } | { type: "Message"; msg: string }
+export type LanguageServerProtocolConnectionType = "StdIO" | "Tcp" | "WebSocket"
+export type LanguageServerResponse = 
+/**
+ * The server is gone for good: released, or it kept crashing
+ */
+{ type: "Closed"; exit_code: number } | { type: "Message"; msg: string } | 
+/**
+ * A line the server printed to stderr
+ */
+{ type: "Log"; line: string } | 
+/**
+ * The server crashed and was started again, it needs to be initialized again
+ */
+{ type: "Restarted"; attempt: number }
+/**
+ * Language server settings, e.g. fallback flags of clangd or the strictness of pyright
+ */
+export type LanguageServerSettings = { 
+/**
+ * Merged into `initializationOptions` of the `initialize` request
+ */
+initialization_options: JsonValue | null; 
+/**
+ * Answers `workspace/configuration` requests, a section like `python.analysis` is
+ * looked up by its dotted path
+ */
+configuration: JsonValue | null }
 export type Problem = { id: string; name: string; url: string | null; description: string; statement: string | null; checker: string | null; create_datetime: string; modified_datetime: string; time_limit: number; memory_limit: number; solutions: Solution[] }
 export type ProblemChangeset = { name: string | null; url: string | null; description: string | null; statement: string | null; checker: string | null; time_limit: number | null; memory_limit: number | null }
 export type ProgramConfig = { workspace: string | null; theme: string; system_titlebar: boolean }
 export type ProgramConfigUpdateEvent = { new: ProgramConfig }
+/**
+ * Where the stdin of a program comes from
+ */
+export type ProgramInputSource = 
+/**
+ * A file on disk
+ */
+{ type: "File"; path: string } | 
+/**
+ * The content of a document, written to the task directory before the run. A raw
+ * document is read from its own file.
+ */
+{ type: "Document"; doc_id: string }
 export type ProgramOutput = { type: "Full"; exit_code: number; is_timeout: boolean; content: string; output_file: string } | { type: "Strip"; exit_code: number; size: number; is_timeout: boolean; content: string; output_file: string }
 export type ProgramOutputEvent = { task_tag: string; source: ProgramOutputSource; line: string }
 export type ProgramOutputSource = "Stdout" | "Stderr"
-export type ProgramSimpleOutput = { exit_code: number; stdout: string; stderr: string; is_timeout: boolean }
+export type ProgramSimpleOutput = { 
+/**
+ * Exit code of the last step that ran
+ */
+exit_code: number; 
+/**
+ * Output of all steps, concatenated
+ */
+stdout: string; stderr: string; is_timeout: boolean; 
+/**
+ * Output of each step that ran, the sequence stops at the first failed step
+ */
+steps: ProgramStepOutput[] }
+export type ProgramStepOutput = { command: string; exit_code: number; stdout: string; stderr: string; is_timeout: boolean }
+/**
+ * Resource usage of a single run
+ */
+export type RunMeasurement = { exit_code: number; is_timeout: boolean; wall_millis: number; cpu_millis: number; 
+/**
+ * Peak resident memory in kilobytes
+ */
+peak_memory_kb: number }
+export type SnapshotDiffKind = "Equal" | "Insert" | "Delete"
+export type SnapshotDiffLine = { kind: SnapshotDiffKind; 
+/**
+ * Line number in the old text, starts from 1, `None` for inserted lines
+ */
+old_line: number | null; 
+/**
+ * Line number in the new text, starts from 1, `None` for deleted lines
+ */
+new_line: number | null; text: string }
+/**
+ * What took a snapshot of a document
+ */
+export type SnapshotKind = 
+/**
+ * Taken by the user, usually with a label
+ */
+"Manual" | 
+/**
+ * Taken before a judge run
+ */
+"Run" | 
+/**
+ * Taken while editing, once per interval
+ */
+"Interval" | 
+/**
+ * The state replaced by a restore
+ */
+"Restore"
 export type Solution = { id: string; author: string; name: string; language: string; problem_id: string; document: Document | null }
 export type SolutionChangeset = { name: string | null; author: string | null; language: string | null }
 export type SortOrder = "Asc" | "Desc"
+/**
+ * The side of a link whose text is kept
+ */
+export type SyncSide = "Document" | "File"
 export type TestCase = { id: string; problem_id: string; input_document_id: string; answer_document_id: string }
-export type WorkspaceConfig = { font_family: string; font_size: number; language: Partial<{ [key in string]: AdvLanguageItem }> }
+/**
+ * Position in a text, line and column start from 1, column is counted in characters
+ */
+export type TextPosition = { line: number; column: number }
+export type TimeLimitStatus = 
+/**
+ * Every run finished well under the time limit
+ */
+"Safe" | 
+/**
+ * The slowest run is under the time limit, but above `TIGHT_TIME_LIMIT_RATIO` of it
+ */
+"Tight" | 
+/**
+ * At least one run exceeded the time limit
+ */
+"Exceeded"
+export type TimingStatistics = { min: number; median: number; max: number; stddev: number }
+export type ToolchainReport = { tools: DetectedTool[]; presets: LanguagePreset[] }
+export type WorkspaceConfig = { font_family: string; font_size: number; 
+/**
+ * Environment of all child processes, including checkers
+ */
+env: EnvironmentConfig; language: Partial<{ [key in string]: AdvLanguageItem }> }
 export type WorkspaceConfigUpdateEvent = { new: WorkspaceConfig }
 
 /** tauri-specta globals **/
@@ -3,8 +3,8 @@ import { identity } from "lodash"
 import { sortBy } from "lodash/fp"
 import { match } from "ts-pattern"
 
-export const languageBaseValues: LanguageBase[] = sortBy(identity, ["Cpp", "TypeScript", "Python", "JavaScript", "Go", "Text"])
-export const languageServerProtocolConnectionTypeValues: LanguageServerProtocolConnectionType[] = ["StdIO", "WebSocket"]
+export const languageBaseValues: LanguageBase[] = sortBy(identity, ["Cpp", "C", "TypeScript", "Python", "JavaScript", "Go", "Rust", "Java", "Kotlin", "CSharp", "Haskell", "Text"])
+export const languageServerProtocolConnectionTypeValues: LanguageServerProtocolConnectionType[] = ["StdIO", "Tcp", "WebSocket"]
 export const textLanguageItem: AdvLanguageItem = {
 	base: "Text",
 	cmd_compile: "",
@@ -13,6 +13,11 @@ export const textLanguageItem: AdvLanguageItem = {
 	cmd_run: "",
 	lsp: null,
 	lsp_connect: null,
+	lsp_url: null,
+	time_multiplier: null,
+	time_offset: null,
+	stack_size: null,
+	env: null,
 }
 
 export function getLanguageID(language: LanguageBase) {
@@ -22,6 +27,12 @@ export function getLanguageID(language: LanguageBase) {
 		.with("TypeScript", () => "typescript")
 		.with("JavaScript", () => "javascript")
 		.with("Go", () => "go")
+		.with("C", () => "c")
+		.with("Rust", () => "rust")
+		.with("Java", () => "java")
+		.with("Kotlin", () => "kotlin")
+		.with("CSharp", () => "csharp")
+		.with("Haskell", () => "haskell")
 		.otherwise(() => "text")
 }
 
@@ -32,6 +43,23 @@ export function getFileExtensionOfLanguage(language: LanguageBase) {
 		.with("TypeScript", () => "ts")
 		.with("JavaScript", () => "js")
 		.with("Go", () => "go")
+		.with("C", () => "c")
+		.with("Rust", () => "rs")
+		.with("Java", () => "java")
+		.with("Kotlin", () => "kt")
+		.with("CSharp", () => "cs")
+		.with("Haskell", () => "hs")
 		.with("Text", () => "txt")
 		.exhaustive()
 }
+
+/**
+ * Name of the source file written before compiling.
+ * Java requires the public class `Main` to live in `Main.java`, and Kotlin names the class of top level functions after the file (`MainKt`),
+ * so presets can refer to a fixed class name.
+ */
+export function getSourceFileName(language: LanguageBase) {
+	return match(language)
+		.with("Java", "Kotlin", () => `Main.${getFileExtensionOfLanguage(language)}`)
+		.otherwise(() => `code.${getFileExtensionOfLanguage(language)}`)
+}
@@ -1,36 +1,36 @@
 import type { AdvLanguageItem, ProgramSimpleOutput } from "./client"
+import * as log from "@tauri-apps/plugin-log"
 import { MD5 } from "crypto-js"
 import { commands, events } from "./client"
-import { getFileExtensionOfLanguage } from "./client/type"
+import { getSourceFileName } from "./client/type"
 import { LRUCache } from "./lru-cache"
 
-const cache = new LRUCache<string, ProgramSimpleOutput>(512)
+type CompileOutput = ProgramSimpleOutput & { source: string }
+const cache = new LRUCache<string, CompileOutput>(512)
 
-export async function compileCode(tag: string, codeDocID: string, language: AdvLanguageItem, timeout: number = 3000) {
-	const code = await commands.getStringOfDoc(codeDocID, "content")
-	const hash = MD5(`${tag}(${language.base}, ${language.cmd_compile}):${code}`).toString()
+export async function compileCode(tag: string, codeDocID: string, language: AdvLanguageItem, env: Partial<Record<string, string>> = {}, timeout: number = 3000) {
+	const codeHash = await commands.getDocHash(codeDocID)
+	const hash = MD5(`${tag}(${language.base}, ${language.cmd_compile}):${codeHash}`).toString()
 	const cached_output = cache.get(hash)
 	if (cached_output) {
 		return cached_output
 	}
 
-	const source = await commands.writeFileToTaskTag(tag, `code.${getFileExtensionOfLanguage(language.base)}`, code)
+	const source = await commands.writeDocToTaskTag(tag, getSourceFileName(language.base), codeDocID)
 	const res = await commands.executeProgram(tag, language.cmd_compile, {
+		...env,
 		SRC: source,
-	}, timeout)
+	}, timeout, language)
 	if (res.is_timeout) {
 		throw new Error("Compile timeout")
 	}
-	cache.put(hash, res)
-	return res
+	const output = { ...res, source }
+	cache.put(hash, output)
+	return output
 }
 
 export type ExecuteProgramOutputListener = (line: string, type: "stdout" | "stderr") => void
-export async function executeProgram(tag: string, inputFileDocID: string, language: AdvLanguageItem, timeout: number, outputListener?: ExecuteProgramOutputListener) {
-	const inputContent = await commands.getStringOfDoc(inputFileDocID, "content")
-	// TODO: Actually we can copy the content in rust, but I am lazy. Fix it later
-	const inputFile = await commands.writeFileToTaskTag(tag, `case-${inputFileDocID}.txt`, inputContent)
-
+export async function executeProgram(tag: string, inputFileDocID: string, language: AdvLanguageItem, env: Partial<Record<string, string>>, timeout: number, outputListener?: ExecuteProgramOutputListener) {
 	let unsub = Promise.resolve(() => {})
 
 	try {
@@ -46,11 +46,11 @@ export async function executeProgram(tag: string, inputFileDocID: string, langua
 		})
 
 		if (language.cmd_before_run) {
-			commands.executeProgram(tag, language.cmd_before_run, {}, 3000)
+			commands.executeProgram(tag, language.cmd_before_run, env, 3000, language)
 		}
-		const execuatedResult = await commands.executeProgramCallback(tag, language.cmd_run, {}, inputFile, timeout)
+		const execuatedResult = await commands.executeProgramCallback(tag, language.cmd_run, env, { type: "Document", doc_id: inputFileDocID }, timeout, language)
 		if (language.cmd_after_run) {
-			commands.executeProgram(tag, language.cmd_after_run, {}, 3000)
+			commands.executeProgram(tag, language.cmd_after_run, env, 3000, language)
 		}
 
 		return execuatedResult
@@ -61,22 +61,22 @@ export async function executeProgram(tag: string, inputFileDocID: string, langua
 }
 
 export async function checkOutput(tag: string, inputDocID: string, outputFile: string, answerDocID: string, checkerName: string) {
-	const inputContent = await commands.getStringOfDoc(inputDocID, "content")
-	const answerContent = await commands.getStringOfDoc(answerDocID, "content")
-	const inputFile = await commands.writeFileToTaskTag(tag, `case-${inputDocID}.in`, inputContent)
-	const answerFile = await commands.writeFileToTaskTag(tag, `case-${answerDocID}.ans`, answerContent)
+	const inputFile = await commands.getDocFile(tag, `case-${inputDocID}.in`, inputDocID)
+	const answerFile = await commands.getDocFile(tag, `case-${answerDocID}.ans`, answerDocID)
 
 	const checker = await commands.resolveChecker(checkerName)
-	const res = await commands.executeProgram(tag, `${checker} %INPUT %OUTPUT %ANSWER`, {
+	const res = await commands.executeProgram(tag, "%CHECKER %INPUT %OUTPUT %ANSWER", {
+		CHECKER: checker,
 		INPUT: inputFile,
 		ANSWER: answerFile,
 		OUTPUT: outputFile,
-	}, 12000)
+	}, 12000, null)
 	return res
 }
 
 interface RunTestcaseParams {
 	tag: string
+	problemID: string
 	testcaseInputDocID: string
 	testcaseOutputDocID: string
 	solutionDocID: string
@@ -146,6 +146,7 @@ type RunTestResult = {
 
 export async function runTestcase({
 	tag,
+	problemID,
 	testcaseInputDocID,
 	testcaseOutputDocID,
 	solutionDocID,
@@ -156,7 +157,12 @@ export async function runTestcase({
 	programOutputListener,
 }: RunTestcaseParams): Promise<RunTestResult> {
 	try {
-		const compileInfo = await compileCode(tag, solutionDocID, language, compileTimeout)
+		// Keep the judged version in the history, unchanged code is skipped by the backend
+		await commands.createDocumentSnapshot(solutionDocID, "Run", null).catch((e) => {
+			log.warn(`failed to take snapshot of ${solutionDocID}: ${e}`)
+		})
+		const problemDir = await commands.getProblemDir(problemID)
+		const compileInfo = await compileCode(tag, solutionDocID, language, { PROBLEM_DIR: problemDir }, compileTimeout)
 		if (compileInfo.is_timeout) {
 			return {
 				result: "CETLE",
@@ -170,7 +176,10 @@ export async function runTestcase({
 				compilerExitCode: compileInfo.exit_code,
 			}
 		}
-		const runInfo = await executeProgram(tag, testcaseInputDocID, language, runTimeout, programOutputListener)
+		const runInfo = await executeProgram(tag, testcaseInputDocID, language, {
+			SRC: compileInfo.source,
+			PROBLEM_DIR: problemDir,
+		}, runTimeout, programOutputListener)
 		if (runInfo.is_timeout) {
 			return {
 				result: "TLE",